@@ -0,0 +1,272 @@
+//! # Rusty Thermostat Migrations
+//! Versioned schema changes for the tables in `src/schema`<br>
+//! Every migration has to run against both Postgres and SQLite, so they are written with sea-query instead of raw SQL
+
+pub use sea_orm_migration::prelude::*;
+pub use sea_orm_migration::MigrationStatus;
+pub use m20261017_000010_convert_legacy_units::{LEGACY_HOME_UNITS_VAR, LEGACY_WEATHER_UNITS_VAR};
+
+mod m20261017_000001_create_tables;
+mod m20261017_000002_add_comfort_columns;
+mod m20261017_000003_create_rollups;
+mod m20261017_000004_history_uuid_keys;
+mod m20261017_000005_create_audit_log;
+mod m20261017_000006_hash_sensor_tokens;
+mod m20261017_000007_optional_weather_values;
+mod m20261017_000008_optional_pollutants;
+mod m20261017_000009_hash_controller_tokens;
+mod m20261017_000010_convert_legacy_units;
+mod m20261017_000011_reading_locations;
+mod rebuild;
+
+pub struct Migrator;
+
+#[async_trait::async_trait]
+impl MigratorTrait for Migrator {
+    fn migrations() -> Vec<Box<dyn MigrationTrait>> {
+        vec![
+            Box::new(m20261017_000001_create_tables::Migration),
+            Box::new(m20261017_000002_add_comfort_columns::Migration),
+            Box::new(m20261017_000003_create_rollups::Migration),
+            Box::new(m20261017_000004_history_uuid_keys::Migration),
+            Box::new(m20261017_000005_create_audit_log::Migration),
+            Box::new(m20261017_000006_hash_sensor_tokens::Migration),
+            Box::new(m20261017_000007_optional_weather_values::Migration),
+            Box::new(m20261017_000008_optional_pollutants::Migration),
+            Box::new(m20261017_000009_hash_controller_tokens::Migration),
+            Box::new(m20261017_000010_convert_legacy_units::Migration),
+            Box::new(m20261017_000011_reading_locations::Migration),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm_migration::sea_orm::{ConnectionTrait, Database, DatabaseConnection, QueryResult};
+    use sea_orm_migration::sea_orm::prelude::Uuid;
+    use m20261017_000010_convert_legacy_units::{LegacyUnits, UnitSystem};
+
+    const TABLES: [&str; 20] = [
+        "PollutionReading", "WeatherReading", "DailyDegreeDays", "ForecastReading", "EnvCapability", "HVACactivity",
+        "HomeSummary", "Zones", "Weekdays", "Schedules", "Communication", "Sensors", "Controllers", "Alerts",
+        "ChangeSource", "ManualChangeHistory", "SensorReadingHistory", "SensorReadingRollup", "WeatherReadingRollup",
+        "AuditLog",
+    ];
+
+    async fn memory_db() -> DatabaseConnection {
+        Database::connect("sqlite::memory:").await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn up_creates_every_table_and_down_drops_them() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, None).await.unwrap();
+        let manager: SchemaManager = SchemaManager::new(&db);
+        for table in TABLES {
+            assert!(manager.has_table(table).await.unwrap(), "{} was not created", table);
+        }
+
+        Migrator::reset(&db).await.unwrap();
+        for table in TABLES {
+            assert!(!manager.has_table(table).await.unwrap(), "{} was not dropped", table);
+        }
+    }
+
+    #[tokio::test]
+    async fn up_twice_is_a_no_op() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, None).await.unwrap();
+        Migrator::up(&db, None).await.unwrap();
+        assert!(Migrator::get_pending_migrations(&db).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn comfort_columns_are_added_to_an_older_weather_table() {
+        let db: DatabaseConnection = memory_db().await;
+        let manager: SchemaManager = SchemaManager::new(&db);
+        manager.create_table(Table::create()
+            .table(Alias::new("WeatherReading"))
+            .col(ColumnDef::new(Alias::new("id")).integer().not_null().auto_increment().primary_key())
+            .to_owned()).await.unwrap();
+
+        // Later migrations copy every column of the table, which this one only has a few of
+        Migrator::up(&db, Some(2)).await.unwrap();
+        for column in ["dewPoint", "heatIndex", "windChill", "humidex", "absoluteHumidity"] {
+            assert!(manager.has_column("WeatherReading", column).await.unwrap(), "{} was not added", column);
+        }
+    }
+
+    // Every history row as (id, time, changeWeather, changePollution), the last two only for manual changes
+    async fn history_rows<K: sea_orm_migration::sea_orm::TryGetable>(db: &DatabaseConnection, table: &str, time: &str) -> Vec<(K, String)> {
+        let rows: Vec<QueryResult> = db.query_all(db.get_database_backend().build(&Query::select()
+            .column(Alias::new("id")).column(Alias::new(time)).from(Alias::new(table))
+            .order_by(Alias::new(time), Order::Asc).to_owned())).await.unwrap();
+        rows.iter().map(|row| (row.try_get_by_index(0).unwrap(), row.try_get_by_index(1).unwrap())).collect()
+    }
+
+    #[tokio::test]
+    async fn history_moves_to_uuid_keys_and_back() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, Some(3)).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "Communication" ("id", "Name", "active") VALUES (1, 'WiFi', true);
+            INSERT INTO "ChangeSource" ("id", "name") VALUES (1, 'web');
+            INSERT INTO "Sensors" ("id", "active", "Name", "Token", "timeAdded", "comType") VALUES (1, true, 'Hall', 'hall', '2024-03-01 00:00:00', 1);
+            INSERT INTO "WeatherReading" ("id", "timestamp", "condition", "description", "icon", "tempReal", "tempFeel", "pressureSea",
+                "humidity", "pressureGround", "visibility", "windSpeed", "windDeg", "windGust", "clouds", "dt", "sunrise", "sunset")
+                VALUES (7, '2024-03-01 09:00:00', 'Rain', 'light rain', '10d', 6.5, 4.0, 1009, 80, 1001, 8000, 3.5, 270, 6.0, 90, 0, 0, 0),
+                       (3, '2024-03-01 10:00:00', 'Rain', 'light rain', '10d', 7.0, 5.0, 1009, 80, 1001, 8000, 3.5, 270, 6.0, 90, 0, 0, 0);
+            INSERT INTO "PollutionReading" ("id", "timestamp", "AQI", "CO", "NO", "NO2", "O3", "SO2", "PM2_5", "PM10", "NH3")
+                VALUES (5, '2024-03-01 10:00:00', 2, 201.9, 0.0, 0.8, 68.7, 0.6, 0.5, 0.5, 0.1);
+            INSERT INTO "SensorReadingHistory" ("id", "sensorID", "timestamp", "readingTemp") VALUES (1, 1, '2024-03-01 10:05:00', 20.5);
+            INSERT INTO "ManualChangeHistory" ("id", "changeTiming", "changeWeather", "changePollution", "changeSource", "newTemp")
+                VALUES (9, '2024-03-01 10:10:00', 3, 5, 1, 21.0);
+        "#).await.unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+        // The UUIDs follow the readings' own times, not the old ids
+        let weather: Vec<(Uuid, String)> = history_rows(&db, "WeatherReading", "timestamp").await;
+        assert_eq!(weather.len(), 2);
+        assert!(weather[0].0 < weather[1].0);
+        assert_eq!(weather[0].0.get_version_num(), 7);
+        let pollution: Vec<(Uuid, String)> = history_rows(&db, "PollutionReading", "timestamp").await;
+        assert_eq!(history_rows::<Uuid>(&db, "SensorReadingHistory", "timestamp").await.len(), 1);
+        let change: QueryResult = db.query_one(db.get_database_backend().build(&Query::select()
+            .columns([Alias::new("changeWeather"), Alias::new("changePollution")]).from(Alias::new("ManualChangeHistory")).to_owned()))
+            .await.unwrap().unwrap();
+        assert_eq!(change.try_get_by_index::<Uuid>(0).unwrap(), weather[1].0);
+        assert_eq!(change.try_get_by_index::<Uuid>(1).unwrap(), pollution[0].0);
+        let manager: SchemaManager = SchemaManager::new(&db);
+        assert!(manager.has_index("WeatherReading", "WeatherReading_timestamp").await.unwrap());
+        assert!(!manager.has_table("WeatherReading_keys").await.unwrap());
+
+        // Going back numbers the rows in time order and keeps the manual change pointing at the same weather
+        Migrator::down(&db, Some(9)).await.unwrap();
+        let weather: Vec<(i32, String)> = history_rows(&db, "WeatherReading", "timestamp").await;
+        assert_eq!(weather.iter().map(|row| row.0).collect::<Vec<i32>>(), vec![1, 2]);
+        let change: QueryResult = db.query_one(db.get_database_backend().build(&Query::select()
+            .column(Alias::new("changeWeather")).from(Alias::new("ManualChangeHistory")).to_owned())).await.unwrap().unwrap();
+        assert_eq!(change.try_get_by_index::<i32>(0).unwrap(), 2);
+    }
+
+    #[tokio::test]
+    async fn plain_sensor_tokens_are_hashed_once() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, Some(5)).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "Communication" ("id", "Name", "active") VALUES (1, 'WiFi', true);
+            INSERT INTO "Sensors" ("id", "active", "Name", "Token", "timeAdded", "comType") VALUES
+                (1, true, 'Hall', 'abc', '2024-03-01 00:00:00', 1),
+                (2, true, 'Den', 'sha256:already', '2024-03-01 00:00:00', 1);
+        "#).await.unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+        let rows: Vec<QueryResult> = db.query_all(db.get_database_backend().build(&Query::select()
+            .column(Alias::new("Token")).from(Alias::new("Sensors")).order_by(Alias::new("id"), Order::Asc).to_owned())).await.unwrap();
+        let tokens: Vec<String> = rows.iter().map(|row| row.try_get_by_index(0).unwrap()).collect();
+        assert_eq!(tokens, vec!["sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", "sha256:already"]);
+    }
+
+    #[tokio::test]
+    async fn plain_controller_tokens_are_hashed_once() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, Some(8)).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "Communication" ("id", "Name", "active") VALUES (1, 'WiFi', true);
+            INSERT INTO "EnvCapability" ("id", "Heating", "Cooling") VALUES (1, true, false), (2, true, false);
+            INSERT INTO "HVACactivity" ("id", "Heating", "Cooling") VALUES (1, false, false), (2, false, false);
+            INSERT INTO "Controllers" ("id", "Name", "active", "comType", "Token", "timeAdded", "Capability", "systemActive") VALUES
+                (1, 'Furnace', true, 1, 'abc', '2024-03-01 00:00:00', 1, 1),
+                (2, 'Fan', true, 1, 'sha256:already', '2024-03-01 00:00:00', 2, 2);
+        "#).await.unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+        let rows: Vec<QueryResult> = db.query_all(db.get_database_backend().build(&Query::select()
+            .column(Alias::new("Token")).from(Alias::new("Controllers")).order_by(Alias::new("id"), Order::Asc).to_owned())).await.unwrap();
+        let tokens: Vec<String> = rows.iter().map(|row| row.try_get_by_index(0).unwrap()).collect();
+        assert_eq!(tokens, vec!["sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad", "sha256:already"]);
+    }
+
+    async fn single_value(db: &DatabaseConnection, table: &str, column: &str) -> f64 {
+        let row: QueryResult = db.query_one(db.get_database_backend().build(&Query::select()
+            .column(Alias::new(column)).from(Alias::new(table)).to_owned())).await.unwrap().unwrap();
+        row.try_get_by_index(0).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rows_stored_before_canonical_units_are_converted_by_group() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, Some(9)).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "Communication" ("id", "Name", "active") VALUES (1, 'WiFi', true);
+            INSERT INTO "Sensors" ("id", "active", "Name", "Token", "timeAdded", "comType", "currentTemp")
+                VALUES (1, true, 'Hall', 'hall', '2024-03-01 00:00:00', 1, 68.0);
+            INSERT INTO "DailyDegreeDays" ("id", "day", "baseTemp", "meanTemp", "hdd", "cdd", "hoursObserved",
+                "hoursInterpolated", "computedAt") VALUES (1, '2024-03-01', 65.0, 50.0, 15.0, 0.0, 24, 0, '2024-03-02 00:00:00');
+        "#).await.unwrap();
+
+        // Nothing says what units those are in, so the migration leaves them alone
+        Migrator::up(&db, None).await.unwrap();
+        assert_eq!(single_value(&db, "Sensors", "currentTemp").await, 68.0);
+        assert_eq!(single_value(&db, "DailyDegreeDays", "baseTemp").await, 65.0);
+
+        let manager: SchemaManager = SchemaManager::new(&db);
+        let home = LegacyUnits { home: Some(UnitSystem::Imperial), weather: None };
+        m20261017_000010_convert_legacy_units::convert(&manager, home).await.unwrap();
+        assert!((single_value(&db, "Sensors", "currentTemp").await - 20.0).abs() < 1e-9);
+        assert_eq!(single_value(&db, "DailyDegreeDays", "baseTemp").await, 65.0);
+
+        let weather = LegacyUnits { home: None, weather: Some(UnitSystem::Imperial) };
+        m20261017_000010_convert_legacy_units::convert(&manager, weather).await.unwrap();
+        assert!((single_value(&db, "DailyDegreeDays", "baseTemp").await - 18.333333333).abs() < 1e-6);
+        assert!((single_value(&db, "DailyDegreeDays", "hdd").await - 25.0 / 3.0).abs() < 1e-9);
+        assert!((single_value(&db, "Sensors", "currentTemp").await - 20.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn readings_the_provider_left_out_can_be_null() {
+        let db: DatabaseConnection = memory_db().await;
+        Migrator::up(&db, Some(6)).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "Communication" ("id", "Name", "active") VALUES (1, 'WiFi', true);
+            INSERT INTO "ChangeSource" ("id", "name") VALUES (1, 'web');
+            INSERT INTO "WeatherReading" ("id", "timestamp", "condition", "description", "icon", "tempReal", "tempFeel", "pressureSea",
+                "humidity", "pressureGround", "visibility", "windSpeed", "windDeg", "windGust", "clouds", "dt", "sunrise", "sunset")
+                VALUES ('0190b3d2-0000-7000-8000-000000000001', '2024-03-01 09:00:00', 'Rain', 'light rain', '10d', 6.5, 4.0, 1009,
+                    80, 1001, 8000, 3.5, 270, 6.0, 90, 0, 0, 0);
+            INSERT INTO "PollutionReading" ("id", "timestamp", "AQI", "CO", "NO", "NO2", "O3", "SO2", "PM2_5", "PM10", "NH3")
+                VALUES ('0190b3d2-0000-7000-8000-000000000002', '2024-03-01 09:00:00', 2, 201.9, 0.0, 0.8, 68.7, 0.6, 0.5, 0.5, 0.1);
+            INSERT INTO "ManualChangeHistory" ("id", "changeTiming", "changeWeather", "changePollution", "changeSource", "newTemp")
+                VALUES ('0190b3d2-0000-7000-8000-000000000003', '2024-03-01 09:10:00', '0190b3d2-0000-7000-8000-000000000001',
+                    '0190b3d2-0000-7000-8000-000000000002', 1, 21.0);
+        "#).await.unwrap();
+
+        Migrator::up(&db, None).await.unwrap();
+        db.execute_unprepared(r#"
+            INSERT INTO "WeatherReading" ("id", "timestamp", "tempReal", "tempFeel", "pressureSea", "humidity", "windSpeed", "windDeg",
+                "clouds", "dt", "sunrise", "sunset")
+                VALUES ('0190b3d2-0000-7000-8000-000000000004', '2024-03-01 10:00:00', 7.0, 5.0, 1010, 78, 4.0, 260, 75, 0, 0, 0);
+            INSERT INTO "PollutionReading" ("id", "timestamp", "AQI", "CO", "NO2", "O3", "PM2_5", "PM10")
+                VALUES ('0190b3d2-0000-7000-8000-000000000005', '2024-03-01 10:00:00', 2, 180.0, 0.9, 70.1, 0.6, 0.7);
+        "#).await.unwrap();
+        assert_eq!(history_rows::<String>(&db, "PollutionReading", "timestamp").await.len(), 2);
+        assert_eq!(history_rows::<String>(&db, "WeatherReading", "timestamp").await.len(), 2);
+        assert_eq!(history_rows::<String>(&db, "ManualChangeHistory", "changeTiming").await.len(), 1);
+        let manager: SchemaManager = SchemaManager::new(&db);
+        assert!(manager.has_index("WeatherReading", "WeatherReading_timestamp").await.unwrap());
+
+        // Going back fills the gaps in the newer readings
+        Migrator::down(&db, Some(5)).await.unwrap();
+        let row: QueryResult = db.query_one(db.get_database_backend().build(&Query::select()
+            .column(Alias::new("NH3")).from(Alias::new("PollutionReading"))
+            .and_where(Expr::col(Alias::new("timestamp")).eq("2024-03-01 10:00:00")).to_owned())).await.unwrap().unwrap();
+        assert_eq!(row.try_get_by_index::<f64>(0).unwrap(), 0.0);
+        let row: QueryResult = db.query_one(db.get_database_backend().build(&Query::select()
+            .columns([Alias::new("pressureGround"), Alias::new("windGust")]).from(Alias::new("WeatherReading"))
+            .and_where(Expr::col(Alias::new("timestamp")).eq("2024-03-01 10:00:00")).to_owned())).await.unwrap().unwrap();
+        assert_eq!(row.try_get_by_index::<i32>(0).unwrap(), 1010);
+        assert_eq!(row.try_get_by_index::<f64>(1).unwrap(), 4.0);
+        assert_eq!(history_rows::<String>(&db, "ManualChangeHistory", "changeTiming").await.len(), 1);
+    }
+}
@@ -0,0 +1,739 @@
+//! Creates every table the entities in `src/schema` map to<br>
+//! Tables are only created if they are missing, so a database that was loaded by hand from the old SQL file keeps its
+//! data and is marked as migrated. The legacy units migration converts that data into canonical units when it is told
+//! what units it was stored in
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(Table::create()
+            .table(PollutionReading::Table)
+            .if_not_exists()
+            .col(&mut id(PollutionReading::Id))
+            .col(ColumnDef::new(PollutionReading::Timestamp).date_time().not_null())
+            .col(ColumnDef::new(PollutionReading::Aqi).integer().not_null())
+            .col(ColumnDef::new(PollutionReading::Co).double().not_null())
+            .col(ColumnDef::new(PollutionReading::No).double().not_null())
+            .col(ColumnDef::new(PollutionReading::No2).double().not_null())
+            .col(ColumnDef::new(PollutionReading::O3).double().not_null())
+            .col(ColumnDef::new(PollutionReading::So2).double().not_null())
+            .col(ColumnDef::new(PollutionReading::Pm25).double().not_null())
+            .col(ColumnDef::new(PollutionReading::Pm10).double().not_null())
+            .col(ColumnDef::new(PollutionReading::Nh3).double().not_null())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(WeatherReading::Table)
+            .if_not_exists()
+            .col(&mut id(WeatherReading::Id))
+            .col(ColumnDef::new(WeatherReading::Timestamp).date_time().not_null())
+            .col(ColumnDef::new(WeatherReading::Condition).text().not_null())
+            .col(ColumnDef::new(WeatherReading::Description).text().not_null())
+            .col(ColumnDef::new(WeatherReading::Icon).text().not_null())
+            .col(ColumnDef::new(WeatherReading::TempReal).double().not_null())
+            .col(ColumnDef::new(WeatherReading::TempFeel).double().not_null())
+            .col(ColumnDef::new(WeatherReading::PressureSea).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::Humidity).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::PressureGround).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::Visibility).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::WindSpeed).double().not_null())
+            .col(ColumnDef::new(WeatherReading::WindDeg).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::WindGust).double().not_null())
+            .col(ColumnDef::new(WeatherReading::Rain1H).double())
+            .col(ColumnDef::new(WeatherReading::Rain3H).double())
+            .col(ColumnDef::new(WeatherReading::Snow1H).double())
+            .col(ColumnDef::new(WeatherReading::Snow3H).double())
+            .col(ColumnDef::new(WeatherReading::Clouds).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::Dt).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::Sunrise).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::Sunset).integer().not_null())
+            .col(ColumnDef::new(WeatherReading::DewPoint).double())
+            .col(ColumnDef::new(WeatherReading::HeatIndex).double())
+            .col(ColumnDef::new(WeatherReading::WindChill).double())
+            .col(ColumnDef::new(WeatherReading::Humidex).double())
+            .col(ColumnDef::new(WeatherReading::AbsoluteHumidity).double())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(DailyDegreeDays::Table)
+            .if_not_exists()
+            .col(&mut id(DailyDegreeDays::Id))
+            .col(ColumnDef::new(DailyDegreeDays::Day).date().not_null().unique_key())
+            .col(ColumnDef::new(DailyDegreeDays::BaseTemp).double().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::MeanTemp).double().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::Hdd).double().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::Cdd).double().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::HoursObserved).integer().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::HoursInterpolated).integer().not_null())
+            .col(ColumnDef::new(DailyDegreeDays::ComputedAt).date_time().not_null())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(ForecastReading::Table)
+            .if_not_exists()
+            .col(&mut id(ForecastReading::Id))
+            .col(ColumnDef::new(ForecastReading::FetchedAt).date_time().not_null())
+            .col(ColumnDef::new(ForecastReading::ForecastTime).date_time().not_null())
+            .col(ColumnDef::new(ForecastReading::Condition).text().not_null())
+            .col(ColumnDef::new(ForecastReading::Description).text().not_null())
+            .col(ColumnDef::new(ForecastReading::Icon).text().not_null())
+            .col(ColumnDef::new(ForecastReading::TempReal).double().not_null())
+            .col(ColumnDef::new(ForecastReading::TempFeel).double().not_null())
+            .col(ColumnDef::new(ForecastReading::TempMin).double().not_null())
+            .col(ColumnDef::new(ForecastReading::TempMax).double().not_null())
+            .col(ColumnDef::new(ForecastReading::PressureSea).integer().not_null())
+            .col(ColumnDef::new(ForecastReading::Humidity).integer().not_null())
+            .col(ColumnDef::new(ForecastReading::PressureGround).integer())
+            .col(ColumnDef::new(ForecastReading::Visibility).integer())
+            .col(ColumnDef::new(ForecastReading::WindSpeed).double().not_null())
+            .col(ColumnDef::new(ForecastReading::WindDeg).integer().not_null())
+            .col(ColumnDef::new(ForecastReading::WindGust).double())
+            .col(ColumnDef::new(ForecastReading::Clouds).integer().not_null())
+            .col(ColumnDef::new(ForecastReading::Pop).double().not_null())
+            .col(ColumnDef::new(ForecastReading::Rain3H).double())
+            .col(ColumnDef::new(ForecastReading::Snow3H).double())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(EnvCapability::Table)
+            .if_not_exists()
+            .col(&mut id(EnvCapability::Id))
+            .col(ColumnDef::new(EnvCapability::Heating).boolean().not_null().default(false))
+            .col(ColumnDef::new(EnvCapability::Cooling).boolean().not_null().default(false))
+            .col(ColumnDef::new(EnvCapability::LastChanged).date_time())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(HvacActivity::Table)
+            .if_not_exists()
+            .col(&mut id(HvacActivity::Id))
+            .col(ColumnDef::new(HvacActivity::Heating).boolean().not_null().default(false))
+            .col(ColumnDef::new(HvacActivity::HeatLastChange).date_time())
+            .col(ColumnDef::new(HvacActivity::Cooling).boolean().not_null().default(false))
+            .col(ColumnDef::new(HvacActivity::CoolLastChange).date_time())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(HomeSummary::Table)
+            .if_not_exists()
+            .col(&mut id(HomeSummary::Id))
+            .col(ColumnDef::new(HomeSummary::LastChanged).date_time().not_null())
+            .col(ColumnDef::new(HomeSummary::HouseTemp).double())
+            .col(ColumnDef::new(HomeSummary::HouseHumidity).integer())
+            .col(ColumnDef::new(HomeSummary::Capability).integer().not_null())
+            .col(ColumnDef::new(HomeSummary::SystemActive).integer().not_null())
+            .foreign_key(ForeignKey::create()
+                .from(HomeSummary::Table, HomeSummary::Capability)
+                .to(EnvCapability::Table, EnvCapability::Id))
+            .foreign_key(ForeignKey::create()
+                .from(HomeSummary::Table, HomeSummary::SystemActive)
+                .to(HvacActivity::Table, HvacActivity::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Zones::Table)
+            .if_not_exists()
+            .col(&mut id(Zones::Id))
+            .col(ColumnDef::new(Zones::Name).text().not_null())
+            .col(ColumnDef::new(Zones::Active).boolean().not_null())
+            .col(ColumnDef::new(Zones::Capability).integer().not_null())
+            .col(ColumnDef::new(Zones::TimeAdded).date_time().not_null())
+            .col(ColumnDef::new(Zones::LastChanged).date_time())
+            .col(ColumnDef::new(Zones::CurrentTemp).double())
+            .col(ColumnDef::new(Zones::CurrentHumid).integer())
+            .col(ColumnDef::new(Zones::SystemActive).integer().not_null())
+            .col(ColumnDef::new(Zones::Presence).boolean())
+            .col(ColumnDef::new(Zones::ThresholdsClosed).boolean())
+            .foreign_key(ForeignKey::create()
+                .from(Zones::Table, Zones::Capability)
+                .to(EnvCapability::Table, EnvCapability::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Zones::Table, Zones::SystemActive)
+                .to(HvacActivity::Table, HvacActivity::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Weekdays::Table)
+            .if_not_exists()
+            .col(&mut id(Weekdays::Id))
+            .col(ColumnDef::new(Weekdays::Sunday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Monday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Tuesday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Wednesday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Thursday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Friday).boolean().not_null())
+            .col(ColumnDef::new(Weekdays::Saturday).boolean().not_null())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Schedules::Table)
+            .if_not_exists()
+            .col(&mut id(Schedules::Id))
+            .col(ColumnDef::new(Schedules::Active).boolean().not_null())
+            .col(ColumnDef::new(Schedules::Name).text().not_null())
+            .col(ColumnDef::new(Schedules::AssociatedZone).integer())
+            .col(ColumnDef::new(Schedules::LastChanged).date_time())
+            .col(ColumnDef::new(Schedules::TimeStart).time())
+            .col(ColumnDef::new(Schedules::TimeEnd).time())
+            .col(ColumnDef::new(Schedules::WeekDay).integer())
+            .col(ColumnDef::new(Schedules::DateStart).date())
+            .col(ColumnDef::new(Schedules::DateEnd).date())
+            .col(ColumnDef::new(Schedules::TempMin).double())
+            .col(ColumnDef::new(Schedules::TempMax).double())
+            .foreign_key(ForeignKey::create()
+                .from(Schedules::Table, Schedules::AssociatedZone)
+                .to(Zones::Table, Zones::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Schedules::Table, Schedules::WeekDay)
+                .to(Weekdays::Table, Weekdays::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Communication::Table)
+            .if_not_exists()
+            .col(&mut id(Communication::Id))
+            .col(ColumnDef::new(Communication::Name).text().not_null())
+            .col(ColumnDef::new(Communication::Active).boolean().not_null())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Sensors::Table)
+            .if_not_exists()
+            .col(&mut id(Sensors::Id))
+            .col(ColumnDef::new(Sensors::Active).boolean().not_null())
+            .col(ColumnDef::new(Sensors::Name).text().not_null())
+            .col(ColumnDef::new(Sensors::Token).text().not_null().unique_key())
+            .col(ColumnDef::new(Sensors::AssociatedZone).integer())
+            .col(ColumnDef::new(Sensors::TimeAdded).date_time().not_null())
+            .col(ColumnDef::new(Sensors::TimeUpdated).date_time())
+            .col(ColumnDef::new(Sensors::ComType).integer().not_null())
+            .col(ColumnDef::new(Sensors::ComLast).date_time())
+            .col(ColumnDef::new(Sensors::CurrentTemp).double())
+            .col(ColumnDef::new(Sensors::CurrentHumid).integer())
+            .col(ColumnDef::new(Sensors::Presence).boolean())
+            .col(ColumnDef::new(Sensors::ThresholdOpen).boolean())
+            .foreign_key(ForeignKey::create()
+                .from(Sensors::Table, Sensors::AssociatedZone)
+                .to(Zones::Table, Zones::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Sensors::Table, Sensors::ComType)
+                .to(Communication::Table, Communication::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Controllers::Table)
+            .if_not_exists()
+            .col(&mut id(Controllers::Id))
+            .col(ColumnDef::new(Controllers::Name).text().not_null().unique_key())
+            .col(ColumnDef::new(Controllers::Active).boolean().not_null())
+            .col(ColumnDef::new(Controllers::ComType).integer().not_null())
+            .col(ColumnDef::new(Controllers::Primary).boolean().not_null().default(true))
+            .col(ColumnDef::new(Controllers::AssociatedZone).integer())
+            .col(ColumnDef::new(Controllers::Token).text().not_null().unique_key())
+            .col(ColumnDef::new(Controllers::TimeAdded).date_time().not_null())
+            .col(ColumnDef::new(Controllers::TimeChanged).date_time())
+            .col(ColumnDef::new(Controllers::TimeConnectLast).date_time())
+            .col(ColumnDef::new(Controllers::Capability).integer().not_null())
+            .col(ColumnDef::new(Controllers::SystemActive).integer().not_null())
+            .foreign_key(ForeignKey::create()
+                .from(Controllers::Table, Controllers::ComType)
+                .to(Communication::Table, Communication::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Controllers::Table, Controllers::AssociatedZone)
+                .to(Zones::Table, Zones::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Controllers::Table, Controllers::Capability)
+                .to(EnvCapability::Table, EnvCapability::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Controllers::Table, Controllers::SystemActive)
+                .to(HvacActivity::Table, HvacActivity::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(Alerts::Table)
+            .if_not_exists()
+            .col(&mut id(Alerts::Id))
+            .col(ColumnDef::new(Alerts::Name).text().not_null().unique_key())
+            .col(ColumnDef::new(Alerts::Active).boolean().not_null())
+            .col(ColumnDef::new(Alerts::Tripped).boolean().not_null())
+            .col(ColumnDef::new(Alerts::ComType).integer())
+            .col(ColumnDef::new(Alerts::AssociatedSchedule).integer())
+            .col(ColumnDef::new(Alerts::AssociatedZone).integer())
+            .col(ColumnDef::new(Alerts::Actions).text())
+            .foreign_key(ForeignKey::create()
+                .from(Alerts::Table, Alerts::ComType)
+                .to(Communication::Table, Communication::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Alerts::Table, Alerts::AssociatedSchedule)
+                .to(Schedules::Table, Schedules::Id))
+            .foreign_key(ForeignKey::create()
+                .from(Alerts::Table, Alerts::AssociatedZone)
+                .to(Zones::Table, Zones::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(ChangeSource::Table)
+            .if_not_exists()
+            .col(&mut id(ChangeSource::Id))
+            .col(ColumnDef::new(ChangeSource::Name).text().not_null().unique_key())
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(ManualChangeHistory::Table)
+            .if_not_exists()
+            .col(&mut id(ManualChangeHistory::Id))
+            .col(ColumnDef::new(ManualChangeHistory::ChangeTiming).date_time().not_null())
+            .col(ColumnDef::new(ManualChangeHistory::ChangeWeather).integer().not_null())
+            .col(ColumnDef::new(ManualChangeHistory::ChangePollution).integer().not_null())
+            .col(ColumnDef::new(ManualChangeHistory::ChangeSource).integer().not_null())
+            .col(ColumnDef::new(ManualChangeHistory::NewTemp).double())
+            .col(ColumnDef::new(ManualChangeHistory::NewHumidity).integer())
+            .col(ColumnDef::new(ManualChangeHistory::ChangeSchedule).integer())
+            .col(ColumnDef::new(ManualChangeHistory::CancelledTiming).date_time())
+            .foreign_key(ForeignKey::create()
+                .from(ManualChangeHistory::Table, ManualChangeHistory::ChangeWeather)
+                .to(WeatherReading::Table, WeatherReading::Id))
+            .foreign_key(ForeignKey::create()
+                .from(ManualChangeHistory::Table, ManualChangeHistory::ChangePollution)
+                .to(PollutionReading::Table, PollutionReading::Id))
+            .foreign_key(ForeignKey::create()
+                .from(ManualChangeHistory::Table, ManualChangeHistory::ChangeSource)
+                .to(ChangeSource::Table, ChangeSource::Id))
+            .foreign_key(ForeignKey::create()
+                .from(ManualChangeHistory::Table, ManualChangeHistory::ChangeSchedule)
+                .to(Schedules::Table, Schedules::Id))
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(SensorReadingHistory::Table)
+            .if_not_exists()
+            .col(&mut id(SensorReadingHistory::Id))
+            .col(ColumnDef::new(SensorReadingHistory::SensorId).integer().not_null())
+            .col(ColumnDef::new(SensorReadingHistory::Timestamp).date_time().not_null())
+            .col(ColumnDef::new(SensorReadingHistory::ReadingTemp).double())
+            .col(ColumnDef::new(SensorReadingHistory::ReadingHumidity).integer())
+            .col(ColumnDef::new(SensorReadingHistory::ReadingPresence).boolean())
+            .col(ColumnDef::new(SensorReadingHistory::ReadingThresholdOpen).boolean())
+            .foreign_key(ForeignKey::create()
+                .from(SensorReadingHistory::Table, SensorReadingHistory::SensorId)
+                .to(Sensors::Table, Sensors::Id))
+            .to_owned()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        // Dropped children first so no foreign key is left pointing at a missing table
+        let tables: [DynIden; 17] = [
+            SensorReadingHistory::Table.into_iden(),
+            ManualChangeHistory::Table.into_iden(),
+            ChangeSource::Table.into_iden(),
+            Alerts::Table.into_iden(),
+            Controllers::Table.into_iden(),
+            Sensors::Table.into_iden(),
+            Communication::Table.into_iden(),
+            Schedules::Table.into_iden(),
+            Weekdays::Table.into_iden(),
+            Zones::Table.into_iden(),
+            HomeSummary::Table.into_iden(),
+            HvacActivity::Table.into_iden(),
+            EnvCapability::Table.into_iden(),
+            ForecastReading::Table.into_iden(),
+            DailyDegreeDays::Table.into_iden(),
+            WeatherReading::Table.into_iden(),
+            PollutionReading::Table.into_iden(),
+        ];
+        for table in tables {
+            manager.drop_table(Table::drop().table(table).if_exists().to_owned()).await?;
+        }
+        Ok(())
+    }
+}
+
+// Every table keys on an auto incrementing integer
+fn id<T: IntoIden>(column: T) -> ColumnDef {
+    ColumnDef::new(column).integer().not_null().auto_increment().primary_key().to_owned()
+}
+
+#[derive(DeriveIden)]
+enum PollutionReading {
+    #[sea_orm(iden = "PollutionReading")]
+    Table,
+    Id,
+    Timestamp,
+    #[sea_orm(iden = "AQI")]
+    Aqi,
+    #[sea_orm(iden = "CO")]
+    Co,
+    #[sea_orm(iden = "NO")]
+    No,
+    #[sea_orm(iden = "NO2")]
+    No2,
+    #[sea_orm(iden = "O3")]
+    O3,
+    #[sea_orm(iden = "SO2")]
+    So2,
+    #[sea_orm(iden = "PM2_5")]
+    Pm25,
+    #[sea_orm(iden = "PM10")]
+    Pm10,
+    #[sea_orm(iden = "NH3")]
+    Nh3,
+}
+
+#[derive(DeriveIden)]
+enum WeatherReading {
+    #[sea_orm(iden = "WeatherReading")]
+    Table,
+    Id,
+    Timestamp,
+    Condition,
+    Description,
+    Icon,
+    #[sea_orm(iden = "tempReal")]
+    TempReal,
+    #[sea_orm(iden = "tempFeel")]
+    TempFeel,
+    #[sea_orm(iden = "pressureSea")]
+    PressureSea,
+    Humidity,
+    #[sea_orm(iden = "pressureGround")]
+    PressureGround,
+    Visibility,
+    #[sea_orm(iden = "windSpeed")]
+    WindSpeed,
+    #[sea_orm(iden = "windDeg")]
+    WindDeg,
+    #[sea_orm(iden = "windGust")]
+    WindGust,
+    #[sea_orm(iden = "rain1H")]
+    Rain1H,
+    #[sea_orm(iden = "rain3H")]
+    Rain3H,
+    #[sea_orm(iden = "snow1H")]
+    Snow1H,
+    #[sea_orm(iden = "snow3H")]
+    Snow3H,
+    Clouds,
+    Dt,
+    Sunrise,
+    Sunset,
+    #[sea_orm(iden = "dewPoint")]
+    DewPoint,
+    #[sea_orm(iden = "heatIndex")]
+    HeatIndex,
+    #[sea_orm(iden = "windChill")]
+    WindChill,
+    Humidex,
+    #[sea_orm(iden = "absoluteHumidity")]
+    AbsoluteHumidity,
+}
+
+#[derive(DeriveIden)]
+enum DailyDegreeDays {
+    #[sea_orm(iden = "DailyDegreeDays")]
+    Table,
+    Id,
+    Day,
+    #[sea_orm(iden = "baseTemp")]
+    BaseTemp,
+    #[sea_orm(iden = "meanTemp")]
+    MeanTemp,
+    Hdd,
+    Cdd,
+    #[sea_orm(iden = "hoursObserved")]
+    HoursObserved,
+    #[sea_orm(iden = "hoursInterpolated")]
+    HoursInterpolated,
+    #[sea_orm(iden = "computedAt")]
+    ComputedAt,
+}
+
+#[derive(DeriveIden)]
+enum ForecastReading {
+    #[sea_orm(iden = "ForecastReading")]
+    Table,
+    Id,
+    #[sea_orm(iden = "fetchedAt")]
+    FetchedAt,
+    #[sea_orm(iden = "forecastTime")]
+    ForecastTime,
+    Condition,
+    Description,
+    Icon,
+    #[sea_orm(iden = "tempReal")]
+    TempReal,
+    #[sea_orm(iden = "tempFeel")]
+    TempFeel,
+    #[sea_orm(iden = "tempMin")]
+    TempMin,
+    #[sea_orm(iden = "tempMax")]
+    TempMax,
+    #[sea_orm(iden = "pressureSea")]
+    PressureSea,
+    Humidity,
+    #[sea_orm(iden = "pressureGround")]
+    PressureGround,
+    Visibility,
+    #[sea_orm(iden = "windSpeed")]
+    WindSpeed,
+    #[sea_orm(iden = "windDeg")]
+    WindDeg,
+    #[sea_orm(iden = "windGust")]
+    WindGust,
+    Clouds,
+    Pop,
+    #[sea_orm(iden = "rain3H")]
+    Rain3H,
+    #[sea_orm(iden = "snow3H")]
+    Snow3H,
+}
+
+#[derive(DeriveIden)]
+enum EnvCapability {
+    #[sea_orm(iden = "EnvCapability")]
+    Table,
+    Id,
+    Heating,
+    Cooling,
+    #[sea_orm(iden = "lastChanged")]
+    LastChanged,
+}
+
+#[derive(DeriveIden)]
+enum HvacActivity {
+    #[sea_orm(iden = "HVACactivity")]
+    Table,
+    Id,
+    Heating,
+    #[sea_orm(iden = "heatLastChange")]
+    HeatLastChange,
+    Cooling,
+    #[sea_orm(iden = "coolLastChange")]
+    CoolLastChange,
+}
+
+#[derive(DeriveIden)]
+enum HomeSummary {
+    #[sea_orm(iden = "HomeSummary")]
+    Table,
+    Id,
+    #[sea_orm(iden = "lastChanged")]
+    LastChanged,
+    #[sea_orm(iden = "houseTemp")]
+    HouseTemp,
+    #[sea_orm(iden = "houseHumidity")]
+    HouseHumidity,
+    Capability,
+    #[sea_orm(iden = "systemActive")]
+    SystemActive,
+}
+
+#[derive(DeriveIden)]
+enum Zones {
+    #[sea_orm(iden = "Zones")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Name")]
+    Name,
+    Active,
+    Capability,
+    #[sea_orm(iden = "timeAdded")]
+    TimeAdded,
+    #[sea_orm(iden = "lastChanged")]
+    LastChanged,
+    #[sea_orm(iden = "currentTemp")]
+    CurrentTemp,
+    #[sea_orm(iden = "currentHumid")]
+    CurrentHumid,
+    #[sea_orm(iden = "systemActive")]
+    SystemActive,
+    Presence,
+    #[sea_orm(iden = "thresholdsClosed")]
+    ThresholdsClosed,
+}
+
+#[derive(DeriveIden)]
+enum Weekdays {
+    #[sea_orm(iden = "Weekdays")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Sunday")]
+    Sunday,
+    #[sea_orm(iden = "Monday")]
+    Monday,
+    #[sea_orm(iden = "Tuesday")]
+    Tuesday,
+    #[sea_orm(iden = "Wednesday")]
+    Wednesday,
+    #[sea_orm(iden = "Thursday")]
+    Thursday,
+    #[sea_orm(iden = "Friday")]
+    Friday,
+    #[sea_orm(iden = "Saturday")]
+    Saturday,
+}
+
+#[derive(DeriveIden)]
+enum Schedules {
+    #[sea_orm(iden = "Schedules")]
+    Table,
+    Id,
+    Active,
+    Name,
+    #[sea_orm(iden = "associatedZone")]
+    AssociatedZone,
+    #[sea_orm(iden = "lastChanged")]
+    LastChanged,
+    #[sea_orm(iden = "timeStart")]
+    TimeStart,
+    #[sea_orm(iden = "timeEnd")]
+    TimeEnd,
+    #[sea_orm(iden = "weekDay")]
+    WeekDay,
+    #[sea_orm(iden = "dateStart")]
+    DateStart,
+    #[sea_orm(iden = "dateEnd")]
+    DateEnd,
+    #[sea_orm(iden = "tempMin")]
+    TempMin,
+    #[sea_orm(iden = "tempMax")]
+    TempMax,
+}
+
+#[derive(DeriveIden)]
+enum Communication {
+    #[sea_orm(iden = "Communication")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Name")]
+    Name,
+    Active,
+}
+
+#[derive(DeriveIden)]
+enum Sensors {
+    #[sea_orm(iden = "Sensors")]
+    Table,
+    Id,
+    Active,
+    #[sea_orm(iden = "Name")]
+    Name,
+    #[sea_orm(iden = "Token")]
+    Token,
+    #[sea_orm(iden = "associatedZone")]
+    AssociatedZone,
+    #[sea_orm(iden = "timeAdded")]
+    TimeAdded,
+    #[sea_orm(iden = "timeUpdated")]
+    TimeUpdated,
+    #[sea_orm(iden = "comType")]
+    ComType,
+    #[sea_orm(iden = "comLast")]
+    ComLast,
+    #[sea_orm(iden = "currentTemp")]
+    CurrentTemp,
+    #[sea_orm(iden = "currentHumid")]
+    CurrentHumid,
+    Presence,
+    #[sea_orm(iden = "thresholdOpen")]
+    ThresholdOpen,
+}
+
+#[derive(DeriveIden)]
+enum Controllers {
+    #[sea_orm(iden = "Controllers")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Name")]
+    Name,
+    Active,
+    #[sea_orm(iden = "comType")]
+    ComType,
+    #[sea_orm(iden = "Primary")]
+    Primary,
+    #[sea_orm(iden = "associatedZone")]
+    AssociatedZone,
+    #[sea_orm(iden = "Token")]
+    Token,
+    #[sea_orm(iden = "timeAdded")]
+    TimeAdded,
+    #[sea_orm(iden = "timeChanged")]
+    TimeChanged,
+    #[sea_orm(iden = "timeConnectLast")]
+    TimeConnectLast,
+    Capability,
+    #[sea_orm(iden = "systemActive")]
+    SystemActive,
+}
+
+#[derive(DeriveIden)]
+enum Alerts {
+    #[sea_orm(iden = "Alerts")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Name")]
+    Name,
+    Active,
+    Tripped,
+    #[sea_orm(iden = "comType")]
+    ComType,
+    #[sea_orm(iden = "associatedSchedule")]
+    AssociatedSchedule,
+    #[sea_orm(iden = "associatedZone")]
+    AssociatedZone,
+    #[sea_orm(iden = "Actions")]
+    Actions,
+}
+
+#[derive(DeriveIden)]
+enum ChangeSource {
+    #[sea_orm(iden = "ChangeSource")]
+    Table,
+    Id,
+    Name,
+}
+
+#[derive(DeriveIden)]
+enum ManualChangeHistory {
+    #[sea_orm(iden = "ManualChangeHistory")]
+    Table,
+    Id,
+    #[sea_orm(iden = "changeTiming")]
+    ChangeTiming,
+    #[sea_orm(iden = "changeWeather")]
+    ChangeWeather,
+    #[sea_orm(iden = "changePollution")]
+    ChangePollution,
+    #[sea_orm(iden = "changeSource")]
+    ChangeSource,
+    #[sea_orm(iden = "newTemp")]
+    NewTemp,
+    #[sea_orm(iden = "newHumidity")]
+    NewHumidity,
+    #[sea_orm(iden = "changeSchedule")]
+    ChangeSchedule,
+    #[sea_orm(iden = "cancelledTiming")]
+    CancelledTiming,
+}
+
+#[derive(DeriveIden)]
+enum SensorReadingHistory {
+    #[sea_orm(iden = "SensorReadingHistory")]
+    Table,
+    Id,
+    #[sea_orm(iden = "sensorID")]
+    SensorId,
+    Timestamp,
+    #[sea_orm(iden = "readingTemp")]
+    ReadingTemp,
+    #[sea_orm(iden = "readingHumidity")]
+    ReadingHumidity,
+    #[sea_orm(iden = "readingPresence")]
+    ReadingPresence,
+    #[sea_orm(iden = "readingThresholdOpen")]
+    ReadingThresholdOpen,
+}
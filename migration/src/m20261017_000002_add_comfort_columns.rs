@@ -0,0 +1,56 @@
+//! Adds the comfort metric columns to weather readings stored before they were calculated<br>
+//! Fresh databases already get them from the first migration, so only missing columns are added
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for column in WeatherReading::comfort_columns() {
+            if manager.has_column("WeatherReading", column.to_string()).await? {
+                continue;
+            }
+            // SQLite only takes one change per ALTER TABLE
+            manager.alter_table(Table::alter()
+                .table(WeatherReading::Table)
+                .add_column(ColumnDef::new(column).double())
+                .to_owned()).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        // The columns belong to the first migration on fresh databases, so they are left for it to drop
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum WeatherReading {
+    #[sea_orm(iden = "WeatherReading")]
+    Table,
+    #[sea_orm(iden = "dewPoint")]
+    DewPoint,
+    #[sea_orm(iden = "heatIndex")]
+    HeatIndex,
+    #[sea_orm(iden = "windChill")]
+    WindChill,
+    Humidex,
+    #[sea_orm(iden = "absoluteHumidity")]
+    AbsoluteHumidity,
+}
+
+impl WeatherReading {
+    fn comfort_columns() -> [WeatherReading; 5] {
+        [
+            WeatherReading::DewPoint,
+            WeatherReading::HeatIndex,
+            WeatherReading::WindChill,
+            WeatherReading::Humidex,
+            WeatherReading::AbsoluteHumidity,
+        ]
+    }
+}
@@ -0,0 +1,172 @@
+//! Adds the hourly and daily rollup tables the retention job fills before raw readings are purged<br>
+//! Also indexes the raw reading timestamps, which every rollup and purge query filters on
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(Table::create()
+            .table(SensorReadingRollup::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(SensorReadingRollup::Id).integer().not_null().auto_increment().primary_key())
+            .col(ColumnDef::new(SensorReadingRollup::SensorId).integer().not_null())
+            .col(ColumnDef::new(SensorReadingRollup::Period).text().not_null())
+            .col(ColumnDef::new(SensorReadingRollup::PeriodStart).date_time().not_null())
+            .col(ColumnDef::new(SensorReadingRollup::ReadingCount).integer().not_null())
+            .col(ColumnDef::new(SensorReadingRollup::TempMin).double())
+            .col(ColumnDef::new(SensorReadingRollup::TempMax).double())
+            .col(ColumnDef::new(SensorReadingRollup::TempAvg).double())
+            .col(ColumnDef::new(SensorReadingRollup::HumidityMin).double())
+            .col(ColumnDef::new(SensorReadingRollup::HumidityMax).double())
+            .col(ColumnDef::new(SensorReadingRollup::HumidityAvg).double())
+            .foreign_key(ForeignKey::create()
+                .from(SensorReadingRollup::Table, SensorReadingRollup::SensorId)
+                .to(Sensors::Table, Sensors::Id))
+            .to_owned()).await?;
+        manager.create_index(Index::create()
+            .name("SensorReadingRollup_period_unique")
+            .table(SensorReadingRollup::Table)
+            .col(SensorReadingRollup::SensorId)
+            .col(SensorReadingRollup::Period)
+            .col(SensorReadingRollup::PeriodStart)
+            .unique()
+            .if_not_exists()
+            .to_owned()).await?;
+
+        manager.create_table(Table::create()
+            .table(WeatherReadingRollup::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(WeatherReadingRollup::Id).integer().not_null().auto_increment().primary_key())
+            .col(ColumnDef::new(WeatherReadingRollup::Period).text().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::PeriodStart).date_time().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::ReadingCount).integer().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::TempMin).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::TempMax).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::TempAvg).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::HumidityMin).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::HumidityMax).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::HumidityAvg).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::PressureMin).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::PressureMax).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::PressureAvg).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::WindSpeedMin).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::WindSpeedMax).double().not_null())
+            .col(ColumnDef::new(WeatherReadingRollup::WindSpeedAvg).double().not_null())
+            .to_owned()).await?;
+        manager.create_index(Index::create()
+            .name("WeatherReadingRollup_period_unique")
+            .table(WeatherReadingRollup::Table)
+            .col(WeatherReadingRollup::Period)
+            .col(WeatherReadingRollup::PeriodStart)
+            .unique()
+            .if_not_exists()
+            .to_owned()).await?;
+
+        manager.create_index(Index::create()
+            .name("SensorReadingHistory_timestamp")
+            .table(SensorReadingHistory::Table)
+            .col(SensorReadingHistory::Timestamp)
+            .if_not_exists()
+            .to_owned()).await?;
+        manager.create_index(Index::create()
+            .name("WeatherReading_timestamp")
+            .table(WeatherReading::Table)
+            .col(WeatherReading::Timestamp)
+            .if_not_exists()
+            .to_owned()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_index(Index::drop().name("WeatherReading_timestamp").table(WeatherReading::Table).to_owned()).await?;
+        manager.drop_index(Index::drop().name("SensorReadingHistory_timestamp").table(SensorReadingHistory::Table).to_owned()).await?;
+        manager.drop_table(Table::drop().table(WeatherReadingRollup::Table).if_exists().to_owned()).await?;
+        manager.drop_table(Table::drop().table(SensorReadingRollup::Table).if_exists().to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum SensorReadingRollup {
+    #[sea_orm(iden = "SensorReadingRollup")]
+    Table,
+    Id,
+    #[sea_orm(iden = "sensorID")]
+    SensorId,
+    Period,
+    #[sea_orm(iden = "periodStart")]
+    PeriodStart,
+    #[sea_orm(iden = "readingCount")]
+    ReadingCount,
+    #[sea_orm(iden = "tempMin")]
+    TempMin,
+    #[sea_orm(iden = "tempMax")]
+    TempMax,
+    #[sea_orm(iden = "tempAvg")]
+    TempAvg,
+    #[sea_orm(iden = "humidityMin")]
+    HumidityMin,
+    #[sea_orm(iden = "humidityMax")]
+    HumidityMax,
+    #[sea_orm(iden = "humidityAvg")]
+    HumidityAvg,
+}
+
+#[derive(DeriveIden)]
+enum WeatherReadingRollup {
+    #[sea_orm(iden = "WeatherReadingRollup")]
+    Table,
+    Id,
+    Period,
+    #[sea_orm(iden = "periodStart")]
+    PeriodStart,
+    #[sea_orm(iden = "readingCount")]
+    ReadingCount,
+    #[sea_orm(iden = "tempMin")]
+    TempMin,
+    #[sea_orm(iden = "tempMax")]
+    TempMax,
+    #[sea_orm(iden = "tempAvg")]
+    TempAvg,
+    #[sea_orm(iden = "humidityMin")]
+    HumidityMin,
+    #[sea_orm(iden = "humidityMax")]
+    HumidityMax,
+    #[sea_orm(iden = "humidityAvg")]
+    HumidityAvg,
+    #[sea_orm(iden = "pressureMin")]
+    PressureMin,
+    #[sea_orm(iden = "pressureMax")]
+    PressureMax,
+    #[sea_orm(iden = "pressureAvg")]
+    PressureAvg,
+    #[sea_orm(iden = "windSpeedMin")]
+    WindSpeedMin,
+    #[sea_orm(iden = "windSpeedMax")]
+    WindSpeedMax,
+    #[sea_orm(iden = "windSpeedAvg")]
+    WindSpeedAvg,
+}
+
+#[derive(DeriveIden)]
+enum Sensors {
+    #[sea_orm(iden = "Sensors")]
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum SensorReadingHistory {
+    #[sea_orm(iden = "SensorReadingHistory")]
+    Table,
+    Timestamp,
+}
+
+#[derive(DeriveIden)]
+enum WeatherReading {
+    #[sea_orm(iden = "WeatherReading")]
+    Table,
+    Timestamp,
+}
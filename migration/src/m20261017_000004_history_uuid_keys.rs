@@ -0,0 +1,489 @@
+//! Keys the history tables, PollutionReading, WeatherReading, SensorReadingHistory and ManualChangeHistory, on UUIDv7
+//! instead of auto incrementing integers, so history from several houses and rows buffered while offline can be merged
+//! without their ids colliding<br>
+//! SQLite cannot change a column's type or a foreign key in place, so each table is built again beside the old one and
+//! the rows copied across through a table of old and new keys. Existing rows get a UUID made from their own time, so
+//! they sort the way they were taken, and ManualChangeHistory follows its weather and pollution to their new keys.
+//! Going down numbers the rows again in the same order
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::{ConnectionTrait, DbBackend, TryGetable};
+use sea_orm_migration::sea_orm::prelude::{DateTime, Uuid};
+use uuid::{ContextV7, Timestamp};
+
+// Rows read from a history table at a time while its keys are handed out
+const PAGE: u64 = 1000;
+// Rows per insert into a key table, well under the bind parameter limits of both backends
+const INSERT_CHUNK: usize = 250;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut rows: Vec<u64> = Vec::new();
+        for history in History::ALL {
+            let context: ContextV7 = ContextV7::new();
+            rows.push(map_keys::<i32, _>(manager, history, Key::Integer, Key::Uuid, move |time| uuid_at(&context, time).into()).await?);
+        }
+        rebuild(manager, Key::Uuid, &rows).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut rows: Vec<u64> = Vec::new();
+        for history in History::ALL {
+            let mut next: i32 = 0;
+            rows.push(map_keys::<Uuid, _>(manager, history, Key::Uuid, Key::Integer, move |_| {
+                next += 1;
+                next.into()
+            }).await?);
+        }
+        rebuild(manager, Key::Integer, &rows).await?;
+        if manager.get_database_backend() == DbBackend::Postgres {
+            // The rows were written with their ids, so the new sequences have to be moved past them
+            for history in History::ALL {
+                manager.get_connection().execute_unprepared(&format!(
+                    "SELECT setval(pg_get_serial_sequence('\"{table}\"', 'id'), COALESCE(MAX(\"id\"), 0) + 1, false) FROM \"{table}\"",
+                    table = history.name()
+                )).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// A UUIDv7 for a row taken at the given time. The context keeps rows from the same millisecond in the order given
+fn uuid_at(context: &ContextV7, time: DateTime) -> Uuid {
+    let time = time.and_utc();
+    Uuid::new_v7(Timestamp::from_unix(context, time.timestamp().max(0) as u64, time.timestamp_subsec_nanos()))
+}
+
+// What the history tables are keyed on, before or after the change
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Integer,
+    Uuid,
+}
+
+impl Key {
+    fn column<T: IntoIden>(self, column: T) -> ColumnDef {
+        let mut column: ColumnDef = ColumnDef::new(column);
+        match self {
+            Key::Integer => column.integer(),
+            Key::Uuid => column.uuid(),
+        };
+        column.not_null().to_owned()
+    }
+
+    fn primary_key<T: IntoIden>(self, column: T) -> ColumnDef {
+        let mut column: ColumnDef = self.column(column);
+        if self == Key::Integer {
+            column.auto_increment();
+        }
+        column.primary_key().to_owned()
+    }
+}
+
+#[derive(Clone, Copy)]
+enum History {
+    Pollution,
+    Weather,
+    SensorReadings,
+    ManualChanges,
+}
+
+impl History {
+    // Tables that are pointed at come before the ones pointing at them
+    const ALL: [History; 4] = [History::Pollution, History::Weather, History::SensorReadings, History::ManualChanges];
+
+    fn name(self) -> &'static str {
+        match self {
+            History::Pollution => "PollutionReading",
+            History::Weather => "WeatherReading",
+            History::SensorReadings => "SensorReadingHistory",
+            History::ManualChanges => "ManualChangeHistory",
+        }
+    }
+
+    fn table(self) -> Alias {
+        Alias::new(self.name())
+    }
+
+    // The table the rows are copied into before it takes the old one's name
+    fn rebuilt(self) -> Alias {
+        Alias::new(format!("{}_rebuilt", self.name()))
+    }
+
+    // Each row's old key next to its new one
+    fn keys(self) -> Alias {
+        Alias::new(format!("{}_keys", self.name()))
+    }
+
+    // The time rows are ordered and their UUIDs made by
+    fn time(self) -> DynIden {
+        match self {
+            History::ManualChanges => ManualChangeHistory::ChangeTiming.into_iden(),
+            _ => Alias::new("timestamp").into_iden(),
+        }
+    }
+
+    // Every column but the keys, copied across as it is
+    fn copied(self) -> Vec<DynIden> {
+        match self {
+            History::Pollution => vec![
+                PollutionReading::Timestamp.into_iden(), PollutionReading::Aqi.into_iden(), PollutionReading::Co.into_iden(),
+                PollutionReading::No.into_iden(), PollutionReading::No2.into_iden(), PollutionReading::O3.into_iden(),
+                PollutionReading::So2.into_iden(), PollutionReading::Pm25.into_iden(), PollutionReading::Pm10.into_iden(),
+                PollutionReading::Nh3.into_iden(),
+            ],
+            History::Weather => vec![
+                WeatherReading::Timestamp.into_iden(), WeatherReading::Condition.into_iden(), WeatherReading::Description.into_iden(),
+                WeatherReading::Icon.into_iden(), WeatherReading::TempReal.into_iden(), WeatherReading::TempFeel.into_iden(),
+                WeatherReading::PressureSea.into_iden(), WeatherReading::Humidity.into_iden(), WeatherReading::PressureGround.into_iden(),
+                WeatherReading::Visibility.into_iden(), WeatherReading::WindSpeed.into_iden(), WeatherReading::WindDeg.into_iden(),
+                WeatherReading::WindGust.into_iden(), WeatherReading::Rain1H.into_iden(), WeatherReading::Rain3H.into_iden(),
+                WeatherReading::Snow1H.into_iden(), WeatherReading::Snow3H.into_iden(), WeatherReading::Clouds.into_iden(),
+                WeatherReading::Dt.into_iden(), WeatherReading::Sunrise.into_iden(), WeatherReading::Sunset.into_iden(),
+                WeatherReading::DewPoint.into_iden(), WeatherReading::HeatIndex.into_iden(), WeatherReading::WindChill.into_iden(),
+                WeatherReading::Humidex.into_iden(), WeatherReading::AbsoluteHumidity.into_iden(),
+            ],
+            History::SensorReadings => vec![
+                SensorReadingHistory::SensorId.into_iden(), SensorReadingHistory::Timestamp.into_iden(),
+                SensorReadingHistory::ReadingTemp.into_iden(), SensorReadingHistory::ReadingHumidity.into_iden(),
+                SensorReadingHistory::ReadingPresence.into_iden(), SensorReadingHistory::ReadingThresholdOpen.into_iden(),
+            ],
+            History::ManualChanges => vec![
+                ManualChangeHistory::ChangeTiming.into_iden(), ManualChangeHistory::ChangeSource.into_iden(),
+                ManualChangeHistory::NewTemp.into_iden(), ManualChangeHistory::NewHumidity.into_iden(),
+                ManualChangeHistory::ChangeSchedule.into_iden(), ManualChangeHistory::CancelledTiming.into_iden(),
+            ],
+        }
+    }
+
+    // The new table, keyed on `key`. ManualChangeHistory points at the new weather and pollution tables, which keep
+    // pointing at the same tables once they are renamed
+    fn create(self, key: Key) -> TableCreateStatement {
+        let mut table: TableCreateStatement = Table::create();
+        table.table(self.rebuilt()).col(&mut key.primary_key(Alias::new("id")));
+        match self {
+            History::Pollution => table
+                .col(ColumnDef::new(PollutionReading::Timestamp).date_time().not_null())
+                .col(ColumnDef::new(PollutionReading::Aqi).integer().not_null())
+                .col(ColumnDef::new(PollutionReading::Co).double().not_null())
+                .col(ColumnDef::new(PollutionReading::No).double().not_null())
+                .col(ColumnDef::new(PollutionReading::No2).double().not_null())
+                .col(ColumnDef::new(PollutionReading::O3).double().not_null())
+                .col(ColumnDef::new(PollutionReading::So2).double().not_null())
+                .col(ColumnDef::new(PollutionReading::Pm25).double().not_null())
+                .col(ColumnDef::new(PollutionReading::Pm10).double().not_null())
+                .col(ColumnDef::new(PollutionReading::Nh3).double().not_null()),
+            History::Weather => table
+                .col(ColumnDef::new(WeatherReading::Timestamp).date_time().not_null())
+                .col(ColumnDef::new(WeatherReading::Condition).text().not_null())
+                .col(ColumnDef::new(WeatherReading::Description).text().not_null())
+                .col(ColumnDef::new(WeatherReading::Icon).text().not_null())
+                .col(ColumnDef::new(WeatherReading::TempReal).double().not_null())
+                .col(ColumnDef::new(WeatherReading::TempFeel).double().not_null())
+                .col(ColumnDef::new(WeatherReading::PressureSea).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::Humidity).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::PressureGround).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::Visibility).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::WindSpeed).double().not_null())
+                .col(ColumnDef::new(WeatherReading::WindDeg).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::WindGust).double().not_null())
+                .col(ColumnDef::new(WeatherReading::Rain1H).double())
+                .col(ColumnDef::new(WeatherReading::Rain3H).double())
+                .col(ColumnDef::new(WeatherReading::Snow1H).double())
+                .col(ColumnDef::new(WeatherReading::Snow3H).double())
+                .col(ColumnDef::new(WeatherReading::Clouds).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::Dt).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::Sunrise).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::Sunset).integer().not_null())
+                .col(ColumnDef::new(WeatherReading::DewPoint).double())
+                .col(ColumnDef::new(WeatherReading::HeatIndex).double())
+                .col(ColumnDef::new(WeatherReading::WindChill).double())
+                .col(ColumnDef::new(WeatherReading::Humidex).double())
+                .col(ColumnDef::new(WeatherReading::AbsoluteHumidity).double()),
+            History::SensorReadings => table
+                .col(ColumnDef::new(SensorReadingHistory::SensorId).integer().not_null())
+                .col(ColumnDef::new(SensorReadingHistory::Timestamp).date_time().not_null())
+                .col(ColumnDef::new(SensorReadingHistory::ReadingTemp).double())
+                .col(ColumnDef::new(SensorReadingHistory::ReadingHumidity).integer())
+                .col(ColumnDef::new(SensorReadingHistory::ReadingPresence).boolean())
+                .col(ColumnDef::new(SensorReadingHistory::ReadingThresholdOpen).boolean())
+                .foreign_key(ForeignKey::create()
+                    .from(self.rebuilt(), SensorReadingHistory::SensorId)
+                    .to(Sensors::Table, Sensors::Id)),
+            History::ManualChanges => table
+                .col(ColumnDef::new(ManualChangeHistory::ChangeTiming).date_time().not_null())
+                .col(&mut key.column(ManualChangeHistory::ChangeWeather))
+                .col(&mut key.column(ManualChangeHistory::ChangePollution))
+                .col(ColumnDef::new(ManualChangeHistory::ChangeSource).integer().not_null())
+                .col(ColumnDef::new(ManualChangeHistory::NewTemp).double())
+                .col(ColumnDef::new(ManualChangeHistory::NewHumidity).integer())
+                .col(ColumnDef::new(ManualChangeHistory::ChangeSchedule).integer())
+                .col(ColumnDef::new(ManualChangeHistory::CancelledTiming).date_time())
+                .foreign_key(ForeignKey::create()
+                    .from(self.rebuilt(), ManualChangeHistory::ChangeWeather)
+                    .to(History::Weather.rebuilt(), Alias::new("id")))
+                .foreign_key(ForeignKey::create()
+                    .from(self.rebuilt(), ManualChangeHistory::ChangePollution)
+                    .to(History::Pollution.rebuilt(), Alias::new("id")))
+                .foreign_key(ForeignKey::create()
+                    .from(self.rebuilt(), ManualChangeHistory::ChangeSource)
+                    .to(ChangeSource::Table, ChangeSource::Id))
+                .foreign_key(ForeignKey::create()
+                    .from(self.rebuilt(), ManualChangeHistory::ChangeSchedule)
+                    .to(Schedules::Table, Schedules::Id)),
+        };
+        table.to_owned()
+    }
+}
+
+// Creates the key table of one history table and fills it with a new key for every row, handing `next_key` the
+// rows oldest first. Returns how many rows there are
+async fn map_keys<K, F>(manager: &SchemaManager<'_>, history: History, from: Key, to: Key, mut next_key: F) -> Result<u64, DbErr>
+where
+    K: TryGetable + Into<Value> + Clone,
+    F: FnMut(DateTime) -> Value + Send,
+{
+    manager.create_table(Table::create()
+        .table(history.keys())
+        .col(from.column(KeyMap::OldId).primary_key())
+        .col(&mut to.column(KeyMap::NewId))
+        .to_owned()).await?;
+
+    let id: Alias = Alias::new("id");
+    let mut rows: u64 = 0;
+    let mut after: Option<(DateTime, K)> = None;
+    loop {
+        let mut page: SelectStatement = Query::select();
+        page.column(id.clone()).column(history.time()).from(history.table())
+            .order_by(history.time(), Order::Asc).order_by(id.clone(), Order::Asc).limit(PAGE);
+        if let Some((time, key)) = after.clone() {
+            page.cond_where(Cond::any()
+                .add(Expr::col(history.time()).gt(time))
+                .add(Expr::col(history.time()).eq(time).and(Expr::col(id.clone()).gt(Into::<Value>::into(key)))));
+        }
+        let found = manager.get_connection().query_all(manager.get_database_backend().build(&page)).await?;
+        let mut pairs: Vec<(Value, Value)> = Vec::with_capacity(found.len());
+        for row in &found {
+            let key: K = row.try_get_by_index(0)?;
+            let time: DateTime = row.try_get_by_index(1)?;
+            pairs.push((key.clone().into(), next_key(time)));
+            after = Some((time, key));
+        }
+        rows += pairs.len() as u64;
+        for chunk in pairs.chunks(INSERT_CHUNK) {
+            let mut insert: InsertStatement = Query::insert();
+            insert.into_table(history.keys()).columns([KeyMap::OldId, KeyMap::NewId]);
+            for (old, new) in chunk {
+                insert.values_panic([old.clone().into(), new.clone().into()]);
+            }
+            manager.exec_stmt(insert).await?;
+        }
+        if (found.len() as u64) < PAGE {
+            return Ok(rows)
+        }
+    }
+}
+
+// Copies every history table into a new one keyed on `key` through the key tables, then swaps the new tables in
+async fn rebuild(manager: &SchemaManager<'_>, key: Key, rows: &[u64]) -> Result<(), DbErr> {
+    let id: Alias = Alias::new("id");
+    for (history, expected) in History::ALL.into_iter().zip(rows) {
+        manager.create_table(history.create(key)).await?;
+
+        let mut columns: Vec<DynIden> = vec![id.clone().into_iden()];
+        columns.extend(history.copied());
+        let mut select: SelectStatement = Query::select();
+        select.column((history.keys(), KeyMap::NewId))
+            .columns(history.copied().into_iter().map(|column| (history.table(), column)))
+            .from(history.table())
+            .inner_join(history.keys(), Expr::col((history.keys(), KeyMap::OldId)).equals((history.table(), id.clone())));
+        if let History::ManualChanges = history {
+            columns.extend([ManualChangeHistory::ChangeWeather.into_iden(), ManualChangeHistory::ChangePollution.into_iden()]);
+            let weather: Alias = Alias::new("weather_keys");
+            let pollution: Alias = Alias::new("pollution_keys");
+            select.column((weather.clone(), KeyMap::NewId)).column((pollution.clone(), KeyMap::NewId))
+                .join_as(JoinType::InnerJoin, History::Weather.keys(), weather.clone(),
+                    Expr::col((weather, KeyMap::OldId)).equals((history.table(), ManualChangeHistory::ChangeWeather)))
+                .join_as(JoinType::InnerJoin, History::Pollution.keys(), pollution.clone(),
+                    Expr::col((pollution, KeyMap::OldId)).equals((history.table(), ManualChangeHistory::ChangePollution)));
+        }
+        let mut insert: InsertStatement = Query::insert();
+        insert.into_table(history.rebuilt()).columns(columns)
+            .select_from(select).map_err(|error| DbErr::Migration(error.to_string()))?;
+        manager.exec_stmt(insert).await?;
+
+        // Only a manual change pointing at weather or pollution that is not there can be left behind
+        let copied: u64 = count(manager, history.rebuilt()).await?;
+        if copied != *expected {
+            return Err(DbErr::Migration(format!("Only {} of the {} {} rows could be copied, the rest point at weather or pollution readings that do not exist",
+                copied, expected, history.name())))
+        }
+    }
+
+    // Children first, so nothing is left pointing at a table that is gone
+    for history in History::ALL.into_iter().rev() {
+        manager.drop_table(Table::drop().table(history.table()).to_owned()).await?;
+    }
+    for history in History::ALL {
+        manager.rename_table(Table::rename().table(history.rebuilt(), history.table()).to_owned()).await?;
+        manager.drop_table(Table::drop().table(history.keys()).to_owned()).await?;
+    }
+    // The timestamp indexes went with the old tables
+    manager.create_index(Index::create()
+        .name("SensorReadingHistory_timestamp")
+        .table(History::SensorReadings.table())
+        .col(SensorReadingHistory::Timestamp)
+        .to_owned()).await?;
+    manager.create_index(Index::create()
+        .name("WeatherReading_timestamp")
+        .table(History::Weather.table())
+        .col(WeatherReading::Timestamp)
+        .to_owned()).await
+}
+
+async fn count(manager: &SchemaManager<'_>, table: Alias) -> Result<u64, DbErr> {
+    let query: SelectStatement = Query::select().expr(Expr::col(Asterisk).count()).from(table).to_owned();
+    let found = manager.get_connection().query_one(manager.get_database_backend().build(&query)).await?;
+    let rows: i64 = match found {
+        Some(row) => row.try_get_by_index(0)?,
+        None => 0,
+    };
+    Ok(rows as u64)
+}
+
+#[derive(DeriveIden)]
+enum KeyMap {
+    #[sea_orm(iden = "old_id")]
+    OldId,
+    #[sea_orm(iden = "new_id")]
+    NewId,
+}
+
+#[derive(DeriveIden)]
+enum PollutionReading {
+    Timestamp,
+    #[sea_orm(iden = "AQI")]
+    Aqi,
+    #[sea_orm(iden = "CO")]
+    Co,
+    #[sea_orm(iden = "NO")]
+    No,
+    #[sea_orm(iden = "NO2")]
+    No2,
+    #[sea_orm(iden = "O3")]
+    O3,
+    #[sea_orm(iden = "SO2")]
+    So2,
+    #[sea_orm(iden = "PM2_5")]
+    Pm25,
+    #[sea_orm(iden = "PM10")]
+    Pm10,
+    #[sea_orm(iden = "NH3")]
+    Nh3,
+}
+
+#[derive(DeriveIden)]
+enum WeatherReading {
+    Timestamp,
+    Condition,
+    Description,
+    Icon,
+    #[sea_orm(iden = "tempReal")]
+    TempReal,
+    #[sea_orm(iden = "tempFeel")]
+    TempFeel,
+    #[sea_orm(iden = "pressureSea")]
+    PressureSea,
+    Humidity,
+    #[sea_orm(iden = "pressureGround")]
+    PressureGround,
+    Visibility,
+    #[sea_orm(iden = "windSpeed")]
+    WindSpeed,
+    #[sea_orm(iden = "windDeg")]
+    WindDeg,
+    #[sea_orm(iden = "windGust")]
+    WindGust,
+    #[sea_orm(iden = "rain1H")]
+    Rain1H,
+    #[sea_orm(iden = "rain3H")]
+    Rain3H,
+    #[sea_orm(iden = "snow1H")]
+    Snow1H,
+    #[sea_orm(iden = "snow3H")]
+    Snow3H,
+    Clouds,
+    Dt,
+    Sunrise,
+    Sunset,
+    #[sea_orm(iden = "dewPoint")]
+    DewPoint,
+    #[sea_orm(iden = "heatIndex")]
+    HeatIndex,
+    #[sea_orm(iden = "windChill")]
+    WindChill,
+    Humidex,
+    #[sea_orm(iden = "absoluteHumidity")]
+    AbsoluteHumidity,
+}
+
+#[derive(DeriveIden)]
+enum SensorReadingHistory {
+    #[sea_orm(iden = "sensorID")]
+    SensorId,
+    Timestamp,
+    #[sea_orm(iden = "readingTemp")]
+    ReadingTemp,
+    #[sea_orm(iden = "readingHumidity")]
+    ReadingHumidity,
+    #[sea_orm(iden = "readingPresence")]
+    ReadingPresence,
+    #[sea_orm(iden = "readingThresholdOpen")]
+    ReadingThresholdOpen,
+}
+
+#[derive(DeriveIden)]
+enum ManualChangeHistory {
+    #[sea_orm(iden = "changeTiming")]
+    ChangeTiming,
+    #[sea_orm(iden = "changeWeather")]
+    ChangeWeather,
+    #[sea_orm(iden = "changePollution")]
+    ChangePollution,
+    #[sea_orm(iden = "changeSource")]
+    ChangeSource,
+    #[sea_orm(iden = "newTemp")]
+    NewTemp,
+    #[sea_orm(iden = "newHumidity")]
+    NewHumidity,
+    #[sea_orm(iden = "changeSchedule")]
+    ChangeSchedule,
+    #[sea_orm(iden = "cancelledTiming")]
+    CancelledTiming,
+}
+
+#[derive(DeriveIden)]
+enum Sensors {
+    #[sea_orm(iden = "Sensors")]
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum ChangeSource {
+    #[sea_orm(iden = "ChangeSource")]
+    Table,
+    Id,
+}
+
+#[derive(DeriveIden)]
+enum Schedules {
+    #[sea_orm(iden = "Schedules")]
+    Table,
+    Id,
+}
@@ -0,0 +1,71 @@
+//! Adds the AuditLog table, one row for every change made to zones, sensors, controllers, schedules and alerts<br>
+//! Rows are keyed on UUIDv7 like the history tables and keep the changed row as JSON before and after the change
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.create_table(Table::create()
+            .table(AuditLog::Table)
+            .if_not_exists()
+            .col(ColumnDef::new(AuditLog::Id).uuid().not_null().primary_key())
+            .col(ColumnDef::new(AuditLog::Timestamp).date_time().not_null())
+            .col(ColumnDef::new(AuditLog::Actor).text().not_null())
+            .col(ColumnDef::new(AuditLog::ChangeSource).integer().not_null())
+            .col(ColumnDef::new(AuditLog::EntityType).text().not_null())
+            .col(ColumnDef::new(AuditLog::EntityId).integer().not_null())
+            .col(ColumnDef::new(AuditLog::Action).text().not_null())
+            .col(ColumnDef::new(AuditLog::Before).json())
+            .col(ColumnDef::new(AuditLog::After).json())
+            .foreign_key(ForeignKey::create()
+                .from(AuditLog::Table, AuditLog::ChangeSource)
+                .to(ChangeSource::Table, ChangeSource::Id))
+            .to_owned()).await?;
+        manager.create_index(Index::create()
+            .name("AuditLog_entity")
+            .table(AuditLog::Table)
+            .col(AuditLog::EntityType)
+            .col(AuditLog::EntityId)
+            .if_not_exists()
+            .to_owned()).await?;
+        manager.create_index(Index::create()
+            .name("AuditLog_timestamp")
+            .table(AuditLog::Table)
+            .col(AuditLog::Timestamp)
+            .if_not_exists()
+            .to_owned()).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.drop_table(Table::drop().table(AuditLog::Table).if_exists().to_owned()).await
+    }
+}
+
+#[derive(DeriveIden)]
+enum AuditLog {
+    #[sea_orm(iden = "AuditLog")]
+    Table,
+    Id,
+    Timestamp,
+    Actor,
+    #[sea_orm(iden = "changeSource")]
+    ChangeSource,
+    #[sea_orm(iden = "entityType")]
+    EntityType,
+    #[sea_orm(iden = "entityId")]
+    EntityId,
+    Action,
+    Before,
+    After,
+}
+
+#[derive(DeriveIden)]
+enum ChangeSource {
+    #[sea_orm(iden = "ChangeSource")]
+    Table,
+    Id,
+}
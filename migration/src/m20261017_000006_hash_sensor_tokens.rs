@@ -0,0 +1,52 @@
+//! Stores sensor tokens as their SHA-256, the way the repository now issues and checks them<br>
+//! Tokens already stored in plain text are hashed in place, so devices carrying them keep working. A hash cannot be
+//! turned back into its token, so going down leaves the hashes where they are
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+use sha2::{Digest, Sha256};
+
+// Must match `repo::tokens::HASH_PREFIX` in the application
+pub(crate) const HASH_PREFIX: &str = "sha256:";
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let plain = manager.get_connection().query_all(manager.get_database_backend().build(&Query::select()
+            .columns([Sensors::Id, Sensors::Token])
+            .from(Sensors::Table)
+            .and_where(Expr::col(Sensors::Token).not_like(format!("{}%", HASH_PREFIX)))
+            .to_owned())).await?;
+        for row in plain {
+            let id: i32 = row.try_get_by_index(0)?;
+            let token: String = row.try_get_by_index(1)?;
+            manager.exec_stmt(Query::update()
+                .table(Sensors::Table)
+                .value(Sensors::Token, hash(&token))
+                .and_where(Expr::col(Sensors::Id).eq(id))
+                .to_owned()).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+pub(crate) fn hash(token: &str) -> String {
+    let digest = Sha256::digest(token.as_bytes());
+    format!("{}{}", HASH_PREFIX, digest.iter().map(|byte| format!("{:02x}", byte)).collect::<String>())
+}
+
+#[derive(DeriveIden)]
+enum Sensors {
+    #[sea_orm(iden = "Sensors")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Token")]
+    Token,
+}
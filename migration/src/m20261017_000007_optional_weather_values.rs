@@ -0,0 +1,124 @@
+//! Lets a WeatherReading leave out what the provider did not report: the condition, description and icon when it
+//! sends no weather entry, the ground level pressure, the visibility and the wind gust<br>
+//! Going down fills those gaps the way readings used to be stored, sea level pressure for the ground, 10 km of
+//! visibility, a gust as strong as the wind and an empty condition
+
+use sea_orm_migration::prelude::*;
+use crate::rebuild::{self, Reading};
+
+// The columns that become optional
+const OPTIONAL: [&str; 6] = ["condition", "description", "icon", "pressureGround", "visibility", "windGust"];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rebuild::set_nullable(manager, Reading::Weather, &OPTIONAL, true, weather_reading(true)).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        manager.exec_stmt(Query::update()
+            .table(WeatherReading::Table)
+            .value(WeatherReading::Condition, Func::coalesce([Expr::col(WeatherReading::Condition).into(), Expr::val("").into()]))
+            .value(WeatherReading::Description, Func::coalesce([Expr::col(WeatherReading::Description).into(), Expr::val("").into()]))
+            .value(WeatherReading::Icon, Func::coalesce([Expr::col(WeatherReading::Icon).into(), Expr::val("").into()]))
+            .value(WeatherReading::PressureGround, Func::coalesce([Expr::col(WeatherReading::PressureGround).into(),
+                Expr::col(WeatherReading::PressureSea).into()]))
+            .value(WeatherReading::Visibility, Func::coalesce([Expr::col(WeatherReading::Visibility).into(), Expr::val(10000).into()]))
+            .value(WeatherReading::WindGust, Func::coalesce([Expr::col(WeatherReading::WindGust).into(),
+                Expr::col(WeatherReading::WindSpeed).into()]))
+            .to_owned()).await?;
+        rebuild::set_nullable(manager, Reading::Weather, &OPTIONAL, false, weather_reading(false)).await
+    }
+}
+
+// WeatherReading as it is after the UUID keys, with the optional columns allowed to be NULL or not
+fn weather_reading(optional: bool) -> TableCreateStatement {
+    let column = |name: WeatherReading| {
+        let mut column: ColumnDef = ColumnDef::new(name);
+        if !optional {
+            column.not_null();
+        }
+        column
+    };
+    Table::create()
+        .table(Reading::Weather.rebuilt())
+        .col(ColumnDef::new(WeatherReading::Id).uuid().not_null().primary_key())
+        .col(ColumnDef::new(WeatherReading::Timestamp).date_time().not_null())
+        .col(column(WeatherReading::Condition).text())
+        .col(column(WeatherReading::Description).text())
+        .col(column(WeatherReading::Icon).text())
+        .col(ColumnDef::new(WeatherReading::TempReal).double().not_null())
+        .col(ColumnDef::new(WeatherReading::TempFeel).double().not_null())
+        .col(ColumnDef::new(WeatherReading::PressureSea).integer().not_null())
+        .col(ColumnDef::new(WeatherReading::Humidity).integer().not_null())
+        .col(column(WeatherReading::PressureGround).integer())
+        .col(column(WeatherReading::Visibility).integer())
+        .col(ColumnDef::new(WeatherReading::WindSpeed).double().not_null())
+        .col(ColumnDef::new(WeatherReading::WindDeg).integer().not_null())
+        .col(column(WeatherReading::WindGust).double())
+        .col(ColumnDef::new(WeatherReading::Rain1H).double())
+        .col(ColumnDef::new(WeatherReading::Rain3H).double())
+        .col(ColumnDef::new(WeatherReading::Snow1H).double())
+        .col(ColumnDef::new(WeatherReading::Snow3H).double())
+        .col(ColumnDef::new(WeatherReading::Clouds).integer().not_null())
+        .col(ColumnDef::new(WeatherReading::Dt).integer().not_null())
+        .col(ColumnDef::new(WeatherReading::Sunrise).integer().not_null())
+        .col(ColumnDef::new(WeatherReading::Sunset).integer().not_null())
+        .col(ColumnDef::new(WeatherReading::DewPoint).double())
+        .col(ColumnDef::new(WeatherReading::HeatIndex).double())
+        .col(ColumnDef::new(WeatherReading::WindChill).double())
+        .col(ColumnDef::new(WeatherReading::Humidex).double())
+        .col(ColumnDef::new(WeatherReading::AbsoluteHumidity).double())
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum WeatherReading {
+    #[sea_orm(iden = "WeatherReading")]
+    Table,
+    Id,
+    Timestamp,
+    Condition,
+    Description,
+    Icon,
+    #[sea_orm(iden = "tempReal")]
+    TempReal,
+    #[sea_orm(iden = "tempFeel")]
+    TempFeel,
+    #[sea_orm(iden = "pressureSea")]
+    PressureSea,
+    Humidity,
+    #[sea_orm(iden = "pressureGround")]
+    PressureGround,
+    Visibility,
+    #[sea_orm(iden = "windSpeed")]
+    WindSpeed,
+    #[sea_orm(iden = "windDeg")]
+    WindDeg,
+    #[sea_orm(iden = "windGust")]
+    WindGust,
+    #[sea_orm(iden = "rain1H")]
+    Rain1H,
+    #[sea_orm(iden = "rain3H")]
+    Rain3H,
+    #[sea_orm(iden = "snow1H")]
+    Snow1H,
+    #[sea_orm(iden = "snow3H")]
+    Snow3H,
+    Clouds,
+    Dt,
+    Sunrise,
+    Sunset,
+    #[sea_orm(iden = "dewPoint")]
+    DewPoint,
+    #[sea_orm(iden = "heatIndex")]
+    HeatIndex,
+    #[sea_orm(iden = "windChill")]
+    WindChill,
+    Humidex,
+    #[sea_orm(iden = "absoluteHumidity")]
+    AbsoluteHumidity,
+}
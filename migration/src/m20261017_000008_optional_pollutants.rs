@@ -0,0 +1,57 @@
+//! Lets a PollutionReading leave out pollutants the provider does not measure, Open-Meteo has no NH3 for one, instead
+//! of storing them as 0<br>
+//! Going down stores the missing pollutants as 0 again
+
+use sea_orm_migration::prelude::*;
+use crate::rebuild::{self, Reading};
+
+// The pollutant columns, every one of which becomes optional
+const POLLUTANTS: [&str; 8] = ["CO", "NO", "NO2", "O3", "SO2", "PM2_5", "PM10", "NH3"];
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        rebuild::set_nullable(manager, Reading::Pollution, &POLLUTANTS, true, pollution_reading(true)).await
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let mut update: UpdateStatement = Query::update().table(PollutionReading::Table).to_owned();
+        for pollutant in POLLUTANTS {
+            update.value(Alias::new(pollutant), Func::coalesce([Expr::col(Alias::new(pollutant)).into(), Expr::val(0.0).into()]));
+        }
+        manager.exec_stmt(update).await?;
+        rebuild::set_nullable(manager, Reading::Pollution, &POLLUTANTS, false, pollution_reading(false)).await
+    }
+}
+
+// PollutionReading as it is after the UUID keys, with the pollutants allowed to be NULL or not
+fn pollution_reading(optional: bool) -> TableCreateStatement {
+    let mut table: TableCreateStatement = Table::create()
+        .table(Reading::Pollution.rebuilt())
+        .col(ColumnDef::new(PollutionReading::Id).uuid().not_null().primary_key())
+        .col(ColumnDef::new(PollutionReading::Timestamp).date_time().not_null())
+        .col(ColumnDef::new(PollutionReading::Aqi).integer().not_null())
+        .to_owned();
+    for pollutant in POLLUTANTS {
+        let mut column: ColumnDef = ColumnDef::new(Alias::new(pollutant));
+        column.double();
+        if !optional {
+            column.not_null();
+        }
+        table.col(&mut column);
+    }
+    table
+}
+
+#[derive(DeriveIden)]
+enum PollutionReading {
+    #[sea_orm(iden = "PollutionReading")]
+    Table,
+    Id,
+    Timestamp,
+    #[sea_orm(iden = "AQI")]
+    Aqi,
+}
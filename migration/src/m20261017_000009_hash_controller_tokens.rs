@@ -0,0 +1,44 @@
+//! Stores controller tokens as their SHA-256, the same way sensor tokens are<br>
+//! Tokens already stored in plain text are hashed in place, so devices carrying them keep working. A hash cannot be
+//! turned back into its token, so going down leaves the hashes where they are
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+use crate::m20261017_000006_hash_sensor_tokens::{hash, HASH_PREFIX};
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        let plain = manager.get_connection().query_all(manager.get_database_backend().build(&Query::select()
+            .columns([Controllers::Id, Controllers::Token])
+            .from(Controllers::Table)
+            .and_where(Expr::col(Controllers::Token).not_like(format!("{}%", HASH_PREFIX)))
+            .to_owned())).await?;
+        for row in plain {
+            let id: i32 = row.try_get_by_index(0)?;
+            let token: String = row.try_get_by_index(1)?;
+            manager.exec_stmt(Query::update()
+                .table(Controllers::Table)
+                .value(Controllers::Token, hash(&token))
+                .and_where(Expr::col(Controllers::Id).eq(id))
+                .to_owned()).await?;
+        }
+        Ok(())
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden)]
+enum Controllers {
+    #[sea_orm(iden = "Controllers")]
+    Table,
+    Id,
+    #[sea_orm(iden = "Token")]
+    Token,
+}
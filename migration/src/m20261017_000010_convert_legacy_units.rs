@@ -0,0 +1,149 @@
+//! Converts the temperatures a database stored before canonical units into Celsius<br>
+//! Until then temperatures were kept in whatever units they came in, which the database itself does not record. The
+//! weather tables followed the units the weather settings asked the provider for, while sensors, zones, schedules and
+//! the manual changes followed the home's own, so each group is named on its own: `RUSTY_THERMO_LEGACY_WEATHER_UNITS`
+//! and `RUSTY_THERMO_LEGACY_HOME_UNITS`, standard, metric or imperial. Only temperature columns are converted, wind
+//! speeds and pressures are left as they were stored<br>
+//! A group whose variable is unset is left alone, with a warning when its tables hold rows. A database created from
+//! scratch has nothing to convert, so nothing is done. Going down leaves the values as they are, so a database that was
+//! migrated without the variables can be converted later by going back down past this migration and up again with
+//! them set
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::ConnectionTrait;
+
+/// Names the units the weather tables stored their temperatures in
+pub const LEGACY_WEATHER_UNITS_VAR: &str = "RUSTY_THERMO_LEGACY_WEATHER_UNITS";
+/// Names the units the sensor, zone, schedule and manual change tables stored their temperatures in
+pub const LEGACY_HOME_UNITS_VAR: &str = "RUSTY_THERMO_LEGACY_HOME_UNITS";
+
+#[derive(Clone, Copy)]
+enum Quantity {
+    Temperature,
+    // A difference between temperatures, like degree days, which only scales
+    Difference,
+}
+
+type Columns = &'static [(&'static str, Quantity)];
+
+// The temperature columns filled from the weather provider, by table
+const WEATHER_COLUMNS: [(&str, Columns); 4] = [
+    ("WeatherReading", &[("tempReal", Quantity::Temperature), ("tempFeel", Quantity::Temperature), ("dewPoint", Quantity::Temperature),
+        ("heatIndex", Quantity::Temperature), ("windChill", Quantity::Temperature), ("humidex", Quantity::Temperature)]),
+    ("WeatherReadingRollup", &[("tempMin", Quantity::Temperature), ("tempMax", Quantity::Temperature), ("tempAvg", Quantity::Temperature)]),
+    ("ForecastReading", &[("tempReal", Quantity::Temperature), ("tempFeel", Quantity::Temperature), ("tempMin", Quantity::Temperature),
+        ("tempMax", Quantity::Temperature)]),
+    ("DailyDegreeDays", &[("baseTemp", Quantity::Temperature), ("meanTemp", Quantity::Temperature), ("hdd", Quantity::Difference),
+        ("cdd", Quantity::Difference)]),
+];
+
+// The temperature columns filled in the home, by sensors and by people, by table
+const HOME_COLUMNS: [(&str, Columns); 7] = [
+    ("HomeSummary", &[("houseTemp", Quantity::Temperature)]),
+    ("Zones", &[("currentTemp", Quantity::Temperature)]),
+    ("Sensors", &[("currentTemp", Quantity::Temperature)]),
+    ("Schedules", &[("tempMin", Quantity::Temperature), ("tempMax", Quantity::Temperature)]),
+    ("ManualChangeHistory", &[("newTemp", Quantity::Temperature)]),
+    ("SensorReadingHistory", &[("readingTemp", Quantity::Temperature)]),
+    ("SensorReadingRollup", &[("tempMin", Quantity::Temperature), ("tempMax", Quantity::Temperature), ("tempAvg", Quantity::Temperature)]),
+];
+
+/// A unit system temperatures may have been stored in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UnitSystem {
+    Standard,
+    Metric,
+    Imperial,
+}
+
+impl UnitSystem {
+    fn parse(var: &str, units: &str) -> Result<UnitSystem, DbErr> {
+        match units.trim().to_lowercase().as_str() {
+            "standard" => Ok(UnitSystem::Standard),
+            "metric" => Ok(UnitSystem::Metric),
+            "imperial" => Ok(UnitSystem::Imperial),
+            _ => Err(DbErr::Migration(format!("{} is {}, expected standard, metric or imperial", var, units))),
+        }
+    }
+
+    // The column in Celsius, or None when it already is
+    fn convert(self, column: &str, quantity: Quantity) -> Option<SimpleExpr> {
+        let value = Expr::col(Alias::new(column));
+        match (self, quantity) {
+            (UnitSystem::Metric, _) | (UnitSystem::Standard, Quantity::Difference) => None,
+            (UnitSystem::Standard, Quantity::Temperature) => Some(value.sub(273.15)),
+            (UnitSystem::Imperial, Quantity::Temperature) => Some(value.sub(32.0).mul(5.0).div(9.0)),
+            (UnitSystem::Imperial, Quantity::Difference) => Some(value.mul(5.0).div(9.0)),
+        }
+    }
+}
+
+/// The units each group of tables stored its temperatures in, None for a group to leave alone
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LegacyUnits {
+    pub weather: Option<UnitSystem>,
+    pub home: Option<UnitSystem>,
+}
+
+impl LegacyUnits {
+    // Reads the units from their environment variables
+    fn from_env() -> Result<LegacyUnits, DbErr> {
+        let read = |var: &str| std::env::var(var).ok().map(|units| UnitSystem::parse(var, &units)).transpose();
+        Ok(LegacyUnits { weather: read(LEGACY_WEATHER_UNITS_VAR)?, home: read(LEGACY_HOME_UNITS_VAR)? })
+    }
+}
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        convert(manager, LegacyUnits::from_env()?).await
+    }
+
+    async fn down(&self, _manager: &SchemaManager) -> Result<(), DbErr> {
+        Ok(())
+    }
+}
+
+/// Converts each group of tables from the units given for it, and warns about a group with rows and no units
+pub async fn convert(manager: &SchemaManager<'_>, units: LegacyUnits) -> Result<(), DbErr> {
+    for (columns, units, var) in [(&WEATHER_COLUMNS[..], units.weather, LEGACY_WEATHER_UNITS_VAR), (&HOME_COLUMNS[..], units.home, LEGACY_HOME_UNITS_VAR)] {
+        match units {
+            Some(units) => convert_columns(manager, columns, units).await?,
+            None => if let Some(table) = first_table_with_rows(manager, columns).await? {
+                log::warn!("{} has temperatures that may predate canonical units and were left as they are. Set {} to the units \
+                    they were stored in and run this migration again to convert them", table, var);
+            },
+        }
+    }
+    Ok(())
+}
+
+async fn convert_columns(manager: &SchemaManager<'_>, columns: &[(&str, Columns)], units: UnitSystem) -> Result<(), DbErr> {
+    for (table, columns) in columns {
+        let mut update: UpdateStatement = Query::update().table(Alias::new(*table)).to_owned();
+        let mut changed: bool = false;
+        for (column, quantity) in columns.iter() {
+            if let Some(converted) = units.convert(column, *quantity) {
+                update.value(Alias::new(*column), converted);
+                changed = true;
+            }
+        }
+        if changed {
+            manager.exec_stmt(update).await?;
+        }
+    }
+    Ok(())
+}
+
+async fn first_table_with_rows(manager: &SchemaManager<'_>, columns: &[(&'static str, Columns)]) -> Result<Option<&'static str>, DbErr> {
+    for (table, _) in columns {
+        let query: SelectStatement = Query::select().expr(Expr::val(1)).from(Alias::new(*table)).limit(1).to_owned();
+        if manager.get_connection().query_one(manager.get_database_backend().build(&query)).await?.is_some() {
+            return Ok(Some(table))
+        }
+    }
+    Ok(None)
+}
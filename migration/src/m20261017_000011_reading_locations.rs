@@ -0,0 +1,46 @@
+//! Records where each WeatherReading and PollutionReading was taken, so a stored reading is only served for the
+//! location it belongs to<br>
+//! Rows stored before then have no location. Going down drops the columns along with the locations in them
+
+use sea_orm_migration::prelude::*;
+
+#[derive(DeriveMigrationName)]
+pub struct Migration;
+
+#[async_trait::async_trait]
+impl MigrationTrait for Migration {
+    async fn up(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [Readings::WeatherReading, Readings::PollutionReading] {
+            // SQLite takes one change per statement
+            for column in [Readings::Lat, Readings::Lon] {
+                manager.alter_table(Table::alter()
+                    .table(table)
+                    .add_column(ColumnDef::new(column).double())
+                    .to_owned()).await?;
+            }
+        }
+        Ok(())
+    }
+
+    async fn down(&self, manager: &SchemaManager) -> Result<(), DbErr> {
+        for table in [Readings::WeatherReading, Readings::PollutionReading] {
+            for column in [Readings::Lat, Readings::Lon] {
+                manager.alter_table(Table::alter()
+                    .table(table)
+                    .drop_column(column)
+                    .to_owned()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(DeriveIden, Clone, Copy)]
+enum Readings {
+    #[sea_orm(iden = "WeatherReading")]
+    WeatherReading,
+    #[sea_orm(iden = "PollutionReading")]
+    PollutionReading,
+    Lat,
+    Lon,
+}
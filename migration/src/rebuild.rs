@@ -0,0 +1,136 @@
+//! Lets columns of WeatherReading or PollutionReading hold NULL, or stop holding it<br>
+//! Postgres changes the constraint in place. SQLite has no way to, so the table is built again beside the old one, the
+//! rows copied across and the new table renamed over the old. SQLite will not drop a table that rows still point at,
+//! so ManualChangeHistory, which points at both, is built again along with it
+
+use sea_orm_migration::prelude::*;
+use sea_orm_migration::sea_orm::DbBackend;
+
+/// The history tables ManualChangeHistory points at
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Reading {
+    Weather,
+    Pollution,
+}
+
+impl Reading {
+    fn name(self) -> &'static str {
+        match self {
+            Reading::Weather => "WeatherReading",
+            Reading::Pollution => "PollutionReading",
+        }
+    }
+
+    /// The name the table is built under on SQLite, which its definition has to use
+    pub(crate) fn rebuilt(self) -> Alias {
+        Alias::new(format!("{}_rebuilt", self.name()))
+    }
+
+    // The name ManualChangeHistory points at while this table is rebuilt
+    fn target_while(self, rebuilding: Reading) -> Alias {
+        match self == rebuilding {
+            true => self.rebuilt(),
+            false => Alias::new(self.name()),
+        }
+    }
+}
+
+/// Sets whether `columns` of the table may be NULL. On SQLite `definition` is the whole table as it should be,
+/// created under `Reading::rebuilt`
+pub(crate) async fn set_nullable(manager: &SchemaManager<'_>, reading: Reading, columns: &[&str], nullable: bool,
+    definition: TableCreateStatement) -> Result<(), DbErr> {
+    if manager.get_database_backend() != DbBackend::Sqlite {
+        let mut alter: TableAlterStatement = Table::alter().table(Alias::new(reading.name())).to_owned();
+        for column in columns {
+            let mut column: ColumnDef = ColumnDef::new(Alias::new(*column));
+            match nullable {
+                true => column.null(),
+                false => column.not_null(),
+            };
+            alter.modify_column(&mut column);
+        }
+        return manager.alter_table(alter).await
+    }
+
+    let manual_changes: Alias = Alias::new("ManualChangeHistory");
+    let manual_changes_rebuilt: Alias = Alias::new("ManualChangeHistory_rebuilt");
+    let reading_columns: Vec<Alias> = definition.get_columns().iter().map(|column| Alias::new(column.get_column_name())).collect();
+    manager.create_table(definition).await?;
+    copy(manager, Alias::new(reading.name()), reading.rebuilt(), reading_columns).await?;
+    let manual_changes_definition: TableCreateStatement = manual_change_history(reading, manual_changes_rebuilt.clone());
+    let manual_change_columns: Vec<Alias> = manual_changes_definition.get_columns().iter()
+        .map(|column| Alias::new(column.get_column_name())).collect();
+    manager.create_table(manual_changes_definition).await?;
+    copy(manager, manual_changes.clone(), manual_changes_rebuilt.clone(), manual_change_columns).await?;
+
+    manager.drop_table(Table::drop().table(manual_changes.clone()).to_owned()).await?;
+    manager.drop_table(Table::drop().table(Alias::new(reading.name())).to_owned()).await?;
+    manager.rename_table(Table::rename().table(reading.rebuilt(), Alias::new(reading.name())).to_owned()).await?;
+    manager.rename_table(Table::rename().table(manual_changes_rebuilt, manual_changes).to_owned()).await?;
+    // The timestamp index went with the old table
+    if reading == Reading::Weather {
+        manager.create_index(Index::create()
+            .name("WeatherReading_timestamp")
+            .table(Alias::new(reading.name()))
+            .col(Alias::new("timestamp"))
+            .to_owned()).await?;
+    }
+    Ok(())
+}
+
+async fn copy(manager: &SchemaManager<'_>, from: Alias, to: Alias, columns: Vec<Alias>) -> Result<(), DbErr> {
+    let mut insert: InsertStatement = Query::insert();
+    insert.into_table(to).columns(columns.clone())
+        .select_from(Query::select().columns(columns).from(from).to_owned())
+        .map_err(|error| DbErr::Migration(error.to_string()))?;
+    manager.exec_stmt(insert).await
+}
+
+// ManualChangeHistory as the UUID keys left it, pointing at the table being rebuilt under its new name
+fn manual_change_history(rebuilding: Reading, name: Alias) -> TableCreateStatement {
+    Table::create()
+        .table(name.clone())
+        .col(ColumnDef::new(ManualChangeHistory::Id).uuid().not_null().primary_key())
+        .col(ColumnDef::new(ManualChangeHistory::ChangeTiming).date_time().not_null())
+        .col(ColumnDef::new(ManualChangeHistory::ChangeWeather).uuid().not_null())
+        .col(ColumnDef::new(ManualChangeHistory::ChangePollution).uuid().not_null())
+        .col(ColumnDef::new(ManualChangeHistory::ChangeSource).integer().not_null())
+        .col(ColumnDef::new(ManualChangeHistory::NewTemp).double())
+        .col(ColumnDef::new(ManualChangeHistory::NewHumidity).integer())
+        .col(ColumnDef::new(ManualChangeHistory::ChangeSchedule).integer())
+        .col(ColumnDef::new(ManualChangeHistory::CancelledTiming).date_time())
+        .foreign_key(ForeignKey::create()
+            .from(name.clone(), ManualChangeHistory::ChangeWeather)
+            .to(Reading::Weather.target_while(rebuilding), Alias::new("id")))
+        .foreign_key(ForeignKey::create()
+            .from(name.clone(), ManualChangeHistory::ChangePollution)
+            .to(Reading::Pollution.target_while(rebuilding), Alias::new("id")))
+        .foreign_key(ForeignKey::create()
+            .from(name.clone(), ManualChangeHistory::ChangeSource)
+            .to(Alias::new("ChangeSource"), Alias::new("id")))
+        .foreign_key(ForeignKey::create()
+            .from(name, ManualChangeHistory::ChangeSchedule)
+            .to(Alias::new("Schedules"), Alias::new("id")))
+        .to_owned()
+}
+
+#[derive(DeriveIden)]
+enum ManualChangeHistory {
+    Id,
+    #[sea_orm(iden = "changeTiming")]
+    ChangeTiming,
+    #[sea_orm(iden = "changeWeather")]
+    ChangeWeather,
+    #[sea_orm(iden = "changePollution")]
+    ChangePollution,
+    #[sea_orm(iden = "changeSource")]
+    ChangeSource,
+    #[sea_orm(iden = "newTemp")]
+    NewTemp,
+    #[sea_orm(iden = "newHumidity")]
+    NewHumidity,
+    #[sea_orm(iden = "changeSchedule")]
+    ChangeSchedule,
+    #[sea_orm(iden = "cancelledTiming")]
+    CancelledTiming,
+}
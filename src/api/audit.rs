@@ -0,0 +1,54 @@
+//! `/audit`, the audit log newest first<br>
+//! Entries can be narrowed to one entity type or row, one actor or change source, and a time range given as dates or
+//! date times like the history export takes. Actor names come from the `X-Actor` header, so they say who a client
+//! claimed to be, not who it was
+
+use rocket::{Route, State};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sea_orm::DatabaseConnection;
+use crate::repo::audit::{self as audit_repo, AuditFilter, Source};
+use crate::schema::audit_log;
+use crate::transfer::TimeRange;
+use super::ApiError;
+
+pub fn routes() -> Vec<Route> {
+    routes![list]
+}
+
+#[get("/audit?<entity>&<id>&<actor>&<source>&<from>&<to>&<limit>")]
+#[allow(clippy::too_many_arguments)]
+async fn list(entity: Option<String>, id: Option<i32>, actor: Option<String>, source: Option<&str>, from: Option<&str>, to: Option<&str>,
+    limit: Option<u64>, db: &State<DatabaseConnection>) -> Result<Json<Vec<audit_log::Model>>, ApiError> {
+    let range: TimeRange = TimeRange::parse(from, to).map_err(|error| ApiError::new(Status::BadRequest, error.to_string()))?;
+    let source: Option<Source> = source.map(Source::parse).transpose()
+        .map_err(|error| ApiError::new(Status::BadRequest, error.to_string()))?;
+    let filter: AuditFilter = AuditFilter { entity_type: entity, entity_id: id, actor, source, from: range.from, to: range.to, limit };
+    Ok(Json(audit_repo::query(db, filter).await?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+    use crate::api::{ACTOR_HEADER, BASE, test_client};
+
+    #[tokio::test]
+    async fn changes_are_listed_under_the_claimed_actor() {
+        let client: Client = test_client().await;
+        client.post(format!("{}/zones", BASE)).header(ContentType::JSON).header(Header::new(ACTOR_HEADER, "panel"))
+            .body(r#"{"name": "Study"}"#).dispatch().await;
+
+        let entries: Value = client.get(format!("{}/audit?entity=zone&actor=panel", BASE)).dispatch().await.into_json().await.unwrap();
+        assert_eq!(entries.as_array().unwrap().len(), 1);
+        assert_eq!(entries[0]["action"], "create");
+
+        let response: LocalResponse = client.get(format!("{}/audit?source=carrier-pigeon", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_json::<Value>().await.unwrap()["status"], 400);
+        let response: LocalResponse = client.get(format!("{}/audit?from=yesterday", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+    }
+}
@@ -0,0 +1,78 @@
+//! `/backup`, the whole configuration as one archive and restoring from one<br>
+//! Both are admin routes. An archive holds every zone, sensor and controller, and a restore replaces all of them
+//! with what the archive has
+
+use rocket::{Route, State};
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::Status;
+use rocket::serde::json::Json;
+use sea_orm::DatabaseConnection;
+use crate::backup::{self, Archive, BackupError, RestoreReport};
+use super::{Admin, ApiError};
+
+/// The largest archive a restore accepts
+pub const ARCHIVE_LIMIT_MIB: u64 = 64;
+
+pub fn routes() -> Vec<Route> {
+    routes![take, restore]
+}
+
+/// The configuration, and the history too when `history` is true
+#[get("/backup?<history>")]
+async fn take(history: Option<bool>, admin: Result<Admin, ApiError>, db: &State<DatabaseConnection>) -> Result<Json<Archive>, ApiError> {
+    admin?;
+    match backup::backup(db, history.unwrap_or(false)).await {
+        Ok(archive) => Ok(Json(archive)),
+        Err(error) => {
+            error!("Unable to take a backup: {}", error);
+            Err(ApiError::new(Status::InternalServerError, "Unable to take a backup"))
+        }
+    }
+}
+
+/// Replaces the configuration with an archive's, leaving everything as it was when the archive is refused
+#[post("/backup/restore", data = "<body>")]
+async fn restore(body: Data<'_>, admin: Result<Admin, ApiError>, db: &State<DatabaseConnection>) -> Result<Json<RestoreReport>, ApiError> {
+    admin?;
+    let input = body.open(ARCHIVE_LIMIT_MIB.mebibytes()).into_bytes().await
+        .map_err(|error| ApiError::new(Status::BadRequest, format!("Unable to read the request body: {}", error)))?;
+    if !input.is_complete() {
+        return Err(ApiError::new(Status::PayloadTooLarge, format!("Archives are limited to {} MiB", ARCHIVE_LIMIT_MIB)))
+    }
+    let archive: Archive = Archive::from_json(&input).map_err(|error| ApiError::new(Status::UnprocessableEntity, error.to_string()))?;
+    match backup::restore(db, archive).await {
+        Ok(report) => Ok(Json(report)),
+        Err(BackupError::Db(error)) => {
+            error!("Unable to restore a backup: {}", error);
+            Err(ApiError::new(Status::InternalServerError, "Unable to restore the archive"))
+        },
+        Err(error) => Err(ApiError::new(Status::UnprocessableEntity, error.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::ContentType;
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+    use crate::api::{BASE, admin_header, test_client};
+
+    #[tokio::test]
+    async fn an_archive_restores_through_the_api() {
+        let client: Client = test_client().await;
+        client.post(format!("{}/zones", BASE)).header(ContentType::JSON).body(r#"{"name": "Cellar"}"#).dispatch().await;
+        let archive: String = client.get(format!("{}/backup", BASE)).header(admin_header()).dispatch().await.into_string().await.unwrap();
+
+        let response: LocalResponse = client.post(format!("{}/backup/restore", BASE)).body(&archive).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response: LocalResponse = client.post(format!("{}/backup/restore", BASE)).header(admin_header()).body(&archive).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let report: Value = response.into_json().await.unwrap();
+        assert!(report.is_object());
+
+        let response: LocalResponse = client.post(format!("{}/backup/restore", BASE)).header(admin_header()).body("{}").dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_json::<Value>().await.unwrap()["status"], 422);
+    }
+}
@@ -0,0 +1,107 @@
+//! `/history/<table>`, the history tables exported and imported as CSV or newline delimited JSON<br>
+//! Both are admin routes. An export hands out everything the sensors recorded and an import writes rows straight into
+//! the history, see `transfer` for the formats and how rows find their sensors and schedules again
+
+use rocket::{Route, State};
+use rocket::data::{Data, ToByteUnit};
+use rocket::http::{ContentType, Status};
+use rocket::response::stream::TextStream;
+use rocket::serde::json::Json;
+use sea_orm::DatabaseConnection;
+use crate::transfer::{self, Exporter, Format, HistoryTable, ImportReport, TimeRange, TransferError};
+use super::{Admin, ApiError};
+
+/// The largest history file an import accepts
+pub const IMPORT_LIMIT_MIB: u64 = 64;
+
+pub fn routes() -> Vec<Route> {
+    routes![export, import]
+}
+
+// The content type an export is served as
+fn content_type(format: Format) -> ContentType {
+    match format {
+        Format::Csv => ContentType::CSV,
+        Format::Ndjson => ContentType::new("application", "x-ndjson"),
+    }
+}
+
+fn bad_request(error: TransferError) -> ApiError {
+    ApiError::new(Status::BadRequest, error.to_string())
+}
+
+#[get("/history/<table>?<format>&<from>&<to>")]
+fn export<'r>(table: &str, format: Option<&str>, from: Option<&str>, to: Option<&str>, admin: Result<Admin, ApiError>,
+    db: &'r State<DatabaseConnection>) -> Result<(ContentType, TextStream![String + 'r]), ApiError> {
+    admin?;
+    let table: HistoryTable = HistoryTable::parse(table).map_err(bad_request)?;
+    let format: Format = format.map(Format::parse).unwrap_or(Ok(Format::default())).map_err(bad_request)?;
+    let range: TimeRange = TimeRange::parse(from, to).map_err(bad_request)?;
+    let db: &DatabaseConnection = db.inner();
+    let mut exporter: Exporter = Exporter::new(table, format, range);
+    // Rows are read and sent a page at a time. Once the response has started an error can only cut it short
+    Ok((content_type(format), TextStream! {
+        loop {
+            match exporter.next_chunk(db).await {
+                Ok(Some(chunk)) => yield chunk,
+                Ok(None) => break,
+                Err(error) => {
+                    error!("Export of {} history stopped early: {}", table.name(), error);
+                    break
+                }
+            }
+        }
+    }))
+}
+
+#[post("/history/<table>?<format>", data = "<body>")]
+async fn import(table: &str, format: Option<&str>, body: Data<'_>, admin: Result<Admin, ApiError>, db: &State<DatabaseConnection>)
+    -> Result<Json<ImportReport>, ApiError> {
+    admin?;
+    let table: HistoryTable = HistoryTable::parse(table).map_err(bad_request)?;
+    let format: Format = format.map(Format::parse).unwrap_or(Ok(Format::default())).map_err(bad_request)?;
+    let input = body.open(IMPORT_LIMIT_MIB.mebibytes()).into_bytes().await
+        .map_err(|error| ApiError::new(Status::BadRequest, format!("Unable to read the request body: {}", error)))?;
+    if !input.is_complete() {
+        return Err(ApiError::new(Status::PayloadTooLarge, format!("Imports are limited to {} MiB", IMPORT_LIMIT_MIB)))
+    }
+    match transfer::import(db, table, format, &input).await {
+        Ok(report) => Ok(Json(report)),
+        Err(TransferError::Db(error)) => {
+            error!("Unable to import {} history: {}", table.name(), error);
+            Err(ApiError::new(Status::InternalServerError, "Unable to store the imported rows"))
+        },
+        Err(error) => Err(ApiError::new(Status::UnprocessableEntity, error.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+    use crate::api::{BASE, admin_header, test_client};
+
+    const READING: &str = r#"{"id":"0190f0a4-6c3e-7000-8000-000000000001","sensor":"Hall","timestamp":"2024-03-01T09:00:00"}"#;
+
+    #[tokio::test]
+    async fn readings_go_in_and_come_out_with_the_admin_token() {
+        let client: Client = test_client().await;
+        client.post(format!("{}/sensors", BASE)).header(ContentType::JSON).body(r#"{"name": "Hall", "com_type": 1}"#).dispatch().await;
+        let path: String = format!("{}/history/sensor-readings?format=ndjson", BASE);
+        assert_eq!(client.post(&path).body(READING).dispatch().await.status(), Status::Unauthorized);
+        assert_eq!(client.get(&path).dispatch().await.status(), Status::Unauthorized);
+
+        let response: LocalResponse = client.post(&path).header(admin_header()).body(READING).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        assert_eq!(response.into_json::<Value>().await.unwrap()["inserted"], 1);
+        let response: LocalResponse = client.get(&path).header(admin_header()).dispatch().await;
+        assert_eq!(response.content_type(), Some(ContentType::new("application", "x-ndjson")));
+        assert!(response.into_string().await.unwrap().contains("0190f0a4-6c3e-7000-8000-000000000001"));
+
+        let response: LocalResponse = client.get(format!("{}/history/thermostats", BASE)).header(admin_header()).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+        let response: LocalResponse = client.post(&path).header(admin_header()).body("not json").dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+    }
+}
@@ -0,0 +1,257 @@
+//! # Rusty Thermostat API
+//! The versioned REST resources clients like the wall panel build on, mounted under `BASE`<br>
+//! Every error, including the ones Rocket raises itself for unknown paths and unreadable bodies, is answered with a
+//! JSON `ErrorBody`. Changes are made on behalf of the actor named in the `X-Actor` header, through the change source
+//! in `X-Change-Source`, and land in the audit log. Neither header is checked against anything, the API has no
+//! logins, so the actor a change is recorded under is whatever the client chose to call itself<br>
+//! The admin routes, backups and moving history in and out, are the exception. They need the admin token from the
+//! configuration as a bearer token, and are turned off while none is set
+
+use std::fmt;
+use rocket::{Catcher, Request, Route};
+use rocket::http::Status;
+use rocket::request::{FromRequest, Outcome};
+use rocket::response::{self, Responder};
+use rocket::serde::json::Json;
+use serde::{Deserialize, Deserializer};
+use serde_derive::Serialize;
+use crate::repo::{RepoError, tokens};
+use crate::repo::audit::{Actor, Source};
+
+pub mod audit;
+pub mod backup;
+pub mod history;
+pub mod sensors;
+pub mod zones;
+
+/// Where the API is mounted
+pub const BASE: &str = "/api/v1";
+/// Names who is making a change. It is an unverified hint, any client can send any name
+pub const ACTOR_HEADER: &str = "X-Actor";
+/// Names the change source a change goes through, api when it is left out
+pub const SOURCE_HEADER: &str = "X-Change-Source";
+/// Carries the admin token as `Bearer <token>`
+pub const AUTHORIZATION_HEADER: &str = "Authorization";
+
+/// Every route of the API, to be mounted at `BASE`
+pub fn routes() -> Vec<Route> {
+    let mut routes: Vec<Route> = zones::routes();
+    routes.extend(sensors::routes());
+    routes.extend(audit::routes());
+    routes.extend(backup::routes());
+    routes.extend(history::routes());
+    routes
+}
+
+/// Catchers that keep Rocket's own errors in JSON, to be registered at `BASE`
+pub fn catchers() -> Vec<Catcher> {
+    catchers![default_catcher]
+}
+
+/// The body of every error response
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ErrorBody {
+    pub status: u16,
+    pub error: String,
+    pub message: String,
+}
+
+/// An error on its way to becoming a JSON response
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiError {
+    pub status: Status,
+    pub message: String,
+}
+
+impl ApiError {
+    pub fn new<S: Into<String>>(status: Status, message: S) -> ApiError {
+        ApiError { status, message: message.into() }
+    }
+
+    pub fn body(&self) -> ErrorBody {
+        ErrorBody {
+            status: self.status.code,
+            error: self.status.reason_lossy().to_string(),
+            message: self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.status, self.message)
+    }
+}
+
+impl From<RepoError> for ApiError {
+    fn from(error: RepoError) -> Self {
+        match error {
+            RepoError::NotFound { .. } => ApiError::new(Status::NotFound, error.to_string()),
+            RepoError::MissingReference { .. } | RepoError::Invalid(_) => ApiError::new(Status::UnprocessableEntity, error.to_string()),
+            RepoError::Db(error) => {
+                error!("API request failed in the database: {}", error);
+                ApiError::new(Status::InternalServerError, "The database was unable to complete the request")
+            },
+        }
+    }
+}
+
+impl<'r> Responder<'r, 'static> for ApiError {
+    fn respond_to(self, request: &'r Request<'_>) -> response::Result<'static> {
+        let status: Status = self.status;
+        response::Response::build_from(Json(self.body()).respond_to(request)?).status(status).ok()
+    }
+}
+
+// Turns a body Rocket could not read into an error naming the problem
+fn unreadable_body(error: rocket::serde::json::Error<'_>) -> ApiError {
+    match error {
+        rocket::serde::json::Error::Io(error) => ApiError::new(Status::BadRequest, format!("Unable to read the request body: {}", error)),
+        rocket::serde::json::Error::Parse(_, error) => ApiError::new(Status::UnprocessableEntity, format!("Unable to read the request body: {}", error)),
+    }
+}
+
+// Reads a field that was given, even as null, as Some so it can be told apart from one left out
+fn present<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    T::deserialize(deserializer).map(Some)
+}
+
+#[catch(default)]
+fn default_catcher(status: Status, _request: &Request) -> (Status, Json<ErrorBody>) {
+    let error: ApiError = ApiError::new(status, match status.code {
+        404 => "Nothing is at this path",
+        422 => "A value in the path or query could not be read",
+        _ => "The request could not be handled",
+    });
+    (status, Json(error.body()))
+}
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Actor {
+    type Error = ApiError;
+
+    /// Names the actor after the `X-Actor` header, falling back on the client address<br>
+    /// The header is taken at its word, it labels the audit entry and does not authenticate anyone
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let source: Source = match request.headers().get_one(SOURCE_HEADER) {
+            Some(name) => match Source::parse(name) {
+                Ok(source) => source,
+                Err(error) => return Outcome::Error((Status::BadRequest, ApiError::new(Status::BadRequest, error.to_string()))),
+            },
+            None => Source::Api,
+        };
+        let name: String = match request.headers().get_one(ACTOR_HEADER).map(str::trim).filter(|name| !name.is_empty()) {
+            Some(name) => name.to_string(),
+            None => request.client_ip().map(|address| address.to_string()).unwrap_or_else(|| "unknown".to_string()),
+        };
+        Outcome::Success(Actor::new(name, source))
+    }
+}
+
+/// The admin token from the configuration, kept only as its hash. Without one nobody gets past `Admin`
+#[derive(Debug, Clone, Default)]
+pub struct AdminToken(Option<String>);
+
+impl AdminToken {
+    pub fn new(token: Option<&str>) -> AdminToken {
+        AdminToken(token.map(str::trim).filter(|token| !token.is_empty()).map(tokens::hash))
+    }
+
+    fn accepts(&self, token: &str) -> bool {
+        self.0.as_deref() == Some(tokens::hash(token).as_str())
+    }
+}
+
+/// A request that carried the admin token
+#[derive(Debug, Clone, Copy)]
+pub struct Admin;
+
+#[rocket::async_trait]
+impl<'r> FromRequest<'r> for Admin {
+    type Error = ApiError;
+
+    /// Lets the request through when its `Authorization` header is `Bearer` and the configured admin token
+    async fn from_request(request: &'r Request<'_>) -> Outcome<Self, Self::Error> {
+        let refuse = |status: Status, message: &str| Outcome::Error((status, ApiError::new(status, message)));
+        let admin_token: &AdminToken = match request.rocket().state::<AdminToken>() {
+            Some(admin_token) if admin_token.0.is_some() => admin_token,
+            _ => return refuse(Status::Forbidden, "Admin routes are turned off until an admin_token is configured"),
+        };
+        match request.headers().get_one(AUTHORIZATION_HEADER).and_then(|value| value.strip_prefix("Bearer ")) {
+            Some(token) if admin_token.accepts(token.trim()) => Outcome::Success(Admin),
+            _ => refuse(Status::Unauthorized, "This route needs the admin token as a bearer token"),
+        }
+    }
+}
+
+/// The admin token `test_client` is configured with
+#[cfg(test)]
+pub const TEST_ADMIN_TOKEN: &str = "test-admin-token";
+
+/// The header that passes `Admin` on a `test_client`
+#[cfg(test)]
+pub fn admin_header() -> rocket::http::Header<'static> {
+    rocket::http::Header::new(AUTHORIZATION_HEADER, format!("Bearer {}", TEST_ADMIN_TOKEN))
+}
+
+/// A Rocket instance with the API mounted on a fresh test database
+#[cfg(test)]
+pub async fn test_client() -> rocket::local::asynchronous::Client {
+    let db: sea_orm::DatabaseConnection = crate::dbman::test_connection().await;
+    let rocket = rocket::build().manage(db).manage(crate::units::UnitSystem::Metric).manage(AdminToken::new(Some(TEST_ADMIN_TOKEN)))
+        .mount(BASE, routes()).register(BASE, catchers());
+    rocket::local::asynchronous::Client::tracked(rocket).await.unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header};
+    use rocket::local::asynchronous::LocalResponse;
+
+    #[test]
+    fn repo_errors_map_to_statuses() {
+        assert_eq!(ApiError::from(RepoError::NotFound { entity: "zone", id: 3 }).status, Status::NotFound);
+        assert_eq!(ApiError::from(RepoError::MissingReference { field: "comType", id: 3 }).status, Status::UnprocessableEntity);
+        let hidden: ApiError = ApiError::from(RepoError::Db(sea_orm::DbErr::Custom("secret detail".to_string())));
+        assert_eq!(hidden.status, Status::InternalServerError);
+        assert!(!hidden.message.contains("secret"));
+    }
+
+    #[tokio::test]
+    async fn unknown_paths_and_sources_answer_in_json() {
+        let client: rocket::local::asynchronous::Client = test_client().await;
+        let response: LocalResponse = client.get(format!("{}/nothing-here", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        let body: serde_json::Value = response.into_json().await.unwrap();
+        assert_eq!(body["status"], 404);
+
+        let response: LocalResponse = client.post(format!("{}/zones", BASE)).header(ContentType::JSON).header(Header::new(SOURCE_HEADER, "carrier pigeon"))
+            .body(r#"{"name": "Loft"}"#).dispatch().await;
+        assert_eq!(response.status(), Status::BadRequest);
+        assert_eq!(response.into_json::<serde_json::Value>().await.unwrap()["error"], "Bad Request");
+    }
+
+    #[tokio::test]
+    async fn admin_routes_need_the_configured_token() {
+        let client: rocket::local::asynchronous::Client = test_client().await;
+        let response: LocalResponse = client.get(format!("{}/backup", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        let response: LocalResponse = client.get(format!("{}/backup", BASE)).header(Header::new(AUTHORIZATION_HEADER, "Bearer guess"))
+            .dispatch().await;
+        assert_eq!(response.status(), Status::Unauthorized);
+        assert_eq!(client.get(format!("{}/backup", BASE)).header(admin_header()).dispatch().await.status(), Status::Ok);
+
+        let db: sea_orm::DatabaseConnection = crate::dbman::test_connection().await;
+        let rocket = rocket::build().manage(db).manage(crate::units::UnitSystem::Metric).manage(AdminToken::new(Some("  ")))
+            .mount(BASE, routes()).register(BASE, catchers());
+        let client: rocket::local::asynchronous::Client = rocket::local::asynchronous::Client::tracked(rocket).await.unwrap();
+        let response: LocalResponse = client.get(format!("{}/backup", BASE)).header(admin_header()).dispatch().await;
+        assert_eq!(response.status(), Status::Forbidden);
+        assert_eq!(response.into_json::<serde_json::Value>().await.unwrap()["status"], 403);
+    }
+}
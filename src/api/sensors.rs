@@ -0,0 +1,232 @@
+//! `/sensors`, the devices that report conditions in a zone<br>
+//! Registering a sensor or rotating its token answers with the plain token once, next to the sensor. Only its hash is
+//! kept, so a sensor is never served with its token afterwards and a lost token can only be replaced
+
+use rocket::{Route, State};
+use rocket::response::status::Created;
+use rocket::serde::json::Json;
+use sea_orm::DatabaseConnection;
+use serde_derive::{Deserialize, Serialize};
+use crate::requested_units;
+use crate::repo::audit::Actor;
+use crate::repo::sensors::{self as sensor_repo, IssuedSensor, NewSensor, SensorUpdate};
+use crate::schema::sensors;
+use crate::units::UnitSystem;
+use super::{ApiError, BASE, present, unreadable_body};
+
+pub fn routes() -> Vec<Route> {
+    routes![list, get, create, update, deactivate, reactivate, rotate_token]
+}
+
+/// A sensor as the API hands it out, without its token and with its temperature in one unit system
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorReport {
+    pub id: i32,
+    pub name: String,
+    pub active: bool,
+    pub zone: Option<i32>,
+    pub com_type: i32,
+    pub time_added: chrono::NaiveDateTime,
+    pub time_updated: Option<chrono::NaiveDateTime>,
+    pub com_last: Option<chrono::NaiveDateTime>,
+    pub units: UnitSystem,
+    pub current_temp: Option<f64>,
+    pub current_humid: Option<i32>,
+    pub presence: Option<bool>,
+    pub threshold_open: Option<bool>,
+}
+
+impl SensorReport {
+    pub fn new(sensor: sensors::Model, units: UnitSystem) -> SensorReport {
+        SensorReport {
+            id: sensor.id,
+            name: sensor.name,
+            active: sensor.active,
+            zone: sensor.associated_zone,
+            com_type: sensor.com_type,
+            time_added: sensor.time_added,
+            time_updated: sensor.time_updated,
+            com_last: sensor.com_last,
+            units,
+            current_temp: sensor.current_temp.map(|temperature| temperature.in_units(units)),
+            current_humid: sensor.current_humid,
+            presence: sensor.presence,
+            threshold_open: sensor.threshold_open,
+        }
+    }
+}
+
+/// A sensor with the token it was just issued, the only response that carries one
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct IssuedToken {
+    pub sensor: SensorReport,
+    pub token: String,
+}
+
+impl IssuedToken {
+    fn new(issued: IssuedSensor, units: UnitSystem) -> IssuedToken {
+        IssuedToken { sensor: SensorReport::new(issued.sensor, units), token: issued.token }
+    }
+}
+
+/// The body that registers a sensor
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorBody {
+    pub name: String,
+    #[serde(default)]
+    pub zone: Option<i32>,
+    pub com_type: i32,
+}
+
+/// The body that changes a sensor, fields left out stay as they are and a `zone` of null takes it out of its zone
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SensorPatch {
+    pub name: Option<String>,
+    #[serde(default, deserialize_with = "present")]
+    pub zone: Option<Option<i32>>,
+    pub com_type: Option<i32>,
+}
+
+#[get("/sensors?<inactive>&<units>")]
+async fn list(inactive: Option<bool>, units: Option<&str>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<Vec<SensorReport>>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    let sensors: Vec<sensors::Model> = sensor_repo::list(db, inactive.unwrap_or(false)).await?;
+    Ok(Json(sensors.into_iter().map(|sensor| SensorReport::new(sensor, units)).collect()))
+}
+
+#[get("/sensors/<id>?<units>")]
+async fn get(id: i32, units: Option<&str>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<SensorReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    Ok(Json(SensorReport::new(sensor_repo::get(db, id).await?, units)))
+}
+
+#[post("/sensors?<units>", data = "<body>")]
+async fn create(units: Option<&str>, body: Result<Json<SensorBody>, rocket::serde::json::Error<'_>>, actor: Result<Actor, ApiError>,
+    db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Created<Json<IssuedToken>>, ApiError> {
+    let (body, actor) = (body.map_err(unreadable_body)?.into_inner(), actor?);
+    let units: UnitSystem = requested_units(units, preference);
+    let issued: IssuedSensor = sensor_repo::create(db, &actor, NewSensor { name: body.name, associated_zone: body.zone, com_type: body.com_type })
+        .await?;
+    let location: String = format!("{}/sensors/{}", BASE, issued.sensor.id);
+    Ok(Created::new(location).body(Json(IssuedToken::new(issued, units))))
+}
+
+#[patch("/sensors/<id>?<units>", data = "<body>")]
+async fn update(id: i32, units: Option<&str>, body: Result<Json<SensorPatch>, rocket::serde::json::Error<'_>>, actor: Result<Actor, ApiError>,
+    db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<SensorReport>, ApiError> {
+    let (body, actor) = (body.map_err(unreadable_body)?.into_inner(), actor?);
+    let units: UnitSystem = requested_units(units, preference);
+    let changes: SensorUpdate = SensorUpdate { name: body.name, associated_zone: body.zone, com_type: body.com_type };
+    Ok(Json(SensorReport::new(sensor_repo::update(db, &actor, id, changes).await?, units)))
+}
+
+/// Stops a sensor's token from being accepted, its readings stay where they are
+#[post("/sensors/<id>/deactivate?<units>")]
+async fn deactivate(id: i32, units: Option<&str>, actor: Result<Actor, ApiError>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<SensorReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    Ok(Json(SensorReport::new(sensor_repo::deactivate(db, &actor?, id).await?, units)))
+}
+
+#[post("/sensors/<id>/reactivate?<units>")]
+async fn reactivate(id: i32, units: Option<&str>, actor: Result<Actor, ApiError>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<SensorReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    Ok(Json(SensorReport::new(sensor_repo::reactivate(db, &actor?, id).await?, units)))
+}
+
+/// Issues a new token, the old one stops working straight away
+#[post("/sensors/<id>/token?<units>")]
+async fn rotate_token(id: i32, units: Option<&str>, actor: Result<Actor, ApiError>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<IssuedToken>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    Ok(Json(IssuedToken::new(sensor_repo::rotate_token(db, &actor?, id).await?, units)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+    use crate::api::{ACTOR_HEADER, test_client};
+    use crate::repo::audit::{self, AuditFilter};
+
+    async fn post_sensor<'c>(client: &'c Client, body: &str) -> LocalResponse<'c> {
+        client.post(format!("{}/sensors", BASE)).header(ContentType::JSON).header(Header::new(ACTOR_HEADER, "installer"))
+            .body(body).dispatch().await
+    }
+
+    #[tokio::test]
+    async fn the_token_is_shown_once_and_only_its_hash_kept() {
+        let client: Client = test_client().await;
+        let response: LocalResponse = post_sensor(&client, r#"{"name": "Hall", "com_type": 1}"#).await;
+        assert_eq!(response.status(), Status::Created);
+        let location: String = response.headers().get_one("Location").unwrap().to_string();
+        let created: Value = response.into_json().await.unwrap();
+        let token: &str = created["token"].as_str().unwrap();
+        assert_eq!(location, format!("{}/sensors/{}", BASE, created["sensor"]["id"]));
+
+        let fetched: Value = client.get(location).dispatch().await.into_json().await.unwrap();
+        assert_eq!(fetched["name"], "Hall");
+        assert!(fetched.get("token").is_none());
+        let db: &DatabaseConnection = client.rocket().state::<DatabaseConnection>().unwrap();
+        let sensor: sensors::Model = sensor_repo::authenticate(db, token).await.unwrap().unwrap();
+        assert_ne!(sensor.token, token);
+        let entries = audit::query(db, AuditFilter { actor: Some("installer".to_string()), ..AuditFilter::default() }).await.unwrap();
+        assert!(!entries[0].after.as_ref().unwrap().to_string().contains(token));
+    }
+
+    #[tokio::test]
+    async fn rotating_replaces_the_token() {
+        let client: Client = test_client().await;
+        let created: Value = post_sensor(&client, r#"{"name": "Porch", "com_type": 1}"#).await.into_json().await.unwrap();
+        let response: LocalResponse = client.post(format!("{}/sensors/{}/token", BASE, created["sensor"]["id"])).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let rotated: Value = response.into_json().await.unwrap();
+        assert_ne!(rotated["token"], created["token"]);
+
+        let db: &DatabaseConnection = client.rocket().state::<DatabaseConnection>().unwrap();
+        assert!(sensor_repo::authenticate(db, created["token"].as_str().unwrap()).await.unwrap().is_none());
+        assert!(sensor_repo::authenticate(db, rotated["token"].as_str().unwrap()).await.unwrap().is_some());
+        let response: LocalResponse = client.post(format!("{}/sensors/77/token", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+    }
+
+    #[tokio::test]
+    async fn update_and_deactivate_change_what_the_list_shows() {
+        let client: Client = test_client().await;
+        let zone: Value = client.post(format!("{}/zones", BASE)).header(ContentType::JSON).body(r#"{"name": "Den"}"#).dispatch().await
+            .into_json().await.unwrap();
+        let kept: Value = post_sensor(&client, &format!(r#"{{"name": "Shelf", "zone": {}, "com_type": 1}}"#, zone["id"])).await
+            .into_json().await.unwrap();
+        let dropped: Value = post_sensor(&client, r#"{"name": "Shed", "com_type": 1}"#).await.into_json().await.unwrap();
+        assert_eq!(kept["sensor"]["zone"], zone["id"]);
+
+        // Leaving zone out keeps it, null takes the sensor out of its zone
+        let response: LocalResponse = client.patch(format!("{}/sensors/{}", BASE, kept["sensor"]["id"])).header(ContentType::JSON)
+            .body(r#"{"name": "Mantel"}"#).dispatch().await;
+        let updated: Value = response.into_json().await.unwrap();
+        assert_eq!((updated["name"].clone(), updated["zone"].clone()), (Value::from("Mantel"), zone["id"].clone()));
+        let response: LocalResponse = client.patch(format!("{}/sensors/{}", BASE, kept["sensor"]["id"])).header(ContentType::JSON)
+            .body(r#"{"zone": null}"#).dispatch().await;
+        assert_eq!(response.into_json::<Value>().await.unwrap()["zone"], Value::Null);
+        let response: LocalResponse = client.patch(format!("{}/sensors/{}", BASE, kept["sensor"]["id"])).header(ContentType::JSON)
+            .body(r#"{"token": "chosen"}"#).dispatch().await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        let response: LocalResponse = post_sensor(&client, r#"{"name": "Loft", "com_type": 77}"#).await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response: LocalResponse = client.post(format!("{}/sensors/{}/deactivate", BASE, dropped["sensor"]["id"])).dispatch().await;
+        assert_eq!(response.into_json::<Value>().await.unwrap()["active"], false);
+        let listed: Vec<Value> = client.get(format!("{}/sensors", BASE)).dispatch().await.into_json().await.unwrap();
+        assert_eq!(listed.iter().map(|sensor| sensor["name"].clone()).collect::<Vec<Value>>(), vec![Value::from("Mantel")]);
+        let listed: Vec<Value> = client.get(format!("{}/sensors?inactive=true", BASE)).dispatch().await.into_json().await.unwrap();
+        assert_eq!(listed.len(), 2);
+        let response: LocalResponse = client.post(format!("{}/sensors/{}/reactivate", BASE, dropped["sensor"]["id"])).dispatch().await;
+        assert_eq!(response.into_json::<Value>().await.unwrap()["active"], true);
+    }
+}
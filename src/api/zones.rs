@@ -0,0 +1,208 @@
+//! `/zones`, the rooms or areas the thermostat looks after<br>
+//! A zone is served with its EnvCapability and HVACactivity rows and how many active sensors and controllers it has.
+//! Those rows are created with the zone and belong to it, so a body never names them. Fields a body should not have
+//! are refused rather than ignored
+
+use rocket::{Route, State};
+use rocket::response::status::Created;
+use rocket::serde::json::Json;
+use sea_orm::DatabaseConnection;
+use serde_derive::{Deserialize, Serialize};
+use crate::requested_units;
+use crate::repo::audit::Actor;
+use crate::repo::zones::{self as zone_repo, NewZone, ZoneOverview, ZoneUpdate};
+use crate::schema::{env_capability, hva_cactivity};
+use crate::units::UnitSystem;
+use super::{ApiError, BASE, unreadable_body};
+
+pub fn routes() -> Vec<Route> {
+    routes![list, get, create, update, deactivate, reactivate]
+}
+
+/// A zone as the API hands it out, with its temperature in one unit system
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ZoneReport {
+    pub id: i32,
+    pub name: String,
+    pub active: bool,
+    pub time_added: chrono::NaiveDateTime,
+    pub last_changed: Option<chrono::NaiveDateTime>,
+    pub units: UnitSystem,
+    pub current_temp: Option<f64>,
+    pub current_humid: Option<i32>,
+    pub presence: Option<bool>,
+    pub thresholds_closed: Option<bool>,
+    pub capability: env_capability::Model,
+    pub activity: hva_cactivity::Model,
+    pub sensor_count: u64,
+    pub controller_count: u64,
+}
+
+impl ZoneReport {
+    pub fn new(overview: ZoneOverview, units: UnitSystem) -> ZoneReport {
+        let zone = overview.zone;
+        ZoneReport {
+            id: zone.id,
+            name: zone.name,
+            active: zone.active,
+            time_added: zone.time_added,
+            last_changed: zone.last_changed,
+            units,
+            current_temp: zone.current_temp.map(|temperature| temperature.in_units(units)),
+            current_humid: zone.current_humid,
+            presence: zone.presence,
+            thresholds_closed: zone.thresholds_closed,
+            capability: overview.capability,
+            activity: overview.activity,
+            sensor_count: overview.sensor_count,
+            controller_count: overview.controller_count,
+        }
+    }
+}
+
+/// The body that creates a zone
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZoneBody {
+    pub name: String,
+    #[serde(default)]
+    pub heating: bool,
+    #[serde(default)]
+    pub cooling: bool,
+}
+
+/// The body that changes a zone, fields left out stay as they are
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ZonePatch {
+    pub name: Option<String>,
+    pub heating: Option<bool>,
+    pub cooling: Option<bool>,
+}
+
+#[get("/zones?<inactive>&<units>")]
+async fn list(inactive: Option<bool>, units: Option<&str>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<Vec<ZoneReport>>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    let overviews: Vec<ZoneOverview> = zone_repo::overviews(db, inactive.unwrap_or(false)).await?;
+    Ok(Json(overviews.into_iter().map(|overview| ZoneReport::new(overview, units)).collect()))
+}
+
+#[get("/zones/<id>?<units>")]
+async fn get(id: i32, units: Option<&str>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<ZoneReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    Ok(Json(ZoneReport::new(zone_repo::overview(db, id).await?, units)))
+}
+
+#[post("/zones?<units>", data = "<body>")]
+async fn create(units: Option<&str>, body: Result<Json<ZoneBody>, rocket::serde::json::Error<'_>>, actor: Result<Actor, ApiError>,
+    db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Created<Json<ZoneReport>>, ApiError> {
+    let (body, actor) = (body.map_err(unreadable_body)?.into_inner(), actor?);
+    let units: UnitSystem = requested_units(units, preference);
+    let zone: i32 = zone_repo::create(db, &actor, NewZone { name: body.name, heating: body.heating, cooling: body.cooling }).await?.id;
+    let report: ZoneReport = ZoneReport::new(zone_repo::overview(db, zone).await?, units);
+    Ok(Created::new(format!("{}/zones/{}", BASE, zone)).body(Json(report)))
+}
+
+#[patch("/zones/<id>?<units>", data = "<body>")]
+async fn update(id: i32, units: Option<&str>, body: Result<Json<ZonePatch>, rocket::serde::json::Error<'_>>, actor: Result<Actor, ApiError>,
+    db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<ZoneReport>, ApiError> {
+    let (body, actor) = (body.map_err(unreadable_body)?.into_inner(), actor?);
+    let units: UnitSystem = requested_units(units, preference);
+    zone_repo::update(db, &actor, id, ZoneUpdate { name: body.name, heating: body.heating, cooling: body.cooling }).await?;
+    Ok(Json(ZoneReport::new(zone_repo::overview(db, id).await?, units)))
+}
+
+/// Switches a zone off, its devices and history stay where they are
+#[post("/zones/<id>/deactivate?<units>")]
+async fn deactivate(id: i32, units: Option<&str>, actor: Result<Actor, ApiError>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<ZoneReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    zone_repo::deactivate(db, &actor?, id).await?;
+    Ok(Json(ZoneReport::new(zone_repo::overview(db, id).await?, units)))
+}
+
+#[post("/zones/<id>/reactivate?<units>")]
+async fn reactivate(id: i32, units: Option<&str>, actor: Result<Actor, ApiError>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>)
+    -> Result<Json<ZoneReport>, ApiError> {
+    let units: UnitSystem = requested_units(units, preference);
+    zone_repo::reactivate(db, &actor?, id).await?;
+    Ok(Json(ZoneReport::new(zone_repo::overview(db, id).await?, units)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rocket::http::{ContentType, Header, Status};
+    use rocket::local::asynchronous::{Client, LocalResponse};
+    use serde_json::Value;
+    use crate::api::{ACTOR_HEADER, test_client};
+    use crate::repo::audit::{self, AuditFilter};
+    use crate::repo::sensors::{self as sensor_repo, NewSensor};
+
+    async fn post_zone<'c>(client: &'c Client, body: &str) -> LocalResponse<'c> {
+        client.post(format!("{}/zones", BASE)).header(ContentType::JSON).header(Header::new(ACTOR_HEADER, "panel"))
+            .body(body).dispatch().await
+    }
+
+    #[tokio::test]
+    async fn create_then_get_embeds_capability_and_counts() {
+        let client: Client = test_client().await;
+        let response: LocalResponse = post_zone(&client, r#"{"name": "Kitchen", "heating": true}"#).await;
+        assert_eq!(response.status(), Status::Created);
+        let location: String = response.headers().get_one("Location").unwrap().to_string();
+        let created: Value = response.into_json().await.unwrap();
+        assert_eq!(location, format!("{}/zones/{}", BASE, created["id"]));
+        assert_eq!((created["capability"]["heating"].clone(), created["capability"]["cooling"].clone()), (Value::Bool(true), Value::Bool(false)));
+        assert_eq!(created["activity"]["heating"], false);
+
+        let db: &DatabaseConnection = client.rocket().state::<DatabaseConnection>().unwrap();
+        let zone: i32 = created["id"].as_i64().unwrap() as i32;
+        sensor_repo::create(db, &audit::test_actor(), NewSensor { name: "Stove".to_string(),
+            associated_zone: Some(zone), com_type: 1 }).await.unwrap();
+        let fetched: Value = client.get(location).dispatch().await.into_json().await.unwrap();
+        assert_eq!((fetched["sensor_count"].clone(), fetched["controller_count"].clone()), (Value::from(1), Value::from(0)));
+        let entries = audit::query(db, AuditFilter { actor: Some("panel".to_string()), ..AuditFilter::default() }).await.unwrap();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn bad_bodies_get_json_errors() {
+        let client: Client = test_client().await;
+        let response: LocalResponse = post_zone(&client, r#"{"name": " "}"#).await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert_eq!(response.into_json::<Value>().await.unwrap()["message"], "Invalid request: name cannot be empty");
+        // A zone's capability is its own, a body cannot point it at another one
+        let response: LocalResponse = post_zone(&client, r#"{"name": "Loft", "capability": 1}"#).await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+        assert!(response.into_json::<Value>().await.unwrap()["message"].as_str().unwrap().contains("capability"));
+        let response: LocalResponse = post_zone(&client, "{not json").await;
+        assert_eq!(response.status(), Status::UnprocessableEntity);
+
+        let response: LocalResponse = client.get(format!("{}/zones/42", BASE)).dispatch().await;
+        assert_eq!(response.status(), Status::NotFound);
+        assert_eq!(response.into_json::<Value>().await.unwrap()["message"], "No zone with id 42");
+    }
+
+    #[tokio::test]
+    async fn update_and_deactivate_change_what_the_list_shows() {
+        let client: Client = test_client().await;
+        let kept: Value = post_zone(&client, r#"{"name": "Den"}"#).await.into_json().await.unwrap();
+        let dropped: Value = post_zone(&client, r#"{"name": "Attic"}"#).await.into_json().await.unwrap();
+
+        let response: LocalResponse = client.patch(format!("{}/zones/{}", BASE, kept["id"])).header(ContentType::JSON)
+            .body(r#"{"name": "Study", "cooling": true}"#).dispatch().await;
+        assert_eq!(response.status(), Status::Ok);
+        let updated: Value = response.into_json().await.unwrap();
+        assert_eq!((updated["name"].clone(), updated["capability"]["cooling"].clone()), (Value::from("Study"), Value::Bool(true)));
+
+        let response: LocalResponse = client.post(format!("{}/zones/{}/deactivate", BASE, dropped["id"])).dispatch().await;
+        assert_eq!(response.into_json::<Value>().await.unwrap()["active"], false);
+        let listed: Vec<Value> = client.get(format!("{}/zones", BASE)).dispatch().await.into_json().await.unwrap();
+        assert_eq!(listed.iter().map(|zone| zone["name"].clone()).collect::<Vec<Value>>(), vec![Value::from("Study")]);
+        let listed: Vec<Value> = client.get(format!("{}/zones?inactive=true", BASE)).dispatch().await.into_json().await.unwrap();
+        assert_eq!(listed.len(), 2);
+        let response: LocalResponse = client.post(format!("{}/zones/{}/reactivate", BASE, dropped["id"])).dispatch().await;
+        assert_eq!(response.into_json::<Value>().await.unwrap()["active"], true);
+    }
+}
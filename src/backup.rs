@@ -0,0 +1,661 @@
+//! # Rusty Thermostat Backup
+//! The whole configuration in one versioned JSON archive: zones, sensors, controllers, schedules, alerts, the home
+//! summary and the lookup tables they point at. The history tables can ride along, but they are optional since
+//! they are most of the database<br>
+//! A restore checks the archive on its own before anything is touched, then replaces the configuration in a single
+//! transaction. Rows keep the ids they had, so history left in place still points at the right sensor, schedule and
+//! change source. When the archive has no history, the restore refuses to drop anything the stored history points at.
+//! The audit log is never part of an archive and always stays, so the same goes for the change sources it points at<br>
+//! Forecasts are not kept, the collector fetches them again<br>
+//! Sensor and controller tokens are only ever stored as their hash, so an archive never holds a usable token and a
+//! device keeps the token it has on whichever database the archive is restored to. Tokens an older archive still has
+//! in plain text are hashed as they are restored<br>
+//! An archive replaces everything, so taking and restoring one is left to the `backup` and `restore` commands and the
+//! admin routes of the API
+
+use std::collections::HashSet;
+use std::fmt;
+use std::hash::Hash;
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbBackend, DbErr,
+    EntityName, EntityTrait, IdenStatic, IntoActiveModel, Iterable, QueryFilter, QueryOrder, QuerySelect, Statement,
+    TransactionTrait};
+use sea_orm::sea_query::OnConflict;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::repo::tokens;
+use crate::schema::{alerts, audit_log, change_source, communication, controllers, daily_degree_days, env_capability, home_summary,
+    hva_cactivity, manual_change_history, pollution_reading, schedules, sensor_reading_history, sensor_reading_rollup,
+    sensors, weather_reading, weather_reading_rollup, weekdays, zones};
+
+/// The archive layout this build writes and reads
+pub const ARCHIVE_VERSION: u32 = 2;
+// Version 1 keyed the history tables on integers. Its configuration is laid out the same, so it is read when it has
+// no history
+const CONFIG_ONLY_VERSION: u32 = 1;
+// Rows per insert, well under the bind parameter limits of both backends
+const INSERT_CHUNK: usize = 500;
+
+/// Everything that can go wrong taking or restoring a backup
+#[derive(Debug)]
+pub enum BackupError {
+    /// The archive was written by a layout this build does not read
+    UnsupportedVersion(u32),
+    /// The archive breaks a rule of its own, like two rows with one id
+    Invalid(String),
+    /// A row in the archive points at a row the archive does not have
+    MissingReference { table: &'static str, id: String, field: &'static str, value: String },
+    /// History that is being kept points at a row the archive would remove
+    HistoryReference { table: &'static str, field: &'static str, value: i32 },
+    /// The audit log points at a change source the archive would remove
+    AuditReference(i32),
+    Json(serde_json::Error),
+    Io(std::io::Error),
+    Db(DbErr),
+}
+
+impl fmt::Display for BackupError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BackupError::UnsupportedVersion(version) => write!(f, "Archive version {} is not supported, expected {}", version, ARCHIVE_VERSION),
+            BackupError::Invalid(reason) => write!(f, "Invalid archive: {}", reason),
+            BackupError::MissingReference { table, id, field, value } => write!(f, "{} {} has a {} of {} that is not in the archive", table, id, field, value),
+            BackupError::HistoryReference { table, field, value } => write!(f, "Stored {} rows have a {} of {} that is not in the archive, restore with history to replace them", table, field, value),
+            BackupError::AuditReference(value) => write!(f, "The audit log has entries from change source {} that is not in the archive", value),
+            BackupError::Json(error) => write!(f, "Unable to read the archive: {}", error),
+            BackupError::Io(error) => write!(f, "Unable to read or write the archive: {}", error),
+            BackupError::Db(error) => write!(f, "Database error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for BackupError {}
+
+impl From<DbErr> for BackupError {
+    fn from(error: DbErr) -> Self {
+        BackupError::Db(error)
+    }
+}
+
+impl From<std::io::Error> for BackupError {
+    fn from(error: std::io::Error) -> Self {
+        BackupError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for BackupError {
+    fn from(error: serde_json::Error) -> Self {
+        BackupError::Json(error)
+    }
+}
+
+/// One backup, the configuration and optionally the history
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    pub version: u32,
+    pub created: NaiveDateTime,
+    /// The version of the application that wrote the archive
+    pub app_version: String,
+    pub config: ConfigTables,
+    #[serde(default)]
+    pub history: Option<HistoryTables>,
+}
+
+/// Every row of the configuration tables
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConfigTables {
+    pub change_sources: Vec<change_source::Model>,
+    pub communication: Vec<communication::Model>,
+    pub weekdays: Vec<weekdays::Model>,
+    pub env_capabilities: Vec<env_capability::Model>,
+    pub hvac_activities: Vec<hva_cactivity::Model>,
+    pub home_summary: Vec<home_summary::Model>,
+    pub zones: Vec<zones::Model>,
+    pub sensors: Vec<sensors::Model>,
+    pub controllers: Vec<controllers::Model>,
+    pub schedules: Vec<schedules::Model>,
+    pub alerts: Vec<alerts::Model>,
+}
+
+/// Every row of the history tables
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HistoryTables {
+    pub weather: Vec<weather_reading::Model>,
+    pub pollution: Vec<pollution_reading::Model>,
+    pub sensor_readings: Vec<sensor_reading_history::Model>,
+    pub manual_changes: Vec<manual_change_history::Model>,
+    pub sensor_rollups: Vec<sensor_reading_rollup::Model>,
+    pub weather_rollups: Vec<weather_reading_rollup::Model>,
+    pub degree_days: Vec<daily_degree_days::Model>,
+}
+
+impl HistoryTables {
+    fn row_count(&self) -> usize {
+        self.weather.len() + self.pollution.len() + self.sensor_readings.len() + self.manual_changes.len()
+            + self.sensor_rollups.len() + self.weather_rollups.len() + self.degree_days.len()
+    }
+}
+
+/// What a restore wrote
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct RestoreReport {
+    pub config_rows: usize,
+    /// None when the archive had no history and the stored history was kept
+    pub history_rows: Option<usize>,
+}
+
+// Only the version is read first, so an archive from another layout says so instead of failing on a field
+#[derive(Deserialize)]
+struct VersionProbe {
+    version: u32,
+    #[serde(default)]
+    history: Option<serde::de::IgnoredAny>,
+}
+
+impl Archive {
+    /// Reads an archive and checks it with `validate`
+    /// # Errors
+    /// JSON that is not an archive, an unsupported version or an archive that fails validation
+    pub fn from_json(input: &[u8]) -> Result<Archive, BackupError> {
+        let probe: VersionProbe = serde_json::from_slice(input)?;
+        let readable: bool = probe.version == ARCHIVE_VERSION || (probe.version == CONFIG_ONLY_VERSION && probe.history.is_none());
+        if !readable {
+            return Err(BackupError::UnsupportedVersion(probe.version))
+        }
+        let mut archive: Archive = serde_json::from_slice(input)?;
+        archive.version = ARCHIVE_VERSION;
+        archive.validate()?;
+        Ok(archive)
+    }
+
+    /// Checks the archive against itself: the version, that ids are unique and that every foreign key points at a
+    /// row in the archive<br>
+    /// # Errors
+    /// The first problem found
+    pub fn validate(&self) -> Result<(), BackupError> {
+        if self.version != ARCHIVE_VERSION {
+            return Err(BackupError::UnsupportedVersion(self.version))
+        }
+        let config: &ConfigTables = &self.config;
+        // Seeding adds the home summary to a database that has none, so an archive taken before that may lack it
+        if config.home_summary.len() > 1 {
+            return Err(BackupError::Invalid(format!("expected at most one home summary, found {}", config.home_summary.len())))
+        }
+        let change_sources: HashSet<i32> = unique_ids("ChangeSource", config.change_sources.iter().map(|row| row.id))?;
+        let communication: HashSet<i32> = unique_ids("Communication", config.communication.iter().map(|row| row.id))?;
+        let weekdays: HashSet<i32> = unique_ids("Weekdays", config.weekdays.iter().map(|row| row.id))?;
+        let capabilities: HashSet<i32> = unique_ids("EnvCapability", config.env_capabilities.iter().map(|row| row.id))?;
+        let activities: HashSet<i32> = unique_ids("HVACactivity", config.hvac_activities.iter().map(|row| row.id))?;
+        let zones: HashSet<i32> = unique_ids("Zones", config.zones.iter().map(|row| row.id))?;
+        let sensors: HashSet<i32> = unique_ids("Sensors", config.sensors.iter().map(|row| row.id))?;
+        unique_ids("Controllers", config.controllers.iter().map(|row| row.id))?;
+        let schedules: HashSet<i32> = unique_ids("Schedules", config.schedules.iter().map(|row| row.id))?;
+        unique_ids("Alerts", config.alerts.iter().map(|row| row.id))?;
+
+        for row in &config.home_summary {
+            check_reference("HomeSummary", row.id, "capability", Some(row.capability), &capabilities)?;
+            check_reference("HomeSummary", row.id, "systemActive", Some(row.system_active), &activities)?;
+        }
+        for row in &config.zones {
+            check_reference("Zones", row.id, "capability", Some(row.capability), &capabilities)?;
+            check_reference("Zones", row.id, "systemActive", Some(row.system_active), &activities)?;
+        }
+        for row in &config.sensors {
+            check_reference("Sensors", row.id, "associatedZone", row.associated_zone, &zones)?;
+            check_reference("Sensors", row.id, "comType", Some(row.com_type), &communication)?;
+        }
+        for row in &config.controllers {
+            check_reference("Controllers", row.id, "associatedZone", row.associated_zone, &zones)?;
+            check_reference("Controllers", row.id, "comType", Some(row.com_type), &communication)?;
+            check_reference("Controllers", row.id, "capability", Some(row.capability), &capabilities)?;
+            check_reference("Controllers", row.id, "systemActive", Some(row.system_active), &activities)?;
+        }
+        for row in &config.schedules {
+            check_reference("Schedules", row.id, "associatedZone", row.associated_zone, &zones)?;
+            check_reference("Schedules", row.id, "weekDay", row.week_day, &weekdays)?;
+        }
+        for row in &config.alerts {
+            check_reference("Alerts", row.id, "comType", row.com_type, &communication)?;
+            check_reference("Alerts", row.id, "associatedSchedule", row.associated_schedule, &schedules)?;
+            check_reference("Alerts", row.id, "associatedZone", row.associated_zone, &zones)?;
+        }
+
+        if let Some(history) = &self.history {
+            let weather: HashSet<Uuid> = unique_ids("WeatherReading", history.weather.iter().map(|row| row.id))?;
+            let pollution: HashSet<Uuid> = unique_ids("PollutionReading", history.pollution.iter().map(|row| row.id))?;
+            unique_ids("SensorReadingHistory", history.sensor_readings.iter().map(|row| row.id))?;
+            unique_ids("ManualChangeHistory", history.manual_changes.iter().map(|row| row.id))?;
+            unique_ids("SensorReadingRollup", history.sensor_rollups.iter().map(|row| row.id))?;
+            unique_ids("WeatherReadingRollup", history.weather_rollups.iter().map(|row| row.id))?;
+            unique_ids("DailyDegreeDays", history.degree_days.iter().map(|row| row.id))?;
+            for row in &history.sensor_readings {
+                check_reference("SensorReadingHistory", row.id, "sensorID", Some(row.sensor_id), &sensors)?;
+            }
+            for row in &history.sensor_rollups {
+                check_reference("SensorReadingRollup", row.id, "sensorID", Some(row.sensor_id), &sensors)?;
+            }
+            for row in &history.manual_changes {
+                check_reference("ManualChangeHistory", row.id, "changeWeather", Some(row.change_weather), &weather)?;
+                check_reference("ManualChangeHistory", row.id, "changePollution", Some(row.change_pollution), &pollution)?;
+                check_reference("ManualChangeHistory", row.id, "changeSource", Some(row.change_source), &change_sources)?;
+                check_reference("ManualChangeHistory", row.id, "changeSchedule", row.change_schedule, &schedules)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// Collects the ids of one table, refusing any that appear twice
+fn unique_ids<K, I>(table: &str, ids: I) -> Result<HashSet<K>, BackupError>
+where
+    K: Eq + Hash + fmt::Display,
+    I: Iterator<Item = K>,
+{
+    let mut seen: HashSet<K> = HashSet::new();
+    for id in ids {
+        if seen.contains(&id) {
+            return Err(BackupError::Invalid(format!("{} has more than one row with id {}", table, id)))
+        }
+        seen.insert(id);
+    }
+    Ok(seen)
+}
+
+fn check_reference<I, K>(table: &'static str, id: I, field: &'static str, value: Option<K>, known: &HashSet<K>) -> Result<(), BackupError>
+where
+    I: fmt::Display,
+    K: Eq + Hash + fmt::Display,
+{
+    match value {
+        Some(value) if !known.contains(&value) => Err(BackupError::MissingReference { table, id: id.to_string(), field, value: value.to_string() }),
+        _ => Ok(()),
+    }
+}
+
+/// Reads the configuration, and the history when asked, in one transaction so the archive is consistent
+/// # Errors
+/// Any failed query
+pub async fn backup(db: &DatabaseConnection, include_history: bool) -> Result<Archive, DbErr> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let config: ConfigTables = ConfigTables {
+        change_sources: change_source::Entity::find().order_by_asc(change_source::Column::Id).all(&txn).await?,
+        communication: communication::Entity::find().order_by_asc(communication::Column::Id).all(&txn).await?,
+        weekdays: weekdays::Entity::find().order_by_asc(weekdays::Column::Id).all(&txn).await?,
+        env_capabilities: env_capability::Entity::find().order_by_asc(env_capability::Column::Id).all(&txn).await?,
+        hvac_activities: hva_cactivity::Entity::find().order_by_asc(hva_cactivity::Column::Id).all(&txn).await?,
+        home_summary: home_summary::Entity::find().order_by_asc(home_summary::Column::Id).all(&txn).await?,
+        zones: zones::Entity::find().order_by_asc(zones::Column::Id).all(&txn).await?,
+        sensors: sensors::Entity::find().order_by_asc(sensors::Column::Id).all(&txn).await?,
+        controllers: controllers::Entity::find().order_by_asc(controllers::Column::Id).all(&txn).await?,
+        schedules: schedules::Entity::find().order_by_asc(schedules::Column::Id).all(&txn).await?,
+        alerts: alerts::Entity::find().order_by_asc(alerts::Column::Id).all(&txn).await?,
+    };
+    let history: Option<HistoryTables> = match include_history {
+        true => Some(HistoryTables {
+            weather: weather_reading::Entity::find().order_by_asc(weather_reading::Column::Id).all(&txn).await?,
+            pollution: pollution_reading::Entity::find().order_by_asc(pollution_reading::Column::Id).all(&txn).await?,
+            sensor_readings: sensor_reading_history::Entity::find().order_by_asc(sensor_reading_history::Column::Id).all(&txn).await?,
+            manual_changes: manual_change_history::Entity::find().order_by_asc(manual_change_history::Column::Id).all(&txn).await?,
+            sensor_rollups: sensor_reading_rollup::Entity::find().order_by_asc(sensor_reading_rollup::Column::Id).all(&txn).await?,
+            weather_rollups: weather_reading_rollup::Entity::find().order_by_asc(weather_reading_rollup::Column::Id).all(&txn).await?,
+            degree_days: daily_degree_days::Entity::find().order_by_asc(daily_degree_days::Column::Id).all(&txn).await?,
+        }),
+        false => None,
+    };
+    txn.commit().await?;
+    Ok(Archive {
+        version: ARCHIVE_VERSION,
+        created: Utc::now().naive_utc(),
+        app_version: env!("CARGO_PKG_VERSION").to_string(),
+        config,
+        history,
+    })
+}
+
+/// Replaces the configuration with the archive's, and the history too when the archive has it<br>
+/// Rows the configuration points at are written over in place and rows the archive does not have are removed
+/// afterwards, so nothing that stays ever points at a missing row
+/// # Errors
+/// An archive that fails validation, stored history the archive would orphan, or a failed query. Nothing is changed
+/// in any of those cases
+pub async fn restore(db: &DatabaseConnection, archive: Archive) -> Result<RestoreReport, BackupError> {
+    archive.validate()?;
+    let config: ConfigTables = archive.config;
+    let txn: DatabaseTransaction = db.begin().await?;
+    match &archive.history {
+        Some(_) => clear_history(&txn).await?,
+        None => check_kept_history(&txn, &config).await?,
+    }
+    check_kept_audit_log(&txn, &config).await?;
+
+    let sensors: Vec<sensors::Model> = config.sensors.into_iter().map(|mut sensor| {
+        sensor.token = hash_plain_token(sensor.token);
+        sensor
+    }).collect();
+    let controllers: Vec<controllers::Model> = config.controllers.into_iter().map(|mut controller| {
+        controller.token = hash_plain_token(controller.token);
+        controller
+    }).collect();
+    // Nothing points at these, so they are simply written again
+    alerts::Entity::delete_many().exec(&txn).await?;
+    controllers::Entity::delete_many().exec(&txn).await?;
+    home_summary::Entity::delete_many().exec(&txn).await?;
+    // Only rows the archive drops go, kept history may point at the others
+    sensors::Entity::delete_many().filter(sensors::Column::Id.is_not_in(sensors.iter().map(|row| row.id))).exec(&txn).await?;
+    schedules::Entity::delete_many().filter(schedules::Column::Id.is_not_in(config.schedules.iter().map(|row| row.id))).exec(&txn).await?;
+    change_source::Entity::delete_many().filter(change_source::Column::Id.is_not_in(config.change_sources.iter().map(|row| row.id))).exec(&txn).await?;
+
+    let config_rows: usize = config.change_sources.len() + config.communication.len() + config.weekdays.len()
+        + config.env_capabilities.len() + config.hvac_activities.len() + config.home_summary.len() + config.zones.len()
+        + sensors.len() + controllers.len() + config.schedules.len() + config.alerts.len();
+    let zone_ids: Vec<i32> = config.zones.iter().map(|row| row.id).collect();
+    let capability_ids: Vec<i32> = config.env_capabilities.iter().map(|row| row.id).collect();
+    let activity_ids: Vec<i32> = config.hvac_activities.iter().map(|row| row.id).collect();
+    let weekday_ids: Vec<i32> = config.weekdays.iter().map(|row| row.id).collect();
+    let communication_ids: Vec<i32> = config.communication.iter().map(|row| row.id).collect();
+
+    upsert_rows::<communication::ActiveModel>(&txn, config.communication).await?;
+    upsert_rows::<weekdays::ActiveModel>(&txn, config.weekdays).await?;
+    upsert_rows::<env_capability::ActiveModel>(&txn, config.env_capabilities).await?;
+    upsert_rows::<hva_cactivity::ActiveModel>(&txn, config.hvac_activities).await?;
+    upsert_rows::<zones::ActiveModel>(&txn, config.zones).await?;
+    upsert_rows::<change_source::ActiveModel>(&txn, config.change_sources).await?;
+    upsert_rows::<sensors::ActiveModel>(&txn, sensors).await?;
+    upsert_rows::<schedules::ActiveModel>(&txn, config.schedules).await?;
+    insert_rows::<controllers::ActiveModel>(&txn, controllers).await?;
+    insert_rows::<alerts::ActiveModel>(&txn, config.alerts).await?;
+    insert_rows::<home_summary::ActiveModel>(&txn, config.home_summary).await?;
+
+    // With everything pointing at the archive's rows, the rest of the old ones can go
+    zones::Entity::delete_many().filter(zones::Column::Id.is_not_in(zone_ids)).exec(&txn).await?;
+    env_capability::Entity::delete_many().filter(env_capability::Column::Id.is_not_in(capability_ids)).exec(&txn).await?;
+    hva_cactivity::Entity::delete_many().filter(hva_cactivity::Column::Id.is_not_in(activity_ids)).exec(&txn).await?;
+    weekdays::Entity::delete_many().filter(weekdays::Column::Id.is_not_in(weekday_ids)).exec(&txn).await?;
+    communication::Entity::delete_many().filter(communication::Column::Id.is_not_in(communication_ids)).exec(&txn).await?;
+
+    let history_rows: Option<usize> = match archive.history {
+        Some(history) => {
+            let rows: usize = history.row_count();
+            insert_rows::<weather_reading::ActiveModel>(&txn, history.weather).await?;
+            insert_rows::<pollution_reading::ActiveModel>(&txn, history.pollution).await?;
+            insert_rows::<sensor_reading_history::ActiveModel>(&txn, history.sensor_readings).await?;
+            insert_rows::<manual_change_history::ActiveModel>(&txn, history.manual_changes).await?;
+            insert_rows::<sensor_reading_rollup::ActiveModel>(&txn, history.sensor_rollups).await?;
+            insert_rows::<weather_reading_rollup::ActiveModel>(&txn, history.weather_rollups).await?;
+            insert_rows::<daily_degree_days::ActiveModel>(&txn, history.degree_days).await?;
+            Some(rows)
+        },
+        None => None,
+    };
+    if txn.get_database_backend() == DbBackend::Postgres {
+        reset_sequences(&txn).await?;
+    }
+    txn.commit().await?;
+    info!("Restored {} configuration rows from an archive written {}", config_rows, archive.created);
+    Ok(RestoreReport { config_rows, history_rows })
+}
+
+// A token as it is stored, archives written before tokens were hashed carry them in plain text
+fn hash_plain_token(token: String) -> String {
+    match token.starts_with(tokens::HASH_PREFIX) {
+        true => token,
+        false => tokens::hash(&token),
+    }
+}
+
+// Removes every history row, the rows that point at others first
+async fn clear_history(txn: &DatabaseTransaction) -> Result<(), DbErr> {
+    manual_change_history::Entity::delete_many().exec(txn).await?;
+    sensor_reading_history::Entity::delete_many().exec(txn).await?;
+    sensor_reading_rollup::Entity::delete_many().exec(txn).await?;
+    weather_reading_rollup::Entity::delete_many().exec(txn).await?;
+    daily_degree_days::Entity::delete_many().exec(txn).await?;
+    weather_reading::Entity::delete_many().exec(txn).await?;
+    pollution_reading::Entity::delete_many().exec(txn).await?;
+    Ok(())
+}
+
+// History that stays must only point at sensors, schedules and change sources the archive keeps
+async fn check_kept_history(txn: &DatabaseTransaction, config: &ConfigTables) -> Result<(), BackupError> {
+    let sensor_ids: Vec<i32> = config.sensors.iter().map(|row| row.id).collect();
+    let schedule_ids: Vec<i32> = config.schedules.iter().map(|row| row.id).collect();
+    let source_ids: Vec<i32> = config.change_sources.iter().map(|row| row.id).collect();
+    let orphan: Option<i32> = sensor_reading_history::Entity::find().select_only()
+        .column(sensor_reading_history::Column::SensorId)
+        .filter(sensor_reading_history::Column::SensorId.is_not_in(sensor_ids.clone()))
+        .into_tuple().one(txn).await?;
+    if let Some(value) = orphan {
+        return Err(BackupError::HistoryReference { table: "SensorReadingHistory", field: "sensorID", value })
+    }
+    let orphan: Option<i32> = sensor_reading_rollup::Entity::find().select_only()
+        .column(sensor_reading_rollup::Column::SensorId)
+        .filter(sensor_reading_rollup::Column::SensorId.is_not_in(sensor_ids))
+        .into_tuple().one(txn).await?;
+    if let Some(value) = orphan {
+        return Err(BackupError::HistoryReference { table: "SensorReadingRollup", field: "sensorID", value })
+    }
+    let orphan: Option<i32> = manual_change_history::Entity::find().select_only()
+        .column(manual_change_history::Column::ChangeSource)
+        .filter(manual_change_history::Column::ChangeSource.is_not_in(source_ids))
+        .into_tuple().one(txn).await?;
+    if let Some(value) = orphan {
+        return Err(BackupError::HistoryReference { table: "ManualChangeHistory", field: "changeSource", value })
+    }
+    let orphan: Option<Option<i32>> = manual_change_history::Entity::find().select_only()
+        .column(manual_change_history::Column::ChangeSchedule)
+        .filter(manual_change_history::Column::ChangeSchedule.is_not_null())
+        .filter(manual_change_history::Column::ChangeSchedule.is_not_in(schedule_ids))
+        .into_tuple().one(txn).await?;
+    if let Some(Some(value)) = orphan {
+        return Err(BackupError::HistoryReference { table: "ManualChangeHistory", field: "changeSchedule", value })
+    }
+    Ok(())
+}
+
+// The audit log outlives every restore, so its change sources have to stay
+async fn check_kept_audit_log(txn: &DatabaseTransaction, config: &ConfigTables) -> Result<(), BackupError> {
+    let orphan: Option<i32> = audit_log::Entity::find().select_only()
+        .column(audit_log::Column::ChangeSource)
+        .filter(audit_log::Column::ChangeSource.is_not_in(config.change_sources.iter().map(|row| row.id)))
+        .into_tuple().one(txn).await?;
+    match orphan {
+        Some(value) => Err(BackupError::AuditReference(value)),
+        None => Ok(()),
+    }
+}
+
+// Writes rows with the ids they already have
+async fn insert_rows<A>(txn: &DatabaseTransaction, rows: Vec<<A::Entity as EntityTrait>::Model>) -> Result<(), DbErr>
+where
+    A: ActiveModelTrait,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let chunk: Vec<A> = rows.by_ref().take(INSERT_CHUNK).map(|row| row.into_active_model().reset_all()).collect();
+        A::Entity::insert_many(chunk).exec(txn).await?;
+    }
+    Ok(())
+}
+
+// Writes rows with the ids they already have, replacing every other column of a row that is already there
+async fn upsert_rows<A>(txn: &DatabaseTransaction, rows: Vec<<A::Entity as EntityTrait>::Model>) -> Result<(), DbErr>
+where
+    A: ActiveModelTrait,
+    <A::Entity as EntityTrait>::Model: IntoActiveModel<A>,
+{
+    let columns: Vec<<A::Entity as EntityTrait>::Column> = <A::Entity as EntityTrait>::Column::iter()
+        .filter(|column| column.as_str() != "id").collect();
+    let mut on_conflict: OnConflict = OnConflict::column(sea_orm::sea_query::Alias::new("id"));
+    match columns.is_empty() {
+        true => on_conflict.do_nothing(),
+        false => on_conflict.update_columns(columns),
+    };
+    let mut rows = rows.into_iter().peekable();
+    while rows.peek().is_some() {
+        let chunk: Vec<A> = rows.by_ref().take(INSERT_CHUNK).map(|row| row.into_active_model().reset_all()).collect();
+        A::Entity::insert_many(chunk).on_conflict(on_conflict.clone()).exec_without_returning(txn).await?;
+    }
+    Ok(())
+}
+
+// Postgres hands out ids from a sequence that knows nothing of the rows written with their own ids. The history
+// tables keyed on UUIDs have no sequence
+async fn reset_sequences(txn: &DatabaseTransaction) -> Result<(), DbErr> {
+    let tables: [&str; 14] = [
+        change_source::Entity.table_name(), communication::Entity.table_name(), weekdays::Entity.table_name(),
+        env_capability::Entity.table_name(), hva_cactivity::Entity.table_name(), home_summary::Entity.table_name(),
+        zones::Entity.table_name(), sensors::Entity.table_name(), controllers::Entity.table_name(),
+        schedules::Entity.table_name(), alerts::Entity.table_name(), sensor_reading_rollup::Entity.table_name(),
+        weather_reading_rollup::Entity.table_name(), daily_degree_days::Entity.table_name(),
+    ];
+    for table in tables {
+        txn.execute(Statement::from_string(DbBackend::Postgres, format!(
+            "SELECT setval(pg_get_serial_sequence('\"{table}\"', 'id'), COALESCE(MAX(\"id\"), 0) + 1, false) FROM \"{table}\""
+        ))).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use sea_orm::PaginatorTrait;
+    use crate::dbman;
+    use crate::repo::alerts::{self as alert_repo, NewAlert};
+    use crate::repo::controllers::{self as controller_repo, IssuedController, NewController};
+    use crate::repo::schedules::{self as schedule_repo, NewSchedule};
+    use crate::repo::sensors::{self as sensor_repo, IssuedSensor, NewSensor};
+    use crate::repo::zones::{self as zone_repo, NewZone};
+    use crate::units::Temperature;
+    use sea_orm::ActiveValue::Set;
+
+    // A zone with a sensor, controller, schedule and alert in it, returning the sensor id
+    async fn configure(db: &DatabaseConnection, name: &str) -> i32 {
+        let zone: zones::Model = zone_repo::create(db, &test_actor(), NewZone { name: name.to_string(), heating: true, cooling: false }).await.unwrap();
+        let sensor: sensors::Model = sensor_repo::create(db, &test_actor(), NewSensor { name: format!("{} sensor", name),
+            associated_zone: Some(zone.id), com_type: 1 }).await.unwrap().sensor;
+        controller_repo::create(db, &test_actor(), NewController { name: format!("{} controller", name), com_type: 2,
+            associated_zone: Some(zone.id), primary: true, heating: true, cooling: false }).await.unwrap();
+        let schedule: schedules::Model = schedule_repo::create(db, &test_actor(), NewSchedule { name: format!("{} nights", name),
+            associated_zone: Some(zone.id), week_day: Some(0b0111110), temp_min: Some(Temperature::from_celsius(18.0)),
+            ..NewSchedule::default() }).await.unwrap();
+        alert_repo::create(db, &test_actor(), NewAlert { name: format!("{} too cold", name), com_type: Some(3),
+            associated_schedule: Some(schedule.id), associated_zone: Some(zone.id), actions: None }).await.unwrap();
+        sensor.id
+    }
+
+    async fn reading(db: &DatabaseConnection, sensor: i32) {
+        sensor_reading_history::ActiveModel { id: Set(Uuid::now_v7()), sensor_id: Set(sensor), timestamp: Set(Utc::now().naive_utc()),
+            reading_temp: Set(Some(Temperature::from_celsius(20.5))), reading_humidity: Set(Some(40)),
+            reading_presence: Set(None), reading_threshold_open: Set(None) }.insert(db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn archive_round_trips_into_another_database() {
+        let source: DatabaseConnection = dbman::test_connection().await;
+        let sensor: i32 = configure(&source, "Office").await;
+        configure(&source, "Bedroom").await;
+        reading(&source, sensor).await;
+        let archive: Archive = backup(&source, true).await.unwrap();
+        let json: Vec<u8> = serde_json::to_vec(&archive).unwrap();
+
+        let target: DatabaseConnection = dbman::test_connection().await;
+        configure(&target, "Garage").await;
+        let report: RestoreReport = restore(&target, Archive::from_json(&json).unwrap()).await.unwrap();
+        assert_eq!(report.history_rows, Some(1));
+        let restored: Archive = backup(&target, true).await.unwrap();
+        assert_eq!(restored.config, archive.config);
+        assert_eq!(restored.history, archive.history);
+        // New rows carry on after the restored ids
+        let zone: zones::Model = zone_repo::create(&target, &test_actor(), NewZone { name: "Attic".to_string(), heating: false, cooling: true }).await.unwrap();
+        assert!(archive.config.zones.iter().all(|row| row.id < zone.id));
+    }
+
+    #[tokio::test]
+    async fn tokens_keep_working_on_another_database() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let sensor: IssuedSensor = sensor_repo::create(&db, &test_actor(), NewSensor { name: "Porch".to_string(), associated_zone: None,
+            com_type: 1 }).await.unwrap();
+        let controller: IssuedController = controller_repo::create(&db, &test_actor(), NewController { name: "Garage heater".to_string(),
+            com_type: 2, associated_zone: None, primary: true, heating: true, cooling: false }).await.unwrap();
+        let archive: Archive = backup(&db, false).await.unwrap();
+        let written: String = serde_json::to_string(&archive).unwrap();
+        assert!(!written.contains(&sensor.token) && !written.contains(&controller.token));
+
+        let elsewhere: DatabaseConnection = dbman::test_connection().await;
+        restore(&elsewhere, archive.clone()).await.unwrap();
+        let restored: controllers::Model = controller_repo::authenticate(&elsewhere, &controller.token).await.unwrap().unwrap();
+        assert_eq!(restored.token, tokens::hash(&controller.token));
+        assert!(controller_repo::authenticate(&elsewhere, &restored.token).await.unwrap().is_none());
+        assert!(sensor_repo::authenticate(&elsewhere, &sensor.token).await.unwrap().is_some());
+
+        // An archive from before tokens were hashed has them in plain text, they are hashed on the way in
+        let mut older: Archive = archive;
+        older.config.controllers[0].token = "plain-controller-token".to_string();
+        restore(&elsewhere, older).await.unwrap();
+        assert_eq!(controller_repo::list(&elsewhere, true).await.unwrap()[0].token, tokens::hash("plain-controller-token"));
+        assert!(controller_repo::authenticate(&elsewhere, "plain-controller-token").await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn bad_archives_change_nothing() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        configure(&db, "Office").await;
+        let archive: Archive = backup(&db, false).await.unwrap();
+
+        let mut json: serde_json::Value = serde_json::to_value(&archive).unwrap();
+        json["version"] = serde_json::json!(ARCHIVE_VERSION + 1);
+        assert!(matches!(Archive::from_json(json.to_string().as_bytes()), Err(BackupError::UnsupportedVersion(_))));
+        // A version 1 archive only reads without the history it keyed on integers
+        json["version"] = serde_json::json!(1);
+        assert_eq!(Archive::from_json(json.to_string().as_bytes()).unwrap().version, ARCHIVE_VERSION);
+        json["history"] = serde_json::json!({});
+        assert!(matches!(Archive::from_json(json.to_string().as_bytes()), Err(BackupError::UnsupportedVersion(1))));
+
+        let mut broken: Archive = archive.clone();
+        broken.config.zones.clear();
+        match restore(&db, broken).await {
+            Err(BackupError::MissingReference { table, field, .. }) => assert_eq!((table, field), ("Sensors", "associatedZone")),
+            other => panic!("expected a missing reference, got {:?}", other),
+        }
+        let mut doubled: Archive = archive.clone();
+        doubled.config.alerts.push(doubled.config.alerts[0].clone());
+        assert!(matches!(restore(&db, doubled).await, Err(BackupError::Invalid(_))));
+        // The configuration was made through the api change source, which the audit log keeps
+        let mut sourceless: Archive = archive.clone();
+        sourceless.config.change_sources.retain(|row| row.name != "api");
+        assert!(matches!(restore(&db, sourceless).await, Err(BackupError::AuditReference(_))));
+        assert_eq!(zones::Entity::find().count(&db).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn history_is_kept_unless_the_archive_has_it() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let sensor: i32 = configure(&db, "Office").await;
+        reading(&db, sensor).await;
+        let archive: Archive = backup(&db, false).await.unwrap();
+
+        // A zone added after the backup goes away, the readings stay
+        configure(&db, "Bedroom").await;
+        assert_eq!(restore(&db, archive.clone()).await.unwrap().history_rows, None);
+        assert_eq!(zones::Entity::find().count(&db).await.unwrap(), 1);
+        assert_eq!(sensor_reading_history::Entity::find().count(&db).await.unwrap(), 1);
+
+        // Dropping a sensor that has readings needs the archive to bring its own history
+        let bedroom: i32 = configure(&db, "Bedroom").await;
+        reading(&db, bedroom).await;
+        match restore(&db, archive.clone()).await {
+            Err(BackupError::HistoryReference { value, .. }) => assert_eq!(value, bedroom),
+            other => panic!("expected a history reference, got {:?}", other),
+        }
+        assert_eq!(zones::Entity::find().count(&db).await.unwrap(), 2);
+
+        let mut with_history: Archive = archive;
+        with_history.history = Some(HistoryTables::default());
+        assert_eq!(restore(&db, with_history).await.unwrap().history_rows, Some(0));
+        assert_eq!(sensor_reading_history::Entity::find().count(&db).await.unwrap(), 0);
+        assert_eq!(sensors::Entity::find().count(&db).await.unwrap(), 1);
+    }
+}
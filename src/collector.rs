@@ -0,0 +1,400 @@
+//! # Rusty Thermostat Collector
+//! Background tasks that poll outside services on a timer and store what they find in the database
+
+use std::{future::Future, sync::Arc, time::Duration};
+use rocket::{Shutdown, fairing::AdHoc};
+use sea_orm::DatabaseConnection;
+use tokio::time::{self, MissedTickBehavior};
+use crate::weather::{self, WeatherProvider};
+use crate::{degree_days, retention};
+use crate::dbman::buffer::BufferedRow;
+use crate::dbman::supervisor::DbSupervisor;
+use crate::units::{Temperature, UnitSystem};
+use crate::schema::pollution_reading;
+
+/// Seconds between current weather polls when no interval is configured
+pub const DEFAULT_WEATHER_INTERVAL: u64 = 600;
+/// Seconds between air pollution polls when no interval is configured
+pub const DEFAULT_POLLUTION_INTERVAL: u64 = 3600;
+/// Seconds between forecast polls when no interval is configured. The forecast only changes every 3 hours
+pub const DEFAULT_FORECAST_INTERVAL: u64 = 10800;
+/// Seconds between degree day runs. Each run catches up on every finished day so once a day is enough
+pub const DEGREE_DAY_INTERVAL: u64 = 86400;
+/// How long the supervisor waits before restarting a collector that died
+const RESTART_DELAY: Duration = Duration::from_secs(5);
+
+/// Everything the collector needs to know about what to poll and how often
+#[derive(Debug, Clone)]
+pub struct CollectorConfig {
+    pub provider: Arc<dyn WeatherProvider>,
+    pub monitor_weather: bool,
+    pub weather_interval: Duration,
+    pub monitor_pollution: bool,
+    pub pollution_interval: Duration,
+    pub monitor_forecast: bool,
+    pub forecast_interval: Duration,
+    pub degree_day_base: Temperature,
+    pub retention: Option<retention::RetentionConfig>,
+    pub retention_interval: Duration,
+}
+
+impl CollectorConfig {
+    /// Builds a collector config with the default intervals
+    pub fn new(provider: Arc<dyn WeatherProvider>, monitor_weather: bool, monitor_pollution: bool) -> CollectorConfig {
+        CollectorConfig {
+            provider,
+            monitor_weather,
+            weather_interval: Duration::from_secs(DEFAULT_WEATHER_INTERVAL),
+            monitor_pollution,
+            pollution_interval: Duration::from_secs(DEFAULT_POLLUTION_INTERVAL),
+            monitor_forecast: false,
+            forecast_interval: Duration::from_secs(DEFAULT_FORECAST_INTERVAL),
+            degree_day_base: degree_days::default_base(UnitSystem::default()),
+            retention: Some(retention::RetentionConfig::default()),
+            retention_interval: Duration::from_secs(retention::DEFAULT_RETENTION_INTERVAL),
+        }
+    }
+    /// Sets the seconds between current weather polls
+    pub fn set_weather_interval(&mut self, seconds: u64) {
+        self.weather_interval = interval_from_secs(seconds);
+        debug!("Weather will be polled every {} seconds", self.weather_interval.as_secs());
+    }
+    /// Sets the seconds between air pollution polls
+    pub fn set_pollution_interval(&mut self, seconds: u64) {
+        self.pollution_interval = interval_from_secs(seconds);
+        debug!("Air pollution will be polled every {} seconds", self.pollution_interval.as_secs());
+    }
+    /// Sets the seconds between forecast polls
+    pub fn set_forecast_interval(&mut self, seconds: u64) {
+        self.forecast_interval = interval_from_secs(seconds);
+        debug!("Forecast will be polled every {} seconds", self.forecast_interval.as_secs());
+    }
+    /// Sets the seconds between retention runs
+    pub fn set_retention_interval(&mut self, seconds: u64) {
+        self.retention_interval = interval_from_secs(seconds);
+        debug!("Retention will run every {} seconds", self.retention_interval.as_secs());
+    }
+}
+
+// An interval of zero would spin, so it is bumped up to one second
+fn interval_from_secs(seconds: u64) -> Duration {
+    Duration::from_secs(seconds.max(1))
+}
+
+/// Creates the fairing that starts the collector tasks once Rocket has lifted off<br>
+/// The tasks use the managed DatabaseConnection and stop when Rocket shuts down. Weather and pollution rows go
+/// through the managed DbSupervisor, so they are buffered rather than lost while the database is down
+pub fn fairing(config: CollectorConfig) -> AdHoc {
+    AdHoc::on_liftoff("Collector", move |rocket| Box::pin(async move {
+        let db: DatabaseConnection = match rocket.state::<DatabaseConnection>() {
+            Some(db) => db.clone(),
+            None => {
+                error!("No database connection is managed, collectors will not start.");
+                return
+            }
+        };
+        let supervisor: Arc<DbSupervisor> = match rocket.state::<Arc<DbSupervisor>>() {
+            Some(supervisor) => supervisor.clone(),
+            None => {
+                error!("No database supervisor is managed, collectors will not start.");
+                return
+            }
+        };
+        let shutdown: Shutdown = rocket.shutdown();
+
+        if config.monitor_weather {
+            info!("Starting weather collector.");
+            let weather_provider: Arc<dyn WeatherProvider> = config.provider.clone();
+            let weather_db: DatabaseConnection = db.clone();
+            let weather_supervisor: Arc<DbSupervisor> = supervisor.clone();
+            spawn_collector("weather", config.weather_interval, shutdown.clone(), move || {
+                let weather_provider: Arc<dyn WeatherProvider> = weather_provider.clone();
+                let weather_db: DatabaseConnection = weather_db.clone();
+                let weather_supervisor: Arc<DbSupervisor> = weather_supervisor.clone();
+                async move { poll_weather(weather_provider.as_ref(), &weather_db, &weather_supervisor).await }
+            });
+        } else {
+            debug!("Weather monitoring is off, weather collector not started.");
+        }
+
+        if config.monitor_pollution {
+            info!("Starting air pollution collector.");
+            let pollution_provider: Arc<dyn WeatherProvider> = config.provider.clone();
+            let pollution_db: DatabaseConnection = db.clone();
+            let pollution_supervisor: Arc<DbSupervisor> = supervisor.clone();
+            spawn_collector("air pollution", config.pollution_interval, shutdown.clone(), move || {
+                let pollution_provider: Arc<dyn WeatherProvider> = pollution_provider.clone();
+                let pollution_db: DatabaseConnection = pollution_db.clone();
+                let pollution_supervisor: Arc<DbSupervisor> = pollution_supervisor.clone();
+                async move { poll_pollution(pollution_provider.as_ref(), &pollution_db, &pollution_supervisor).await }
+            });
+        } else {
+            debug!("Pollution monitoring is off, air pollution collector not started.");
+        }
+
+        if config.monitor_forecast {
+            info!("Starting forecast collector.");
+            let forecast_provider: Arc<dyn WeatherProvider> = config.provider.clone();
+            let forecast_db: DatabaseConnection = db.clone();
+            let forecast_supervisor: Arc<DbSupervisor> = supervisor.clone();
+            spawn_collector("forecast", config.forecast_interval, shutdown.clone(), move || {
+                let forecast_provider: Arc<dyn WeatherProvider> = forecast_provider.clone();
+                let forecast_db: DatabaseConnection = forecast_db.clone();
+                let forecast_supervisor: Arc<DbSupervisor> = forecast_supervisor.clone();
+                async move { poll_forecast(forecast_provider.as_ref(), &forecast_db, &forecast_supervisor).await }
+            });
+        } else {
+            debug!("Forecast monitoring is off, forecast collector not started.");
+        }
+
+        if config.monitor_weather {
+            info!("Starting degree day job with a base of {}.", config.degree_day_base);
+            let base_temp: Temperature = config.degree_day_base;
+            let degree_day_db: DatabaseConnection = db.clone();
+            spawn_collector("degree day", Duration::from_secs(DEGREE_DAY_INTERVAL), shutdown.clone(), move || {
+                let degree_day_db: DatabaseConnection = degree_day_db.clone();
+                async move { compute_degree_days(&degree_day_db, base_temp).await }
+            });
+        }
+
+        // Sensor readings arrive whether or not the weather is monitored, so retention runs on its own
+        match config.retention {
+            Some(retention_config) => {
+                info!("Starting retention job, raw readings are kept for {} days.", retention_config.raw_max_age.num_days());
+                let retention_db: DatabaseConnection = db.clone();
+                spawn_collector("retention", config.retention_interval, shutdown.clone(), move || {
+                    let retention_db: DatabaseConnection = retention_db.clone();
+                    async move { apply_retention(&retention_db, &retention_config).await }
+                });
+            },
+            None => debug!("Retention is off, raw readings are kept forever."),
+        }
+    }))
+}
+
+/// Spawns a supervised task that runs the poll on the given interval until shutdown
+fn spawn_collector<F, Fut>(name: &'static str, interval: Duration, shutdown: Shutdown, poll: F)
+where
+    F: Fn() -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let task_shutdown: Shutdown = shutdown.clone();
+    tokio::spawn(supervise(name, shutdown, move || poll_loop(interval, task_shutdown.clone(), poll.clone())));
+}
+
+/// Keeps a collector task running until shutdown<br>
+/// If the task panics it is restarted after a short delay. If it returns on its own, the supervisor is done
+async fn supervise<F, Fut>(name: &'static str, shutdown: Shutdown, task: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    loop {
+        let mut handle = tokio::spawn(task());
+        tokio::select! {
+            result = &mut handle => match result {
+                Ok(()) => {
+                    info!("The {} collector has stopped.", name);
+                    return
+                },
+                Err(error) => error!("The {} collector died: {}. Restarting.", name, error),
+            },
+            _ = shutdown.clone() => {
+                info!("Shutdown requested, stopping the {} collector.", name);
+                handle.abort();
+                return
+            },
+        }
+        tokio::select! {
+            _ = time::sleep(RESTART_DELAY) => (),
+            _ = shutdown.clone() => return,
+        }
+    }
+}
+
+/// Runs the poll on every tick of the interval until shutdown is requested
+async fn poll_loop<F, Fut>(interval: Duration, shutdown: Shutdown, poll: F)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()>,
+{
+    let mut ticker = time::interval(interval);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => poll().await,
+            _ = shutdown.clone() => return,
+        }
+    }
+}
+
+/// Logs a failed fetch. A used up quota is a planned skip rather than a failure so it reads that way
+fn report_fetch_error(what: &str, provider: &dyn WeatherProvider, error: &weather::WeatherError) {
+    match error {
+        weather::WeatherError::QuotaExceeded(window) => warn!("Skipping {} poll, {} API quota for the {} is used up", what, provider.name(), window),
+        _ => warn!("Unable to fetch {} from {}: {}", what, provider.name(), error),
+    }
+}
+
+/// Fetches the current weather once and writes it to the WeatherReading table, or the write buffer while the
+/// database is down<br>
+/// Failures are logged and the next tick tries again
+async fn poll_weather(provider: &dyn WeatherProvider, db: &DatabaseConnection, supervisor: &DbSupervisor) {
+    let conditions: weather::CurrentConditions = match provider.current().await {
+        Ok(resp) => resp,
+        Err(error) => {
+            report_fetch_error("current weather", provider, &error);
+            return
+        }
+    };
+    trace!("Current conditions: {:?}", conditions);
+    match BufferedRow::weather(conditions.generate_db_model(provider.location())) {
+        Ok(row) => supervisor.store(db, row).await,
+        Err(error) => error!("Unable to store weather reading: {}", error),
+    }
+}
+
+/// Fetches the current air pollution once and writes every entry in the response to the PollutionReading table, or
+/// the write buffer while the database is down<br>
+/// Failures are logged and the next tick tries again
+async fn poll_pollution(provider: &dyn WeatherProvider, db: &DatabaseConnection, supervisor: &DbSupervisor) {
+    let air_quality: Vec<weather::AirQuality> = match provider.air_quality().await {
+        Ok(resp) => resp,
+        Err(error) => {
+            report_fetch_error("current air pollution", provider, &error);
+            return
+        }
+    };
+    trace!("Air quality: {:?}", air_quality);
+    let readings: Vec<pollution_reading::ActiveModel> = air_quality.into_iter().map(|reading| reading.generate_db_model(provider.location())).collect();
+    if readings.is_empty() {
+        warn!("Air pollution response did not contain any readings.");
+        return
+    }
+    let count: usize = readings.len();
+    for reading in readings {
+        match BufferedRow::pollution(reading) {
+            Ok(row) => supervisor.store(db, row).await,
+            Err(error) => error!("Unable to store air pollution reading: {}", error),
+        }
+    }
+    debug!("Handed {} air pollution readings to the database", count);
+}
+
+/// Fetches the forecast once and writes the whole snapshot to the ForecastReading table, or the write buffer while
+/// the database is down<br>
+/// Failures are logged and the next tick tries again
+async fn poll_forecast(provider: &dyn WeatherProvider, db: &DatabaseConnection, supervisor: &DbSupervisor) {
+    let forecast: Vec<weather::ForecastPoint> = match provider.forecast().await {
+        Ok(resp) => resp,
+        Err(error) => {
+            report_fetch_error("forecast", provider, &error);
+            return
+        }
+    };
+    if forecast.is_empty() {
+        warn!("Forecast response did not contain any entries.");
+        return
+    }
+    let count: usize = forecast.len();
+    supervisor.store(db, BufferedRow::forecast(forecast)).await;
+    debug!("Handed a forecast snapshot with {} entries to the database", count);
+}
+
+/// Works out degree days for every finished day that does not have them yet<br>
+/// Failures are logged and the next run picks up where this one stopped
+async fn compute_degree_days(db: &DatabaseConnection, base_temp: Temperature) {
+    match degree_days::run_job(db, base_temp).await {
+        Ok(0) => debug!("No new days to work out degree days for"),
+        Ok(count) => info!("Stored degree days for {} days", count),
+        Err(error) => error!("Unable to work out degree days: {}", error),
+    }
+}
+
+/// Rolls up finished days and purges old raw readings<br>
+/// Failures are logged and the next run picks up where this one stopped
+async fn apply_retention(db: &DatabaseConnection, config: &retention::RetentionConfig) {
+    match retention::run_job(db, config).await {
+        Ok(report) if report.is_empty() => debug!("Retention had nothing to roll up or purge"),
+        Ok(report) => info!("Retention rolled up {} sensor and {} weather days, purged {} sensor and {} weather readings",
+            report.sensor_days, report.weather_days, report.sensor_rows_purged, report.weather_rows_purged),
+        Err(error) => error!("Unable to apply retention: {}", error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, atomic::{AtomicUsize, Ordering}};
+
+    async fn test_shutdown() -> Shutdown {
+        rocket::custom(rocket::Config::debug_default()).ignite().await.unwrap().shutdown()
+    }
+
+    #[test]
+    fn collector_config_zero_interval_is_bumped() {
+        let mut config = CollectorConfig::new(Arc::new(weather::Configuration::new()), true, true);
+
+        config.set_weather_interval(0);
+        config.set_pollution_interval(0);
+
+        assert_eq!(config.weather_interval, Duration::from_secs(1));
+        assert_eq!(config.pollution_interval, Duration::from_secs(1));
+    }
+
+    #[test]
+    fn collector_config_default_intervals() {
+        let config = CollectorConfig::new(Arc::new(weather::Configuration::new()), true, true);
+
+        assert_eq!(config.weather_interval, Duration::from_secs(DEFAULT_WEATHER_INTERVAL));
+        assert_eq!(config.pollution_interval, Duration::from_secs(DEFAULT_POLLUTION_INTERVAL));
+    }
+
+    #[test]
+    fn collector_config_intervals_are_separate() {
+        let mut config = CollectorConfig::new(Arc::new(weather::Configuration::new()), true, true);
+
+        config.set_pollution_interval(1800);
+
+        assert_eq!(config.weather_interval, Duration::from_secs(DEFAULT_WEATHER_INTERVAL));
+        assert_eq!(config.pollution_interval, Duration::from_secs(1800));
+    }
+
+    #[tokio::test]
+    async fn supervise_returns_when_task_finishes() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+
+        supervise("test", test_shutdown().await, move || {
+            let counter = counter.clone();
+            async move { counter.fetch_add(1, Ordering::SeqCst); }
+        }).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn supervise_restarts_panicked_task() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+
+        supervise("test", test_shutdown().await, move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    panic!("first run fails");
+                }
+            }
+        }).await;
+
+        assert_eq!(runs.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn supervise_stops_on_shutdown() {
+        let shutdown = test_shutdown().await;
+        shutdown.clone().notify();
+
+        supervise("test", shutdown, std::future::pending::<()>).await;
+    }
+}
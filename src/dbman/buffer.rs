@@ -0,0 +1,341 @@
+//! # Write buffer
+//! An on-disk queue for the rows the application writes on its own, sensor readings, weather and forecasts, while the
+//! database cannot take them<br>
+//! Rows are appended to a newline delimited JSON file as they arrive and replayed oldest first once the database is
+//! back. A replay that is cut short keeps whatever it did not get to. Rows carry the UUID they were given when they
+//! were taken, so one stored just before a crash is refused as a duplicate when it comes round again instead of being
+//! stored twice. A forecast is kept as one entry for the whole snapshot and is refused the same way once a snapshot
+//! fetched at that time is stored
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use chrono::{NaiveDateTime, SubsecRound, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DbErr, EntityTrait, IntoActiveModel, PaginatorTrait, QueryFilter, TryIntoModel};
+use serde_derive::{Deserialize, Serialize};
+use crate::schema::{forecast_reading, pollution_reading, sensor_reading_history, weather_reading};
+use crate::weather::ForecastPoint;
+
+/// Where the buffer is kept when no path is configured
+pub const DEFAULT_BUFFER_PATH: &str = "./data/db_buffer.ndjson";
+
+/// A row waiting for the database, with the id it will be stored under
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "table", content = "row", rename_all = "kebab-case")]
+pub enum BufferedRow {
+    // Boxed, a weather row is several times the size of the others
+    Weather(Box<weather_reading::Model>),
+    Pollution(pollution_reading::Model),
+    SensorReading(sensor_reading_history::Model),
+    Forecast(ForecastSnapshot),
+}
+
+/// A whole forecast, stored together under the time it was fetched
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForecastSnapshot {
+    pub fetched_at: NaiveDateTime,
+    pub points: Vec<ForecastPoint>,
+}
+
+impl BufferedRow {
+    /// # Errors
+    /// A row missing a column, the id included, cannot be kept
+    pub fn weather(row: weather_reading::ActiveModel) -> Result<BufferedRow, DbErr> {
+        Ok(BufferedRow::Weather(Box::new(row.try_into_model()?)))
+    }
+    /// # Errors
+    /// A row missing a column, the id included, cannot be kept
+    pub fn pollution(row: pollution_reading::ActiveModel) -> Result<BufferedRow, DbErr> {
+        Ok(BufferedRow::Pollution(row.try_into_model()?))
+    }
+    /// # Errors
+    /// A row missing a column, the id included, cannot be kept
+    pub fn sensor_reading(row: sensor_reading_history::ActiveModel) -> Result<BufferedRow, DbErr> {
+        Ok(BufferedRow::SensorReading(row.try_into_model()?))
+    }
+    /// The forecast as fetched now. The time is kept to the second so it reads back the same from any database
+    pub fn forecast(points: Vec<ForecastPoint>) -> BufferedRow {
+        BufferedRow::Forecast(ForecastSnapshot { fetched_at: Utc::now().naive_utc().trunc_subsecs(0), points })
+    }
+
+    pub fn table(&self) -> &'static str {
+        match self {
+            BufferedRow::Weather(_) => "WeatherReading",
+            BufferedRow::Pollution(_) => "PollutionReading",
+            BufferedRow::SensorReading(_) => "SensorReadingHistory",
+            BufferedRow::Forecast(_) => "ForecastReading",
+        }
+    }
+
+    /// Writes the row under its own id
+    /// # Errors
+    /// Whatever the database says, a duplicate key when the row is already stored
+    pub async fn insert<C: ConnectionTrait>(self, db: &C) -> Result<(), DbErr> {
+        match self {
+            BufferedRow::Weather(row) => insert_row::<weather_reading::ActiveModel, C>(db, (*row).into_active_model()).await,
+            BufferedRow::Pollution(row) => insert_row::<pollution_reading::ActiveModel, C>(db, row.into_active_model()).await,
+            BufferedRow::SensorReading(row) => insert_row::<sensor_reading_history::ActiveModel, C>(db, row.into_active_model()).await,
+            BufferedRow::Forecast(snapshot) => insert_forecast(db, snapshot).await,
+        }
+    }
+}
+
+async fn insert_row<A, C>(db: &C, row: A) -> Result<(), DbErr>
+where
+    A: ActiveModelTrait,
+    C: ConnectionTrait,
+{
+    A::Entity::insert(row.reset_all()).exec(db).await?;
+    Ok(())
+}
+
+// Forecast rows are numbered by the database, so a snapshot already stored is recognised by its fetch time instead
+async fn insert_forecast<C: ConnectionTrait>(db: &C, snapshot: ForecastSnapshot) -> Result<(), DbErr> {
+    let stored: u64 = forecast_reading::Entity::find()
+        .filter(forecast_reading::Column::FetchedAt.eq(snapshot.fetched_at))
+        .count(db).await?;
+    if stored > 0 {
+        return Err(DbErr::Custom(format!("A forecast fetched at {} is already stored", snapshot.fetched_at)))
+    }
+    let rows: Vec<forecast_reading::ActiveModel> = snapshot.points.into_iter()
+        .filter_map(|point| point.generate_db_model(snapshot.fetched_at)).collect();
+    if !rows.is_empty() {
+        forecast_reading::Entity::insert_many(rows).exec(db).await?;
+    }
+    Ok(())
+}
+
+/// What became of the rows a replay went through
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayReport {
+    pub stored: usize,
+    /// Rows the database refused while it was up. They would never go in, so they are dropped
+    pub rejected: usize,
+    /// Rows still waiting because the database went away again
+    pub remaining: usize,
+}
+
+/// The queue file and a count of what is in it
+#[derive(Debug)]
+pub struct WriteBuffer {
+    path: PathBuf,
+    len: usize,
+}
+
+impl WriteBuffer {
+    /// Opens the buffer at the path, counting any rows left from before a restart<br>
+    /// The file and its folder are only created once a row needs them
+    /// # Errors
+    /// An existing file that cannot be read
+    pub fn open<P: AsRef<Path>>(path: P) -> std::io::Result<WriteBuffer> {
+        let path: PathBuf = path.as_ref().to_path_buf();
+        let len: usize = match File::open(&path) {
+            Ok(file) => BufReader::new(file).lines().filter(|line| !matches!(line, Ok(text) if text.trim().is_empty())).count(),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        if len > 0 {
+            warn!("Database write buffer {} holds {} rows from before the restart", path.display(), len);
+        }
+        Ok(WriteBuffer { path, len })
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Adds a row to the end of the queue
+    /// # Errors
+    /// The file cannot be created or written
+    pub fn push(&mut self, row: &BufferedRow) -> std::io::Result<()> {
+        if let Some(folder) = self.path.parent().filter(|folder| !folder.as_os_str().is_empty()) {
+            fs::create_dir_all(folder)?;
+        }
+        let mut file: File = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        let mut line: String = serde_json::to_string(row)?;
+        line.push('\n');
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Stores the queued rows oldest first. A row the database refuses while `is_live` still passes is logged and
+    /// dropped, anything else stops the replay and keeps the rest for next time
+    /// # Errors
+    /// The file cannot be read or rewritten. Rows already stored stay stored
+    pub async fn replay<C, F, Fut>(&mut self, db: &C, is_live: F) -> std::io::Result<ReplayReport>
+    where
+        C: ConnectionTrait,
+        F: Fn() -> Fut,
+        Fut: std::future::Future<Output = bool>,
+    {
+        let mut report: ReplayReport = ReplayReport::default();
+        if self.is_empty() {
+            return Ok(report)
+        }
+        let lines: Vec<String> = BufReader::new(File::open(&self.path)?).lines()
+            .collect::<std::io::Result<Vec<String>>>()?
+            .into_iter().filter(|line| !line.trim().is_empty()).collect();
+        let mut done: usize = 0;
+        for line in &lines {
+            let row: BufferedRow = match serde_json::from_str(line) {
+                Ok(row) => row,
+                Err(error) => {
+                    error!("Dropping a buffered row that cannot be read: {}", error);
+                    report.rejected += 1;
+                    done += 1;
+                    continue
+                }
+            };
+            let table: &str = row.table();
+            match row.insert(db).await {
+                Ok(()) => report.stored += 1,
+                Err(error) if is_live().await => {
+                    error!("Dropping a buffered {} row the database refused: {}", table, error);
+                    report.rejected += 1;
+                },
+                Err(error) => {
+                    warn!("Database went away while replaying the write buffer: {}", error);
+                    break
+                },
+            }
+            done += 1;
+        }
+        report.remaining = lines.len() - done;
+        self.rewrite(&lines[done..])?;
+        Ok(report)
+    }
+
+    // Swaps the file for one holding only the rows still waiting, through a rename so a crash leaves one or the other
+    fn rewrite(&mut self, remaining: &[String]) -> std::io::Result<()> {
+        if remaining.is_empty() {
+            fs::remove_file(&self.path)?;
+        } else {
+            let temporary: PathBuf = self.path.with_extension("tmp");
+            let mut writer: BufWriter<File> = BufWriter::new(File::create(&temporary)?);
+            for line in remaining {
+                writeln!(writer, "{}", line)?;
+            }
+            writer.into_inner().map_err(|error| error.into_error())?.sync_data()?;
+            fs::rename(&temporary, &self.path)?;
+        }
+        self.len = remaining.len();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use chrono::{NaiveDate, NaiveDateTime};
+    use sea_orm::{DatabaseConnection, PaginatorTrait, QueryOrder};
+    use sea_orm::ActiveValue::Set;
+    use uuid::Uuid;
+    use crate::dbman;
+    use crate::repo::sensors::{self as sensor_repo, NewSensor};
+    use crate::units::{Pressure, Speed, Temperature};
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("rusty_thermostat_{}_{}", std::process::id(), name)).join("buffer.ndjson")
+    }
+
+    fn at(minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, 1).unwrap().and_hms_opt(8, minute, 0).unwrap()
+    }
+
+    fn reading(sensor: i32, minute: u32) -> BufferedRow {
+        BufferedRow::sensor_reading(sensor_reading_history::ActiveModel { id: Set(Uuid::now_v7()), sensor_id: Set(sensor), timestamp: Set(at(minute)),
+            reading_temp: Set(Some(Temperature::from_celsius(20.0 + minute as f64))), reading_humidity: Set(None),
+            reading_presence: Set(None), reading_threshold_open: Set(None) }).unwrap()
+    }
+
+    #[tokio::test]
+    async fn rows_survive_a_restart_and_replay_in_order() {
+        let path: PathBuf = temp_path("replay");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let sensor: i32 = sensor_repo::create(&db, &test_actor(), NewSensor { name: "Hall".to_string(),
+            associated_zone: None, com_type: 1 }).await.unwrap().sensor.id;
+
+        let mut buffer: WriteBuffer = WriteBuffer::open(&path).unwrap();
+        assert!(buffer.is_empty());
+        for minute in [5, 1, 3] {
+            buffer.push(&reading(sensor, minute)).unwrap();
+        }
+        // A sensor that does not exist is refused while the database is up
+        buffer.push(&reading(sensor + 100, 9)).unwrap();
+        drop(buffer);
+
+        let mut buffer: WriteBuffer = WriteBuffer::open(&path).unwrap();
+        assert_eq!(buffer.len(), 4);
+        let report: ReplayReport = buffer.replay(&db, || async { true }).await.unwrap();
+        assert_eq!(report, ReplayReport { stored: 3, rejected: 1, remaining: 0 });
+        assert!(buffer.is_empty() && !path.exists());
+        let stored: Vec<NaiveDateTime> = sensor_reading_history::Entity::find().order_by_asc(sensor_reading_history::Column::Id)
+            .all(&db).await.unwrap().into_iter().map(|row| row.timestamp).collect();
+        assert_eq!(stored, vec![at(5), at(1), at(3)]);
+
+        // A row stored before a crash could take it off the queue is refused the second time round
+        let again: BufferedRow = reading(sensor, 7);
+        again.clone().insert(&db).await.unwrap();
+        buffer.push(&again).unwrap();
+        let report: ReplayReport = buffer.replay(&db, || async { true }).await.unwrap();
+        assert_eq!(report, ReplayReport { stored: 0, rejected: 1, remaining: 0 });
+        assert_eq!(sensor_reading_history::Entity::find().count(&db).await.unwrap(), 4);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    fn forecast_point(hour: i64) -> ForecastPoint {
+        ForecastPoint { forecast_time: 1709280000 + hour * 3600, condition: "Clouds".to_string(), description: "overcast clouds".to_string(),
+            icon: "04d".to_string(), temperature: Temperature::from_celsius(8.0), feels_like: Temperature::from_celsius(6.5),
+            temp_min: Temperature::from_celsius(7.0), temp_max: Temperature::from_celsius(9.0), pressure_sea: Pressure::from_hpa(1012.0),
+            humidity: 70, pressure_ground: None, visibility: None, wind_speed: Speed::from_meters_per_second(3.0), wind_deg: 220,
+            wind_gust: None, clouds: 100, pop: 0.2, rain_3h: None, snow_3h: None }
+    }
+
+    #[tokio::test]
+    async fn a_forecast_snapshot_is_replayed_whole_and_once() {
+        let path: PathBuf = temp_path("forecast");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let snapshot: BufferedRow = BufferedRow::forecast(vec![forecast_point(0), forecast_point(3), forecast_point(6)]);
+        let mut buffer: WriteBuffer = WriteBuffer::open(&path).unwrap();
+        buffer.push(&snapshot).unwrap();
+        buffer.push(&snapshot).unwrap();
+
+        let report: ReplayReport = buffer.replay(&db, || async { true }).await.unwrap();
+        assert_eq!(report, ReplayReport { stored: 1, rejected: 1, remaining: 0 });
+        let stored: Vec<forecast_reading::Model> = forecast_reading::Entity::find().all(&db).await.unwrap();
+        assert_eq!(stored.len(), 3);
+        let BufferedRow::Forecast(ForecastSnapshot { fetched_at, .. }) = snapshot else { unreachable!() };
+        assert!(stored.iter().all(|row| row.fetched_at == fetched_at));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn an_outage_mid_replay_keeps_the_rest() {
+        let path: PathBuf = temp_path("outage");
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let mut buffer: WriteBuffer = WriteBuffer::open(&path).unwrap();
+        // No sensors exist, so every insert fails, and the database claims to be down
+        buffer.push(&reading(1, 1)).unwrap();
+        buffer.push(&reading(1, 2)).unwrap();
+
+        let report: ReplayReport = buffer.replay(&db, || async { false }).await.unwrap();
+        assert_eq!(report, ReplayReport { stored: 0, rejected: 0, remaining: 2 });
+        assert_eq!(WriteBuffer::open(&path).unwrap().len(), 2);
+        assert_eq!(sensor_reading_history::Entity::find().count(&db).await.unwrap(), 0);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
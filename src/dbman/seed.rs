@@ -0,0 +1,192 @@
+//! # Seed data
+//! Lookup rows that foreign keys point at and that nothing else creates<br>
+//! Every step only inserts what is missing, so seeding runs on every start and is safe to repeat
+
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, DatabaseTransaction, DbErr, EntityTrait, PaginatorTrait, QuerySelect, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use sea_orm::DatabaseConnection;
+use crate::schema::{change_source, communication, env_capability, home_summary, hva_cactivity, weekdays};
+
+/// Where a manual change came from
+pub const CHANGE_SOURCES: [&str; 4] = ["web", "schedule", "sensor", "api"];
+/// How sensors and controllers talk to the application
+pub const COMMUNICATION_TYPES: [&str; 3] = ["http", "rs485", "mqtt"];
+// One Weekdays row for every combination of the seven days
+const WEEKDAY_COMBINATIONS: i32 = 1 << 7;
+
+/// Counts of the rows one seeding run added
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SeedReport {
+    pub change_sources: usize,
+    pub communication_types: usize,
+    pub weekdays: usize,
+    pub home_summary: bool,
+}
+
+impl SeedReport {
+    // True when the database already had everything
+    pub fn is_empty(&self) -> bool {
+        *self == SeedReport::default()
+    }
+}
+
+/// Inserts the missing lookup rows in one transaction<br>
+/// # Errors
+/// Any failed query rolls the whole run back
+pub async fn seed(db: &DatabaseConnection) -> Result<SeedReport, DbErr> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let report: SeedReport = SeedReport {
+        change_sources: seed_change_sources(&txn).await?,
+        communication_types: seed_communication_types(&txn).await?,
+        weekdays: seed_weekdays(&txn).await?,
+        home_summary: seed_home_summary(&txn).await?,
+    };
+    txn.commit().await?;
+    if report.is_empty() {
+        debug!("Seed data already present");
+    } else {
+        info!("Seeded {} change sources, {} communication types, {} weekday sets and {} home summary",
+            report.change_sources, report.communication_types, report.weekdays, if report.home_summary { "a" } else { "no" });
+    }
+    Ok(report)
+}
+
+async fn seed_change_sources(txn: &DatabaseTransaction) -> Result<usize, DbErr> {
+    let existing: Vec<String> = change_source::Entity::find().select_only()
+        .column(change_source::Column::Name).into_tuple().all(txn).await?;
+    let mut added: usize = 0;
+    for name in CHANGE_SOURCES.iter().filter(|name| !existing.iter().any(|found| found == *name)) {
+        change_source::ActiveModel { id: NotSet, name: Set(name.to_string()) }.insert(txn).await?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+async fn seed_communication_types(txn: &DatabaseTransaction) -> Result<usize, DbErr> {
+    let existing: Vec<String> = communication::Entity::find().select_only()
+        .column(communication::Column::Name).into_tuple().all(txn).await?;
+    let mut added: usize = 0;
+    for name in COMMUNICATION_TYPES.iter().filter(|name| !existing.iter().any(|found| found == *name)) {
+        communication::ActiveModel { id: NotSet, name: Set(name.to_string()), active: Set(true) }.insert(txn).await?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+async fn seed_weekdays(txn: &DatabaseTransaction) -> Result<usize, DbErr> {
+    let existing: Vec<i32> = weekdays::Entity::find().select_only()
+        .column(weekdays::Column::Id).into_tuple().all(txn).await?;
+    let mut added: usize = 0;
+    for mask in (0..WEEKDAY_COMBINATIONS).filter(|mask| !existing.contains(mask)) {
+        let days: weekdays::Model = weekdays_from_mask(mask);
+        weekdays::ActiveModel::from(days).insert(txn).await?;
+        added += 1;
+    }
+    Ok(added)
+}
+
+// The home summary is a single row, along with the capability and activity rows it points at
+async fn seed_home_summary(txn: &DatabaseTransaction) -> Result<bool, DbErr> {
+    if home_summary::Entity::find().count(txn).await? > 0 {
+        return Ok(false);
+    }
+    let capability: env_capability::Model = env_capability::ActiveModel {
+        id: NotSet,
+        heating: Set(false),
+        cooling: Set(false),
+        last_changed: Set(None),
+    }.insert(txn).await?;
+    let activity: hva_cactivity::Model = hva_cactivity::ActiveModel {
+        id: NotSet,
+        heating: Set(false),
+        heat_last_change: Set(None),
+        cooling: Set(false),
+        cool_last_change: Set(None),
+    }.insert(txn).await?;
+    home_summary::ActiveModel {
+        id: NotSet,
+        last_changed: Set(Utc::now().naive_utc()),
+        house_temp: Set(None),
+        house_humidity: Set(None),
+        capability: Set(capability.id),
+        system_active: Set(activity.id),
+    }.insert(txn).await?;
+    Ok(true)
+}
+
+/// Builds the Weekdays row for a bitmask, Sunday in the lowest bit through Saturday in the seventh<br>
+/// The row id is the mask itself, so a schedule can point at its days without a lookup
+pub fn weekdays_from_mask(mask: i32) -> weekdays::Model {
+    let has = |bit: i32| -> bool { mask & (1 << bit) != 0 };
+    weekdays::Model {
+        id: mask,
+        sunday: has(0),
+        monday: has(1),
+        tuesday: has(2),
+        wednesday: has(3),
+        thursday: has(4),
+        friday: has(5),
+        saturday: has(6),
+    }
+}
+
+// Bitmask for a Weekdays row, the reverse of weekdays_from_mask
+pub fn weekday_mask(days: &weekdays::Model) -> i32 {
+    [days.sunday, days.monday, days.tuesday, days.wednesday, days.thursday, days.friday, days.saturday].iter()
+        .enumerate()
+        .filter(|(_, set)| **set)
+        .fold(0, |mask, (bit, _)| mask | (1 << bit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbman;
+    use sea_orm::ModelTrait;
+
+    #[test]
+    fn weekday_masks_round_trip() {
+        for mask in 0..WEEKDAY_COMBINATIONS {
+            assert_eq!(weekday_mask(&weekdays_from_mask(mask)), mask);
+        }
+        let weekend: weekdays::Model = weekdays_from_mask(0b100_0001);
+        assert!(weekend.sunday && weekend.saturday);
+        assert!(!weekend.monday && !weekend.friday);
+    }
+
+    #[tokio::test]
+    async fn seeding_fills_every_lookup_table() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let names: Vec<String> = change_source::Entity::find().all(&db).await.unwrap()
+            .into_iter().map(|source| source.name).collect();
+        assert_eq!(names, CHANGE_SOURCES);
+        assert_eq!(communication::Entity::find().count(&db).await.unwrap(), 3);
+        assert_eq!(weekdays::Entity::find().count(&db).await.unwrap(), 128);
+        let every_day: weekdays::Model = weekdays::Entity::find_by_id(127).one(&db).await.unwrap().unwrap();
+        assert!(every_day.sunday && every_day.wednesday && every_day.saturday);
+
+        let summary: home_summary::Model = home_summary::Entity::find().one(&db).await.unwrap().unwrap();
+        let capability: env_capability::Model = summary.find_related(env_capability::Entity).one(&db).await.unwrap().unwrap();
+        assert!(!capability.heating && !capability.cooling);
+    }
+
+    #[tokio::test]
+    async fn seeding_again_adds_nothing() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        assert!(seed(&db).await.unwrap().is_empty());
+        assert_eq!(home_summary::Entity::find().count(&db).await.unwrap(), 1);
+        assert_eq!(env_capability::Entity::find().count(&db).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn seeding_only_fills_gaps() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        change_source::Entity::delete_many().exec(&db).await.unwrap();
+        change_source::ActiveModel { id: NotSet, name: Set("web".to_string()) }.insert(&db).await.unwrap();
+
+        let report: SeedReport = seed(&db).await.unwrap();
+        assert_eq!(report, SeedReport { change_sources: 3, ..SeedReport::default() });
+        assert_eq!(change_source::Entity::find().count(&db).await.unwrap(), 4);
+    }
+}
@@ -0,0 +1,300 @@
+//! # Database supervisor
+//! Watches the database connection so an outage is noticed, reported and ridden out instead of losing readings<br>
+//! The pool opens fresh connections on its own once the server is back, so the supervisor's job is pacing: it probes
+//! with `is_live`, backs off while the database is down, and replays the write buffer once a probe succeeds.
+//! Rows written through `store` go to the buffer while the database is down, and also while older rows are still
+//! waiting there, so they reach the database in the order they were taken. Inserts run without the buffer locked, so
+//! rows caught mid-insert as an outage begins can be buffered behind a newer one, every row carries its own time
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use chrono::{NaiveDateTime, Utc};
+use rocket::{Shutdown, fairing::AdHoc};
+use sea_orm::{DatabaseBackend, DatabaseConnection, DbErr};
+use serde_derive::Serialize;
+use tokio::time;
+use super::{DBConfig, is_live};
+use super::buffer::{BufferedRow, ReplayReport, WriteBuffer};
+
+/// Seconds between probes while the database is up when no interval is configured
+pub const DEFAULT_PROBE_INTERVAL: u64 = 30;
+/// Connection attempts at startup before giving up on a database that never comes up
+pub const STARTUP_ATTEMPTS: u32 = 10;
+// The first wait after a failure, doubled after every failure that follows
+const BACKOFF_START: Duration = Duration::from_secs(2);
+// Waits never grow past this, so a recovered database is noticed within a few minutes
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+// Startup gives up sooner than a running server does
+const STARTUP_BACKOFF_MAX: Duration = Duration::from_secs(60);
+// A ping waits on the pool for a connection, which would otherwise hang until the acquire timeout while the server is gone
+const PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Whether the last probe reached the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DbStatus {
+    Up,
+    Down,
+}
+
+/// What the supervisor knows about the database, as served by the health endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DbHealth {
+    pub status: DbStatus,
+    /// When the status last changed
+    pub since: NaiveDateTime,
+    pub last_checked: Option<NaiveDateTime>,
+    pub last_error: Option<String>,
+    /// Probes that have failed in a row
+    pub failed_probes: u32,
+    /// Rows waiting in the write buffer
+    pub buffered_rows: usize,
+}
+
+/// Probes the database, keeps its health and owns the write buffer
+#[derive(Debug)]
+pub struct DbSupervisor {
+    health: Mutex<DbHealth>,
+    buffer: tokio::sync::Mutex<WriteBuffer>,
+    probe_interval: Duration,
+}
+
+impl DbSupervisor {
+    /// Starts out assuming the database is up, since startup only gets this far once it has connected
+    pub fn new(buffer: WriteBuffer, probe_interval: u64) -> DbSupervisor {
+        DbSupervisor {
+            health: Mutex::new(DbHealth {
+                status: DbStatus::Up,
+                since: Utc::now().naive_utc(),
+                last_checked: None,
+                last_error: None,
+                failed_probes: 0,
+                buffered_rows: buffer.len(),
+            }),
+            buffer: tokio::sync::Mutex::new(buffer),
+            probe_interval: Duration::from_secs(probe_interval.max(1)),
+        }
+    }
+
+    pub async fn health(&self) -> DbHealth {
+        let buffered_rows: usize = self.buffer.lock().await.len();
+        let mut health: DbHealth = self.health.lock().unwrap().clone();
+        health.buffered_rows = buffered_rows;
+        health
+    }
+
+    pub fn is_up(&self) -> bool {
+        self.health.lock().unwrap().status == DbStatus::Up
+    }
+
+    /// Writes a row now if it can, or queues it for the next replay<br>
+    /// A row the database refuses while it is otherwise up is logged and dropped, since it would never go in
+    pub async fn store(&self, db: &DatabaseConnection, row: BufferedRow) {
+        let table: &str = row.table();
+        // The lock is only held to look at and append to the buffer, so one slow insert does not hold up other writers
+        let waiting: bool = !self.buffer.lock().await.is_empty();
+        if self.is_up() && !waiting {
+            match row.clone().insert(db).await {
+                Ok(()) => {
+                    trace!("Stored a {} row", table);
+                    return
+                },
+                Err(error) => match probe_once(db).await {
+                    Ok(()) => {
+                        error!("Unable to store a {} row: {}", table, error);
+                        return
+                    },
+                    Err(probe_error) => self.mark_down(&probe_error),
+                },
+            }
+        }
+        let mut buffer = self.buffer.lock().await;
+        match buffer.push(&row) {
+            Ok(()) => debug!("Buffered a {} row, {} waiting for the database", table, buffer.len()),
+            Err(error) => error!("Unable to buffer a {} row in {}, it is lost: {}", table, buffer.path().display(), error),
+        }
+    }
+
+    /// Checks the database once and replays the buffer when it answers<br>
+    /// Returns whether the database answered
+    pub async fn probe(&self, db: &DatabaseConnection) -> bool {
+        match probe_once(db).await {
+            Ok(()) => {
+                self.mark_up();
+                let mut buffer = self.buffer.lock().await;
+                if !buffer.is_empty() {
+                    match buffer.replay(db, || async { probe_once(db).await.is_ok() }).await {
+                        Ok(ReplayReport { stored, rejected, remaining }) => info!("Replayed the database write buffer: {} stored, {} refused, {} still waiting",
+                            stored, rejected, remaining),
+                        Err(error) => error!("Unable to replay the database write buffer {}: {}", buffer.path().display(), error),
+                    }
+                }
+                true
+            },
+            Err(error) => {
+                self.mark_down(&error);
+                false
+            },
+        }
+    }
+
+    /// How long to wait before the next probe, backing off while the database is down
+    pub fn next_delay(&self) -> Duration {
+        let health = self.health.lock().unwrap();
+        match health.status {
+            DbStatus::Up => self.probe_interval,
+            DbStatus::Down => backoff(health.failed_probes, BACKOFF_MAX),
+        }
+    }
+
+    /// Probes on a timer until Rocket shuts down
+    pub async fn run(&self, db: DatabaseConnection, shutdown: Shutdown) {
+        loop {
+            self.probe(&db).await;
+            tokio::select! {
+                _ = time::sleep(self.next_delay()) => (),
+                _ = shutdown.clone() => return,
+            }
+        }
+    }
+
+    fn mark_up(&self) {
+        let mut health = self.health.lock().unwrap();
+        let now: NaiveDateTime = Utc::now().naive_utc();
+        if health.status == DbStatus::Down {
+            info!("Database is reachable again after {} failed probes", health.failed_probes);
+            health.status = DbStatus::Up;
+            health.since = now;
+        }
+        health.last_checked = Some(now);
+        health.last_error = None;
+        health.failed_probes = 0;
+    }
+
+    fn mark_down(&self, error: &DbErr) {
+        let mut health = self.health.lock().unwrap();
+        let now: NaiveDateTime = Utc::now().naive_utc();
+        if health.status == DbStatus::Up {
+            error!("Database is unreachable, buffering readings until it is back: {}", error);
+            health.status = DbStatus::Down;
+            health.since = now;
+        } else {
+            debug!("Database is still unreachable: {}", error);
+        }
+        health.last_checked = Some(now);
+        health.last_error = Some(error.to_string());
+        health.failed_probes = health.failed_probes.saturating_add(1);
+    }
+}
+
+// is_live with a time limit
+async fn probe_once(db: &DatabaseConnection) -> Result<(), DbErr> {
+    match time::timeout(PROBE_TIMEOUT, is_live(db)).await {
+        Ok(result) => result,
+        Err(_) => Err(DbErr::Custom(format!("The database did not answer within {} seconds", PROBE_TIMEOUT.as_secs()))),
+    }
+}
+
+// The wait after a number of failures in a row, doubling from the start up to the cap
+fn backoff(failures: u32, cap: Duration) -> Duration {
+    BACKOFF_START.saturating_mul(1 << failures.saturating_sub(1).min(16)).min(cap)
+}
+
+/// Connects at startup, waiting out a Postgres server that is not up yet<br>
+/// SQLite and settings that can never work, like a bad certificate, fail straight away
+/// # Errors
+/// The last connection error once every attempt has failed
+pub async fn connect_with_retry(config: &DBConfig) -> Result<DatabaseConnection, DbErr> {
+    if config.backend() != DatabaseBackend::Postgres {
+        return config.connect().await
+    }
+    config.check_backend()?;
+    config.check_tls()?;
+    let mut failures: u32 = 0;
+    loop {
+        match config.connect().await {
+            Ok(db) => return Ok(db),
+            Err(error @ DbErr::Custom(_)) => return Err(error),
+            Err(error) => {
+                failures += 1;
+                if failures >= STARTUP_ATTEMPTS {
+                    return Err(error)
+                }
+                let wait: Duration = backoff(failures, STARTUP_BACKOFF_MAX);
+                warn!("Unable to connect to the database, attempt {} of {}, trying again in {} seconds: {}",
+                    failures, STARTUP_ATTEMPTS, wait.as_secs(), error);
+                time::sleep(wait).await;
+            },
+        }
+    }
+}
+
+/// Creates the fairing that starts probing once Rocket has lifted off, using the managed DatabaseConnection
+pub fn fairing(supervisor: Arc<DbSupervisor>) -> AdHoc {
+    AdHoc::on_liftoff("Database supervisor", move |rocket| Box::pin(async move {
+        let Some(db) = rocket.state::<DatabaseConnection>().cloned() else {
+            error!("No database connection is managed, the database supervisor will not start.");
+            return
+        };
+        let shutdown: Shutdown = rocket.shutdown();
+        tokio::spawn(async move { supervisor.run(db, shutdown).await });
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sea_orm::{EntityTrait, PaginatorTrait};
+    use sea_orm::ActiveValue::Set;
+    use uuid::Uuid;
+    use crate::dbman;
+    use crate::schema::pollution_reading;
+
+    fn buffer_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("rusty_thermostat_{}_{}", std::process::id(), name)).join("buffer.ndjson")
+    }
+
+    fn pollution(aqi: i32) -> BufferedRow {
+        BufferedRow::pollution(pollution_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(Utc::now().naive_utc()), aqi: Set(aqi),
+            co: Set(Some(201.9)), no: Set(Some(0.0)), no2: Set(Some(0.8)), o3: Set(Some(68.7)), so2: Set(Some(0.6)), pm2_5: Set(Some(0.5)), pm10: Set(Some(0.5)), nh3: Set(Some(0.1)),
+            lat: Set(None), lon: Set(None) }).unwrap()
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        assert_eq!(backoff(1, BACKOFF_MAX), Duration::from_secs(2));
+        assert_eq!(backoff(3, BACKOFF_MAX), Duration::from_secs(8));
+        assert_eq!(backoff(40, BACKOFF_MAX), BACKOFF_MAX);
+        assert_eq!(backoff(40, STARTUP_BACKOFF_MAX), STARTUP_BACKOFF_MAX);
+    }
+
+    #[tokio::test]
+    async fn outage_buffers_rows_and_recovery_replays_them() {
+        let path: std::path::PathBuf = buffer_path("supervisor");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let supervisor: DbSupervisor = DbSupervisor::new(WriteBuffer::open(&path).unwrap(), 30);
+
+        supervisor.store(&db, pollution(1)).await;
+        assert_eq!(pollution_reading::Entity::find().count(&db).await.unwrap(), 1);
+
+        // Nothing reaches a closed pool, so probes fail and rows queue up
+        let closed: DatabaseConnection = dbman::test_connection().await;
+        closed.clone().close().await.unwrap();
+        assert!(!supervisor.probe(&closed).await);
+        assert!(!supervisor.probe(&closed).await);
+        supervisor.store(&closed, pollution(2)).await;
+        supervisor.store(&closed, pollution(3)).await;
+        let health: DbHealth = supervisor.health().await;
+        assert_eq!((health.status, health.failed_probes, health.buffered_rows), (DbStatus::Down, 2, 2));
+        assert!(health.last_error.is_some());
+        assert_eq!(supervisor.next_delay(), Duration::from_secs(4));
+
+        assert!(supervisor.probe(&db).await);
+        let health: DbHealth = supervisor.health().await;
+        assert_eq!((health.status, health.failed_probes, health.buffered_rows), (DbStatus::Up, 0, 0));
+        let stored: Vec<i32> = pollution_reading::Entity::find().all(&db).await.unwrap().into_iter().map(|row| row.aqi).collect();
+        assert_eq!(stored, vec![1, 2, 3]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,196 @@
+//! # Database TLS
+//! Checks the certificate files a Postgres connection is set up with before anything connects, so a bad or
+//! expired certificate stops startup with a message naming the file instead of a handshake failure later<br>
+//! The checks use the same rustls the driver connects with, so a certificate accepted here is one it can load
+
+use std::fs::File;
+use std::io::BufReader;
+use chrono::{NaiveDateTime, Utc};
+use rustls_pemfile::Item;
+use sea_orm::DbErr;
+use serde_derive::Deserialize;
+
+/// How the Postgres connection uses TLS, with the names libpq gives them
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SslMode {
+    /// Plain text, never TLS
+    Disable,
+    /// TLS without checking who the server is
+    Require,
+    /// TLS with a server certificate signed by a trusted CA
+    VerifyCa,
+    /// TLS with a trusted server certificate issued for the host that was connected to
+    VerifyFull,
+}
+
+impl SslMode {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            SslMode::Disable => "disable",
+            SslMode::Require => "require",
+            SslMode::VerifyCa => "verify-ca",
+            SslMode::VerifyFull => "verify-full",
+        }
+    }
+
+    // True for the modes that check the server certificate
+    pub fn verifies(self) -> bool {
+        matches!(self, SslMode::VerifyCa | SslMode::VerifyFull)
+    }
+}
+
+/// Reads the CA certificates a server is checked against<br>
+/// Returns how many the file held
+/// # Errors
+/// A file that cannot be read, holds no certificate, or holds one that is malformed, expired or not yet valid
+pub fn check_root_cert(path: &str) -> Result<usize, DbErr> {
+    let certs: Vec<Vec<u8>> = read_certs(path)?;
+    let mut store: rustls::RootCertStore = rustls::RootCertStore::empty();
+    for cert in &certs {
+        store.add(&rustls::Certificate(cert.clone()))
+            .map_err(|error| tls_error(path, &format!("is not a usable CA certificate: {}", error)))?;
+        check_validity(path, cert)?;
+    }
+    Ok(certs.len())
+}
+
+/// Reads the certificate and key the application proves who it is with
+/// # Errors
+/// Either file cannot be read, the certificate is malformed or out of date, or the key file holds no private key
+pub fn check_client_identity(cert_path: &str, key_path: &str) -> Result<(), DbErr> {
+    for cert in read_certs(cert_path)? {
+        check_validity(cert_path, &cert)?;
+    }
+    let mut reader: BufReader<File> = BufReader::new(File::open(key_path)
+        .map_err(|error| tls_error(key_path, &format!("cannot be read: {}", error)))?);
+    loop {
+        match rustls_pemfile::read_one(&mut reader).map_err(|error| tls_error(key_path, &format!("cannot be read: {}", error)))? {
+            Some(Item::RSAKey(_)) | Some(Item::PKCS8Key(_)) | Some(Item::ECKey(_)) => return Ok(()),
+            Some(_) => continue,
+            None => return Err(tls_error(key_path, "holds no private key")),
+        }
+    }
+}
+
+fn read_certs(path: &str) -> Result<Vec<Vec<u8>>, DbErr> {
+    let mut reader: BufReader<File> = BufReader::new(File::open(path)
+        .map_err(|error| tls_error(path, &format!("cannot be read: {}", error)))?);
+    let certs: Vec<Vec<u8>> = rustls_pemfile::certs(&mut reader)
+        .map_err(|error| tls_error(path, &format!("cannot be read: {}", error)))?;
+    if certs.is_empty() {
+        return Err(tls_error(path, "holds no PEM certificate"))
+    }
+    Ok(certs)
+}
+
+fn check_validity(path: &str, cert: &[u8]) -> Result<(), DbErr> {
+    let Some((not_before, not_after)) = validity(cert) else {
+        return Err(tls_error(path, "holds a certificate whose dates cannot be read"))
+    };
+    let now: NaiveDateTime = Utc::now().naive_utc();
+    if now < not_before {
+        return Err(tls_error(path, &format!("holds a certificate that is not valid until {}", not_before)))
+    }
+    if now > not_after {
+        return Err(tls_error(path, &format!("holds a certificate that expired {}", not_after)))
+    }
+    Ok(())
+}
+
+fn tls_error(path: &str, problem: &str) -> DbErr {
+    DbErr::Custom(format!("Database TLS file {} {}", path, problem))
+}
+
+// The notBefore and notAfter of a DER certificate. They sit in the TBSCertificate after the optional version,
+// serial number, signature algorithm and issuer
+fn validity(cert: &[u8]) -> Option<(NaiveDateTime, NaiveDateTime)> {
+    let (_, certificate, _) = der_next(cert)?;
+    let (_, tbs, _) = der_next(certificate)?;
+    let (tag, _, mut rest) = der_next(tbs)?;
+    // The first field is the explicitly tagged version, or the serial number when the version is left out
+    let skip: usize = if tag == 0xa0 { 3 } else { 2 };
+    for _ in 0..skip {
+        rest = der_next(rest)?.2;
+    }
+    let (_, validity, _) = der_next(rest)?;
+    let (before_tag, not_before, rest) = der_next(validity)?;
+    let (after_tag, not_after, _) = der_next(rest)?;
+    Some((der_time(before_tag, not_before)?, der_time(after_tag, not_after)?))
+}
+
+// Splits the first DER value off the front of the input as its tag, contents and whatever follows
+fn der_next(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = input.split_first()?;
+    let (&first, mut rest) = rest.split_first()?;
+    let length: usize = if first & 0x80 == 0 {
+        first as usize
+    } else {
+        let count: usize = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None
+        }
+        let (bytes, after) = rest.split_at(count);
+        rest = after;
+        bytes.iter().fold(0, |length, byte| (length << 8) | *byte as usize)
+    };
+    if rest.len() < length {
+        return None
+    }
+    let (contents, rest) = rest.split_at(length);
+    Some((tag, contents, rest))
+}
+
+// UTCTime has a two digit year, 50 and up being the 1900s, GeneralizedTime has all four
+fn der_time(tag: u8, contents: &[u8]) -> Option<NaiveDateTime> {
+    let text: &str = std::str::from_utf8(contents).ok()?;
+    let full: String = match tag {
+        0x17 => format!("{}{}", if text.get(..2)? >= "50" { "19" } else { "20" }, text),
+        0x18 => text.to_string(),
+        _ => return None,
+    };
+    NaiveDateTime::parse_from_str(&full, "%Y%m%d%H%M%SZ").ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(name: &str) -> String {
+        format!("{}/tests/fixtures/tls/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[test]
+    fn modes_use_libpq_names() {
+        let mode: SslMode = serde_json::from_str("\"verify-full\"").unwrap();
+        assert_eq!(mode, SslMode::VerifyFull);
+        assert_eq!(mode.as_str(), "verify-full");
+        assert!(mode.verifies());
+        assert!(!SslMode::Require.verifies());
+        assert!(serde_json::from_str::<SslMode>("\"prefer\"").is_err());
+    }
+
+    #[test]
+    fn good_certificates_pass() {
+        assert_eq!(check_root_cert(&fixture("ca.pem")).unwrap(), 1);
+        check_client_identity(&fixture("client.pem"), &fixture("client.key")).unwrap();
+    }
+
+    #[test]
+    fn bad_certificates_are_refused() {
+        let expired: String = check_root_cert(&fixture("expired.pem")).unwrap_err().to_string();
+        assert!(expired.contains("expired 2021-01-01"), "{}", expired);
+        assert!(check_root_cert(&fixture("not_a_cert.pem")).is_err());
+        assert!(check_root_cert(&fixture("missing.pem")).unwrap_err().to_string().contains("cannot be read"));
+        // A certificate file has no key in it
+        assert!(check_client_identity(&fixture("client.pem"), &fixture("client.pem")).is_err());
+    }
+
+    #[test]
+    fn both_kinds_of_der_time_are_read() {
+        assert_eq!(der_time(0x17, b"491231235959Z"), NaiveDateTime::parse_from_str("20491231235959", "%Y%m%d%H%M%S").ok());
+        assert_eq!(der_time(0x17, b"700101000000Z"), NaiveDateTime::parse_from_str("19700101000000", "%Y%m%d%H%M%S").ok());
+        assert_eq!(der_time(0x18, b"21260101000000Z"), NaiveDateTime::parse_from_str("21260101000000", "%Y%m%d%H%M%S").ok());
+        assert_eq!(der_time(0x02, b"20260101000000Z"), None);
+    }
+}
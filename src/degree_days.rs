@@ -0,0 +1,415 @@
+//! # Rusty Thermostat Degree Days
+//! Heating and cooling degree days worked out from the WeatherReading history, one row per UTC day<br>
+//! Each hour of the day gets the average of the readings taken in it. Hours without readings are interpolated
+//! from the nearest hours that have them, so a collector outage does not skew a whole day<br>
+//! Degree days are kept in Celsius degrees like every other temperature and converted on the way out
+
+use std::collections::{BTreeMap, HashSet};
+use chrono::{Duration, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc};
+use sea_orm::{ColumnTrait, DatabaseConnection, DbErr, EntityTrait, QueryFilter, QueryOrder, QuerySelect};
+use sea_orm::ActiveValue::{Set, NotSet};
+use serde_derive::Serialize;
+use crate::units::{self, Temperature, UnitSystem};
+use crate::schema::{daily_degree_days, weather_reading};
+
+/// Days with fewer observed hours than this are left out rather than guessed
+pub const MIN_OBSERVED_HOURS: usize = 6;
+/// How far back the job looks for days that have not been worked out yet
+pub const MAX_CATCH_UP_DAYS: i64 = 366;
+
+/// Base temperature used when none is configured: 65F for imperial units and 18C otherwise
+pub fn default_base(units: UnitSystem) -> Temperature {
+    match units {
+        UnitSystem::Imperial => Temperature::from_fahrenheit(65.0),
+        _ => Temperature::from_celsius(18.0),
+    }
+}
+
+/// Degree days for a single day, hdd and cdd are in Celsius degree days
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeDay {
+    pub day: NaiveDate,
+    pub base_temp: Temperature,
+    pub mean_temp: Temperature,
+    pub hdd: f64,
+    pub cdd: f64,
+    pub hours_observed: i32,
+    pub hours_interpolated: i32,
+}
+
+/// Degree days for a single day in one unit system, the way the API hands them out
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DegreeDayReport {
+    pub units: UnitSystem,
+    pub day: NaiveDate,
+    pub base_temp: f64,
+    pub mean_temp: f64,
+    pub hdd: f64,
+    pub cdd: f64,
+    pub hours_observed: i32,
+    pub hours_interpolated: i32,
+}
+
+impl DegreeDay {
+    // Consumes the DegreeDay into an ActiveModel to be put into the DB
+    pub fn generate_db_model(self) -> daily_degree_days::ActiveModel {
+        daily_degree_days::ActiveModel {
+            id: NotSet,
+            day: Set(self.day),
+            base_temp: Set(self.base_temp),
+            mean_temp: Set(self.mean_temp),
+            hdd: Set(self.hdd),
+            cdd: Set(self.cdd),
+            hours_observed: Set(self.hours_observed),
+            hours_interpolated: Set(self.hours_interpolated),
+            computed_at: Set(Utc::now().naive_utc()),
+        }
+    }
+    /// Gets the degree days as plain numbers in the unit system
+    pub fn report(&self, units: UnitSystem) -> DegreeDayReport {
+        DegreeDayReport {
+            units,
+            day: self.day,
+            base_temp: self.base_temp.in_units(units),
+            mean_temp: self.mean_temp.in_units(units),
+            hdd: units::degrees_in_units(self.hdd, units),
+            cdd: units::degrees_in_units(self.cdd, units),
+            hours_observed: self.hours_observed,
+            hours_interpolated: self.hours_interpolated,
+        }
+    }
+}
+
+impl From<daily_degree_days::Model> for DegreeDay {
+    fn from(stored: daily_degree_days::Model) -> Self {
+        DegreeDay {
+            day: stored.day,
+            base_temp: stored.base_temp,
+            mean_temp: stored.mean_temp,
+            hdd: stored.hdd,
+            cdd: stored.cdd,
+            hours_observed: stored.hours_observed,
+            hours_interpolated: stored.hours_interpolated,
+        }
+    }
+}
+
+/// Works out the degree days for one day from temperature readings<br>
+/// Readings from the days either side are used to interpolate hours at the edges of the day. Returns None when the day
+/// itself has fewer than MIN_OBSERVED_HOURS hours with readings
+pub fn compute_day(day: NaiveDate, readings: &[(NaiveDateTime, Temperature)], base_temp: Temperature) -> Option<DegreeDay> {
+    let day_start: NaiveDateTime = day.and_hms_opt(0, 0, 0)?;
+    // Hourly averages keyed by whole hours from the start of the day, so yesterday's last hour is -1
+    let mut hourly: Vec<(i64, f64, u32)> = Vec::new();
+    for (taken_at, temp) in readings {
+        let hour: i64 = (*taken_at - day_start).num_seconds().div_euclid(3600);
+        match hourly.iter_mut().find(|(known, _, _)| *known == hour) {
+            Some((_, total, count)) => {
+                *total += temp.celsius();
+                *count += 1;
+            },
+            None => hourly.push((hour, temp.celsius(), 1)),
+        }
+    }
+    let mut known: Vec<(i64, f64)> = hourly.into_iter().map(|(hour, total, count)| (hour, total / count as f64)).collect();
+    known.sort_by_key(|(hour, _)| *hour);
+
+    let mut temps: Vec<f64> = Vec::with_capacity(24);
+    let mut observed: usize = 0;
+    for hour in 0..24 {
+        if let Some((_, temp)) = known.iter().find(|(known_hour, _)| *known_hour == hour) {
+            observed += 1;
+            temps.push(*temp);
+            continue
+        }
+        let before: Option<&(i64, f64)> = known.iter().rev().find(|(known_hour, _)| *known_hour < hour);
+        let after: Option<&(i64, f64)> = known.iter().find(|(known_hour, _)| *known_hour > hour);
+        let filled: f64 = match (before, after) {
+            (Some((left_hour, left)), Some((right_hour, right))) => {
+                left + (right - left) * (hour - left_hour) as f64 / (right_hour - left_hour) as f64
+            },
+            (Some((_, only)), None) | (None, Some((_, only))) => *only,
+            (None, None) => return None,
+        };
+        temps.push(filled);
+    }
+    if observed < MIN_OBSERVED_HOURS {
+        return None
+    }
+
+    let base: f64 = base_temp.celsius();
+    let hdd: f64 = temps.iter().map(|temp| (base - temp).max(0.0)).sum::<f64>() / 24.0;
+    let cdd: f64 = temps.iter().map(|temp| (temp - base).max(0.0)).sum::<f64>() / 24.0;
+    Some(DegreeDay {
+        day,
+        base_temp,
+        mean_temp: Temperature::from_celsius(temps.iter().sum::<f64>() / 24.0),
+        hdd,
+        cdd,
+        hours_observed: observed as i32,
+        hours_interpolated: (24 - observed) as i32,
+    })
+}
+
+/// Works out and stores degree days for every finished day that has none stored and enough readings<br>
+/// Days are UTC days. Today is left alone until it is over. A day skipped for too few readings is picked up on a later
+/// run once readings for it arrive, an import for one, and costs nothing while it has none
+/// # Errors
+/// Returns the database error if the readings can't be read or the results can't be stored
+pub async fn run_job(db: &DatabaseConnection, base_temp: Temperature) -> Result<usize, DbErr> {
+    let today: NaiveDate = Utc::now().date_naive();
+    let earliest: NaiveDate = today - Duration::days(MAX_CATCH_UP_DAYS);
+    let already_stored: HashSet<NaiveDate> = daily_degree_days::Entity::find()
+        .select_only()
+        .column(daily_degree_days::Column::Day)
+        .filter(daily_degree_days::Column::Day.gte(earliest))
+        .into_tuple()
+        .all(db)
+        .await?
+        .into_iter()
+        .collect();
+    let waiting: Vec<NaiveDate> = days_with_enough_readings(db, earliest, today).await?
+        .into_iter().filter(|day| !already_stored.contains(day)).collect();
+    if waiting.is_empty() {
+        debug!("No finished days with enough weather readings are waiting for degree days.");
+        return Ok(0)
+    }
+
+    let mut stored: usize = 0;
+    for day in waiting {
+        let readings: Vec<(NaiveDateTime, Temperature)> = readings_around(db, day).await?;
+        match compute_day(day, &readings, base_temp) {
+            Some(result) => {
+                debug!("Degree days for {}: HDD {:.2}, CDD {:.2} with {} hours interpolated", day, result.hdd, result.cdd, result.hours_interpolated);
+                daily_degree_days::Entity::insert(result.generate_db_model()).exec(db).await?;
+                stored += 1;
+            },
+            None => info!("Not enough weather readings on {} to work out degree days.", day),
+        }
+    }
+    Ok(stored)
+}
+
+// Days from the first date up to but not including the last with readings in at least MIN_OBSERVED_HOURS of their
+// hours, earliest first
+async fn days_with_enough_readings(db: &DatabaseConnection, from: NaiveDate, until: NaiveDate) -> Result<Vec<NaiveDate>, DbErr> {
+    let taken: Vec<NaiveDateTime> = weather_reading::Entity::find()
+        .select_only()
+        .column(weather_reading::Column::Timestamp)
+        .filter(weather_reading::Column::Timestamp.gte(from.and_time(NaiveTime::MIN)))
+        .filter(weather_reading::Column::Timestamp.lt(until.and_time(NaiveTime::MIN)))
+        .into_tuple()
+        .all(db)
+        .await?;
+    let mut hours: BTreeMap<NaiveDate, HashSet<u32>> = BTreeMap::new();
+    for taken_at in taken {
+        hours.entry(taken_at.date()).or_default().insert(taken_at.hour());
+    }
+    Ok(hours.into_iter().filter(|(_, hours)| hours.len() >= MIN_OBSERVED_HOURS).map(|(day, _)| day).collect())
+}
+
+// Readings from the start of the day before to the end of the day after
+async fn readings_around(db: &DatabaseConnection, day: NaiveDate) -> Result<Vec<(NaiveDateTime, Temperature)>, DbErr> {
+    let Some(start) = (day - Duration::days(1)).and_hms_opt(0, 0, 0) else {
+        return Ok(Vec::new())
+    };
+    let end: NaiveDateTime = start + Duration::days(3);
+    weather_reading::Entity::find()
+        .select_only()
+        .column(weather_reading::Column::Timestamp)
+        .column(weather_reading::Column::TempReal)
+        .filter(weather_reading::Column::Timestamp.gte(start))
+        .filter(weather_reading::Column::Timestamp.lt(end))
+        .order_by_asc(weather_reading::Column::Timestamp)
+        .into_tuple()
+        .all(db)
+        .await
+}
+
+/// Gets the stored degree days between two dates, both included, earliest first
+/// # Errors
+/// Returns the database error if the query fails
+pub async fn in_range(db: &DatabaseConnection, from: NaiveDate, to: NaiveDate) -> Result<Vec<DegreeDay>, DbErr> {
+    let stored: Vec<daily_degree_days::Model> = daily_degree_days::Entity::find()
+        .filter(daily_degree_days::Column::Day.between(from, to))
+        .order_by_asc(daily_degree_days::Column::Day)
+        .all(db)
+        .await?;
+    Ok(stored.into_iter().map(DegreeDay::from).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::Speed;
+    use crate::dbman;
+    use sea_orm::ActiveModelTrait;
+    use uuid::Uuid;
+
+    fn day() -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 15).unwrap()
+    }
+
+    fn at(day: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+        day.and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn celsius(temp: f64) -> Temperature {
+        Temperature::from_celsius(temp)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {} to be {}", actual, expected);
+    }
+
+    #[test]
+    fn constant_temperature_below_base_is_all_heating() {
+        let readings: Vec<(NaiveDateTime, Temperature)> = (0..24).map(|hour| (at(day(), hour, 10), celsius(50.0))).collect();
+
+        let result = compute_day(day(), &readings, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, 15.0);
+        assert_close(result.cdd, 0.0);
+        assert_eq!(result.hours_observed, 24);
+        assert_eq!(result.hours_interpolated, 0);
+    }
+
+    #[test]
+    fn temperature_crossing_base_splits_heating_and_cooling() {
+        let readings: Vec<(NaiveDateTime, Temperature)> = (0..24)
+            .map(|hour| (at(day(), hour, 0), celsius(if hour < 12 { 60.0 } else { 70.0 }))).collect();
+
+        let result = compute_day(day(), &readings, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, 2.5);
+        assert_close(result.cdd, 2.5);
+        assert_close(result.mean_temp.celsius(), 65.0);
+    }
+
+    #[test]
+    fn readings_in_one_hour_are_averaged() {
+        let mut readings: Vec<(NaiveDateTime, Temperature)> = (0..24).map(|hour| (at(day(), hour, 0), celsius(60.0))).collect();
+        readings.push((at(day(), 5, 30), celsius(40.0)));
+
+        let result = compute_day(day(), &readings, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, (23.0 * 5.0 + 15.0) / 24.0);
+    }
+
+    #[test]
+    fn missing_hours_are_interpolated() {
+        // A ramp from 40 to 63 with every other hour missing should come out the same as the full ramp
+        let full: Vec<(NaiveDateTime, Temperature)> = (0..24).map(|hour| (at(day(), hour, 0), celsius(40.0 + hour as f64))).collect();
+        let mut gappy: Vec<(NaiveDateTime, Temperature)> = full.iter().copied().filter(|(taken_at, _)| taken_at.hour() % 2 == 0).collect();
+        gappy.push((at(day(), 23, 0), celsius(63.0)));
+
+        let expected = compute_day(day(), &full, celsius(65.0)).unwrap();
+        let result = compute_day(day(), &gappy, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, expected.hdd);
+        assert_eq!(result.hours_interpolated, 11);
+    }
+
+    #[test]
+    fn edge_hours_use_neighbouring_days() {
+        let previous: NaiveDate = day() - Duration::days(1);
+        let next: NaiveDate = day() + Duration::days(1);
+        let mut readings: Vec<(NaiveDateTime, Temperature)> = (6..18).map(|hour| (at(day(), hour, 0), celsius(50.0))).collect();
+        readings.push((at(previous, 23, 30), celsius(50.0)));
+        readings.push((at(next, 0, 30), celsius(50.0)));
+
+        let result = compute_day(day(), &readings, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, 15.0);
+        assert_eq!(result.hours_observed, 12);
+    }
+
+    #[test]
+    fn edge_hours_without_neighbours_hold_the_nearest_value() {
+        let readings: Vec<(NaiveDateTime, Temperature)> = (8..16).map(|hour| (at(day(), hour, 0), celsius(55.0))).collect();
+
+        let result = compute_day(day(), &readings, celsius(65.0)).unwrap();
+
+        assert_close(result.hdd, 10.0);
+    }
+
+    #[test]
+    fn too_few_hours_is_none() {
+        let readings: Vec<(NaiveDateTime, Temperature)> = (0..MIN_OBSERVED_HOURS as u32 - 1).map(|hour| (at(day(), hour, 0), celsius(55.0))).collect();
+
+        assert!(compute_day(day(), &readings, celsius(65.0)).is_none());
+        assert!(compute_day(day(), &[], celsius(65.0)).is_none());
+    }
+
+    #[test]
+    fn default_base_follows_units() {
+        assert_close(default_base(UnitSystem::Imperial).fahrenheit(), 65.0);
+        assert_close(default_base(UnitSystem::Metric).celsius(), 18.0);
+    }
+
+    #[test]
+    fn report_converts_degree_days_as_differences() {
+        let readings: Vec<(NaiveDateTime, Temperature)> = (0..24).map(|hour| (at(day(), hour, 0), celsius(8.0))).collect();
+        let result = compute_day(day(), &readings, celsius(18.0)).unwrap();
+
+        let report: DegreeDayReport = result.report(UnitSystem::Imperial);
+
+        assert_close(report.hdd, 18.0);
+        assert_close(report.base_temp, 64.4);
+        assert_close(report.mean_temp, 46.4);
+    }
+
+    fn reading(timestamp: NaiveDateTime, temp: f64) -> weather_reading::ActiveModel {
+        weather_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(timestamp), condition: Set(Some("Clear".to_string())),
+            description: Set(Some("clear sky".to_string())), icon: Set(Some("01d".to_string())), temp_real: Set(celsius(temp)), temp_feel: Set(celsius(temp)),
+            pressure_sea: Set(1015), humidity: Set(50), pressure_ground: Set(Some(1010)), visibility: Set(Some(10000)), wind_speed: Set(Speed::from_meters_per_second(1.0)),
+            wind_deg: Set(0), wind_gust: Set(Some(Speed::from_meters_per_second(1.0))), rain1_h: Set(None), rain3_h: Set(None), snow1_h: Set(None), snow3_h: Set(None),
+            clouds: Set(0), dt: Set(0), sunrise: Set(0), sunset: Set(0), dew_point: Set(None), heat_index: Set(None),
+            wind_chill: Set(None), humidex: Set(None), absolute_humidity: Set(None), lat: Set(None), lon: Set(None) }
+    }
+
+    #[tokio::test]
+    async fn job_stores_each_finished_day_once() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let today: NaiveDate = Utc::now().date_naive();
+        for days_ago in 1..=2 {
+            let past: NaiveDate = today - Duration::days(days_ago);
+            for hour in 0..24 {
+                reading(at(past, hour, 15), 10.0).insert(&db).await.unwrap();
+            }
+        }
+
+        let first_run: usize = run_job(&db, celsius(18.0)).await.unwrap();
+        let second_run: usize = run_job(&db, celsius(18.0)).await.unwrap();
+        let stored: Vec<DegreeDay> = in_range(&db, today - Duration::days(7), today).await.unwrap();
+
+        assert_eq!(first_run, 2);
+        assert_eq!(second_run, 0);
+        assert_eq!(stored.len(), 2);
+        assert_close(stored[0].hdd, 8.0);
+        assert_eq!(stored[1].day, today - Duration::days(1));
+    }
+
+    #[tokio::test]
+    async fn job_picks_up_a_skipped_day_once_its_readings_arrive() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let today: NaiveDate = Utc::now().date_naive();
+        let (gap, after) = (today - Duration::days(3), today - Duration::days(2));
+        for hour in 0..24 {
+            reading(at(after, hour, 15), 10.0).insert(&db).await.unwrap();
+        }
+        for hour in 0..3 {
+            reading(at(gap, hour, 15), 10.0).insert(&db).await.unwrap();
+        }
+        assert_eq!(run_job(&db, celsius(18.0)).await.unwrap(), 1);
+
+        // Readings for the gap show up later, behind the day already stored
+        for hour in 3..24 {
+            reading(at(gap, hour, 15), 12.0).insert(&db).await.unwrap();
+        }
+        assert_eq!(run_job(&db, celsius(18.0)).await.unwrap(), 1);
+        let stored: Vec<DegreeDay> = in_range(&db, gap, after).await.unwrap();
+        assert_eq!(stored.iter().map(|day| day.day).collect::<Vec<NaiveDate>>(), vec![gap, after]);
+        assert_eq!(run_job(&db, celsius(18.0)).await.unwrap(), 0);
+    }
+}
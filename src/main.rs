@@ -1,11 +1,25 @@
+use std::sync::Arc;
 use rocket::figment::providers::{Toml, Format, Env};
 use rocket::State;
+use rocket::http::Status;
+use rocket::serde::json::Json;
 use sea_orm::DatabaseConnection;
+use chrono::NaiveDate;
 use serde_derive::Deserialize;
+use units::{Temperature, UnitSystem};
 
 pub mod weather;
 pub mod schema;
 pub mod dbman;
+pub mod collector;
+pub mod psychrometrics;
+pub mod degree_days;
+pub mod units;
+pub mod repo;
+pub mod retention;
+pub mod transfer;
+pub mod backup;
+pub mod api;
 
 #[macro_use] extern crate rocket;
 #[macro_use] extern crate log;
@@ -14,7 +28,11 @@ pub mod dbman;
 struct AppConfiguration {
     weather: WeatherSettings,
     database: DatabaseSettings,
-    logging: LogSettings
+    logging: LogSettings,
+    #[serde(default)]
+    retention: RetentionSettings,
+    #[serde(default)]
+    api: ApiSettings
 }
 
 impl Default for AppConfiguration {
@@ -23,7 +41,9 @@ impl Default for AppConfiguration {
         AppConfiguration {
             weather: WeatherSettings::default(),
             database: DatabaseSettings::default(),
-            logging: LogSettings::default()
+            logging: LogSettings::default(),
+            retention: RetentionSettings::default(),
+            api: ApiSettings::default()
         }
     }
 }
@@ -49,23 +69,38 @@ impl Default for LogSettings {
 struct WeatherSettings {
     monitor_weather: bool,
     monitor_pollution: bool,
-    zip_code: String,
+    monitor_forecast: Option<bool>,
+    provider: Option<String>,
+    zip_code: Option<String>,
+    city: Option<String>,
     country: Option<String>,
+    latitude: Option<f32>,
+    longitude: Option<f32>,
     units: Option<String>,
-    openweather_apikey: Option<String>
+    openweather_apikey: Option<String>,
+    weather_interval: Option<u64>,
+    pollution_interval: Option<u64>,
+    forecast_interval: Option<u64>,
+    quota_per_minute: Option<u32>,
+    quota_per_day: Option<u32>,
+    retry_attempts: Option<u32>,
+    degree_day_base: Option<f64>
 }
 
 impl WeatherSettings {
     fn is_active(&self) -> bool {
         if self.monitor_weather {
             trace!("Monitor weather set to true");
-            return true
+            true
         } else if self.monitor_pollution {
             trace!("Monitor pollution set to true");
-            return true
+            true
+        } else if self.monitor_forecast.unwrap_or(false) {
+            trace!("Monitor forecast set to true");
+            true
         } else {
-            trace!("Neither weather or pollution being monitored");
-            return false
+            trace!("Neither weather, pollution or forecast being monitored");
+            false
         }
     }
 }
@@ -76,10 +111,22 @@ impl Default for WeatherSettings {
         WeatherSettings {
             monitor_weather: false,
             monitor_pollution: false,
-            zip_code: "N/A".to_string(),
+            monitor_forecast: None,
+            provider: None,
+            zip_code: None,
+            city: None,
             country: None,
+            latitude: None,
+            longitude: None,
             units: None,
-            openweather_apikey: None
+            openweather_apikey: None,
+            weather_interval: None,
+            pollution_interval: None,
+            forecast_interval: None,
+            quota_per_minute: None,
+            quota_per_day: None,
+            retry_attempts: None,
+            degree_day_base: None
         }
     }
 }
@@ -93,6 +140,13 @@ struct DatabaseSettings {
     user: Option<String>,
     passw: Option<String>,
     schema_path: Option<String>,
+    auto_migrate: Option<bool>,
+    sslmode: Option<dbman::SslMode>,
+    ssl_root_cert: Option<String>,
+    ssl_client_cert: Option<String>,
+    ssl_client_key: Option<String>,
+    buffer_path: Option<String>,
+    probe_interval: Option<u64>,
 }
 
 impl Default for DatabaseSettings {
@@ -106,13 +160,51 @@ impl Default for DatabaseSettings {
             user: None,
             passw: None,
             schema_path: None,
+            auto_migrate: None,
+            sslmode: None,
+            ssl_root_cert: None,
+            ssl_client_cert: None,
+            ssl_client_key: None,
+            buffer_path: None,
+            probe_interval: None,
         }
     }
 }
 
-async fn parse_weather(fig: &AppConfiguration) -> Result<weather::Configuration, weather::APIError> {
+#[derive(Clone, Debug, Default, Deserialize)]
+struct RetentionSettings {
+    enabled: Option<bool>,
+    raw_max_age_days: Option<u32>,
+    interval: Option<u64>
+}
+
+#[derive(Clone, Debug, Default, Deserialize)]
+struct ApiSettings {
+    admin_token: Option<String>
+}
+
+fn parse_quota(fig: &AppConfiguration) -> Arc<weather::ApiQuota> {
+    // Unset limits use the free OpenWeather tier, 0 turns a limit off
+    let limit = |setting: Option<u32>, default: u32| -> Option<u32> {
+        match setting.unwrap_or(default) {
+            0 => None,
+            set_limit => Some(set_limit),
+        }
+    };
+    let per_minute: Option<u32> = limit(fig.weather.quota_per_minute, weather::quota::DEFAULT_PER_MINUTE);
+    let per_day: Option<u32> = limit(fig.weather.quota_per_day, weather::quota::DEFAULT_PER_DAY);
+    debug!("API quota per minute: {:?}, per day: {:?}", per_minute, per_day);
+    Arc::new(weather::ApiQuota::new(per_minute, per_day))
+}
+
+async fn parse_weather(fig: &AppConfiguration, quota: Arc<weather::ApiQuota>) -> Result<weather::Configuration, weather::WeatherError> {
     let mut wea_config: weather::Configuration = weather::Configuration::new();
     let wea_part: WeatherSettings = fig.weather.clone();
+    wea_config.quota = quota;
+    if let Some(attempts) = wea_part.retry_attempts {
+        wea_config.retry = weather::RetryPolicy::new(attempts);
+        debug!("Set retry attempts: {}", attempts);
+    }
     if let Some(set_units) = wea_part.units {
         wea_config.set_units(&set_units);
         debug!("Set units: {}", set_units);
@@ -123,18 +215,72 @@ async fn parse_weather(fig: &AppConfiguration) -> Result<weather::Configuration,
         trace!("API key detected as: {}", key);
     }
 
-    let mut zip_local: String = String::from(wea_part.zip_code);
-    debug!("Found ZIP of: {}", zip_local);
-    if let Some(country) = wea_part.country {
-        zip_local = format!("{},{}", zip_local, country);
-        debug!("Found country setting of: {}", country);
-        debug!("Zip now reads as: {}", zip_local);
-    }
-    wea_config.location = Some(wea_config.parse_zipcode(&zip_local).await?);
+    // Coordinates are the most precise, then a city name, then a zip code
+    let location: weather::GeoLocation = match (wea_part.latitude, wea_part.longitude, wea_part.city, wea_part.zip_code) {
+        (Some(lat), Some(lon), _, _) => {
+            debug!("Found coordinates of: {},{}", lat, lon);
+            match wea_config.reverse_geocode(lat, lon).await {
+                Ok(named) => named,
+                Err(error) => {
+                    warn!("Unable to name the location at {},{}: {}", lat, lon, error);
+                    weather::GeoLocation::from_coordinates(lat, lon)
+                }
+            }
+        },
+        (_, _, Some(city), _) => {
+            debug!("Found city of: {}", city);
+            wea_config.parse_city(&city).await?
+        },
+        (_, _, _, Some(zip_code)) => {
+            let mut zip_local: String = zip_code;
+            debug!("Found ZIP of: {}", zip_local);
+            if let Some(country) = wea_part.country {
+                zip_local = format!("{},{}", zip_local, country);
+                debug!("Found country setting of: {}", country);
+                debug!("Zip now reads as: {}", zip_local);
+            }
+            wea_config.parse_zipcode(&zip_local).await?
+        },
+        _ => return Err(weather::WeatherError::MissingConfig("latitude and longitude, city or zip_code".to_string())),
+    };
+    debug!("Weather location set to: {}, {}", location.name, location.country);
+    wea_config.location = Some(location);
 
     Ok(wea_config)
 }
 
+async fn parse_provider(fig: &AppConfiguration, quota: Arc<weather::ApiQuota>) -> Result<Arc<dyn weather::WeatherProvider>, weather::WeatherError> {
+    let wea_part: WeatherSettings = fig.weather.clone();
+    let provider_kind: weather::ProviderKind = match &wea_part.provider {
+        Some(provider) => weather::ProviderKind::parse(provider),
+        None => weather::ProviderKind::default(),
+    };
+    debug!("Weather provider set to: {:?}", provider_kind);
+
+    match provider_kind {
+        weather::ProviderKind::OpenWeather => Ok(Arc::new(parse_weather(fig, quota).await?)),
+        weather::ProviderKind::OpenMeteo => {
+            // Open-Meteo has no geocoder of its own, so a city or zip code is looked up through OpenWeather
+            let location: weather::GeoLocation = match (wea_part.latitude, wea_part.longitude, &wea_part.openweather_apikey) {
+                (Some(lat), Some(lon), _) => weather::GeoLocation::from_coordinates(lat, lon),
+                (_, _, Some(_)) => match parse_weather(fig, quota).await?.location {
+                    Some(location) => location,
+                    None => return Err(weather::WeatherError::MissingConfig("latitude and longitude, city or zip_code".to_string())),
+                },
+                _ => return Err(weather::WeatherError::MissingConfig(
+                    "latitude and longitude, or openweather_apikey to look up the city or zip_code".to_string())),
+            };
+            debug!("Open-Meteo location: {}", location.create_uri());
+            let mut open_meteo: weather::open_meteo::OpenMeteo = weather::open_meteo::OpenMeteo::new(location);
+            if let Some(set_units) = wea_part.units {
+                open_meteo.set_units(&set_units);
+                debug!("Set units: {}", set_units);
+            }
+            Ok(Arc::new(open_meteo))
+        }
+    }
+}
+
 fn parse_db(fig: &AppConfiguration) -> dbman::DBConfig {
     let mut data_config: dbman::DBConfig = dbman::DBConfig::default();
     let local_db_sets: DatabaseSettings = fig.database.clone();
@@ -156,28 +302,30 @@ fn parse_db(fig: &AppConfiguration) -> dbman::DBConfig {
             local_db_sets.db_name.unwrap_or("rusty_thermostat".to_string()),
             local_db_sets.schema_path,
             local_db_sets.db_port);
+        data_config.ssl_mode = local_db_sets.sslmode;
+        data_config.ssl_root_cert = local_db_sets.ssl_root_cert;
+        data_config.ssl_client_cert = local_db_sets.ssl_client_cert;
+        data_config.ssl_client_key = local_db_sets.ssl_client_key;
     } else if data_type == "sqlite" {
         trace!("sqlite database being set.");
-        data_config = dbman::DBConfig::new_sqlite(local_db_sets.host);  
+        data_config = dbman::DBConfig::new_sqlite(local_db_sets.host);
+    } else {
+        warn!("Unknown database type {}, using an in-memory SQLite database", local_db_sets.database_type);
     }
 
     data_config
 }
 
-fn parse_log(fig: &AppConfiguration) -> () {
+fn parse_log(fig: &AppConfiguration) {
     let log_sets: LogSettings = fig.logging.clone();
-    let mut log_leveling: simplelog::LevelFilter = simplelog::LevelFilter::Error;
-    if log_sets.log_level.is_some() {
-        match log_sets.log_level.unwrap().to_lowercase().as_str() {
-            "debug" => log_leveling = simplelog::LevelFilter::Debug,
-            "info" => log_leveling = simplelog::LevelFilter::Info,
-            "error" => log_leveling = simplelog::LevelFilter::Error,
-            "warn" => log_leveling = simplelog::LevelFilter::Warn,
-            "trace" => log_leveling = simplelog::LevelFilter::Trace,
-            "off" => log_leveling = simplelog::LevelFilter::Off,
-            _ => log_leveling = simplelog::LevelFilter::Error,
-        }
-    }
+    let log_leveling: simplelog::LevelFilter = match log_sets.log_level.unwrap_or_default().to_lowercase().as_str() {
+        "debug" => simplelog::LevelFilter::Debug,
+        "info" => simplelog::LevelFilter::Info,
+        "warn" => simplelog::LevelFilter::Warn,
+        "trace" => simplelog::LevelFilter::Trace,
+        "off" => simplelog::LevelFilter::Off,
+        _ => simplelog::LevelFilter::Error,
+    };
     let logging_configuration = simplelog::ConfigBuilder::new()
         .set_time_format_rfc3339().build();
     if log_sets.enabled {
@@ -211,26 +359,322 @@ async fn db_ping(db: &State<DatabaseConnection>) -> &'static str {
 
 }
 
-#[launch]
-async fn rocket() -> _ {
-    let figment: rocket::figment::Figment = rocket::Config::figment()
-        .merge(Toml::file("config/rusty_thermostat.toml"))
-        .merge(Env::prefixed("RUSTY_THERMO_"));
+// 503 while the database is down, so a load balancer or monitor can act on the status alone
+#[get("/health/db")]
+async fn db_health(supervisor: &State<Arc<dbman::supervisor::DbSupervisor>>) -> (Status, Json<dbman::supervisor::DbHealth>) {
+    let health: dbman::supervisor::DbHealth = supervisor.health().await;
+    match health.status {
+        dbman::supervisor::DbStatus::Up => (Status::Ok, Json(health)),
+        dbman::supervisor::DbStatus::Down => (Status::ServiceUnavailable, Json(health)),
+    }
+}
 
-    let runtime_settings: AppConfiguration = figment.clone().extract().unwrap();
-    parse_log(&runtime_settings);
-    info!("Logging has been enabled");
-    let _weather_settings: weather::Configuration = match runtime_settings.weather.is_active() {
-        true => parse_weather(&runtime_settings).await.unwrap(),
-        false => weather::Configuration::default()
+// Picks the units asked for in the query over the configured preference
+fn requested_units(units: Option<&str>, preference: &UnitSystem) -> UnitSystem {
+    match units {
+        Some(units) => UnitSystem::parse(units),
+        None => *preference,
+    }
+}
+
+#[get("/weather/current?<units>")]
+async fn weather_current(units: Option<&str>, cache: &State<Arc<weather::WeatherCache>>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<weather::Cached<weather::ConditionsReport>>, Status> {
+    let units: UnitSystem = requested_units(units, preference);
+    match cache.latest_current(db).await {
+        Ok(conditions) => Ok(Json(conditions.map(|current| current.report(units)))),
+        Err(error) => {
+            warn!("No current weather to serve: {}", error);
+            Err(Status::ServiceUnavailable)
+        }
+    }
+}
+
+#[get("/weather/air")]
+async fn weather_air(cache: &State<Arc<weather::WeatherCache>>, db: &State<DatabaseConnection>) -> Result<Json<weather::Cached<Vec<weather::AirQuality>>>, Status> {
+    match cache.latest_air_quality(db).await {
+        Ok(readings) => Ok(Json(readings)),
+        Err(error) => {
+            warn!("No air quality to serve: {}", error);
+            Err(Status::ServiceUnavailable)
+        }
+    }
+}
+
+#[get("/weather/quota")]
+fn weather_quota(quota: &State<Arc<weather::ApiQuota>>) -> Json<weather::QuotaSnapshot> {
+    Json(quota.snapshot())
+}
+
+#[get("/weather/degree-days?<from>&<to>&<units>")]
+async fn weather_degree_days(from: &str, to: &str, units: Option<&str>, db: &State<DatabaseConnection>, preference: &State<UnitSystem>) -> Result<Json<Vec<degree_days::DegreeDayReport>>, Status> {
+    let units: UnitSystem = requested_units(units, preference);
+    let (Ok(from), Ok(to)) = (NaiveDate::parse_from_str(from, "%Y-%m-%d"), NaiveDate::parse_from_str(to, "%Y-%m-%d")) else {
+        debug!("Degree days asked for with unreadable dates {} and {}", from, to);
+        return Err(Status::BadRequest)
+    };
+    match degree_days::in_range(db, from, to).await {
+        Ok(days) => Ok(Json(days.iter().map(|day| day.report(units)).collect())),
+        Err(error) => {
+            error!("Unable to read degree days: {}", error);
+            Err(Status::InternalServerError)
+        }
+    }
+}
+
+// Runs `rusty_thermostat export <table> [--format csv|ndjson] [--from time] [--to time] [--output file]`
+async fn run_export_command(fig: &AppConfiguration, args: &[String]) -> Result<(), transfer::TransferError> {
+    use std::io::Write;
+    let table: transfer::HistoryTable = transfer::HistoryTable::parse(args.first().map(String::as_str).unwrap_or_default())?;
+    let output: Option<&str> = flag_value(args, "--output");
+    let format: transfer::Format = match (flag_value(args, "--format"), output.and_then(transfer::Format::from_path)) {
+        (Some(format), _) => transfer::Format::parse(format)?,
+        (None, Some(format)) => format,
+        (None, None) => transfer::Format::default(),
+    };
+    let range: transfer::TimeRange = transfer::TimeRange::parse(flag_value(args, "--from"), flag_value(args, "--to"))?;
+    let db: DatabaseConnection = parse_db(fig).connect().await?;
+    let mut writer: Box<dyn Write + Send> = match output {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout()),
+    };
+    let mut exporter: transfer::Exporter = transfer::Exporter::new(table, format, range);
+    while let Some(chunk) = exporter.next_chunk(&db).await? {
+        writer.write_all(chunk.as_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Runs `rusty_thermostat import <table> <file> [--format csv|ndjson]`, the format defaults to the file's extension
+async fn run_import_command(fig: &AppConfiguration, args: &[String]) -> Result<(), transfer::TransferError> {
+    let table: transfer::HistoryTable = transfer::HistoryTable::parse(args.first().map(String::as_str).unwrap_or_default())?;
+    let Some(path) = args.get(1) else {
+        return Err(transfer::TransferError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No file to import was given")))
+    };
+    let format: transfer::Format = match flag_value(args, "--format") {
+        Some(format) => transfer::Format::parse(format)?,
+        None => transfer::Format::from_path(path).unwrap_or_default(),
+    };
+    let input: Vec<u8> = std::fs::read(path)?;
+    let db: DatabaseConnection = parse_db(fig).connect().await?;
+    let report: transfer::ImportReport = transfer::import(&db, table, format, &input).await?;
+    println!("Imported {} rows, skipped {} already stored", report.inserted, report.skipped);
+    Ok(())
+}
+
+// The value after a flag like --format, if it was given
+fn flag_value<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+    args.iter().position(|arg| arg == flag).and_then(|index| args.get(index + 1)).map(String::as_str)
+}
+
+// Runs `rusty_thermostat backup [--history] [--output file]`
+async fn run_backup_command(fig: &AppConfiguration, args: &[String]) -> Result<(), backup::BackupError> {
+    use std::io::Write;
+    let db: DatabaseConnection = parse_db(fig).connect().await?;
+    let archive: backup::Archive = backup::backup(&db, args.iter().any(|arg| arg == "--history")).await?;
+    let mut writer: Box<dyn Write + Send> = match flag_value(args, "--output") {
+        Some(path) => Box::new(std::io::BufWriter::new(std::fs::File::create(path)?)),
+        None => Box::new(std::io::stdout()),
+    };
+    serde_json::to_writer_pretty(&mut writer, &archive)?;
+    writer.flush()?;
+    Ok(())
+}
+
+// Runs `rusty_thermostat restore <file>`
+async fn run_restore_command(fig: &AppConfiguration, args: &[String]) -> Result<(), backup::BackupError> {
+    let Some(path) = args.first() else {
+        return Err(backup::BackupError::Io(std::io::Error::new(std::io::ErrorKind::InvalidInput, "No archive to restore was given")))
+    };
+    let input: Vec<u8> = std::fs::read(path)?;
+    let archive: backup::Archive = backup::Archive::from_json(&input)?;
+    let db: DatabaseConnection = parse_db(fig).connect().await?;
+    let report: backup::RestoreReport = backup::restore(&db, archive).await?;
+    match report.history_rows {
+        Some(rows) => println!("Restored {} configuration rows and {} history rows", report.config_rows, rows),
+        None => println!("Restored {} configuration rows, stored history was kept", report.config_rows),
+    }
+    Ok(())
+}
+
+// Runs `rusty_thermostat migrate [up|status|down]` against the configured database
+async fn run_migrate_command(fig: &AppConfiguration, action: Option<&str>) -> Result<(), sea_orm::DbErr> {
+    let db: sea_orm::prelude::DatabaseConnection = parse_db(fig).connect().await?;
+    match action.unwrap_or("up") {
+        "up" => dbman::migrate(&db).await,
+        "down" => dbman::rollback_migration(&db).await,
+        "status" => {
+            for (name, applied) in dbman::migration_status(&db).await? {
+                println!("{} {}", if applied { "applied" } else { "pending" }, name);
+            }
+            Ok(())
+        },
+        unknown => Err(sea_orm::DbErr::Custom(format!("Unknown migrate action {}, expected up, status or down", unknown))),
+    }
+}
+
+async fn rocket(figment: rocket::figment::Figment, runtime_settings: AppConfiguration) -> rocket::Rocket<rocket::Build> {
+    let weather_quota: Arc<weather::ApiQuota> = parse_quota(&runtime_settings);
+    // Everything is stored in canonical units, this is only what the API hands back
+    let display_units: UnitSystem = UnitSystem::parse(runtime_settings.weather.units.as_deref().unwrap_or_default());
+    let weather_settings: Arc<dyn weather::WeatherProvider> = match runtime_settings.weather.is_active() {
+        true => match parse_provider(&runtime_settings, weather_quota.clone()).await {
+            Ok(provider) => provider,
+            Err(error) => {
+                error!("Unable to set up the weather provider: {}", error);
+                eprintln!("Unable to set up the weather provider: {}", error);
+                std::process::exit(1);
+            }
+        },
+        false => Arc::new(weather::Configuration::default())
+    };
+    let mut weather_cache: weather::WeatherCache = weather::WeatherCache::new(weather_settings);
+    // Values stay fresh for as long as the collector takes to poll again
+    if let Some(interval) = runtime_settings.weather.weather_interval {
+        weather_cache.set_current_max_age(interval);
+    }
+    if let Some(interval) = runtime_settings.weather.pollution_interval {
+        weather_cache.set_air_quality_max_age(interval);
+    }
+    let weather_cache: Arc<weather::WeatherCache> = Arc::new(weather_cache);
+    let mut collector_settings: collector::CollectorConfig = collector::CollectorConfig::new(
+        weather_cache.clone(),
+        runtime_settings.weather.monitor_weather,
+        runtime_settings.weather.monitor_pollution);
+    if let Some(interval) = runtime_settings.weather.weather_interval {
+        collector_settings.set_weather_interval(interval);
+    }
+    if let Some(interval) = runtime_settings.weather.pollution_interval {
+        collector_settings.set_pollution_interval(interval);
+    }
+    collector_settings.monitor_forecast = runtime_settings.weather.monitor_forecast.unwrap_or(false);
+    if let Some(interval) = runtime_settings.weather.forecast_interval {
+        collector_settings.set_forecast_interval(interval);
+    }
+    collector_settings.degree_day_base = match runtime_settings.weather.degree_day_base {
+        Some(base) => Temperature::from_units(base, display_units),
+        None => degree_days::default_base(display_units),
     };
+    collector_settings.retention = match runtime_settings.retention.enabled.unwrap_or(true) {
+        true => Some(retention::RetentionConfig::new(runtime_settings.retention.raw_max_age_days.unwrap_or(retention::DEFAULT_RAW_MAX_AGE_DAYS))),
+        false => None,
+    };
+    if let Some(interval) = runtime_settings.retention.interval {
+        collector_settings.set_retention_interval(interval);
+    }
     let db_settings: dbman::DBConfig = parse_db(&runtime_settings);
-    let db_options: sea_orm::ConnectOptions = db_settings.set_connect_options();
-    let db: sea_orm::prelude::DatabaseConnection = dbman::begin_connection(db_options).await.unwrap();
+    if db_settings.is_memory() {
+        warn!("Using an in-memory SQLite database, nothing will be kept once the application stops");
+    }
+    let db: sea_orm::prelude::DatabaseConnection = match dbman::supervisor::connect_with_retry(&db_settings).await {
+        Ok(db) => db,
+        Err(error) => {
+            error!("Unable to connect to the database: {}", error);
+            eprintln!("Unable to connect to the database: {}", error);
+            std::process::exit(1);
+        }
+    };
     match dbman::is_live(&db).await {
         Ok(()) => info!("Db looks live."),
         Err(_) => error!("DBPing did not work."),
     };
+    let buffer_path: String = runtime_settings.database.buffer_path.clone().unwrap_or(dbman::buffer::DEFAULT_BUFFER_PATH.to_string());
+    let write_buffer: dbman::buffer::WriteBuffer = match dbman::buffer::WriteBuffer::open(&buffer_path) {
+        Ok(buffer) => buffer,
+        Err(error) => {
+            error!("Unable to open the database write buffer {}: {}", buffer_path, error);
+            eprintln!("Unable to open the database write buffer {}: {}", buffer_path, error);
+            std::process::exit(1);
+        }
+    };
+    let db_supervisor: Arc<dbman::supervisor::DbSupervisor> = Arc::new(dbman::supervisor::DbSupervisor::new(write_buffer,
+        runtime_settings.database.probe_interval.unwrap_or(dbman::supervisor::DEFAULT_PROBE_INTERVAL)));
+    if runtime_settings.database.auto_migrate.unwrap_or(true) {
+        if let Err(error) = dbman::migrate(&db).await {
+            error!("Database migrations failed: {}", error);
+            eprintln!("Database migrations failed: {}", error);
+            std::process::exit(1);
+        }
+    } else {
+        match dbman::pending_migrations(&db).await {
+            Ok(pending) if !pending.is_empty() => warn!("Database has {} pending migrations, run the migrate command", pending.len()),
+            Ok(_) => debug!("Database schema is up to date"),
+            Err(error) => error!("Unable to check database migrations: {}", error),
+        }
+    }
+    if let Err(error) = dbman::seed::seed(&db).await {
+        error!("Unable to seed the database: {}", error);
+    }
+    let admin_token: api::AdminToken = api::AdminToken::new(runtime_settings.api.admin_token.as_deref());
+    if runtime_settings.api.admin_token.is_none() {
+        info!("No admin token is configured, the admin routes of the API are turned off");
+    }
     info!("Setting parsing complete. Starting web server now.");
-    rocket::build().configure(figment).manage(db).mount("/", routes![index, db_ping])
-}
\ No newline at end of file
+    rocket::build().configure(figment).manage(db).manage(weather_quota).manage(weather_cache).manage(display_units)
+        .manage(db_supervisor.clone()).manage(admin_token)
+        .attach(dbman::supervisor::fairing(db_supervisor))
+        .attach(collector::fairing(collector_settings))
+        .mount("/", routes![index, db_ping, weather_current, weather_air, weather_quota, weather_degree_days, db_health])
+        .mount(api::BASE, api::routes())
+        .register(api::BASE, api::catchers())
+}
+
+#[rocket::main]
+async fn main() {
+    let figment: rocket::figment::Figment = rocket::Config::figment()
+        .merge(Toml::file("config/rusty_thermostat.toml"))
+        .merge(Env::prefixed("RUSTY_THERMO_"));
+
+    let runtime_settings: AppConfiguration = figment.clone().extract().unwrap();
+    parse_log(&runtime_settings);
+    info!("Logging has been enabled");
+
+    let args: Vec<String> = std::env::args().collect();
+    match args.get(1).map(String::as_str) {
+        None => {
+            if let Err(error) = rocket(figment, runtime_settings).await.launch().await {
+                error!("Web server stopped: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some("migrate") => {
+            if let Err(error) = run_migrate_command(&runtime_settings, args.get(2).map(String::as_str)).await {
+                error!("Migration failed: {}", error);
+                eprintln!("Migration failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some("export") => {
+            if let Err(error) = run_export_command(&runtime_settings, &args[2..]).await {
+                error!("Export failed: {}", error);
+                eprintln!("Export failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some("import") => {
+            if let Err(error) = run_import_command(&runtime_settings, &args[2..]).await {
+                error!("Import failed: {}", error);
+                eprintln!("Import failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some("backup") => {
+            if let Err(error) = run_backup_command(&runtime_settings, &args[2..]).await {
+                error!("Backup failed: {}", error);
+                eprintln!("Backup failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some("restore") => {
+            if let Err(error) = run_restore_command(&runtime_settings, &args[2..]).await {
+                error!("Restore failed: {}", error);
+                eprintln!("Restore failed: {}", error);
+                std::process::exit(1);
+            }
+        },
+        Some(unknown) => {
+            eprintln!("Unknown command {}, expected no command, migrate, export, import, backup or restore", unknown);
+            std::process::exit(2);
+        },
+    }
+}
@@ -0,0 +1,191 @@
+//! # Rusty Thermostat Psychrometrics
+//! Apparent conditions worked out from temperature, humidity and wind<br>
+//! Every function takes and returns typed values, so nothing here has to know which unit system the readings came in
+
+use serde_derive::{Serialize, Deserialize};
+use crate::schema::sensor_reading_history;
+use crate::units::{Temperature, Speed};
+
+/// Apparent conditions for one set of readings. Absolute humidity is in g/m3
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ComfortMetrics {
+    pub dew_point: Temperature,
+    pub heat_index: Temperature,
+    pub wind_chill: Temperature,
+    pub humidex: Temperature,
+    pub absolute_humidity: f64,
+}
+
+impl ComfortMetrics {
+    /// Works out every metric for outdoor conditions
+    pub fn outdoor(temp: Temperature, humidity: f64, wind_speed: Speed) -> ComfortMetrics {
+        ComfortMetrics {
+            dew_point: dew_point(temp, humidity),
+            heat_index: heat_index(temp, humidity),
+            wind_chill: wind_chill(temp, wind_speed),
+            humidex: humidex(temp, humidity),
+            absolute_humidity: absolute_humidity(temp, humidity),
+        }
+    }
+    /// Works out every metric for still indoor air, so wind chill is the temperature itself
+    pub fn indoor(temp: Temperature, humidity: f64) -> ComfortMetrics {
+        ComfortMetrics::outdoor(temp, humidity, Speed::default())
+    }
+    /// Works out the metrics for a sensor reading<br>
+    /// Readings without both a temperature and a humidity give None
+    pub fn from_sensor_reading(reading: &sensor_reading_history::Model) -> Option<ComfortMetrics> {
+        let temp: Temperature = reading.reading_temp?;
+        let humidity: i32 = reading.reading_humidity?;
+        Some(ComfortMetrics::indoor(temp, humidity.into()))
+    }
+}
+
+// Relative humidity is kept off zero so the logarithms stay finite
+fn clamp_humidity(humidity: f64) -> f64 {
+    humidity.clamp(0.1, 100.0)
+}
+
+/// Dew point using the Magnus formula, accurate to a few tenths of a degree between -40C and 50C
+pub fn dew_point(temp: Temperature, humidity: f64) -> Temperature {
+    let celsius: f64 = temp.celsius();
+    let gamma: f64 = (clamp_humidity(humidity) / 100.0).ln() + (17.625 * celsius) / (243.04 + celsius);
+    Temperature::from_celsius(243.04 * gamma / (17.625 - gamma))
+}
+
+/// Heat index from the US National Weather Service<br>
+/// Uses the Rothfusz regression with its humidity adjustments when the simple formula comes out at 80F or above
+pub fn heat_index(temp: Temperature, humidity: f64) -> Temperature {
+    let fahrenheit: f64 = temp.fahrenheit();
+    let humidity: f64 = clamp_humidity(humidity);
+    let simple: f64 = 0.5 * (fahrenheit + 61.0 + (fahrenheit - 68.0) * 1.2 + humidity * 0.094);
+    let mut index: f64 = (simple + fahrenheit) / 2.0;
+    if index >= 80.0 {
+        index = -42.379 + 2.049_015_23 * fahrenheit + 10.143_331_27 * humidity
+            - 0.224_755_41 * fahrenheit * humidity - 0.006_837_83 * fahrenheit * fahrenheit
+            - 0.054_817_17 * humidity * humidity + 0.001_228_74 * fahrenheit * fahrenheit * humidity
+            + 0.000_852_82 * fahrenheit * humidity * humidity - 0.000_001_99 * fahrenheit * fahrenheit * humidity * humidity;
+        if humidity < 13.0 && (80.0..=112.0).contains(&fahrenheit) {
+            index -= ((13.0 - humidity) / 4.0) * ((17.0 - (fahrenheit - 95.0).abs()) / 17.0).sqrt();
+        } else if humidity > 85.0 && (80.0..=87.0).contains(&fahrenheit) {
+            index += ((humidity - 85.0) / 10.0) * ((87.0 - fahrenheit) / 5.0);
+        }
+    }
+    Temperature::from_fahrenheit(index)
+}
+
+/// Wind chill from the US National Weather Service<br>
+/// Only defined at or below 50F with wind of at least 3 mph, otherwise the temperature is returned as is
+pub fn wind_chill(temp: Temperature, wind_speed: Speed) -> Temperature {
+    let fahrenheit: f64 = temp.fahrenheit();
+    let mph: f64 = wind_speed.mph();
+    if fahrenheit > 50.0 || mph < 3.0 {
+        return temp
+    }
+    let wind_factor: f64 = mph.powf(0.16);
+    Temperature::from_fahrenheit(35.74 + 0.6215 * fahrenheit - 35.75 * wind_factor + 0.4275 * fahrenheit * wind_factor)
+}
+
+/// Humidex from Environment Canada, worked out from the dew point<br>
+/// Humidex is a Celsius scale value, it is kept as a temperature so it converts like the others
+pub fn humidex(temp: Temperature, humidity: f64) -> Temperature {
+    let dew_kelvin: f64 = dew_point(temp, humidity).kelvin();
+    let vapour_pressure: f64 = 6.11 * (5417.7530 * (1.0 / 273.16 - 1.0 / dew_kelvin)).exp();
+    Temperature::from_celsius(temp.celsius() + 0.5555 * (vapour_pressure - 10.0))
+}
+
+/// Absolute humidity in grams of water per cubic meter of air
+pub fn absolute_humidity(temp: Temperature, humidity: f64) -> f64 {
+    let celsius: f64 = temp.celsius();
+    let saturation: f64 = 6.112 * ((17.67 * celsius) / (celsius + 243.5)).exp();
+    saturation * clamp_humidity(humidity) * 2.1674 / (273.15 + celsius)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::units::UnitSystem;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!((actual - expected).abs() <= tolerance, "expected {} to be within {} of {}", actual, tolerance, expected);
+    }
+
+    #[test]
+    fn dew_point_matches_reference() {
+        assert_close(dew_point(Temperature::from_celsius(25.0), 60.0).celsius(), 16.7, 0.1);
+        assert_close(dew_point(Temperature::from_fahrenheit(77.0), 60.0).fahrenheit(), 62.1, 0.2);
+        assert_close(dew_point(Temperature::from_kelvin(298.15), 60.0).kelvin(), 289.85, 0.1);
+    }
+
+    #[test]
+    fn dew_point_at_saturation_is_temperature() {
+        assert_close(dew_point(Temperature::from_celsius(10.0), 100.0).celsius(), 10.0, 0.01);
+    }
+
+    #[test]
+    fn heat_index_matches_nws_table() {
+        // NWS table: 90F at 70% humidity is 105F
+        assert_close(heat_index(Temperature::from_fahrenheit(90.0), 70.0).fahrenheit(), 105.0, 1.0);
+        assert_close(heat_index(Temperature::from_celsius(32.22), 70.0).celsius(), 40.6, 0.6);
+    }
+
+    #[test]
+    fn heat_index_in_mild_weather_is_near_temperature() {
+        assert_close(heat_index(Temperature::from_fahrenheit(70.0), 50.0).fahrenheit(), 69.1, 1.0);
+    }
+
+    #[test]
+    fn wind_chill_matches_nws_table() {
+        // NWS table: 0F with a 15 mph wind is -19F
+        assert_close(wind_chill(Temperature::from_fahrenheit(0.0), Speed::from_mph(15.0)).fahrenheit(), -19.0, 0.5);
+        assert_close(wind_chill(Temperature::from_celsius(-17.78), Speed::from_meters_per_second(6.7056)).celsius(), -28.3, 0.3);
+    }
+
+    #[test]
+    fn wind_chill_outside_range_is_temperature() {
+        let mild: Temperature = Temperature::from_fahrenheit(60.0);
+        let freezing: Temperature = Temperature::from_kelvin(273.15);
+
+        assert_eq!(wind_chill(mild, Speed::from_mph(20.0)), mild);
+        assert_eq!(wind_chill(freezing, Speed::from_meters_per_second(0.5)), freezing);
+    }
+
+    #[test]
+    fn humidex_matches_reference() {
+        // Environment Canada: 30C with a 15C dew point is a humidex of 34
+        let humidity: f64 = 100.0 * (17.625_f64 * 15.0 / (243.04 + 15.0) - 17.625 * 30.0 / (243.04 + 30.0)).exp();
+        assert_close(humidex(Temperature::from_celsius(30.0), humidity).celsius(), 34.0, 0.5);
+    }
+
+    #[test]
+    fn absolute_humidity_matches_reference() {
+        assert_close(absolute_humidity(Temperature::from_celsius(20.0), 50.0), 8.6, 0.1);
+        assert_close(absolute_humidity(Temperature::from_units(68.0, UnitSystem::Imperial), 50.0), 8.6, 0.1);
+    }
+
+    #[test]
+    fn indoor_metrics_have_no_wind_chill() {
+        let temp: Temperature = Temperature::from_fahrenheit(40.0);
+
+        let metrics = ComfortMetrics::indoor(temp, 50.0);
+
+        assert_eq!(metrics.wind_chill, temp);
+    }
+
+    #[test]
+    fn sensor_reading_without_humidity_has_no_metrics() {
+        let reading = sensor_reading_history::Model { id: uuid::Uuid::nil(), sensor_id: 1, timestamp: chrono::Utc::now().naive_utc(),
+            reading_temp: Some(Temperature::from_celsius(21.0)), reading_humidity: None, reading_presence: None, reading_threshold_open: None };
+
+        assert!(ComfortMetrics::from_sensor_reading(&reading).is_none());
+    }
+
+    #[test]
+    fn sensor_reading_metrics() {
+        let reading = sensor_reading_history::Model { id: uuid::Uuid::nil(), sensor_id: 1, timestamp: chrono::Utc::now().naive_utc(),
+            reading_temp: Some(Temperature::from_celsius(21.0)), reading_humidity: Some(45), reading_presence: None, reading_threshold_open: None };
+
+        let metrics = ComfortMetrics::from_sensor_reading(&reading).unwrap();
+
+        assert_close(metrics.dew_point.celsius(), 8.7, 0.2);
+    }
+}
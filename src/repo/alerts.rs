@@ -0,0 +1,187 @@
+//! Alerts watch a zone or schedule and report over a communication type when they trip
+
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use crate::schema::{alerts, communication, schedules, zones};
+use super::{RepoError, check_optional_reference, find_or_missing, require_text};
+use super::audit::{self, Action, Actor};
+
+/// What is needed to add an alert
+#[derive(Debug, Clone, Default)]
+pub struct NewAlert {
+    pub name: String,
+    pub com_type: Option<i32>,
+    pub associated_schedule: Option<i32>,
+    pub associated_zone: Option<i32>,
+    pub actions: Option<String>,
+}
+
+/// Changes to an alert, anything left as None stays as it is<br>
+/// The fields that may be empty take `Some(None)` to clear them
+#[derive(Debug, Clone, Default)]
+pub struct AlertUpdate {
+    pub name: Option<String>,
+    pub com_type: Option<Option<i32>>,
+    pub associated_schedule: Option<Option<i32>>,
+    pub associated_zone: Option<Option<i32>>,
+    pub actions: Option<Option<String>>,
+}
+
+/// Adds an active alert that has not tripped
+/// # Errors
+/// A blank name is invalid, and a communication type, schedule or zone that does not exist is a missing reference
+pub async fn create(db: &DatabaseConnection, actor: &Actor, new_alert: NewAlert) -> Result<alerts::Model, RepoError> {
+    require_text(&new_alert.name, "name")?;
+    let txn: DatabaseTransaction = db.begin().await?;
+    check_optional_reference::<communication::Entity, _>(&txn, new_alert.com_type, "comType").await?;
+    check_optional_reference::<schedules::Entity, _>(&txn, new_alert.associated_schedule, "associatedSchedule").await?;
+    check_optional_reference::<zones::Entity, _>(&txn, new_alert.associated_zone, "associatedZone").await?;
+    let alert: alerts::Model = alerts::ActiveModel {
+        id: NotSet,
+        name: Set(new_alert.name),
+        active: Set(true),
+        tripped: Set(false),
+        com_type: Set(new_alert.com_type),
+        associated_schedule: Set(new_alert.associated_schedule),
+        associated_zone: Set(new_alert.associated_zone),
+        actions: Set(new_alert.actions),
+    }.insert(&txn).await?;
+    audit::record(&txn, actor, Action::Create, "alert", alert.id, None, Some(&alert)).await?;
+    txn.commit().await?;
+    debug!("Added alert {} as {}", alert.name, alert.id);
+    Ok(alert)
+}
+
+pub async fn get(db: &DatabaseConnection, id: i32) -> Result<alerts::Model, RepoError> {
+    find_or_missing::<alerts::Entity, _>(db, id, "alert").await
+}
+
+/// Lists alerts by id, leaving out deactivated ones unless asked for
+pub async fn list(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<alerts::Model>, RepoError> {
+    let mut query = alerts::Entity::find().order_by_asc(alerts::Column::Id);
+    if !include_inactive {
+        query = query.filter(alerts::Column::Active.eq(true));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// Active alerts that have tripped and not been reset
+pub async fn tripped(db: &DatabaseConnection) -> Result<Vec<alerts::Model>, RepoError> {
+    Ok(alerts::Entity::find()
+        .filter(alerts::Column::Active.eq(true))
+        .filter(alerts::Column::Tripped.eq(true))
+        .order_by_asc(alerts::Column::Id)
+        .all(db).await?)
+}
+
+pub async fn update(db: &DatabaseConnection, actor: &Actor, id: i32, changes: AlertUpdate) -> Result<alerts::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: alerts::Model = find_or_missing::<alerts::Entity, _>(&txn, id, "alert").await?;
+    let mut alert: alerts::ActiveModel = before.clone().into_active_model();
+    if let Some(name) = changes.name {
+        require_text(&name, "name")?;
+        alert.name = Set(name);
+    }
+    if let Some(com_type) = changes.com_type {
+        check_optional_reference::<communication::Entity, _>(&txn, com_type, "comType").await?;
+        alert.com_type = Set(com_type);
+    }
+    if let Some(schedule) = changes.associated_schedule {
+        check_optional_reference::<schedules::Entity, _>(&txn, schedule, "associatedSchedule").await?;
+        alert.associated_schedule = Set(schedule);
+    }
+    if let Some(zone) = changes.associated_zone {
+        check_optional_reference::<zones::Entity, _>(&txn, zone, "associatedZone").await?;
+        alert.associated_zone = Set(zone);
+    }
+    if let Some(actions) = changes.actions {
+        alert.actions = Set(actions);
+    }
+    finish(txn, actor, Action::Update, before, alert).await
+}
+
+/// Marks an alert as tripped, or clears it when `tripped` is false
+pub async fn set_tripped(db: &DatabaseConnection, actor: &Actor, id: i32, tripped: bool) -> Result<alerts::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: alerts::Model = find_or_missing::<alerts::Entity, _>(&txn, id, "alert").await?;
+    let mut alert: alerts::ActiveModel = before.clone().into_active_model();
+    alert.tripped = Set(tripped);
+    finish(txn, actor, if tripped { Action::Trip } else { Action::Reset }, before, alert).await
+}
+
+/// Switches an alert off. A deactivated alert is also reset
+pub async fn deactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<alerts::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: alerts::Model = find_or_missing::<alerts::Entity, _>(&txn, id, "alert").await?;
+    let mut alert: alerts::ActiveModel = before.clone().into_active_model();
+    alert.active = Set(false);
+    alert.tripped = Set(false);
+    finish(txn, actor, Action::Deactivate, before, alert).await
+}
+
+pub async fn reactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<alerts::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: alerts::Model = find_or_missing::<alerts::Entity, _>(&txn, id, "alert").await?;
+    let mut alert: alerts::ActiveModel = before.clone().into_active_model();
+    alert.active = Set(true);
+    finish(txn, actor, Action::Reactivate, before, alert).await
+}
+
+// Saves the changed alert, logs it and commits
+async fn finish(txn: DatabaseTransaction, actor: &Actor, action: Action, before: alerts::Model, alert: alerts::ActiveModel)
+    -> Result<alerts::Model, RepoError> {
+    let alert: alerts::Model = alert.update(&txn).await?;
+    audit::record(&txn, actor, action, "alert", alert.id, Some(&before), Some(&alert)).await?;
+    txn.commit().await?;
+    Ok(alert)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+
+    fn new_alert(name: &str) -> NewAlert {
+        NewAlert { name: name.to_string(), com_type: Some(1), actions: Some("notify".to_string()), ..NewAlert::default() }
+    }
+
+    #[tokio::test]
+    async fn create_checks_references() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let mut orphan: NewAlert = new_alert("Freeze");
+        orphan.associated_schedule = Some(3);
+        assert!(matches!(create(&db, &test_actor(), orphan).await, Err(RepoError::MissingReference { field: "associatedSchedule", id: 3 })));
+
+        let alert: alerts::Model = create(&db, &test_actor(), new_alert("Freeze")).await.unwrap();
+        assert!(alert.active && !alert.tripped);
+    }
+
+    #[tokio::test]
+    async fn tripped_lists_only_active_tripped_alerts() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let freeze: alerts::Model = create(&db, &test_actor(), new_alert("Freeze")).await.unwrap();
+        let heat: alerts::Model = create(&db, &test_actor(), new_alert("Heat")).await.unwrap();
+        create(&db, &test_actor(), new_alert("Humidity")).await.unwrap();
+        set_tripped(&db, &test_actor(), freeze.id, true).await.unwrap();
+        set_tripped(&db, &test_actor(), heat.id, true).await.unwrap();
+        let heat: alerts::Model = deactivate(&db, &test_actor(), heat.id).await.unwrap();
+        assert!(!heat.tripped);
+
+        let ids: Vec<i32> = tripped(&db).await.unwrap().iter().map(|alert| alert.id).collect();
+        assert_eq!(ids, vec![freeze.id]);
+        assert_eq!(list(&db, false).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn update_clears_optional_fields() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let alert: alerts::Model = create(&db, &test_actor(), new_alert("Door open")).await.unwrap();
+        let changes: AlertUpdate = AlertUpdate { com_type: Some(None), actions: Some(None), ..AlertUpdate::default() };
+        let updated: alerts::Model = update(&db, &test_actor(), alert.id, changes).await.unwrap();
+        assert_eq!(updated.com_type, None);
+        assert_eq!(updated.actions, None);
+        assert_eq!(updated.name, "Door open");
+    }
+}
@@ -0,0 +1,258 @@
+//! The audit log, which says who changed a zone, sensor, controller, schedule or alert, how and when<br>
+//! Every change made through the repository writes its entry in the same transaction as the change itself, so a
+//! change is never kept without its entry. Entries hold the row as JSON before and after the change, with tokens
+//! left out
+
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect};
+use sea_orm::ActiveValue::Set;
+use sea_orm::prelude::Json;
+use serde::Serialize;
+use uuid::Uuid;
+use crate::schema::{audit_log, change_source};
+use super::RepoError;
+
+/// The kinds of rows the repository changes, as stored in `entityType`
+pub const ENTITY_TYPES: [&str; 6] = ["zone", "capability", "sensor", "controller", "schedule", "alert"];
+/// Entries a query returns when no limit is given
+pub const DEFAULT_LIMIT: u64 = 100;
+/// The most entries one query returns
+pub const MAX_LIMIT: u64 = 1000;
+// Stands in for a token in the stored JSON
+const REDACTED: &str = "[redacted]";
+
+/// Where a change came from, one of the seeded ChangeSource rows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    Web,
+    Schedule,
+    Sensor,
+    Api,
+}
+
+impl Source {
+    /// The ChangeSource name, as seeded by `dbman::seed`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Source::Web => "web",
+            Source::Schedule => "schedule",
+            Source::Sensor => "sensor",
+            Source::Api => "api",
+        }
+    }
+
+    /// # Errors
+    /// A name that is not a change source is invalid
+    pub fn parse(name: &str) -> Result<Source, RepoError> {
+        match name.to_lowercase().as_str() {
+            "web" => Ok(Source::Web),
+            "schedule" => Ok(Source::Schedule),
+            "sensor" => Ok(Source::Sensor),
+            "api" => Ok(Source::Api),
+            _ => Err(RepoError::Invalid(format!("{} is not a change source", name))),
+        }
+    }
+}
+
+/// Who is making a change and through what
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Actor {
+    pub name: String,
+    pub source: Source,
+}
+
+impl Actor {
+    pub fn new<S: Into<String>>(name: S, source: Source) -> Actor {
+        Actor { name: name.into(), source }
+    }
+}
+
+/// What a change did to its row
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Create,
+    Update,
+    Deactivate,
+    Reactivate,
+    Trip,
+    Reset,
+    RotateToken,
+}
+
+impl Action {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Action::Create => "create",
+            Action::Update => "update",
+            Action::Deactivate => "deactivate",
+            Action::Reactivate => "reactivate",
+            Action::Trip => "trip",
+            Action::Reset => "reset",
+            Action::RotateToken => "rotate_token",
+        }
+    }
+}
+
+/// Narrows a query, anything left as None matches every entry<br>
+/// `from` is inclusive and `to` exclusive
+#[derive(Debug, Clone, Default)]
+pub struct AuditFilter {
+    pub entity_type: Option<String>,
+    pub entity_id: Option<i32>,
+    pub actor: Option<String>,
+    pub source: Option<Source>,
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+    pub limit: Option<u64>,
+}
+
+/// Writes one entry, meant to be called inside the transaction making the change
+pub(super) async fn record<C, M>(db: &C, actor: &Actor, action: Action, entity_type: &'static str, entity_id: i32,
+    before: Option<&M>, after: Option<&M>) -> Result<(), RepoError>
+where
+    C: ConnectionTrait,
+    M: Serialize,
+{
+    let change_source: i32 = change_source::Entity::find()
+        .filter(change_source::Column::Name.eq(actor.source.name()))
+        .one(db).await?
+        .ok_or_else(|| RepoError::Invalid(format!("Change source {} has not been seeded", actor.source.name())))?
+        .id;
+    audit_log::ActiveModel {
+        id: Set(Uuid::now_v7()),
+        timestamp: Set(Utc::now().naive_utc()),
+        actor: Set(actor.name.clone()),
+        change_source: Set(change_source),
+        entity_type: Set(entity_type.to_string()),
+        entity_id: Set(entity_id),
+        action: Set(action.name().to_string()),
+        before: Set(before.map(snapshot).transpose()?),
+        after: Set(after.map(snapshot).transpose()?),
+    }.insert(db).await?;
+    trace!("{} {} {} {} through {}", actor.name, action.name(), entity_type, entity_id, actor.source.name());
+    Ok(())
+}
+
+// The row as JSON with any token blanked out
+fn snapshot<M: Serialize>(row: &M) -> Result<Json, RepoError> {
+    let mut json: Json = serde_json::to_value(row)
+        .map_err(|error| RepoError::Invalid(format!("Unable to record the change: {}", error)))?;
+    if let Some(token) = json.get_mut("token") {
+        *token = Json::String(REDACTED.to_string());
+    }
+    Ok(json)
+}
+
+/// Lists entries newest first
+/// # Errors
+/// An entity type the repository does not change is invalid
+pub async fn query(db: &DatabaseConnection, filter: AuditFilter) -> Result<Vec<audit_log::Model>, RepoError> {
+    let mut query = audit_log::Entity::find()
+        .order_by_desc(audit_log::Column::Timestamp)
+        .order_by_desc(audit_log::Column::Id)
+        .limit(filter.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT));
+    if let Some(entity_type) = filter.entity_type {
+        if !ENTITY_TYPES.contains(&entity_type.as_str()) {
+            return Err(RepoError::Invalid(format!("{} is not one of {}", entity_type, ENTITY_TYPES.join(", "))));
+        }
+        query = query.filter(audit_log::Column::EntityType.eq(entity_type));
+    }
+    if let Some(entity_id) = filter.entity_id {
+        query = query.filter(audit_log::Column::EntityId.eq(entity_id));
+    }
+    if let Some(actor) = filter.actor {
+        query = query.filter(audit_log::Column::Actor.eq(actor));
+    }
+    if let Some(source) = filter.source {
+        let Some(change_source) = change_source::Entity::find()
+            .filter(change_source::Column::Name.eq(source.name())).one(db).await? else {
+            return Ok(Vec::new())
+        };
+        query = query.filter(audit_log::Column::ChangeSource.eq(change_source.id));
+    }
+    if let Some(from) = filter.from {
+        query = query.filter(audit_log::Column::Timestamp.gte(from));
+    }
+    if let Some(to) = filter.to {
+        query = query.filter(audit_log::Column::Timestamp.lt(to));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// The actor tests make their changes as
+#[cfg(test)]
+pub fn test_actor() -> Actor {
+    Actor::new("test", Source::Api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbman;
+    use crate::dbman::seed::CHANGE_SOURCES;
+    use crate::repo::sensors::{self as sensor_repo, NewSensor, SensorUpdate};
+    use crate::repo::zones::{self as zone_repo, NewZone};
+
+    #[test]
+    fn every_source_is_seeded() {
+        for source in [Source::Web, Source::Schedule, Source::Sensor, Source::Api] {
+            assert!(CHANGE_SOURCES.contains(&source.name()));
+            assert_eq!(Source::parse(source.name()).unwrap(), source);
+        }
+        assert!(Source::parse("telepathy").is_err());
+    }
+
+    #[tokio::test]
+    async fn changes_are_recorded_with_the_row_before_and_after() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let admin: Actor = Actor::new("alex", Source::Web);
+        let sensor: i32 = sensor_repo::create(&db, &admin, NewSensor { name: "Hall".to_string(),
+            associated_zone: None, com_type: 1 }).await.unwrap().sensor.id;
+        sensor_repo::update(&db, &test_actor(), sensor, SensorUpdate { name: Some("Landing".to_string()), ..SensorUpdate::default() })
+            .await.unwrap();
+        sensor_repo::deactivate(&db, &admin, sensor).await.unwrap();
+
+        let entries: Vec<audit_log::Model> = query(&db, AuditFilter { entity_type: Some("sensor".to_string()), entity_id: Some(sensor),
+            ..AuditFilter::default() }).await.unwrap();
+        let actions: Vec<&str> = entries.iter().map(|entry| entry.action.as_str()).collect();
+        assert_eq!(actions, vec!["deactivate", "update", "create"]);
+        let created: &audit_log::Model = &entries[2];
+        assert_eq!((created.actor.as_str(), created.before.is_none()), ("alex", true));
+        assert_eq!(created.after.as_ref().unwrap()["token"], REDACTED);
+        let renamed: &audit_log::Model = &entries[1];
+        assert_eq!(renamed.before.as_ref().unwrap()["name"], "Hall");
+        assert_eq!(renamed.after.as_ref().unwrap()["name"], "Landing");
+        assert_eq!(entries[0].after.as_ref().unwrap()["active"], false);
+    }
+
+    #[tokio::test]
+    async fn query_filters_by_actor_source_and_time() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let start: NaiveDateTime = Utc::now().naive_utc() - chrono::Duration::seconds(1);
+        let panel: Actor = Actor::new("panel", Source::Web);
+        let zone: i32 = zone_repo::create(&db, &panel, NewZone { name: "Den".to_string(), heating: true, cooling: false }).await.unwrap().id;
+        zone_repo::deactivate(&db, &test_actor(), zone).await.unwrap();
+
+        let by_panel: Vec<audit_log::Model> = query(&db, AuditFilter { actor: Some("panel".to_string()), ..AuditFilter::default() })
+            .await.unwrap();
+        assert_eq!(by_panel.len(), 1);
+        assert_eq!(by_panel[0].entity_type, "zone");
+        let through_api: Vec<audit_log::Model> = query(&db, AuditFilter { source: Some(Source::Api), ..AuditFilter::default() })
+            .await.unwrap();
+        assert_eq!(through_api.len(), 1);
+        assert_eq!(through_api[0].action, "deactivate");
+        assert!(query(&db, AuditFilter { to: Some(start), ..AuditFilter::default() }).await.unwrap().is_empty());
+        assert_eq!(query(&db, AuditFilter { from: Some(start), limit: Some(1), ..AuditFilter::default() }).await.unwrap().len(), 1);
+        assert!(matches!(query(&db, AuditFilter { entity_type: Some("house".to_string()), ..AuditFilter::default() }).await,
+            Err(RepoError::Invalid(_))));
+    }
+
+    #[tokio::test]
+    async fn a_refused_change_leaves_no_entry() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let sensor: NewSensor = NewSensor { name: "Hall".to_string(), associated_zone: Some(9), com_type: 1 };
+        assert!(sensor_repo::create(&db, &test_actor(), sensor).await.is_err());
+        assert!(query(&db, AuditFilter::default()).await.unwrap().is_empty());
+    }
+}
@@ -0,0 +1,249 @@
+//! Controllers switch the heating and cooling equipment<br>
+//! Like zones, every controller owns an EnvCapability row for what its equipment can do and an HVACactivity row for
+//! what it is doing<br>
+//! A controller authenticates with a token issued when it is added or its token is rotated. Only the token's hash is
+//! stored, so the plain token in an `IssuedController` is the one chance to hand it to the device
+
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel,
+    QueryFilter, QueryOrder, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use crate::schema::{communication, controllers, env_capability, hva_cactivity, zones};
+use super::{RepoError, check_optional_reference, check_reference, find_or_missing, require_text};
+use super::audit::{self, Action, Actor};
+use super::tokens;
+
+/// What is needed to add a controller
+#[derive(Debug, Clone)]
+pub struct NewController {
+    pub name: String,
+    pub com_type: i32,
+    pub associated_zone: Option<i32>,
+    pub primary: bool,
+    pub heating: bool,
+    pub cooling: bool,
+}
+
+/// A controller along with the plain token it was just issued
+#[derive(Debug, Clone)]
+pub struct IssuedController {
+    pub controller: controllers::Model,
+    pub token: String,
+}
+
+/// Changes to a controller, anything left as None stays as it is<br>
+/// `associated_zone` is `Some(None)` to take the controller out of its zone
+#[derive(Debug, Clone, Default)]
+pub struct ControllerUpdate {
+    pub name: Option<String>,
+    pub com_type: Option<i32>,
+    pub associated_zone: Option<Option<i32>>,
+    pub primary: Option<bool>,
+}
+
+/// Adds an active controller with a new token and its own capability and idle activity rows
+/// # Errors
+/// A blank name is invalid, and a zone or communication type that does not exist is a missing reference
+pub async fn create(db: &DatabaseConnection, actor: &Actor, new_controller: NewController) -> Result<IssuedController, RepoError> {
+    require_text(&new_controller.name, "name")?;
+    let token: String = tokens::generate();
+    let txn: DatabaseTransaction = db.begin().await?;
+    check_optional_reference::<zones::Entity, _>(&txn, new_controller.associated_zone, "associatedZone").await?;
+    check_reference::<communication::Entity, _>(&txn, new_controller.com_type, "comType").await?;
+    let capability: env_capability::Model = env_capability::ActiveModel {
+        id: NotSet,
+        heating: Set(new_controller.heating),
+        cooling: Set(new_controller.cooling),
+        last_changed: Set(None),
+    }.insert(&txn).await?;
+    let activity: hva_cactivity::Model = hva_cactivity::ActiveModel {
+        id: NotSet,
+        heating: Set(false),
+        heat_last_change: Set(None),
+        cooling: Set(false),
+        cool_last_change: Set(None),
+    }.insert(&txn).await?;
+    let controller: controllers::Model = controllers::ActiveModel {
+        id: NotSet,
+        name: Set(new_controller.name),
+        active: Set(true),
+        com_type: Set(new_controller.com_type),
+        primary: Set(new_controller.primary),
+        associated_zone: Set(new_controller.associated_zone),
+        token: Set(tokens::hash(&token)),
+        time_added: Set(Utc::now().naive_utc()),
+        time_changed: Set(None),
+        time_connect_last: Set(None),
+        capability: Set(capability.id),
+        system_active: Set(activity.id),
+    }.insert(&txn).await?;
+    audit::record(&txn, actor, Action::Create, "controller", controller.id, None, Some(&controller)).await?;
+    txn.commit().await?;
+    debug!("Added controller {} as {}", controller.name, controller.id);
+    Ok(IssuedController { controller, token })
+}
+
+pub async fn get(db: &DatabaseConnection, id: i32) -> Result<controllers::Model, RepoError> {
+    find_or_missing::<controllers::Entity, _>(db, id, "controller").await
+}
+
+/// Lists controllers by id, leaving out deactivated ones unless asked for
+pub async fn list(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<controllers::Model>, RepoError> {
+    let mut query = controllers::Entity::find().order_by_asc(controllers::Column::Id);
+    if !include_inactive {
+        query = query.filter(controllers::Column::Active.eq(true));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// Renames a controller, moves it or changes how it talks
+pub async fn update(db: &DatabaseConnection, actor: &Actor, id: i32, changes: ControllerUpdate) -> Result<controllers::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: controllers::Model = find_or_missing::<controllers::Entity, _>(&txn, id, "controller").await?;
+    let mut controller: controllers::ActiveModel = before.clone().into_active_model();
+    if let Some(name) = changes.name {
+        require_text(&name, "name")?;
+        controller.name = Set(name);
+    }
+    if let Some(com_type) = changes.com_type {
+        check_reference::<communication::Entity, _>(&txn, com_type, "comType").await?;
+        controller.com_type = Set(com_type);
+    }
+    if let Some(zone) = changes.associated_zone {
+        check_optional_reference::<zones::Entity, _>(&txn, zone, "associatedZone").await?;
+        controller.associated_zone = Set(zone);
+    }
+    if let Some(primary) = changes.primary {
+        controller.primary = Set(primary);
+    }
+    controller.time_changed = Set(Some(Utc::now().naive_utc()));
+    let controller: controllers::Model = controller.update(&txn).await?;
+    audit::record(&txn, actor, Action::Update, "controller", id, Some(&before), Some(&controller)).await?;
+    txn.commit().await?;
+    Ok(controller)
+}
+
+/// Stops using a controller
+pub async fn deactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<controllers::Model, RepoError> {
+    set_active(db, actor, id, false).await
+}
+
+pub async fn reactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<controllers::Model, RepoError> {
+    set_active(db, actor, id, true).await
+}
+
+async fn set_active(db: &DatabaseConnection, actor: &Actor, id: i32, active: bool) -> Result<controllers::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: controllers::Model = find_or_missing::<controllers::Entity, _>(&txn, id, "controller").await?;
+    let mut controller: controllers::ActiveModel = before.clone().into_active_model();
+    controller.active = Set(active);
+    controller.time_changed = Set(Some(Utc::now().naive_utc()));
+    let controller: controllers::Model = controller.update(&txn).await?;
+    let action: Action = if active { Action::Reactivate } else { Action::Deactivate };
+    audit::record(&txn, actor, action, "controller", id, Some(&before), Some(&controller)).await?;
+    txn.commit().await?;
+    Ok(controller)
+}
+
+/// Issues a controller a new token, after which the old one no longer works
+pub async fn rotate_token(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<IssuedController, RepoError> {
+    let token: String = tokens::generate();
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: controllers::Model = find_or_missing::<controllers::Entity, _>(&txn, id, "controller").await?;
+    let mut controller: controllers::ActiveModel = before.clone().into_active_model();
+    controller.token = Set(tokens::hash(&token));
+    controller.time_changed = Set(Some(Utc::now().naive_utc()));
+    let controller: controllers::Model = controller.update(&txn).await?;
+    audit::record(&txn, actor, Action::RotateToken, "controller", id, Some(&before), Some(&controller)).await?;
+    txn.commit().await?;
+    info!("Issued controller {} a new token", controller.name);
+    Ok(IssuedController { controller, token })
+}
+
+/// The active controller a token belongs to, if any
+pub async fn authenticate(db: &DatabaseConnection, token: &str) -> Result<Option<controllers::Model>, RepoError> {
+    Ok(controllers::Entity::find()
+        .filter(controllers::Column::Token.eq(tokens::hash(token)))
+        .filter(controllers::Column::Active.eq(true))
+        .one(db).await?)
+}
+
+/// The active controllers in a zone, primary ones first
+pub async fn for_zone(db: &DatabaseConnection, zone: i32) -> Result<Vec<controllers::Model>, RepoError> {
+    find_or_missing::<zones::Entity, _>(db, zone, "zone").await?;
+    Ok(controllers::Entity::find()
+        .filter(controllers::Column::AssociatedZone.eq(zone))
+        .filter(controllers::Column::Active.eq(true))
+        .order_by_desc(controllers::Column::Primary)
+        .order_by_asc(controllers::Column::Id)
+        .all(db).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+    use crate::repo::zones::{self as zone_repo, NewZone};
+
+    fn new_controller(name: &str, zone: Option<i32>, primary: bool) -> NewController {
+        NewController { name: name.to_string(), com_type: 2, associated_zone: zone, primary, heating: true, cooling: true }
+    }
+
+    #[tokio::test]
+    async fn create_gives_the_controller_its_capability() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let controller: controllers::Model = create(&db, &test_actor(), new_controller("Furnace", None, true)).await.unwrap().controller;
+        let capability: env_capability::Model = env_capability::Entity::find_by_id(controller.capability).one(&db).await.unwrap().unwrap();
+        assert!(capability.heating && capability.cooling);
+        assert_eq!(get(&db, controller.id).await.unwrap().name, "Furnace");
+    }
+
+    #[tokio::test]
+    async fn duplicate_names_are_refused_by_the_database() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        create(&db, &test_actor(), new_controller("Boiler", None, true)).await.unwrap();
+        assert!(matches!(create(&db, &test_actor(), new_controller("Boiler", None, false)).await, Err(RepoError::Db(_))));
+        // The capability and activity rows of the failed insert are rolled back with it
+        assert_eq!(env_capability::Entity::find().all(&db).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn for_zone_puts_primary_controllers_first() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: i32 = zone_repo::create(&db, &test_actor(), NewZone { name: "Basement".to_string(), heating: true, cooling: true }).await.unwrap().id;
+        let backup: controllers::Model = create(&db, &test_actor(), new_controller("Space heater", Some(zone), false)).await.unwrap().controller;
+        let main: controllers::Model = create(&db, &test_actor(), new_controller("Heat pump", Some(zone), true)).await.unwrap().controller;
+        let retired: controllers::Model = create(&db, &test_actor(), new_controller("Old boiler", Some(zone), true)).await.unwrap().controller;
+        deactivate(&db, &test_actor(), retired.id).await.unwrap();
+
+        let ids: Vec<i32> = for_zone(&db, zone).await.unwrap().iter().map(|controller| controller.id).collect();
+        assert_eq!(ids, vec![main.id, backup.id]);
+    }
+
+    #[tokio::test]
+    async fn only_the_current_token_authenticates() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let issued: IssuedController = create(&db, &test_actor(), new_controller("Furnace", None, true)).await.unwrap();
+        assert_eq!(issued.controller.token, tokens::hash(&issued.token));
+        assert_eq!(authenticate(&db, &issued.token).await.unwrap().map(|controller| controller.id), Some(issued.controller.id));
+        assert!(authenticate(&db, &issued.controller.token).await.unwrap().is_none());
+
+        let rotated: IssuedController = rotate_token(&db, &test_actor(), issued.controller.id).await.unwrap();
+        assert_ne!(rotated.token, issued.token);
+        assert!(authenticate(&db, &issued.token).await.unwrap().is_none());
+        assert!(authenticate(&db, &rotated.token).await.unwrap().is_some());
+        deactivate(&db, &test_actor(), issued.controller.id).await.unwrap();
+        assert!(authenticate(&db, &rotated.token).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn update_checks_the_new_zone() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let controller: controllers::Model = create(&db, &test_actor(), new_controller("Fan", None, true)).await.unwrap().controller;
+        let changes: ControllerUpdate = ControllerUpdate { associated_zone: Some(Some(8)), ..ControllerUpdate::default() };
+        assert!(matches!(update(&db, &test_actor(), controller.id, changes).await, Err(RepoError::MissingReference { field: "associatedZone", id: 8 })));
+        let changes: ControllerUpdate = ControllerUpdate { primary: Some(false), ..ControllerUpdate::default() };
+        assert!(!update(&db, &test_actor(), controller.id, changes).await.unwrap().primary);
+    }
+}
@@ -0,0 +1,111 @@
+//! # Rusty Thermostat Repository
+//! Typed queries over the `schema` entities, so features share one way of reading and changing zones, sensors,
+//! controllers, schedules and alerts<br>
+//! Nothing here deletes a row. Things that go away are deactivated, which keeps the history pointing at them intact.
+//! Every change is made on behalf of an `audit::Actor` and leaves an entry in the audit log
+
+use std::fmt;
+use sea_orm::{ConnectionTrait, DbErr, EntityTrait, PrimaryKeyTrait};
+
+pub mod zones;
+pub mod sensors;
+pub mod controllers;
+pub mod schedules;
+pub mod alerts;
+pub mod audit;
+pub mod tokens;
+
+/// RepoError covers everything that can go wrong in the repository
+#[derive(Debug)]
+pub enum RepoError {
+    /// No row has the id that was asked for
+    NotFound { entity: &'static str, id: i32 },
+    /// A foreign key points at a row that does not exist
+    MissingReference { field: &'static str, id: i32 },
+    /// The values given break a rule the database cannot check on its own
+    Invalid(String),
+    /// The database failed the query
+    Db(DbErr),
+}
+
+impl fmt::Display for RepoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RepoError::NotFound { entity, id } => write!(f, "No {} with id {}", entity, id),
+            RepoError::MissingReference { field, id } => write!(f, "{} {} does not exist", field, id),
+            RepoError::Invalid(reason) => write!(f, "Invalid request: {}", reason),
+            RepoError::Db(error) => write!(f, "Database error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for RepoError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepoError::Db(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<DbErr> for RepoError {
+    fn from(error: DbErr) -> Self {
+        RepoError::Db(error)
+    }
+}
+
+// Finds a row by id or says which entity was missing
+async fn find_or_missing<E, C>(db: &C, id: i32, entity: &'static str) -> Result<E::Model, RepoError>
+where
+    E: EntityTrait,
+    <E::PrimaryKey as PrimaryKeyTrait>::ValueType: From<i32>,
+    C: ConnectionTrait,
+{
+    E::find_by_id(id).one(db).await?.ok_or(RepoError::NotFound { entity, id })
+}
+
+// Checks a foreign key before it is written, so callers get a clear error instead of a constraint failure
+async fn check_reference<E, C>(db: &C, id: i32, field: &'static str) -> Result<(), RepoError>
+where
+    E: EntityTrait,
+    <E::PrimaryKey as PrimaryKeyTrait>::ValueType: From<i32>,
+    C: ConnectionTrait,
+{
+    match E::find_by_id(id).one(db).await? {
+        Some(_) => Ok(()),
+        None => Err(RepoError::MissingReference { field, id }),
+    }
+}
+
+// Same as check_reference for a key that may be left empty
+async fn check_optional_reference<E, C>(db: &C, id: Option<i32>, field: &'static str) -> Result<(), RepoError>
+where
+    E: EntityTrait,
+    <E::PrimaryKey as PrimaryKeyTrait>::ValueType: From<i32>,
+    C: ConnectionTrait,
+{
+    match id {
+        Some(id) => check_reference::<E, C>(db, id, field).await,
+        None => Ok(()),
+    }
+}
+
+// Names and tokens may not be blank
+fn require_text(value: &str, field: &str) -> Result<(), RepoError> {
+    if value.trim().is_empty() {
+        return Err(RepoError::Invalid(format!("{} cannot be empty", field)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repo_error_display_names_the_row() {
+        assert_eq!(RepoError::NotFound { entity: "zone", id: 4 }.to_string(), "No zone with id 4");
+        assert_eq!(RepoError::MissingReference { field: "comType", id: 9 }.to_string(), "comType 9 does not exist");
+        assert!(require_text("  ", "name").is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Schedules hold a zone between two temperatures for a span of times, weekdays and dates<br>
+//! The weekdays are a Weekdays row, whose id is the bitmask built by `dbman::seed::weekdays_from_mask`
+
+use chrono::{NaiveDate, NaiveTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, QueryFilter,
+    QueryOrder, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use crate::schema::{schedules, weekdays, zones};
+use crate::units::Temperature;
+use super::{RepoError, check_optional_reference, find_or_missing, require_text};
+use super::audit::{self, Action, Actor};
+
+/// What is needed to add a schedule. Everything but the name may be left open
+#[derive(Debug, Clone, Default)]
+pub struct NewSchedule {
+    pub name: String,
+    pub associated_zone: Option<i32>,
+    pub time_start: Option<NaiveTime>,
+    pub time_end: Option<NaiveTime>,
+    pub week_day: Option<i32>,
+    pub date_start: Option<NaiveDate>,
+    pub date_end: Option<NaiveDate>,
+    pub temp_min: Option<Temperature>,
+    pub temp_max: Option<Temperature>,
+}
+
+/// Changes to a schedule, anything left as None stays as it is<br>
+/// The fields that may be empty take `Some(None)` to clear them
+#[derive(Debug, Clone, Default)]
+pub struct ScheduleUpdate {
+    pub name: Option<String>,
+    pub associated_zone: Option<Option<i32>>,
+    pub time_start: Option<Option<NaiveTime>>,
+    pub time_end: Option<Option<NaiveTime>>,
+    pub week_day: Option<Option<i32>>,
+    pub date_start: Option<Option<NaiveDate>>,
+    pub date_end: Option<Option<NaiveDate>>,
+    pub temp_min: Option<Option<Temperature>>,
+    pub temp_max: Option<Option<Temperature>>,
+}
+
+/// Adds an active schedule
+/// # Errors
+/// A blank name, a minimum above the maximum or an end date before the start is invalid. A zone or weekday set that
+/// does not exist is a missing reference
+pub async fn create(db: &DatabaseConnection, actor: &Actor, new_schedule: NewSchedule) -> Result<schedules::Model, RepoError> {
+    require_text(&new_schedule.name, "name")?;
+    check_ranges(new_schedule.temp_min, new_schedule.temp_max, new_schedule.date_start, new_schedule.date_end)?;
+    let txn: DatabaseTransaction = db.begin().await?;
+    check_optional_reference::<zones::Entity, _>(&txn, new_schedule.associated_zone, "associatedZone").await?;
+    check_optional_reference::<weekdays::Entity, _>(&txn, new_schedule.week_day, "weekDay").await?;
+    let schedule: schedules::Model = schedules::ActiveModel {
+        id: NotSet,
+        active: Set(true),
+        name: Set(new_schedule.name),
+        associated_zone: Set(new_schedule.associated_zone),
+        last_changed: Set(None),
+        time_start: Set(new_schedule.time_start),
+        time_end: Set(new_schedule.time_end),
+        week_day: Set(new_schedule.week_day),
+        date_start: Set(new_schedule.date_start),
+        date_end: Set(new_schedule.date_end),
+        temp_min: Set(new_schedule.temp_min),
+        temp_max: Set(new_schedule.temp_max),
+    }.insert(&txn).await?;
+    audit::record(&txn, actor, Action::Create, "schedule", schedule.id, None, Some(&schedule)).await?;
+    txn.commit().await?;
+    debug!("Added schedule {} as {}", schedule.name, schedule.id);
+    Ok(schedule)
+}
+
+pub async fn get(db: &DatabaseConnection, id: i32) -> Result<schedules::Model, RepoError> {
+    find_or_missing::<schedules::Entity, _>(db, id, "schedule").await
+}
+
+/// Lists schedules by id, leaving out deactivated ones unless asked for
+pub async fn list(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<schedules::Model>, RepoError> {
+    let mut query = schedules::Entity::find().order_by_asc(schedules::Column::Id);
+    if !include_inactive {
+        query = query.filter(schedules::Column::Active.eq(true));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// Changes any part of a schedule, checking the result as a whole
+pub async fn update(db: &DatabaseConnection, actor: &Actor, id: i32, changes: ScheduleUpdate) -> Result<schedules::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let current: schedules::Model = find_or_missing::<schedules::Entity, _>(&txn, id, "schedule").await?;
+    check_ranges(
+        changes.temp_min.unwrap_or(current.temp_min),
+        changes.temp_max.unwrap_or(current.temp_max),
+        changes.date_start.unwrap_or(current.date_start),
+        changes.date_end.unwrap_or(current.date_end))?;
+    let mut schedule: schedules::ActiveModel = current.clone().into_active_model();
+    if let Some(name) = changes.name {
+        require_text(&name, "name")?;
+        schedule.name = Set(name);
+    }
+    if let Some(zone) = changes.associated_zone {
+        check_optional_reference::<zones::Entity, _>(&txn, zone, "associatedZone").await?;
+        schedule.associated_zone = Set(zone);
+    }
+    if let Some(week_day) = changes.week_day {
+        check_optional_reference::<weekdays::Entity, _>(&txn, week_day, "weekDay").await?;
+        schedule.week_day = Set(week_day);
+    }
+    if let Some(time_start) = changes.time_start {
+        schedule.time_start = Set(time_start);
+    }
+    if let Some(time_end) = changes.time_end {
+        schedule.time_end = Set(time_end);
+    }
+    if let Some(date_start) = changes.date_start {
+        schedule.date_start = Set(date_start);
+    }
+    if let Some(date_end) = changes.date_end {
+        schedule.date_end = Set(date_end);
+    }
+    if let Some(temp_min) = changes.temp_min {
+        schedule.temp_min = Set(temp_min);
+    }
+    if let Some(temp_max) = changes.temp_max {
+        schedule.temp_max = Set(temp_max);
+    }
+    schedule.last_changed = Set(Some(Utc::now().naive_utc()));
+    let schedule: schedules::Model = schedule.update(&txn).await?;
+    audit::record(&txn, actor, Action::Update, "schedule", id, Some(&current), Some(&schedule)).await?;
+    txn.commit().await?;
+    Ok(schedule)
+}
+
+/// Switches a schedule off without losing it
+pub async fn deactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<schedules::Model, RepoError> {
+    set_active(db, actor, id, false).await
+}
+
+pub async fn reactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<schedules::Model, RepoError> {
+    set_active(db, actor, id, true).await
+}
+
+async fn set_active(db: &DatabaseConnection, actor: &Actor, id: i32, active: bool) -> Result<schedules::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: schedules::Model = find_or_missing::<schedules::Entity, _>(&txn, id, "schedule").await?;
+    let mut schedule: schedules::ActiveModel = before.clone().into_active_model();
+    schedule.active = Set(active);
+    schedule.last_changed = Set(Some(Utc::now().naive_utc()));
+    let schedule: schedules::Model = schedule.update(&txn).await?;
+    let action: Action = if active { Action::Reactivate } else { Action::Deactivate };
+    audit::record(&txn, actor, action, "schedule", id, Some(&before), Some(&schedule)).await?;
+    txn.commit().await?;
+    Ok(schedule)
+}
+
+// The lower ends of the temperature and date spans may not be past the upper ends
+fn check_ranges(temp_min: Option<Temperature>, temp_max: Option<Temperature>, date_start: Option<NaiveDate>,
+    date_end: Option<NaiveDate>) -> Result<(), RepoError> {
+    if let (Some(low), Some(high)) = (temp_min, temp_max) {
+        if low > high {
+            return Err(RepoError::Invalid(format!("tempMin {} is above tempMax {}", low, high)));
+        }
+    }
+    if let (Some(start), Some(end)) = (date_start, date_end) {
+        if start > end {
+            return Err(RepoError::Invalid(format!("dateEnd {} is before dateStart {}", end, start)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+    use crate::repo::zones::{self as zone_repo, NewZone};
+
+    fn weeknights(zone: Option<i32>) -> NewSchedule {
+        NewSchedule {
+            name: "Weeknights".to_string(),
+            associated_zone: zone,
+            time_start: NaiveTime::from_hms_opt(18, 0, 0),
+            time_end: NaiveTime::from_hms_opt(22, 30, 0),
+            week_day: Some(0b011_1110),
+            temp_min: Some(Temperature::from_celsius(19.0)),
+            temp_max: Some(Temperature::from_celsius(23.0)),
+            ..NewSchedule::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn create_links_the_weekday_set() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: i32 = zone_repo::create(&db, &test_actor(), NewZone { name: "Study".to_string(), heating: true, cooling: false }).await.unwrap().id;
+        let schedule: schedules::Model = create(&db, &test_actor(), weeknights(Some(zone))).await.unwrap();
+        let days: weekdays::Model = weekdays::Entity::find_by_id(schedule.week_day.unwrap()).one(&db).await.unwrap().unwrap();
+        assert!(days.monday && days.friday && !days.saturday && !days.sunday);
+        assert_eq!(zone_repo::active_schedules(&db, zone).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn create_refuses_an_inverted_range() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let mut backwards: NewSchedule = weeknights(None);
+        backwards.temp_min = Some(Temperature::from_celsius(25.0));
+        assert!(matches!(create(&db, &test_actor(), backwards).await, Err(RepoError::Invalid(_))));
+        let mut unknown_days: NewSchedule = weeknights(None);
+        unknown_days.week_day = Some(500);
+        assert!(matches!(create(&db, &test_actor(), unknown_days).await, Err(RepoError::MissingReference { field: "weekDay", id: 500 })));
+    }
+
+    #[tokio::test]
+    async fn update_checks_against_the_stored_values() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let schedule: schedules::Model = create(&db, &test_actor(), weeknights(None)).await.unwrap();
+        let too_low: ScheduleUpdate = ScheduleUpdate { temp_max: Some(Some(Temperature::from_celsius(15.0))), ..ScheduleUpdate::default() };
+        assert!(matches!(update(&db, &test_actor(), schedule.id, too_low).await, Err(RepoError::Invalid(_))));
+
+        let open_ended: ScheduleUpdate = ScheduleUpdate { temp_max: Some(None), week_day: Some(Some(127)), ..ScheduleUpdate::default() };
+        let updated: schedules::Model = update(&db, &test_actor(), schedule.id, open_ended).await.unwrap();
+        assert_eq!(updated.temp_max, None);
+        assert_eq!(updated.week_day, Some(127));
+        assert_eq!(updated.temp_min, Some(Temperature::from_celsius(19.0)));
+    }
+
+    #[tokio::test]
+    async fn deactivated_schedules_are_not_active_for_the_zone() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: i32 = zone_repo::create(&db, &test_actor(), NewZone { name: "Guest room".to_string(), heating: true, cooling: true }).await.unwrap().id;
+        let schedule: schedules::Model = create(&db, &test_actor(), weeknights(Some(zone))).await.unwrap();
+        deactivate(&db, &test_actor(), schedule.id).await.unwrap();
+        assert!(zone_repo::active_schedules(&db, zone).await.unwrap().is_empty());
+        assert_eq!(list(&db, true).await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,268 @@
+//! Sensors report temperature, humidity, presence and open windows or doors for a zone<br>
+//! A sensor authenticates with a token issued when it is registered or its token is rotated. Only the token's hash is
+//! stored, so the plain token in an `IssuedSensor` is the one chance to hand it to the device
+
+use chrono::Utc;
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, EntityTrait, IntoActiveModel, ModelTrait,
+    QueryFilter, QueryOrder, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use crate::schema::{communication, sensor_reading_history, sensors, zones};
+use super::{RepoError, check_optional_reference, check_reference, find_or_missing, require_text};
+use super::audit::{self, Action, Actor};
+use super::tokens;
+
+/// What is needed to register a sensor
+#[derive(Debug, Clone)]
+pub struct NewSensor {
+    pub name: String,
+    pub associated_zone: Option<i32>,
+    pub com_type: i32,
+}
+
+/// A sensor along with the plain token it was just issued
+#[derive(Debug, Clone)]
+pub struct IssuedSensor {
+    pub sensor: sensors::Model,
+    pub token: String,
+}
+
+/// Changes to a sensor, anything left as None stays as it is<br>
+/// `associated_zone` is `Some(None)` to take the sensor out of its zone
+#[derive(Debug, Clone, Default)]
+pub struct SensorUpdate {
+    pub name: Option<String>,
+    pub associated_zone: Option<Option<i32>>,
+    pub com_type: Option<i32>,
+}
+
+/// Adds an active sensor with a new token after checking its zone and communication type exist
+/// # Errors
+/// A blank name is invalid, and a zone or communication type that does not exist is a missing reference
+pub async fn create(db: &DatabaseConnection, actor: &Actor, new_sensor: NewSensor) -> Result<IssuedSensor, RepoError> {
+    require_text(&new_sensor.name, "name")?;
+    let token: String = tokens::generate();
+    let txn: DatabaseTransaction = db.begin().await?;
+    check_optional_reference::<zones::Entity, _>(&txn, new_sensor.associated_zone, "associatedZone").await?;
+    check_reference::<communication::Entity, _>(&txn, new_sensor.com_type, "comType").await?;
+    let sensor: sensors::Model = sensors::ActiveModel {
+        id: NotSet,
+        active: Set(true),
+        name: Set(new_sensor.name),
+        token: Set(tokens::hash(&token)),
+        associated_zone: Set(new_sensor.associated_zone),
+        time_added: Set(Utc::now().naive_utc()),
+        time_updated: Set(None),
+        com_type: Set(new_sensor.com_type),
+        com_last: Set(None),
+        current_temp: Set(None),
+        current_humid: Set(None),
+        presence: Set(None),
+        threshold_open: Set(None),
+    }.insert(&txn).await?;
+    audit::record(&txn, actor, Action::Create, "sensor", sensor.id, None, Some(&sensor)).await?;
+    txn.commit().await?;
+    debug!("Added sensor {} as {}", sensor.name, sensor.id);
+    Ok(IssuedSensor { sensor, token })
+}
+
+pub async fn get(db: &DatabaseConnection, id: i32) -> Result<sensors::Model, RepoError> {
+    find_or_missing::<sensors::Entity, _>(db, id, "sensor").await
+}
+
+/// Lists sensors by id, leaving out deactivated ones unless asked for
+pub async fn list(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<sensors::Model>, RepoError> {
+    let mut query = sensors::Entity::find().order_by_asc(sensors::Column::Id);
+    if !include_inactive {
+        query = query.filter(sensors::Column::Active.eq(true));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// Renames a sensor, moves it to another zone or changes how it talks
+pub async fn update(db: &DatabaseConnection, actor: &Actor, id: i32, changes: SensorUpdate) -> Result<sensors::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: sensors::Model = find_or_missing::<sensors::Entity, _>(&txn, id, "sensor").await?;
+    let mut sensor: sensors::ActiveModel = before.clone().into_active_model();
+    if let Some(name) = changes.name {
+        require_text(&name, "name")?;
+        sensor.name = Set(name);
+    }
+    if let Some(zone) = changes.associated_zone {
+        check_optional_reference::<zones::Entity, _>(&txn, zone, "associatedZone").await?;
+        sensor.associated_zone = Set(zone);
+    }
+    if let Some(com_type) = changes.com_type {
+        check_reference::<communication::Entity, _>(&txn, com_type, "comType").await?;
+        sensor.com_type = Set(com_type);
+    }
+    sensor.time_updated = Set(Some(Utc::now().naive_utc()));
+    let sensor: sensors::Model = sensor.update(&txn).await?;
+    audit::record(&txn, actor, Action::Update, "sensor", id, Some(&before), Some(&sensor)).await?;
+    txn.commit().await?;
+    Ok(sensor)
+}
+
+/// Stops accepting readings from a sensor. Its history is kept
+pub async fn deactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<sensors::Model, RepoError> {
+    set_active(db, actor, id, false).await
+}
+
+pub async fn reactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<sensors::Model, RepoError> {
+    set_active(db, actor, id, true).await
+}
+
+async fn set_active(db: &DatabaseConnection, actor: &Actor, id: i32, active: bool) -> Result<sensors::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: sensors::Model = find_or_missing::<sensors::Entity, _>(&txn, id, "sensor").await?;
+    let mut sensor: sensors::ActiveModel = before.clone().into_active_model();
+    sensor.active = Set(active);
+    sensor.time_updated = Set(Some(Utc::now().naive_utc()));
+    let sensor: sensors::Model = sensor.update(&txn).await?;
+    let action: Action = if active { Action::Reactivate } else { Action::Deactivate };
+    audit::record(&txn, actor, action, "sensor", id, Some(&before), Some(&sensor)).await?;
+    txn.commit().await?;
+    Ok(sensor)
+}
+
+/// Issues a sensor a new token, after which the old one no longer works
+pub async fn rotate_token(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<IssuedSensor, RepoError> {
+    let token: String = tokens::generate();
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: sensors::Model = find_or_missing::<sensors::Entity, _>(&txn, id, "sensor").await?;
+    let mut sensor: sensors::ActiveModel = before.clone().into_active_model();
+    sensor.token = Set(tokens::hash(&token));
+    sensor.time_updated = Set(Some(Utc::now().naive_utc()));
+    let sensor: sensors::Model = sensor.update(&txn).await?;
+    audit::record(&txn, actor, Action::RotateToken, "sensor", id, Some(&before), Some(&sensor)).await?;
+    txn.commit().await?;
+    info!("Issued sensor {} a new token", sensor.name);
+    Ok(IssuedSensor { sensor, token })
+}
+
+/// The active sensor a token belongs to, if any
+pub async fn authenticate(db: &DatabaseConnection, token: &str) -> Result<Option<sensors::Model>, RepoError> {
+    Ok(sensors::Entity::find()
+        .filter(sensors::Column::Token.eq(tokens::hash(token)))
+        .filter(sensors::Column::Active.eq(true))
+        .one(db).await?)
+}
+
+/// The newest stored reading for a sensor, if it has sent any
+pub async fn latest_reading(db: &DatabaseConnection, id: i32) -> Result<Option<sensor_reading_history::Model>, RepoError> {
+    let sensor: sensors::Model = get(db, id).await?;
+    Ok(sensor.find_related(sensor_reading_history::Entity)
+        .order_by_desc(sensor_reading_history::Column::Timestamp)
+        .one(db).await?)
+}
+
+/// The newest reading of every active sensor that has sent one, paired with the sensor
+pub async fn latest_readings(db: &DatabaseConnection) -> Result<Vec<(sensors::Model, sensor_reading_history::Model)>, RepoError> {
+    let mut latest: Vec<(sensors::Model, sensor_reading_history::Model)> = Vec::new();
+    for sensor in list(db, false).await? {
+        let newest: Option<sensor_reading_history::Model> = sensor.find_related(sensor_reading_history::Entity)
+            .order_by_desc(sensor_reading_history::Column::Timestamp)
+            .one(db).await?;
+        if let Some(reading) = newest {
+            latest.push((sensor, reading));
+        }
+    }
+    Ok(latest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use chrono::{Duration, NaiveDateTime};
+    use crate::dbman;
+    use crate::units::Temperature;
+    use uuid::Uuid;
+
+    fn new_sensor(name: &str, zone: Option<i32>) -> NewSensor {
+        NewSensor { name: name.to_string(), associated_zone: zone, com_type: 1 }
+    }
+
+    async fn record(db: &DatabaseConnection, sensor: i32, timestamp: NaiveDateTime, celsius: f64) {
+        sensor_reading_history::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            sensor_id: Set(sensor),
+            timestamp: Set(timestamp),
+            reading_temp: Set(Some(Temperature::from_celsius(celsius))),
+            reading_humidity: Set(None),
+            reading_presence: Set(None),
+            reading_threshold_open: Set(None),
+        }.insert(db).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn create_checks_zone_and_com_type() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        assert!(matches!(create(&db, &test_actor(), new_sensor("Hall", Some(42))).await,
+            Err(RepoError::MissingReference { field: "associatedZone", id: 42 })));
+        let mut unknown_com: NewSensor = new_sensor("Hall", None);
+        unknown_com.com_type = 77;
+        assert!(matches!(create(&db, &test_actor(), unknown_com).await, Err(RepoError::MissingReference { field: "comType", .. })));
+
+        let sensor: sensors::Model = create(&db, &test_actor(), new_sensor("Hall", None)).await.unwrap().sensor;
+        assert!(sensor.active);
+    }
+
+    #[tokio::test]
+    async fn only_the_current_token_authenticates() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let issued: IssuedSensor = create(&db, &test_actor(), new_sensor("Hall", None)).await.unwrap();
+        assert_eq!(issued.sensor.token, tokens::hash(&issued.token));
+        assert_eq!(authenticate(&db, &issued.token).await.unwrap().map(|sensor| sensor.id), Some(issued.sensor.id));
+        assert!(authenticate(&db, &issued.sensor.token).await.unwrap().is_none());
+
+        let rotated: IssuedSensor = rotate_token(&db, &test_actor(), issued.sensor.id).await.unwrap();
+        assert_ne!(rotated.token, issued.token);
+        assert!(authenticate(&db, &issued.token).await.unwrap().is_none());
+        assert!(authenticate(&db, &rotated.token).await.unwrap().is_some());
+        deactivate(&db, &test_actor(), issued.sensor.id).await.unwrap();
+        assert!(authenticate(&db, &rotated.token).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn update_moves_a_sensor_out_of_its_zone() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: i32 = crate::repo::zones::create(&db, &test_actor(), crate::repo::zones::NewZone { name: "Porch".to_string(), heating: false, cooling: false })
+            .await.unwrap().id;
+        let sensor: sensors::Model = create(&db, &test_actor(), new_sensor("Door", Some(zone))).await.unwrap().sensor;
+        let changes: SensorUpdate = SensorUpdate { associated_zone: Some(None), com_type: Some(3), ..SensorUpdate::default() };
+        let updated: sensors::Model = update(&db, &test_actor(), sensor.id, changes).await.unwrap();
+        assert_eq!(updated.associated_zone, None);
+        assert_eq!(updated.com_type, 3);
+        assert!(updated.time_updated.is_some());
+    }
+
+    #[tokio::test]
+    async fn deactivated_sensors_are_left_out() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let sensor: sensors::Model = create(&db, &test_actor(), new_sensor("Shed", None)).await.unwrap().sensor;
+        deactivate(&db, &test_actor(), sensor.id).await.unwrap();
+        assert!(list(&db, false).await.unwrap().is_empty());
+        assert_eq!(list(&db, true).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn latest_reading_is_the_newest_per_sensor() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let first: sensors::Model = create(&db, &test_actor(), new_sensor("Bedroom", None)).await.unwrap().sensor;
+        let second: sensors::Model = create(&db, &test_actor(), new_sensor("Bath", None)).await.unwrap().sensor;
+        let quiet: sensors::Model = create(&db, &test_actor(), new_sensor("Closet", None)).await.unwrap().sensor;
+        let now: NaiveDateTime = Utc::now().naive_utc();
+        record(&db, first.id, now - Duration::minutes(10), 19.0).await;
+        record(&db, first.id, now, 20.5).await;
+        record(&db, first.id, now - Duration::minutes(5), 19.5).await;
+        record(&db, second.id, now, 23.0).await;
+
+        let newest: sensor_reading_history::Model = latest_reading(&db, first.id).await.unwrap().unwrap();
+        assert_eq!(newest.reading_temp, Some(Temperature::from_celsius(20.5)));
+        assert!(latest_reading(&db, quiet.id).await.unwrap().is_none());
+
+        let all: Vec<(sensors::Model, sensor_reading_history::Model)> = latest_readings(&db).await.unwrap();
+        let summary: Vec<(i32, Option<Temperature>)> = all.iter().map(|(sensor, reading)| (sensor.id, reading.reading_temp)).collect();
+        assert_eq!(summary, vec![(first.id, Some(Temperature::from_celsius(20.5))), (second.id, Some(Temperature::from_celsius(23.0)))]);
+    }
+}
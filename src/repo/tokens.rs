@@ -0,0 +1,48 @@
+//! Device tokens, the secrets sensors send to authenticate to the API<br>
+//! A token is 32 bytes from the operating system's random source, handed out as hex once and stored only as its
+//! SHA-256. Tokens are random rather than chosen, so a plain hash is enough to keep a stolen database from giving them
+//! away
+
+use rand::RngCore;
+use rand::rngs::OsRng;
+use sha2::{Digest, Sha256};
+
+// Random bytes in a token
+const TOKEN_BYTES: usize = 32;
+/// Marks a stored value as a hash, and which one
+pub const HASH_PREFIX: &str = "sha256:";
+
+/// A new token in plain text, to be shown to whoever registered the device and then forgotten
+pub fn generate() -> String {
+    let mut bytes: [u8; TOKEN_BYTES] = [0; TOKEN_BYTES];
+    OsRng.fill_bytes(&mut bytes);
+    hex(&bytes)
+}
+
+/// The form a token is stored and looked up in
+pub fn hash(token: &str) -> String {
+    format!("{}{}", HASH_PREFIX, hex(&Sha256::digest(token.as_bytes())))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_hex() {
+        let token: String = generate();
+        assert_eq!(token.len(), TOKEN_BYTES * 2);
+        assert!(token.chars().all(|digit| digit.is_ascii_hexdigit()));
+        assert_ne!(token, generate());
+    }
+
+    #[test]
+    fn hash_is_sha256_in_hex() {
+        assert_eq!(hash("abc"), "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_ne!(hash(&generate()), hash(&generate()));
+    }
+}
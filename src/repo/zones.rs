@@ -0,0 +1,335 @@
+//! Zones, the rooms or areas the thermostat looks after<br>
+//! Every zone owns an EnvCapability row saying whether it can be heated or cooled and an HVACactivity row saying
+//! whether it is right now
+
+use std::collections::HashMap;
+use chrono::{NaiveDateTime, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, EntityTrait,
+    IntoActiveModel, ModelTrait, QueryFilter, QueryOrder, QuerySelect, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use crate::schema::{controllers, env_capability, hva_cactivity, schedules, sensors, zones};
+use super::{RepoError, find_or_missing, require_text};
+use super::audit::{self, Action, Actor};
+
+/// What is needed to add a zone
+#[derive(Debug, Clone)]
+pub struct NewZone {
+    pub name: String,
+    pub heating: bool,
+    pub cooling: bool,
+}
+
+/// Changes to a zone, anything left as None stays as it is
+#[derive(Debug, Clone, Default)]
+pub struct ZoneUpdate {
+    pub name: Option<String>,
+    pub heating: Option<bool>,
+    pub cooling: Option<bool>,
+}
+
+/// A zone together with what it can do, what it is doing and the devices in it
+#[derive(Debug, Clone)]
+pub struct ZoneDetail {
+    pub zone: zones::Model,
+    pub capability: env_capability::Model,
+    pub activity: hva_cactivity::Model,
+    pub sensors: Vec<sensors::Model>,
+    pub controllers: Vec<controllers::Model>,
+}
+
+/// A zone with what it can do, what it is doing and how many active sensors and controllers it has
+#[derive(Debug, Clone)]
+pub struct ZoneOverview {
+    pub zone: zones::Model,
+    pub capability: env_capability::Model,
+    pub activity: hva_cactivity::Model,
+    pub sensor_count: u64,
+    pub controller_count: u64,
+}
+
+/// Adds a zone along with its own capability and idle activity rows
+/// # Errors
+/// A blank name is invalid
+pub async fn create(db: &DatabaseConnection, actor: &Actor, new_zone: NewZone) -> Result<zones::Model, RepoError> {
+    require_text(&new_zone.name, "name")?;
+    let txn: DatabaseTransaction = db.begin().await?;
+    let capability: env_capability::Model = env_capability::ActiveModel {
+        id: NotSet,
+        heating: Set(new_zone.heating),
+        cooling: Set(new_zone.cooling),
+        last_changed: Set(None),
+    }.insert(&txn).await?;
+    let activity: hva_cactivity::Model = hva_cactivity::ActiveModel {
+        id: NotSet,
+        heating: Set(false),
+        heat_last_change: Set(None),
+        cooling: Set(false),
+        cool_last_change: Set(None),
+    }.insert(&txn).await?;
+    let zone: zones::Model = zones::ActiveModel {
+        id: NotSet,
+        name: Set(new_zone.name),
+        active: Set(true),
+        capability: Set(capability.id),
+        time_added: Set(Utc::now().naive_utc()),
+        last_changed: Set(None),
+        current_temp: Set(None),
+        current_humid: Set(None),
+        system_active: Set(activity.id),
+        presence: Set(None),
+        thresholds_closed: Set(None),
+    }.insert(&txn).await?;
+    audit::record(&txn, actor, Action::Create, "zone", zone.id, None, Some(&zone)).await?;
+    txn.commit().await?;
+    debug!("Added zone {} as {}", zone.name, zone.id);
+    Ok(zone)
+}
+
+pub async fn get(db: &DatabaseConnection, id: i32) -> Result<zones::Model, RepoError> {
+    find_or_missing::<zones::Entity, _>(db, id, "zone").await
+}
+
+/// Lists zones by id, leaving out deactivated ones unless asked for
+pub async fn list(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<zones::Model>, RepoError> {
+    let mut query = zones::Entity::find().order_by_asc(zones::Column::Id);
+    if !include_inactive {
+        query = query.filter(zones::Column::Active.eq(true));
+    }
+    Ok(query.all(db).await?)
+}
+
+/// Gets a zone with its capability, activity and the sensors and controllers assigned to it
+pub async fn detail(db: &DatabaseConnection, id: i32) -> Result<ZoneDetail, RepoError> {
+    let zone: zones::Model = get(db, id).await?;
+    let capability: env_capability::Model = zone.find_related(env_capability::Entity).one(db).await?
+        .ok_or(RepoError::NotFound { entity: "capability", id: zone.capability })?;
+    let activity: hva_cactivity::Model = zone.find_related(hva_cactivity::Entity).one(db).await?
+        .ok_or(RepoError::NotFound { entity: "activity", id: zone.system_active })?;
+    let sensors: Vec<sensors::Model> = zone.find_related(sensors::Entity)
+        .order_by_asc(sensors::Column::Id).all(db).await?;
+    let controllers: Vec<controllers::Model> = zone.find_related(controllers::Entity)
+        .order_by_asc(controllers::Column::Id).all(db).await?;
+    Ok(ZoneDetail { zone, capability, activity, sensors, controllers })
+}
+
+/// Gets one zone's overview
+pub async fn overview(db: &DatabaseConnection, id: i32) -> Result<ZoneOverview, RepoError> {
+    let zone: zones::Model = get(db, id).await?;
+    let mut overviews: Vec<ZoneOverview> = overviews_of(db, vec![zone]).await?;
+    Ok(overviews.remove(0))
+}
+
+/// Lists zone overviews by id, leaving out deactivated zones unless asked for
+pub async fn overviews(db: &DatabaseConnection, include_inactive: bool) -> Result<Vec<ZoneOverview>, RepoError> {
+    let zones: Vec<zones::Model> = list(db, include_inactive).await?;
+    overviews_of(db, zones).await
+}
+
+// Reads the capability and activity rows and the device counts of all the zones at once
+async fn overviews_of(db: &DatabaseConnection, zones: Vec<zones::Model>) -> Result<Vec<ZoneOverview>, RepoError> {
+    let capabilities: HashMap<i32, env_capability::Model> = env_capability::Entity::find()
+        .filter(env_capability::Column::Id.is_in(zones.iter().map(|zone| zone.capability)))
+        .all(db).await?.into_iter().map(|row| (row.id, row)).collect();
+    let activities: HashMap<i32, hva_cactivity::Model> = hva_cactivity::Entity::find()
+        .filter(hva_cactivity::Column::Id.is_in(zones.iter().map(|zone| zone.system_active)))
+        .all(db).await?.into_iter().map(|row| (row.id, row)).collect();
+    let sensor_counts: HashMap<i32, i64> = active_counts::<sensors::Entity, _>(db, sensors::Column::AssociatedZone,
+        sensors::Column::Active).await?;
+    let controller_counts: HashMap<i32, i64> = active_counts::<controllers::Entity, _>(db, controllers::Column::AssociatedZone,
+        controllers::Column::Active).await?;
+    let mut overviews: Vec<ZoneOverview> = Vec::with_capacity(zones.len());
+    for zone in zones {
+        let capability: env_capability::Model = capabilities.get(&zone.capability).cloned()
+            .ok_or(RepoError::NotFound { entity: "capability", id: zone.capability })?;
+        let activity: hva_cactivity::Model = activities.get(&zone.system_active).cloned()
+            .ok_or(RepoError::NotFound { entity: "activity", id: zone.system_active })?;
+        overviews.push(ZoneOverview {
+            sensor_count: sensor_counts.get(&zone.id).copied().unwrap_or(0) as u64,
+            controller_count: controller_counts.get(&zone.id).copied().unwrap_or(0) as u64,
+            zone,
+            capability,
+            activity,
+        });
+    }
+    Ok(overviews)
+}
+
+// Active rows per zone for a table with a zone column
+async fn active_counts<E, C>(db: &C, zone: E::Column, active: E::Column) -> Result<HashMap<i32, i64>, RepoError>
+where
+    E: EntityTrait,
+    C: ConnectionTrait,
+{
+    let counts: Vec<(i32, i64)> = E::find().select_only()
+        .column(zone)
+        .column_as(zone.count(), "count")
+        .filter(active.eq(true))
+        .filter(zone.is_not_null())
+        .group_by(zone)
+        .into_tuple().all(db).await?;
+    Ok(counts.into_iter().collect())
+}
+
+/// Schedules for the zone that are switched on
+pub async fn active_schedules(db: &DatabaseConnection, id: i32) -> Result<Vec<schedules::Model>, RepoError> {
+    let zone: zones::Model = get(db, id).await?;
+    Ok(zone.find_related(schedules::Entity)
+        .filter(schedules::Column::Active.eq(true))
+        .order_by_asc(schedules::Column::Id)
+        .all(db).await?)
+}
+
+/// Renames a zone or changes what it can do<br>
+/// A change to what it can do is logged against the zone's capability row
+pub async fn update(db: &DatabaseConnection, actor: &Actor, id: i32, changes: ZoneUpdate) -> Result<zones::Model, RepoError> {
+    if let Some(name) = &changes.name {
+        require_text(name, "name")?;
+    }
+    let now: NaiveDateTime = Utc::now().naive_utc();
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: zones::Model = find_or_missing::<zones::Entity, _>(&txn, id, "zone").await?;
+    if changes.heating.is_some() || changes.cooling.is_some() {
+        let previous: env_capability::Model = find_or_missing::<env_capability::Entity, _>(&txn, before.capability, "capability").await?;
+        let mut capability: env_capability::ActiveModel = previous.clone().into_active_model();
+        if let Some(heating) = changes.heating {
+            capability.heating = Set(heating);
+        }
+        if let Some(cooling) = changes.cooling {
+            capability.cooling = Set(cooling);
+        }
+        capability.last_changed = Set(Some(now));
+        let capability: env_capability::Model = capability.update(&txn).await?;
+        audit::record(&txn, actor, Action::Update, "capability", capability.id, Some(&previous), Some(&capability)).await?;
+    }
+    let mut zone: zones::ActiveModel = before.clone().into_active_model();
+    if let Some(name) = changes.name {
+        zone.name = Set(name);
+    }
+    zone.last_changed = Set(Some(now));
+    let zone: zones::Model = zone.update(&txn).await?;
+    audit::record(&txn, actor, Action::Update, "zone", id, Some(&before), Some(&zone)).await?;
+    txn.commit().await?;
+    Ok(zone)
+}
+
+/// Switches a zone off. Its sensors, controllers and history stay where they are
+pub async fn deactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<zones::Model, RepoError> {
+    set_active(db, actor, id, false).await
+}
+
+pub async fn reactivate(db: &DatabaseConnection, actor: &Actor, id: i32) -> Result<zones::Model, RepoError> {
+    set_active(db, actor, id, true).await
+}
+
+async fn set_active(db: &DatabaseConnection, actor: &Actor, id: i32, active: bool) -> Result<zones::Model, RepoError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let before: zones::Model = find_or_missing::<zones::Entity, _>(&txn, id, "zone").await?;
+    let mut zone: zones::ActiveModel = before.clone().into_active_model();
+    zone.active = Set(active);
+    zone.last_changed = Set(Some(Utc::now().naive_utc()));
+    let zone: zones::Model = zone.update(&txn).await?;
+    let action: Action = if active { Action::Reactivate } else { Action::Deactivate };
+    audit::record(&txn, actor, action, "zone", id, Some(&before), Some(&zone)).await?;
+    txn.commit().await?;
+    Ok(zone)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+    use crate::repo::controllers::{self as controller_repo, NewController};
+    use crate::repo::sensors::{self as sensor_repo, NewSensor};
+
+    fn new_zone(name: &str) -> NewZone {
+        NewZone { name: name.to_string(), heating: true, cooling: false }
+    }
+
+    #[tokio::test]
+    async fn create_gives_the_zone_its_own_capability() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let first: zones::Model = create(&db, &test_actor(), new_zone("Kitchen")).await.unwrap();
+        let second: zones::Model = create(&db, &test_actor(), new_zone("Office")).await.unwrap();
+        assert!(first.active);
+        assert_ne!(first.capability, second.capability);
+
+        let detail: ZoneDetail = detail(&db, first.id).await.unwrap();
+        assert!(detail.capability.heating && !detail.capability.cooling);
+        assert!(!detail.activity.heating);
+        assert!(detail.sensors.is_empty());
+    }
+
+    #[tokio::test]
+    async fn create_refuses_a_blank_name() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        assert!(matches!(create(&db, &test_actor(), new_zone(" ")).await, Err(RepoError::Invalid(_))));
+        assert!(list(&db, true).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn update_changes_name_and_capability() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: zones::Model = create(&db, &test_actor(), new_zone("Den")).await.unwrap();
+        let changes: ZoneUpdate = ZoneUpdate { name: Some("Living room".to_string()), cooling: Some(true), ..ZoneUpdate::default() };
+        let updated: zones::Model = update(&db, &test_actor(), zone.id, changes).await.unwrap();
+        assert_eq!(updated.name, "Living room");
+        assert!(updated.last_changed.is_some());
+        let detail: ZoneDetail = detail(&db, zone.id).await.unwrap();
+        assert!(detail.capability.heating && detail.capability.cooling);
+    }
+
+    #[tokio::test]
+    async fn deactivated_zones_leave_the_default_list() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let kept: zones::Model = create(&db, &test_actor(), new_zone("Garage")).await.unwrap();
+        let dropped: zones::Model = create(&db, &test_actor(), new_zone("Attic")).await.unwrap();
+        assert!(!deactivate(&db, &test_actor(), dropped.id).await.unwrap().active);
+
+        let active: Vec<i32> = list(&db, false).await.unwrap().iter().map(|zone| zone.id).collect();
+        assert_eq!(active, vec![kept.id]);
+        assert_eq!(list(&db, true).await.unwrap().len(), 2);
+        assert!(reactivate(&db, &test_actor(), dropped.id).await.unwrap().active);
+    }
+
+    #[tokio::test]
+    async fn detail_lists_the_zone_sensors() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let zone: zones::Model = create(&db, &test_actor(), new_zone("Nursery")).await.unwrap();
+        let sensor: NewSensor = NewSensor { name: "Crib".to_string(), associated_zone: Some(zone.id), com_type: 1 };
+        sensor_repo::create(&db, &test_actor(), sensor).await.unwrap();
+        let detail: ZoneDetail = detail(&db, zone.id).await.unwrap();
+        assert_eq!(detail.sensors.len(), 1);
+        assert_eq!(detail.sensors[0].name, "Crib");
+    }
+
+    #[tokio::test]
+    async fn overviews_count_active_devices() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let busy: zones::Model = create(&db, &test_actor(), new_zone("Lounge")).await.unwrap();
+        let empty: zones::Model = create(&db, &test_actor(), new_zone("Hallway")).await.unwrap();
+        for name in ["Sofa", "Window", "Door"] {
+            let sensor: NewSensor = NewSensor { name: name.to_string(), associated_zone: Some(busy.id), com_type: 1 };
+            let sensor: i32 = sensor_repo::create(&db, &test_actor(), sensor).await.unwrap().sensor.id;
+            if name == "Door" {
+                sensor_repo::deactivate(&db, &test_actor(), sensor).await.unwrap();
+            }
+        }
+        controller_repo::create(&db, &test_actor(), NewController { name: "Radiator".to_string(), com_type: 2,
+            associated_zone: Some(busy.id), primary: true, heating: true, cooling: false }).await.unwrap();
+
+        let overviews: Vec<ZoneOverview> = overviews(&db, false).await.unwrap();
+        let counts: Vec<(i32, u64, u64)> = overviews.iter().map(|found| (found.zone.id, found.sensor_count, found.controller_count)).collect();
+        assert_eq!(counts, vec![(busy.id, 2, 1), (empty.id, 0, 0)]);
+        let single: ZoneOverview = overview(&db, busy.id).await.unwrap();
+        assert_eq!(single.capability.id, busy.capability);
+        assert_eq!(single.activity.id, busy.system_active);
+    }
+
+    #[tokio::test]
+    async fn missing_zone_is_not_found() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        assert!(matches!(get(&db, 99).await, Err(RepoError::NotFound { entity: "zone", id: 99 })));
+        assert!(matches!(deactivate(&db, &test_actor(), 99).await, Err(RepoError::NotFound { .. })));
+    }
+}
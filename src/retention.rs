@@ -0,0 +1,611 @@
+//! # Rusty Thermostat Retention
+//! Rolls raw SensorReadingHistory and WeatherReading rows into hourly and daily aggregates, then purges raw rows older
+//! than the configured age<br>
+//! Only finished UTC days are rolled up, and a raw row is only purged once its day has been rolled up, so a job that
+//! falls behind never loses readings<br>
+//! Weather readings that a ManualChangeHistory row points at are kept so the history stays intact
+
+use std::collections::BTreeMap;
+use chrono::{Duration, NaiveDate, NaiveDateTime, Timelike, Utc};
+use sea_orm::{ActiveModelTrait, ColumnTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, QueryFilter,
+    QueryOrder, QuerySelect, TransactionTrait};
+use sea_orm::ActiveValue::{Set, NotSet};
+use sea_orm::sea_query::Query;
+use crate::units::{Speed, Temperature};
+use crate::schema::{manual_change_history, sensor_reading_history, sensor_reading_rollup, weather_reading, weather_reading_rollup};
+use crate::schema::sea_orm_active_enums::RollupPeriod;
+
+/// Days of raw readings kept when no age is configured
+pub const DEFAULT_RAW_MAX_AGE_DAYS: u32 = 30;
+/// Seconds between retention runs when no interval is configured
+pub const DEFAULT_RETENTION_INTERVAL: u64 = 3600;
+/// The most days a single run rolls up for each table. A long backlog is worked off over several runs
+pub const MAX_DAYS_PER_RUN: usize = 366;
+// Rollup rows written per insert, well under the bind parameter limits of SQLite and Postgres
+const INSERT_CHUNK: usize = 500;
+
+/// How long raw readings are kept once they have been rolled up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionConfig {
+    pub raw_max_age: Duration,
+}
+
+impl RetentionConfig {
+    /// Keeps raw readings for the given number of days. At least one day is always kept
+    pub fn new(raw_max_age_days: u32) -> RetentionConfig {
+        RetentionConfig { raw_max_age: Duration::days(i64::from(raw_max_age_days.max(1))) }
+    }
+}
+
+impl Default for RetentionConfig {
+    fn default() -> Self {
+        RetentionConfig::new(DEFAULT_RAW_MAX_AGE_DAYS)
+    }
+}
+
+/// What a retention run did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionReport {
+    pub sensor_days: usize,
+    pub weather_days: usize,
+    pub sensor_rows_purged: u64,
+    pub weather_rows_purged: u64,
+}
+
+impl RetentionReport {
+    // True when the run had nothing to do
+    pub fn is_empty(&self) -> bool {
+        *self == RetentionReport::default()
+    }
+}
+
+/// Minimum, maximum and running total of a set of values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aggregate {
+    pub min: f64,
+    pub max: f64,
+    pub sum: f64,
+    pub count: i32,
+}
+
+impl Aggregate {
+    pub fn new(value: f64) -> Aggregate {
+        Aggregate { min: value, max: value, sum: value, count: 1 }
+    }
+    pub fn add(&mut self, value: f64) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.sum += value;
+        self.count += 1;
+    }
+    pub fn avg(&self) -> f64 {
+        self.sum / f64::from(self.count)
+    }
+    // Adds a value that may be missing, starting the aggregate on the first one present
+    fn include(aggregate: &mut Option<Aggregate>, value: Option<f64>) {
+        match (aggregate.as_mut(), value) {
+            (Some(known), Some(value)) => known.add(value),
+            (None, Some(value)) => *aggregate = Some(Aggregate::new(value)),
+            (_, None) => (),
+        }
+    }
+}
+
+// Sensor readings in one hour or day. Temperature and humidity are optional on a reading so either may stay empty
+#[derive(Debug, Clone, Copy, Default)]
+struct SensorBucket {
+    readings: i32,
+    temp: Option<Aggregate>,
+    humidity: Option<Aggregate>,
+}
+
+impl SensorBucket {
+    fn add(&mut self, temp: Option<Temperature>, humidity: Option<i32>) {
+        self.readings += 1;
+        Aggregate::include(&mut self.temp, temp.map(Temperature::celsius));
+        Aggregate::include(&mut self.humidity, humidity.map(f64::from));
+    }
+    // Consumes the bucket into an ActiveModel to be put into the DB
+    fn generate_db_model(self, sensor_id: i32, period: RollupPeriod, period_start: NaiveDateTime) -> sensor_reading_rollup::ActiveModel {
+        sensor_reading_rollup::ActiveModel {
+            id: NotSet,
+            sensor_id: Set(sensor_id),
+            period: Set(period),
+            period_start: Set(period_start),
+            reading_count: Set(self.readings),
+            temp_min: Set(self.temp.map(|temp| Temperature::from_celsius(temp.min))),
+            temp_max: Set(self.temp.map(|temp| Temperature::from_celsius(temp.max))),
+            temp_avg: Set(self.temp.map(|temp| Temperature::from_celsius(temp.avg()))),
+            humidity_min: Set(self.humidity.map(|humidity| humidity.min)),
+            humidity_max: Set(self.humidity.map(|humidity| humidity.max)),
+            humidity_avg: Set(self.humidity.map(|humidity| humidity.avg())),
+        }
+    }
+}
+
+// The columns of a weather reading that are rolled up
+type WeatherRow = (NaiveDateTime, Temperature, i32, i32, Speed);
+
+// Weather readings in one hour or day. Every weather reading has all of the values, so a bucket always has all four
+#[derive(Debug, Clone, Copy)]
+struct WeatherBucket {
+    temp: Aggregate,
+    humidity: Aggregate,
+    pressure: Aggregate,
+    wind_speed: Aggregate,
+}
+
+impl WeatherBucket {
+    fn new(row: &WeatherRow) -> WeatherBucket {
+        let (_, temp, humidity, pressure, wind_speed) = *row;
+        WeatherBucket {
+            temp: Aggregate::new(temp.celsius()),
+            humidity: Aggregate::new(f64::from(humidity)),
+            pressure: Aggregate::new(f64::from(pressure)),
+            wind_speed: Aggregate::new(wind_speed.meters_per_second()),
+        }
+    }
+    fn add(&mut self, row: &WeatherRow) {
+        let (_, temp, humidity, pressure, wind_speed) = *row;
+        self.temp.add(temp.celsius());
+        self.humidity.add(f64::from(humidity));
+        self.pressure.add(f64::from(pressure));
+        self.wind_speed.add(wind_speed.meters_per_second());
+    }
+    // Consumes the bucket into an ActiveModel to be put into the DB
+    fn generate_db_model(self, period: RollupPeriod, period_start: NaiveDateTime) -> weather_reading_rollup::ActiveModel {
+        weather_reading_rollup::ActiveModel {
+            id: NotSet,
+            period: Set(period),
+            period_start: Set(period_start),
+            reading_count: Set(self.temp.count),
+            temp_min: Set(Temperature::from_celsius(self.temp.min)),
+            temp_max: Set(Temperature::from_celsius(self.temp.max)),
+            temp_avg: Set(Temperature::from_celsius(self.temp.avg())),
+            humidity_min: Set(self.humidity.min),
+            humidity_max: Set(self.humidity.max),
+            humidity_avg: Set(self.humidity.avg()),
+            pressure_min: Set(self.pressure.min),
+            pressure_max: Set(self.pressure.max),
+            pressure_avg: Set(self.pressure.avg()),
+            wind_speed_min: Set(Speed::from_meters_per_second(self.wind_speed.min)),
+            wind_speed_max: Set(Speed::from_meters_per_second(self.wind_speed.max)),
+            wind_speed_avg: Set(Speed::from_meters_per_second(self.wind_speed.avg())),
+        }
+    }
+}
+
+/// Rolls up every finished day that has not been rolled up yet, then purges raw readings past the configured age
+/// # Errors
+/// Returns the database error if the readings can't be read, the rollups can't be stored or the purge fails.
+/// Days rolled up before the error are kept
+pub async fn run_job(db: &DatabaseConnection, config: &RetentionConfig) -> Result<RetentionReport, DbErr> {
+    let sensor_days: usize = roll_up_sensor_readings(db).await?;
+    let weather_days: usize = roll_up_weather_readings(db).await?;
+    let older_than: NaiveDateTime = Utc::now().naive_utc() - config.raw_max_age;
+    let sensor_rows_purged: u64 = purge_sensor_readings(db, older_than).await?;
+    let weather_rows_purged: u64 = purge_weather_readings(db, older_than).await?;
+    Ok(RetentionReport { sensor_days, weather_days, sensor_rows_purged, weather_rows_purged })
+}
+
+/// Rolls sensor readings into hourly and daily rollups for each finished day after the last rolled up one<br>
+/// Days without readings are skipped. Returns the number of days rolled up
+/// # Errors
+/// Returns the database error if the readings can't be read or the rollups can't be stored
+pub async fn roll_up_sensor_readings(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    let today: NaiveDate = Utc::now().date_naive();
+    let mut from: Option<NaiveDate> = sensor_rolled_through(db).await?;
+    let mut days: usize = 0;
+    while days < MAX_DAYS_PER_RUN {
+        let Some(day) = first_sensor_day(db, from).await? else { break };
+        if day >= today {
+            break
+        }
+        let readings: usize = roll_up_sensor_day(db, day).await?;
+        days += 1;
+        info!("Rolled up {} sensor readings for {} ({} days this run)", readings, day, days);
+        from = Some(day + Duration::days(1));
+    }
+    Ok(days)
+}
+
+/// Rolls weather readings into hourly and daily rollups for each finished day after the last rolled up one<br>
+/// Days without readings are skipped. Returns the number of days rolled up
+/// # Errors
+/// Returns the database error if the readings can't be read or the rollups can't be stored
+pub async fn roll_up_weather_readings(db: &DatabaseConnection) -> Result<usize, DbErr> {
+    let today: NaiveDate = Utc::now().date_naive();
+    let mut from: Option<NaiveDate> = weather_rolled_through(db).await?;
+    let mut days: usize = 0;
+    while days < MAX_DAYS_PER_RUN {
+        let Some(day) = first_weather_day(db, from).await? else { break };
+        if day >= today {
+            break
+        }
+        let readings: usize = roll_up_weather_day(db, day).await?;
+        days += 1;
+        info!("Rolled up {} weather readings for {} ({} days this run)", readings, day, days);
+        from = Some(day + Duration::days(1));
+    }
+    Ok(days)
+}
+
+/// Deletes raw sensor readings taken before `older_than` whose day has been rolled up
+/// # Errors
+/// Returns the database error if the delete fails
+pub async fn purge_sensor_readings(db: &DatabaseConnection, older_than: NaiveDateTime) -> Result<u64, DbErr> {
+    let Some(rolled_through) = sensor_rolled_through(db).await? else {
+        return Ok(0)
+    };
+    let cutoff: NaiveDateTime = older_than.min(start_of_day(rolled_through));
+    let purged: u64 = sensor_reading_history::Entity::delete_many()
+        .filter(sensor_reading_history::Column::Timestamp.lt(cutoff))
+        .exec(db).await?.rows_affected;
+    if purged > 0 {
+        info!("Purged {} raw sensor readings from before {}", purged, cutoff);
+    }
+    Ok(purged)
+}
+
+/// Deletes raw weather readings taken before `older_than` whose day has been rolled up<br>
+/// Readings a manual change points at are kept
+/// # Errors
+/// Returns the database error if the delete fails
+pub async fn purge_weather_readings(db: &DatabaseConnection, older_than: NaiveDateTime) -> Result<u64, DbErr> {
+    let Some(rolled_through) = weather_rolled_through(db).await? else {
+        return Ok(0)
+    };
+    let cutoff: NaiveDateTime = older_than.min(start_of_day(rolled_through));
+    let purged: u64 = weather_reading::Entity::delete_many()
+        .filter(weather_reading::Column::Timestamp.lt(cutoff))
+        .filter(weather_reading::Column::Id.not_in_subquery(Query::select()
+            .column(manual_change_history::Column::ChangeWeather)
+            .from(manual_change_history::Entity)
+            .to_owned()))
+        .exec(db).await?.rows_affected;
+    if purged > 0 {
+        info!("Purged {} raw weather readings from before {}", purged, cutoff);
+    }
+    Ok(purged)
+}
+
+fn start_of_day(day: NaiveDate) -> NaiveDateTime {
+    day.and_time(chrono::NaiveTime::MIN)
+}
+
+fn start_of_hour(timestamp: NaiveDateTime) -> NaiveDateTime {
+    start_of_day(timestamp.date()) + Duration::hours(i64::from(timestamp.hour()))
+}
+
+// The day after the newest daily sensor rollup, so everything before it is rolled up
+async fn sensor_rolled_through(db: &DatabaseConnection) -> Result<Option<NaiveDate>, DbErr> {
+    let newest: Option<NaiveDateTime> = sensor_reading_rollup::Entity::find()
+        .select_only()
+        .column_as(sensor_reading_rollup::Column::PeriodStart.max(), "newest")
+        .filter(sensor_reading_rollup::Column::Period.eq(RollupPeriod::Day))
+        .into_tuple::<Option<NaiveDateTime>>()
+        .one(db).await?
+        .flatten();
+    Ok(newest.map(|start| start.date() + Duration::days(1)))
+}
+
+// The day after the newest daily weather rollup, so everything before it is rolled up
+async fn weather_rolled_through(db: &DatabaseConnection) -> Result<Option<NaiveDate>, DbErr> {
+    let newest: Option<NaiveDateTime> = weather_reading_rollup::Entity::find()
+        .select_only()
+        .column_as(weather_reading_rollup::Column::PeriodStart.max(), "newest")
+        .filter(weather_reading_rollup::Column::Period.eq(RollupPeriod::Day))
+        .into_tuple::<Option<NaiveDateTime>>()
+        .one(db).await?
+        .flatten();
+    Ok(newest.map(|start| start.date() + Duration::days(1)))
+}
+
+// The day of the oldest sensor reading on or after `from`
+async fn first_sensor_day(db: &DatabaseConnection, from: Option<NaiveDate>) -> Result<Option<NaiveDate>, DbErr> {
+    let mut query = sensor_reading_history::Entity::find()
+        .select_only()
+        .column(sensor_reading_history::Column::Timestamp)
+        .order_by_asc(sensor_reading_history::Column::Timestamp);
+    if let Some(from) = from {
+        query = query.filter(sensor_reading_history::Column::Timestamp.gte(start_of_day(from)));
+    }
+    let first: Option<NaiveDateTime> = query.into_tuple().one(db).await?;
+    Ok(first.map(|timestamp| timestamp.date()))
+}
+
+// The day of the oldest weather reading on or after `from`
+async fn first_weather_day(db: &DatabaseConnection, from: Option<NaiveDate>) -> Result<Option<NaiveDate>, DbErr> {
+    let mut query = weather_reading::Entity::find()
+        .select_only()
+        .column(weather_reading::Column::Timestamp)
+        .order_by_asc(weather_reading::Column::Timestamp);
+    if let Some(from) = from {
+        query = query.filter(weather_reading::Column::Timestamp.gte(start_of_day(from)));
+    }
+    let first: Option<NaiveDateTime> = query.into_tuple().one(db).await?;
+    Ok(first.map(|timestamp| timestamp.date()))
+}
+
+// Replaces the rollups of one day with ones worked out from its sensor readings. Returns how many readings there were
+async fn roll_up_sensor_day(db: &DatabaseConnection, day: NaiveDate) -> Result<usize, DbErr> {
+    let start: NaiveDateTime = start_of_day(day);
+    let end: NaiveDateTime = start + Duration::days(1);
+    let readings: Vec<(i32, NaiveDateTime, Option<Temperature>, Option<i32>)> = sensor_reading_history::Entity::find()
+        .select_only()
+        .column(sensor_reading_history::Column::SensorId)
+        .column(sensor_reading_history::Column::Timestamp)
+        .column(sensor_reading_history::Column::ReadingTemp)
+        .column(sensor_reading_history::Column::ReadingHumidity)
+        .filter(sensor_reading_history::Column::Timestamp.gte(start))
+        .filter(sensor_reading_history::Column::Timestamp.lt(end))
+        .into_tuple()
+        .all(db).await?;
+
+    let mut hourly: BTreeMap<(i32, NaiveDateTime), SensorBucket> = BTreeMap::new();
+    let mut daily: BTreeMap<i32, SensorBucket> = BTreeMap::new();
+    for (sensor, timestamp, temp, humidity) in &readings {
+        hourly.entry((*sensor, start_of_hour(*timestamp))).or_default().add(*temp, *humidity);
+        daily.entry(*sensor).or_default().add(*temp, *humidity);
+    }
+    let rollups: Vec<sensor_reading_rollup::ActiveModel> = hourly.into_iter()
+        .map(|((sensor, hour), bucket)| bucket.generate_db_model(sensor, RollupPeriod::Hour, hour))
+        .chain(daily.into_iter().map(|(sensor, bucket)| bucket.generate_db_model(sensor, RollupPeriod::Day, start)))
+        .collect();
+
+    let txn: DatabaseTransaction = db.begin().await?;
+    sensor_reading_rollup::Entity::delete_many()
+        .filter(sensor_reading_rollup::Column::PeriodStart.gte(start))
+        .filter(sensor_reading_rollup::Column::PeriodStart.lt(end))
+        .exec(&txn).await?;
+    insert_rollups(&txn, rollups).await?;
+    txn.commit().await?;
+    Ok(readings.len())
+}
+
+// Replaces the rollups of one day with ones worked out from its weather readings. Returns how many readings there were
+async fn roll_up_weather_day(db: &DatabaseConnection, day: NaiveDate) -> Result<usize, DbErr> {
+    let start: NaiveDateTime = start_of_day(day);
+    let end: NaiveDateTime = start + Duration::days(1);
+    let readings: Vec<WeatherRow> = weather_reading::Entity::find()
+        .select_only()
+        .column(weather_reading::Column::Timestamp)
+        .column(weather_reading::Column::TempReal)
+        .column(weather_reading::Column::Humidity)
+        .column(weather_reading::Column::PressureSea)
+        .column(weather_reading::Column::WindSpeed)
+        .filter(weather_reading::Column::Timestamp.gte(start))
+        .filter(weather_reading::Column::Timestamp.lt(end))
+        .into_tuple()
+        .all(db).await?;
+
+    let mut hourly: BTreeMap<NaiveDateTime, WeatherBucket> = BTreeMap::new();
+    let mut daily: Option<WeatherBucket> = None;
+    for row in &readings {
+        hourly.entry(start_of_hour(row.0))
+            .and_modify(|bucket| bucket.add(row))
+            .or_insert_with(|| WeatherBucket::new(row));
+        match daily.as_mut() {
+            Some(bucket) => bucket.add(row),
+            None => daily = Some(WeatherBucket::new(row)),
+        }
+    }
+    let rollups: Vec<weather_reading_rollup::ActiveModel> = hourly.into_iter()
+        .map(|(hour, bucket)| bucket.generate_db_model(RollupPeriod::Hour, hour))
+        .chain(daily.map(|bucket| bucket.generate_db_model(RollupPeriod::Day, start)))
+        .collect();
+
+    let txn: DatabaseTransaction = db.begin().await?;
+    weather_reading_rollup::Entity::delete_many()
+        .filter(weather_reading_rollup::Column::PeriodStart.gte(start))
+        .filter(weather_reading_rollup::Column::PeriodStart.lt(end))
+        .exec(&txn).await?;
+    insert_rollups(&txn, rollups).await?;
+    txn.commit().await?;
+    Ok(readings.len())
+}
+
+// Inserts rollups a chunk at a time. An empty list is left alone since an empty insert is an error
+async fn insert_rollups<A, C>(db: &C, rollups: Vec<A>) -> Result<(), DbErr>
+where
+    A: ActiveModelTrait,
+    C: ConnectionTrait,
+{
+    let mut rollups = rollups.into_iter().peekable();
+    while rollups.peek().is_some() {
+        let chunk: Vec<A> = rollups.by_ref().take(INSERT_CHUNK).collect();
+        A::Entity::insert_many(chunk).exec(db).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+    use crate::repo::sensors::{self as sensor_repo, NewSensor};
+    use uuid::Uuid;
+
+    fn at(day: NaiveDate, hour: u32, minute: u32) -> NaiveDateTime {
+        day.and_hms_opt(hour, minute, 0).unwrap()
+    }
+
+    fn days_ago(days: i64) -> NaiveDate {
+        Utc::now().date_naive() - Duration::days(days)
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() < 1e-9, "expected {} to be {}", actual, expected);
+    }
+
+    async fn sensor(db: &DatabaseConnection, name: &str) -> i32 {
+        sensor_repo::create(db, &test_actor(), NewSensor { name: name.to_string(), associated_zone: None, com_type: 1 })
+            .await.unwrap().sensor.id
+    }
+
+    async fn record(db: &DatabaseConnection, sensor: i32, timestamp: NaiveDateTime, celsius: Option<f64>, humidity: Option<i32>) {
+        sensor_reading_history::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            sensor_id: Set(sensor),
+            timestamp: Set(timestamp),
+            reading_temp: Set(celsius.map(Temperature::from_celsius)),
+            reading_humidity: Set(humidity),
+            reading_presence: Set(None),
+            reading_threshold_open: Set(None),
+        }.insert(db).await.unwrap();
+    }
+
+    async fn weather(db: &DatabaseConnection, timestamp: NaiveDateTime, celsius: f64, wind: f64) -> Uuid {
+        weather_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(timestamp), condition: Set(Some("Clear".to_string())),
+            description: Set(Some("clear sky".to_string())), icon: Set(Some("01d".to_string())), temp_real: Set(Temperature::from_celsius(celsius)),
+            temp_feel: Set(Temperature::from_celsius(celsius)), pressure_sea: Set(1015), humidity: Set(50), pressure_ground: Set(Some(1010)),
+            visibility: Set(Some(10000)), wind_speed: Set(Speed::from_meters_per_second(wind)), wind_deg: Set(0),
+            wind_gust: Set(Some(Speed::from_meters_per_second(wind))), rain1_h: Set(None), rain3_h: Set(None), snow1_h: Set(None),
+            snow3_h: Set(None), clouds: Set(0), dt: Set(0), sunrise: Set(0), sunset: Set(0), dew_point: Set(None), heat_index: Set(None),
+            wind_chill: Set(None), humidex: Set(None), absolute_humidity: Set(None), lat: Set(None), lon: Set(None) }.insert(db).await.unwrap().id
+    }
+
+    #[test]
+    fn aggregate_tracks_min_max_and_average() {
+        let mut aggregate: Aggregate = Aggregate::new(20.0);
+        aggregate.add(18.0);
+        aggregate.add(25.0);
+        assert_close(aggregate.min, 18.0);
+        assert_close(aggregate.max, 25.0);
+        assert_close(aggregate.avg(), 21.0);
+        assert_eq!(aggregate.count, 3);
+
+        let mut missing: Option<Aggregate> = None;
+        Aggregate::include(&mut missing, None);
+        assert!(missing.is_none());
+        Aggregate::include(&mut missing, Some(4.0));
+        assert_eq!(missing.map(|aggregate| aggregate.count), Some(1));
+    }
+
+    #[test]
+    fn config_keeps_at_least_a_day() {
+        assert_eq!(RetentionConfig::new(0).raw_max_age, Duration::days(1));
+        assert_eq!(RetentionConfig::default().raw_max_age, Duration::days(i64::from(DEFAULT_RAW_MAX_AGE_DAYS)));
+    }
+
+    #[tokio::test]
+    async fn sensor_readings_roll_up_per_hour_and_day() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let hall: i32 = sensor(&db, "Hall").await;
+        let attic: i32 = sensor(&db, "Attic").await;
+        let day: NaiveDate = days_ago(2);
+        record(&db, hall, at(day, 8, 0), Some(19.0), Some(40)).await;
+        record(&db, hall, at(day, 8, 30), Some(21.0), None).await;
+        record(&db, hall, at(day, 9, 15), Some(23.0), Some(50)).await;
+        record(&db, attic, at(day, 9, 0), None, None).await;
+        // Today is not finished so it is left alone
+        record(&db, hall, Utc::now().naive_utc(), Some(30.0), None).await;
+
+        assert_eq!(roll_up_sensor_readings(&db).await.unwrap(), 1);
+        assert_eq!(roll_up_sensor_readings(&db).await.unwrap(), 0);
+
+        let rollups: Vec<sensor_reading_rollup::Model> = sensor_reading_rollup::Entity::find()
+            .order_by_asc(sensor_reading_rollup::Column::Id).all(&db).await.unwrap();
+        assert_eq!(rollups.len(), 5);
+        let hall_daily: &sensor_reading_rollup::Model = rollups.iter()
+            .find(|rollup| rollup.sensor_id == hall && rollup.period == RollupPeriod::Day).unwrap();
+        assert_eq!(hall_daily.period_start, at(day, 0, 0));
+        assert_eq!(hall_daily.reading_count, 3);
+        assert_close(hall_daily.temp_avg.unwrap().celsius(), 21.0);
+        assert_eq!(hall_daily.humidity_max, Some(50.0));
+        let hall_eight: &sensor_reading_rollup::Model = rollups.iter()
+            .find(|rollup| rollup.sensor_id == hall && rollup.period_start == at(day, 8, 0)).unwrap();
+        assert_eq!(hall_eight.reading_count, 2);
+        assert_close(hall_eight.temp_min.unwrap().celsius(), 19.0);
+        assert_close(hall_eight.humidity_avg.unwrap(), 40.0);
+        let attic_daily: &sensor_reading_rollup::Model = rollups.iter()
+            .find(|rollup| rollup.sensor_id == attic && rollup.period == RollupPeriod::Day).unwrap();
+        assert_eq!((attic_daily.reading_count, attic_daily.temp_avg), (1, None));
+    }
+
+    #[tokio::test]
+    async fn gaps_between_days_are_skipped() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let hall: i32 = sensor(&db, "Hall").await;
+        record(&db, hall, at(days_ago(40), 12, 0), Some(18.0), None).await;
+        record(&db, hall, at(days_ago(3), 12, 0), Some(20.0), None).await;
+
+        assert_eq!(roll_up_sensor_readings(&db).await.unwrap(), 2);
+        assert_eq!(sensor_rolled_through(&db).await.unwrap(), Some(days_ago(2)));
+    }
+
+    #[tokio::test]
+    async fn weather_readings_roll_up_per_hour_and_day() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let day: NaiveDate = days_ago(1);
+        weather(&db, at(day, 0, 10), 10.0, 2.0).await;
+        weather(&db, at(day, 0, 40), 12.0, 4.0).await;
+        weather(&db, at(day, 23, 50), 5.0, 0.0).await;
+
+        assert_eq!(roll_up_weather_readings(&db).await.unwrap(), 1);
+
+        let daily: weather_reading_rollup::Model = weather_reading_rollup::Entity::find()
+            .filter(weather_reading_rollup::Column::Period.eq(RollupPeriod::Day))
+            .one(&db).await.unwrap().unwrap();
+        assert_eq!(daily.reading_count, 3);
+        assert_close(daily.temp_min.celsius(), 5.0);
+        assert_close(daily.temp_max.celsius(), 12.0);
+        assert_close(daily.wind_speed_avg.meters_per_second(), 2.0);
+        assert_close(daily.pressure_avg, 1015.0);
+        let hourly: Vec<weather_reading_rollup::Model> = weather_reading_rollup::Entity::find()
+            .filter(weather_reading_rollup::Column::Period.eq(RollupPeriod::Hour))
+            .order_by_asc(weather_reading_rollup::Column::PeriodStart)
+            .all(&db).await.unwrap();
+        assert_eq!(hourly.iter().map(|rollup| rollup.period_start).collect::<Vec<NaiveDateTime>>(), vec![at(day, 0, 0), at(day, 23, 0)]);
+        assert_close(hourly[0].temp_avg.celsius(), 11.0);
+    }
+
+    #[tokio::test]
+    async fn purge_only_removes_old_rolled_up_readings() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let hall: i32 = sensor(&db, "Hall").await;
+        record(&db, hall, at(days_ago(45), 12, 0), Some(18.0), None).await;
+        record(&db, hall, at(days_ago(5), 12, 0), Some(20.0), None).await;
+        let old_weather: Uuid = weather(&db, at(days_ago(45), 12, 0), 1.0, 1.0).await;
+        weather(&db, at(days_ago(44), 12, 0), 2.0, 1.0).await;
+        let recent_weather: Uuid = weather(&db, at(days_ago(5), 12, 0), 3.0, 1.0).await;
+
+        // Nothing has been rolled up yet, so nothing may go
+        let now: NaiveDateTime = Utc::now().naive_utc();
+        assert_eq!(purge_sensor_readings(&db, now).await.unwrap(), 0);
+
+        let report: RetentionReport = run_job(&db, &RetentionConfig::new(30)).await.unwrap();
+        assert_eq!(report, RetentionReport { sensor_days: 2, weather_days: 3, sensor_rows_purged: 1, weather_rows_purged: 2 });
+        let left: Vec<sensor_reading_history::Model> = sensor_reading_history::Entity::find().all(&db).await.unwrap();
+        assert_eq!(left.len(), 1);
+        let weather_ids: Vec<Uuid> = weather_reading::Entity::find().all(&db).await.unwrap().iter().map(|reading| reading.id).collect();
+        assert_eq!(weather_ids, vec![recent_weather]);
+        assert!(!weather_ids.contains(&old_weather));
+        // The rollups outlive the raw rows
+        assert_eq!(sensor_reading_rollup::Entity::find().all(&db).await.unwrap().len(), 4);
+
+        assert!(run_job(&db, &RetentionConfig::new(30)).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn purge_keeps_weather_a_manual_change_points_at() {
+        use crate::schema::{manual_change_history, pollution_reading};
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let taken_at: NaiveDateTime = at(days_ago(60), 9, 0);
+        let referenced: Uuid = weather(&db, taken_at, 4.0, 1.0).await;
+        weather(&db, taken_at + Duration::minutes(10), 5.0, 1.0).await;
+        let pollution: Uuid = pollution_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(taken_at), aqi: Set(1), co: Set(Some(0.0)),
+            no: Set(Some(0.0)), no2: Set(Some(0.0)), o3: Set(Some(0.0)), so2: Set(Some(0.0)), pm2_5: Set(Some(0.0)), pm10: Set(Some(0.0)), nh3: Set(Some(0.0)),
+            lat: Set(None), lon: Set(None) }
+            .insert(&db).await.unwrap().id;
+        manual_change_history::ActiveModel { id: Set(Uuid::now_v7()), change_timing: Set(taken_at), change_weather: Set(referenced),
+            change_pollution: Set(pollution), change_source: Set(1), new_temp: Set(Some(Temperature::from_celsius(21.0))),
+            new_humidity: Set(None), change_schedule: Set(None), cancelled_timing: Set(None) }.insert(&db).await.unwrap();
+
+        let report: RetentionReport = run_job(&db, &RetentionConfig::default()).await.unwrap();
+        assert_eq!(report.weather_rows_purged, 1);
+        let left: Vec<Uuid> = weather_reading::Entity::find().all(&db).await.unwrap().iter().map(|reading| reading.id).collect();
+        assert_eq!(left, vec![referenced]);
+    }
+}
@@ -1,8 +1,9 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq, Serialize, Deserialize)]
 #[sea_orm(table_name = "ChangeSource")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -13,10 +14,18 @@ pub struct Model {
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
 pub enum Relation {
+    #[sea_orm(has_many = "super::audit_log::Entity")]
+    AuditLog,
     #[sea_orm(has_many = "super::manual_change_history::Entity")]
     ManualChangeHistory,
 }
 
+impl Related<super::audit_log::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::AuditLog.def()
+    }
+}
+
 impl Related<super::manual_change_history::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::ManualChangeHistory.def()
@@ -1,8 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "HomeSummary")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -10,7 +12,7 @@ pub struct Model {
     #[sea_orm(column_name = "lastChanged")]
     pub last_changed: DateTime,
     #[sea_orm(column_name = "houseTemp", column_type = "Double", nullable)]
-    pub house_temp: Option<f64>,
+    pub house_temp: Option<Temperature>,
     #[sea_orm(column_name = "houseHumidity")]
     pub house_humidity: Option<i32>,
     pub capability: i32,
@@ -1,22 +1,24 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "ManualChangeHistory")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
     #[sea_orm(column_name = "changeTiming")]
     pub change_timing: DateTime,
     #[sea_orm(column_name = "changeWeather")]
-    pub change_weather: i32,
+    pub change_weather: Uuid,
     #[sea_orm(column_name = "changePollution")]
-    pub change_pollution: i32,
+    pub change_pollution: Uuid,
     #[sea_orm(column_name = "changeSource")]
     pub change_source: i32,
     #[sea_orm(column_name = "newTemp", column_type = "Double", nullable)]
-    pub new_temp: Option<f64>,
+    pub new_temp: Option<Temperature>,
     #[sea_orm(column_name = "newHumidity")]
     pub new_humidity: Option<i32>,
     #[sea_orm(column_name = "changeSchedule")]
@@ -3,17 +3,23 @@
 pub mod prelude;
 
 pub mod alerts;
+pub mod audit_log;
 pub mod change_source;
 pub mod communication;
 pub mod controllers;
+pub mod daily_degree_days;
 pub mod env_capability;
+pub mod forecast_reading;
 pub mod home_summary;
 pub mod hva_cactivity;
 pub mod manual_change_history;
 pub mod pollution_reading;
 pub mod schedules;
+pub mod sea_orm_active_enums;
 pub mod sensor_reading_history;
+pub mod sensor_reading_rollup;
 pub mod sensors;
 pub mod weather_reading;
+pub mod weather_reading_rollup;
 pub mod weekdays;
 pub mod zones;
@@ -1,31 +1,36 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "PollutionReading")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
     pub timestamp: DateTime,
     #[sea_orm(column_name = "AQI")]
     pub aqi: i32,
-    #[sea_orm(column_name = "CO", column_type = "Double")]
-    pub co: f64,
-    #[sea_orm(column_name = "NO", column_type = "Double")]
-    pub no: f64,
-    #[sea_orm(column_name = "NO2", column_type = "Double")]
-    pub no2: f64,
-    #[sea_orm(column_name = "O3", column_type = "Double")]
-    pub o3: f64,
-    #[sea_orm(column_name = "SO2", column_type = "Double")]
-    pub so2: f64,
-    #[sea_orm(column_name = "PM2_5", column_type = "Double")]
-    pub pm2_5: f64,
-    #[sea_orm(column_name = "PM10", column_type = "Double")]
-    pub pm10: f64,
-    #[sea_orm(column_name = "NH3", column_type = "Double")]
-    pub nh3: f64,
+    #[sea_orm(column_name = "CO", column_type = "Double", nullable)]
+    pub co: Option<f64>,
+    #[sea_orm(column_name = "NO", column_type = "Double", nullable)]
+    pub no: Option<f64>,
+    #[sea_orm(column_name = "NO2", column_type = "Double", nullable)]
+    pub no2: Option<f64>,
+    #[sea_orm(column_name = "O3", column_type = "Double", nullable)]
+    pub o3: Option<f64>,
+    #[sea_orm(column_name = "SO2", column_type = "Double", nullable)]
+    pub so2: Option<f64>,
+    #[sea_orm(column_name = "PM2_5", column_type = "Double", nullable)]
+    pub pm2_5: Option<f64>,
+    #[sea_orm(column_name = "PM10", column_type = "Double", nullable)]
+    pub pm10: Option<f64>,
+    #[sea_orm(column_name = "NH3", column_type = "Double", nullable)]
+    pub nh3: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub lat: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub lon: Option<f64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
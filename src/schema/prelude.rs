@@ -1,17 +1,22 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 pub use super::alerts::Entity as Alerts;
+pub use super::audit_log::Entity as AuditLog;
 pub use super::change_source::Entity as ChangeSource;
 pub use super::communication::Entity as Communication;
 pub use super::controllers::Entity as Controllers;
+pub use super::daily_degree_days::Entity as DailyDegreeDays;
 pub use super::env_capability::Entity as EnvCapability;
+pub use super::forecast_reading::Entity as ForecastReading;
 pub use super::home_summary::Entity as HomeSummary;
 pub use super::hva_cactivity::Entity as HvaCactivity;
 pub use super::manual_change_history::Entity as ManualChangeHistory;
 pub use super::pollution_reading::Entity as PollutionReading;
 pub use super::schedules::Entity as Schedules;
 pub use super::sensor_reading_history::Entity as SensorReadingHistory;
+pub use super::sensor_reading_rollup::Entity as SensorReadingRollup;
 pub use super::sensors::Entity as Sensors;
 pub use super::weather_reading::Entity as WeatherReading;
+pub use super::weather_reading_rollup::Entity as WeatherReadingRollup;
 pub use super::weekdays::Entity as Weekdays;
 pub use super::zones::Entity as Zones;
@@ -1,8 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "Schedules")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -25,9 +27,9 @@ pub struct Model {
     #[sea_orm(column_name = "dateEnd")]
     pub date_end: Option<Date>,
     #[sea_orm(column_name = "tempMin", column_type = "Double", nullable)]
-    pub temp_min: Option<f64>,
+    pub temp_min: Option<Temperature>,
     #[sea_orm(column_name = "tempMax", column_type = "Double", nullable)]
-    pub temp_max: Option<f64>,
+    pub temp_max: Option<Temperature>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -1,17 +1,19 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "SensorReadingHistory")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
     #[sea_orm(column_name = "sensorID")]
     pub sensor_id: i32,
     pub timestamp: DateTime,
     #[sea_orm(column_name = "readingTemp", column_type = "Double", nullable)]
-    pub reading_temp: Option<f64>,
+    pub reading_temp: Option<Temperature>,
     #[sea_orm(column_name = "readingHumidity")]
     pub reading_humidity: Option<i32>,
     #[sea_orm(column_name = "readingPresence")]
@@ -1,8 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "Sensors")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -23,7 +25,7 @@ pub struct Model {
     #[sea_orm(column_name = "comLast")]
     pub com_last: Option<DateTime>,
     #[sea_orm(column_name = "currentTemp", column_type = "Double", nullable)]
-    pub current_temp: Option<f64>,
+    pub current_temp: Option<Temperature>,
     #[sea_orm(column_name = "currentHumid")]
     pub current_humid: Option<i32>,
     pub presence: Option<bool>,
@@ -43,6 +45,8 @@ pub enum Relation {
     Communication,
     #[sea_orm(has_many = "super::sensor_reading_history::Entity")]
     SensorReadingHistory,
+    #[sea_orm(has_many = "super::sensor_reading_rollup::Entity")]
+    SensorReadingRollup,
     #[sea_orm(
         belongs_to = "super::zones::Entity",
         from = "Column::AssociatedZone",
@@ -65,6 +69,12 @@ impl Related<super::sensor_reading_history::Entity> for Entity {
     }
 }
 
+impl Related<super::sensor_reading_rollup::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::SensorReadingRollup.def()
+    }
+}
+
 impl Related<super::zones::Entity> for Entity {
     fn to() -> RelationDef {
         Relation::Zones.def()
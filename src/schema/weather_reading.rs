@@ -1,35 +1,38 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::{Speed, Temperature};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "WeatherReading")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub id: i32,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Uuid,
     pub timestamp: DateTime,
-    #[sea_orm(column_type = "Text")]
-    pub condition: String,
-    #[sea_orm(column_type = "Text")]
-    pub description: String,
-    #[sea_orm(column_type = "Text")]
-    pub icon: String,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub condition: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub description: Option<String>,
+    #[sea_orm(column_type = "Text", nullable)]
+    pub icon: Option<String>,
     #[sea_orm(column_name = "tempReal", column_type = "Double")]
-    pub temp_real: f64,
+    pub temp_real: Temperature,
     #[sea_orm(column_name = "tempFeel", column_type = "Double")]
-    pub temp_feel: f64,
+    pub temp_feel: Temperature,
     #[sea_orm(column_name = "pressureSea")]
     pub pressure_sea: i32,
     pub humidity: i32,
-    #[sea_orm(column_name = "pressureGround")]
-    pub pressure_ground: i32,
-    pub visibility: i32,
+    #[sea_orm(column_name = "pressureGround", nullable)]
+    pub pressure_ground: Option<i32>,
+    #[sea_orm(nullable)]
+    pub visibility: Option<i32>,
     #[sea_orm(column_name = "windSpeed", column_type = "Double")]
-    pub wind_speed: f64,
+    pub wind_speed: Speed,
     #[sea_orm(column_name = "windDeg")]
     pub wind_deg: i32,
-    #[sea_orm(column_name = "windGust", column_type = "Double")]
-    pub wind_gust: f64,
+    #[sea_orm(column_name = "windGust", column_type = "Double", nullable)]
+    pub wind_gust: Option<Speed>,
     #[sea_orm(column_name = "rain1H", column_type = "Double", nullable)]
     pub rain1_h: Option<f64>,
     #[sea_orm(column_name = "rain3H", column_type = "Double", nullable)]
@@ -42,6 +45,20 @@ pub struct Model {
     pub dt: i32,
     pub sunrise: i32,
     pub sunset: i32,
+    #[sea_orm(column_name = "dewPoint", column_type = "Double", nullable)]
+    pub dew_point: Option<Temperature>,
+    #[sea_orm(column_name = "heatIndex", column_type = "Double", nullable)]
+    pub heat_index: Option<Temperature>,
+    #[sea_orm(column_name = "windChill", column_type = "Double", nullable)]
+    pub wind_chill: Option<Temperature>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub humidex: Option<Temperature>,
+    #[sea_orm(column_name = "absoluteHumidity", column_type = "Double", nullable)]
+    pub absolute_humidity: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub lat: Option<f64>,
+    #[sea_orm(column_type = "Double", nullable)]
+    pub lon: Option<f64>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
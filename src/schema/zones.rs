@@ -1,8 +1,10 @@
 //! `SeaORM` Entity. Generated by sea-orm-codegen 0.12.5
 
 use sea_orm::entity::prelude::*;
+use serde_derive::{Deserialize, Serialize};
+use crate::units::Temperature;
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Serialize, Deserialize)]
 #[sea_orm(table_name = "Zones")]
 pub struct Model {
     #[sea_orm(primary_key)]
@@ -16,7 +18,7 @@ pub struct Model {
     #[sea_orm(column_name = "lastChanged")]
     pub last_changed: Option<DateTime>,
     #[sea_orm(column_name = "currentTemp", column_type = "Double", nullable)]
-    pub current_temp: Option<f64>,
+    pub current_temp: Option<Temperature>,
     #[sea_orm(column_name = "currentHumid")]
     pub current_humid: Option<i32>,
     #[sea_orm(column_name = "systemActive")]
@@ -0,0 +1,760 @@
+//! # Rusty Thermostat Transfer
+//! Export and import of the history tables, WeatherReading, PollutionReading, SensorReadingHistory and
+//! ManualChangeHistory, as CSV or newline delimited JSON<br>
+//! Exports are read a page at a time so a large table never has to fit in memory. Values are in the stored units,
+//! Celsius, m/s and hPa<br>
+//! History rows keep their UUID wherever they go, so exports from several houses can be imported into one database
+//! without their ids colliding, and a row that is already stored is recognized by its id and skipped. That makes it
+//! safe to import a file twice<br>
+//! Configuration ids are different on every install, so the sensor, change source and schedule a row points at are
+//! written out by name and looked up again on import. Sensor and schedule names need not be unique, so a record whose
+//! name matches more than one row is refused rather than guessed at. Manual changes point at their weather and pollution by id, so
+//! import weather and pollution before the manual changes that point at them<br>
+//! An import runs in a single transaction
+
+use std::collections::HashMap;
+use std::fmt;
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use sea_orm::{ActiveModelTrait, ColumnTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, QueryFilter, QueryOrder,
+    QuerySelect, Select, TransactionTrait};
+use sea_orm::ActiveValue::Set;
+use serde::de::DeserializeOwned;
+use serde_derive::{Deserialize, Serialize};
+use uuid::Uuid;
+use crate::units::Temperature;
+use crate::schema::{change_source, manual_change_history, pollution_reading, schedules, sensor_reading_history, sensors,
+    weather_reading};
+
+/// Rows read from the database for each chunk of an export
+pub const EXPORT_PAGE_SIZE: u64 = 1000;
+
+/// Everything that can go wrong moving history in or out
+#[derive(Debug)]
+pub enum TransferError {
+    UnknownTable(String),
+    UnknownFormat(String),
+    BadTime(String),
+    /// A record in an import could not be read. Records are counted from 1
+    BadRecord { record: usize, reason: String },
+    /// A record in an import points at a row this database does not have
+    MissingReference { record: usize, field: &'static str, value: String },
+    /// A record in an import names a row this database has more than one of
+    AmbiguousReference { record: usize, field: &'static str, value: String },
+    Io(std::io::Error),
+    Db(DbErr),
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TransferError::UnknownTable(table) => write!(f, "Unknown history table {}, expected weather, pollution, sensor-readings or manual-changes", table),
+            TransferError::UnknownFormat(format) => write!(f, "Unknown format {}, expected csv or ndjson", format),
+            TransferError::BadTime(time) => write!(f, "Unable to read {} as a date or date and time", time),
+            TransferError::BadRecord { record, reason } => write!(f, "Record {} could not be read: {}", record, reason),
+            TransferError::MissingReference { record, field, value } => write!(f, "Record {} has a {} of {} that does not exist", record, field, value),
+            TransferError::AmbiguousReference { record, field, value } => write!(f, "Record {} has a {} of {} that more than one row is named", record, field, value),
+            TransferError::Io(error) => write!(f, "Unable to read or write history: {}", error),
+            TransferError::Db(error) => write!(f, "Database error: {}", error),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+impl From<DbErr> for TransferError {
+    fn from(error: DbErr) -> Self {
+        TransferError::Db(error)
+    }
+}
+
+impl From<std::io::Error> for TransferError {
+    fn from(error: std::io::Error) -> Self {
+        TransferError::Io(error)
+    }
+}
+
+/// The history tables that can be moved
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryTable {
+    Weather,
+    Pollution,
+    SensorReadings,
+    ManualChanges,
+}
+
+impl HistoryTable {
+    /// Reads the name used on the command line and in URLs
+    /// # Errors
+    /// Anything but weather, pollution, sensor-readings or manual-changes is an unknown table
+    pub fn parse(table: &str) -> Result<HistoryTable, TransferError> {
+        match table.to_lowercase().as_str() {
+            "weather" => Ok(HistoryTable::Weather),
+            "pollution" => Ok(HistoryTable::Pollution),
+            "sensor-readings" | "sensors" => Ok(HistoryTable::SensorReadings),
+            "manual-changes" | "changes" => Ok(HistoryTable::ManualChanges),
+            _ => Err(TransferError::UnknownTable(table.to_string())),
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            HistoryTable::Weather => "weather",
+            HistoryTable::Pollution => "pollution",
+            HistoryTable::SensorReadings => "sensor-readings",
+            HistoryTable::ManualChanges => "manual-changes",
+        }
+    }
+}
+
+/// How records are written out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Format {
+    /// Comma separated values with a header row
+    #[default]
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl Format {
+    /// # Errors
+    /// Anything but csv, ndjson or jsonl is an unknown format
+    pub fn parse(format: &str) -> Result<Format, TransferError> {
+        match format.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "ndjson" | "jsonl" | "json" => Ok(Format::Ndjson),
+            _ => Err(TransferError::UnknownFormat(format.to_string())),
+        }
+    }
+    /// Picks the format from a file name's extension, None when the extension is not one of the formats
+    pub fn from_path(path: &str) -> Option<Format> {
+        let extension: &str = path.rsplit_once('.')?.1;
+        Format::parse(extension).ok()
+    }
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Ndjson => "ndjson",
+        }
+    }
+}
+
+/// The span of time to export, either end may be left open<br>
+/// `from` is included and `to` is not
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct TimeRange {
+    pub from: Option<NaiveDateTime>,
+    pub to: Option<NaiveDateTime>,
+}
+
+impl TimeRange {
+    /// Reads each end as either a date or a date and time like 2024-01-15T08:00:00<br>
+    /// A plain date for `to` covers that whole day
+    /// # Errors
+    /// Returns BadTime for an end that is neither
+    pub fn parse(from: Option<&str>, to: Option<&str>) -> Result<TimeRange, TransferError> {
+        Ok(TimeRange {
+            from: from.map(|from| parse_time(from, false)).transpose()?,
+            to: to.map(|to| parse_time(to, true)).transpose()?,
+        })
+    }
+    // Limits a query to rows whose time column is in the range
+    fn apply<E: EntityTrait, C: ColumnTrait>(&self, mut query: Select<E>, column: C) -> Select<E> {
+        if let Some(from) = self.from {
+            query = query.filter(column.gte(from));
+        }
+        if let Some(to) = self.to {
+            query = query.filter(column.lt(to));
+        }
+        query
+    }
+}
+
+fn parse_time(time: &str, end_of_day: bool) -> Result<NaiveDateTime, TransferError> {
+    if let Ok(exact) = NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S") {
+        return Ok(exact)
+    }
+    match NaiveDate::parse_from_str(time, "%Y-%m-%d") {
+        Ok(day) if end_of_day => Ok(day.and_time(chrono::NaiveTime::MIN) + Duration::days(1)),
+        Ok(day) => Ok(day.and_time(chrono::NaiveTime::MIN)),
+        Err(_) => Err(TransferError::BadTime(time.to_string())),
+    }
+}
+
+/// What an import did
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ImportReport {
+    pub inserted: usize,
+    pub skipped: usize,
+}
+
+/// A sensor reading with the sensor named instead of numbered
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SensorReadingRecord {
+    pub id: Uuid,
+    pub sensor: String,
+    pub timestamp: NaiveDateTime,
+    pub reading_temp: Option<Temperature>,
+    pub reading_humidity: Option<i32>,
+    pub reading_presence: Option<bool>,
+    pub reading_threshold_open: Option<bool>,
+}
+
+/// A manual change with the source and schedule given by name
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManualChangeRecord {
+    pub id: Uuid,
+    pub change_timing: NaiveDateTime,
+    pub change_weather: Uuid,
+    pub change_pollution: Uuid,
+    pub change_source: String,
+    pub new_temp: Option<Temperature>,
+    pub new_humidity: Option<i32>,
+    pub schedule: Option<String>,
+    pub cancelled_timing: Option<NaiveDateTime>,
+}
+
+// Ids found for names during an import, and whether weather and pollution ids are stored, so each one is only
+// looked up once
+#[derive(Debug, Default)]
+struct KeyCache {
+    // Up to two ids for each name, enough to tell one match from several
+    sensors: HashMap<String, Vec<i32>>,
+    change_sources: HashMap<String, Option<i32>>,
+    schedules: HashMap<String, Vec<i32>>,
+    weather: HashMap<Uuid, bool>,
+    pollution: HashMap<Uuid, bool>,
+}
+
+impl KeyCache {
+    async fn sensor(&mut self, txn: &DatabaseTransaction, name: &str, record: usize) -> Result<i32, TransferError> {
+        if !self.sensors.contains_key(name) {
+            let found: Vec<i32> = sensors::Entity::find().select_only().column(sensors::Column::Id)
+                .filter(sensors::Column::Name.eq(name)).order_by_asc(sensors::Column::Id).limit(2).into_tuple().all(txn).await?;
+            self.sensors.insert(name.to_string(), found);
+        }
+        only_match(&self.sensors[name], record, "sensor", name)
+    }
+    async fn change_source(&mut self, txn: &DatabaseTransaction, name: &str) -> Result<Option<i32>, DbErr> {
+        if let Some(known) = self.change_sources.get(name) {
+            return Ok(*known)
+        }
+        let found: Option<i32> = change_source::Entity::find().select_only().column(change_source::Column::Id)
+            .filter(change_source::Column::Name.eq(name)).order_by_asc(change_source::Column::Id).into_tuple().one(txn).await?;
+        self.change_sources.insert(name.to_string(), found);
+        Ok(found)
+    }
+    async fn schedule(&mut self, txn: &DatabaseTransaction, name: &str, record: usize) -> Result<i32, TransferError> {
+        if !self.schedules.contains_key(name) {
+            let found: Vec<i32> = schedules::Entity::find().select_only().column(schedules::Column::Id)
+                .filter(schedules::Column::Name.eq(name)).order_by_asc(schedules::Column::Id).limit(2).into_tuple().all(txn).await?;
+            self.schedules.insert(name.to_string(), found);
+        }
+        only_match(&self.schedules[name], record, "schedule", name)
+    }
+    async fn weather(&mut self, txn: &DatabaseTransaction, id: Uuid) -> Result<bool, DbErr> {
+        if let Some(known) = self.weather.get(&id) {
+            return Ok(*known)
+        }
+        let found: Option<Uuid> = weather_reading::Entity::find_by_id(id).select_only().column(weather_reading::Column::Id)
+            .into_tuple().one(txn).await?;
+        self.weather.insert(id, found.is_some());
+        Ok(found.is_some())
+    }
+    async fn pollution(&mut self, txn: &DatabaseTransaction, id: Uuid) -> Result<bool, DbErr> {
+        if let Some(known) = self.pollution.get(&id) {
+            return Ok(*known)
+        }
+        let found: Option<Uuid> = pollution_reading::Entity::find_by_id(id).select_only().column(pollution_reading::Column::Id)
+            .into_tuple().one(txn).await?;
+        self.pollution.insert(id, found.is_some());
+        Ok(found.is_some())
+    }
+}
+
+// The one id a name was found under
+fn only_match(ids: &[i32], record: usize, field: &'static str, name: &str) -> Result<i32, TransferError> {
+    match ids {
+        [id] => Ok(*id),
+        [] => Err(TransferError::MissingReference { record, field, value: name.to_string() }),
+        _ => Err(TransferError::AmbiguousReference { record, field, value: name.to_string() }),
+    }
+}
+
+// A row of one of the history tables as it is written to and read from a file
+#[async_trait::async_trait]
+trait HistoryRecord: serde::Serialize + DeserializeOwned + Send + Sized {
+    // The id of the row the record came from, used to page through the table
+    fn source_id(&self) -> Uuid;
+    // The next page of records after the given id, or from the start, in id order
+    async fn page(db: &DatabaseConnection, range: &TimeRange, after: Option<Uuid>, limit: u64) -> Result<Vec<Self>, DbErr>;
+    // Stores the record under its own id unless that id is already there. Returns false when it was skipped
+    async fn import(self, txn: &DatabaseTransaction, keys: &mut KeyCache, record: usize) -> Result<bool, TransferError>;
+}
+
+#[async_trait::async_trait]
+impl HistoryRecord for weather_reading::Model {
+    fn source_id(&self) -> Uuid {
+        self.id
+    }
+    async fn page(db: &DatabaseConnection, range: &TimeRange, after: Option<Uuid>, limit: u64) -> Result<Vec<Self>, DbErr> {
+        after_id(range.apply(weather_reading::Entity::find(), weather_reading::Column::Timestamp), weather_reading::Column::Id, after)
+            .order_by_asc(weather_reading::Column::Id)
+            .limit(limit)
+            .all(db).await
+    }
+    async fn import(self, txn: &DatabaseTransaction, keys: &mut KeyCache, _record: usize) -> Result<bool, TransferError> {
+        let id: Uuid = self.id;
+        if keys.weather(txn, id).await? {
+            return Ok(false)
+        }
+        let reading: weather_reading::ActiveModel = self.into();
+        weather_reading::Entity::insert(reading.reset_all()).exec(txn).await?;
+        keys.weather.insert(id, true);
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl HistoryRecord for pollution_reading::Model {
+    fn source_id(&self) -> Uuid {
+        self.id
+    }
+    async fn page(db: &DatabaseConnection, range: &TimeRange, after: Option<Uuid>, limit: u64) -> Result<Vec<Self>, DbErr> {
+        after_id(range.apply(pollution_reading::Entity::find(), pollution_reading::Column::Timestamp), pollution_reading::Column::Id, after)
+            .order_by_asc(pollution_reading::Column::Id)
+            .limit(limit)
+            .all(db).await
+    }
+    async fn import(self, txn: &DatabaseTransaction, keys: &mut KeyCache, _record: usize) -> Result<bool, TransferError> {
+        let id: Uuid = self.id;
+        if keys.pollution(txn, id).await? {
+            return Ok(false)
+        }
+        let reading: pollution_reading::ActiveModel = self.into();
+        pollution_reading::Entity::insert(reading.reset_all()).exec(txn).await?;
+        keys.pollution.insert(id, true);
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl HistoryRecord for SensorReadingRecord {
+    fn source_id(&self) -> Uuid {
+        self.id
+    }
+    async fn page(db: &DatabaseConnection, range: &TimeRange, after: Option<Uuid>, limit: u64) -> Result<Vec<Self>, DbErr> {
+        let readings: Vec<sensor_reading_history::Model> = after_id(range
+            .apply(sensor_reading_history::Entity::find(), sensor_reading_history::Column::Timestamp),
+                sensor_reading_history::Column::Id, after)
+            .order_by_asc(sensor_reading_history::Column::Id)
+            .limit(limit)
+            .all(db).await?;
+        let names: HashMap<i32, String> = names_by_id::<sensors::Entity, _, _>(db, sensors::Column::Id, sensors::Column::Name,
+            readings.iter().map(|reading| reading.sensor_id)).await?;
+        Ok(readings.into_iter().map(|reading| SensorReadingRecord {
+            id: reading.id,
+            sensor: names.get(&reading.sensor_id).cloned().unwrap_or_default(),
+            timestamp: reading.timestamp,
+            reading_temp: reading.reading_temp,
+            reading_humidity: reading.reading_humidity,
+            reading_presence: reading.reading_presence,
+            reading_threshold_open: reading.reading_threshold_open,
+        }).collect())
+    }
+    async fn import(self, txn: &DatabaseTransaction, keys: &mut KeyCache, record: usize) -> Result<bool, TransferError> {
+        let sensor_id: i32 = keys.sensor(txn, &self.sensor, record).await?;
+        let stored: Option<Uuid> = sensor_reading_history::Entity::find_by_id(self.id).select_only()
+            .column(sensor_reading_history::Column::Id).into_tuple().one(txn).await?;
+        if stored.is_some() {
+            return Ok(false)
+        }
+        sensor_reading_history::Entity::insert(sensor_reading_history::ActiveModel {
+            id: Set(self.id),
+            sensor_id: Set(sensor_id),
+            timestamp: Set(self.timestamp),
+            reading_temp: Set(self.reading_temp),
+            reading_humidity: Set(self.reading_humidity),
+            reading_presence: Set(self.reading_presence),
+            reading_threshold_open: Set(self.reading_threshold_open),
+        }).exec(txn).await?;
+        Ok(true)
+    }
+}
+
+#[async_trait::async_trait]
+impl HistoryRecord for ManualChangeRecord {
+    fn source_id(&self) -> Uuid {
+        self.id
+    }
+    async fn page(db: &DatabaseConnection, range: &TimeRange, after: Option<Uuid>, limit: u64) -> Result<Vec<Self>, DbErr> {
+        let changes: Vec<manual_change_history::Model> = after_id(range
+            .apply(manual_change_history::Entity::find(), manual_change_history::Column::ChangeTiming),
+                manual_change_history::Column::Id, after)
+            .order_by_asc(manual_change_history::Column::Id)
+            .limit(limit)
+            .all(db).await?;
+        let sources: HashMap<i32, String> = names_by_id::<change_source::Entity, _, _>(db, change_source::Column::Id,
+            change_source::Column::Name, changes.iter().map(|change| change.change_source)).await?;
+        let schedule_names: HashMap<i32, String> = names_by_id::<schedules::Entity, _, _>(db, schedules::Column::Id,
+            schedules::Column::Name, changes.iter().filter_map(|change| change.change_schedule)).await?;
+        Ok(changes.into_iter().map(|change| ManualChangeRecord {
+            id: change.id,
+            change_timing: change.change_timing,
+            change_weather: change.change_weather,
+            change_pollution: change.change_pollution,
+            change_source: sources.get(&change.change_source).cloned().unwrap_or_default(),
+            new_temp: change.new_temp,
+            new_humidity: change.new_humidity,
+            schedule: change.change_schedule.and_then(|schedule| schedule_names.get(&schedule).cloned()),
+            cancelled_timing: change.cancelled_timing,
+        }).collect())
+    }
+    async fn import(self, txn: &DatabaseTransaction, keys: &mut KeyCache, record: usize) -> Result<bool, TransferError> {
+        if !keys.weather(txn, self.change_weather).await? {
+            return Err(TransferError::MissingReference { record, field: "change_weather", value: self.change_weather.to_string() })
+        }
+        if !keys.pollution(txn, self.change_pollution).await? {
+            return Err(TransferError::MissingReference { record, field: "change_pollution", value: self.change_pollution.to_string() })
+        }
+        let Some(source_id) = keys.change_source(txn, &self.change_source).await? else {
+            return Err(TransferError::MissingReference { record, field: "change_source", value: self.change_source })
+        };
+        let schedule_id: Option<i32> = match self.schedule {
+            Some(name) => Some(keys.schedule(txn, &name, record).await?),
+            None => None,
+        };
+        let stored: Option<Uuid> = manual_change_history::Entity::find_by_id(self.id).select_only()
+            .column(manual_change_history::Column::Id).into_tuple().one(txn).await?;
+        if stored.is_some() {
+            return Ok(false)
+        }
+        manual_change_history::Entity::insert(manual_change_history::ActiveModel {
+            id: Set(self.id),
+            change_timing: Set(self.change_timing),
+            change_weather: Set(self.change_weather),
+            change_pollution: Set(self.change_pollution),
+            change_source: Set(source_id),
+            new_temp: Set(self.new_temp),
+            new_humidity: Set(self.new_humidity),
+            change_schedule: Set(schedule_id),
+            cancelled_timing: Set(self.cancelled_timing),
+        }).exec(txn).await?;
+        Ok(true)
+    }
+}
+
+// Limits a query to ids after the given one
+fn after_id<E: EntityTrait, C: ColumnTrait>(query: Select<E>, column: C, after: Option<Uuid>) -> Select<E> {
+    match after {
+        Some(after) => query.filter(column.gt(after)),
+        None => query,
+    }
+}
+
+// Looks up one column of the rows with the given ids, keyed by id
+async fn names_by_id<E, V, I>(db: &DatabaseConnection, id: E::Column, value: E::Column, ids: I) -> Result<HashMap<i32, V>, DbErr>
+where
+    E: EntityTrait,
+    V: sea_orm::TryGetable + Send,
+    I: Iterator<Item = i32>,
+{
+    let mut ids: Vec<i32> = ids.collect();
+    ids.sort_unstable();
+    ids.dedup();
+    if ids.is_empty() {
+        return Ok(HashMap::new())
+    }
+    let pairs: Vec<(i32, V)> = E::find().select_only().column(id).column(value).filter(id.is_in(ids)).into_tuple().all(db).await?;
+    Ok(pairs.into_iter().collect())
+}
+
+/// Writes a history table out a chunk at a time<br>
+/// Each chunk is a page of rows already formatted, the first CSV chunk carries the header
+#[derive(Debug, Clone)]
+pub struct Exporter {
+    table: HistoryTable,
+    format: Format,
+    range: TimeRange,
+    after: Option<Uuid>,
+    started: bool,
+    finished: bool,
+}
+
+impl Exporter {
+    pub fn new(table: HistoryTable, format: Format, range: TimeRange) -> Exporter {
+        Exporter { table, format, range, after: None, started: false, finished: false }
+    }
+    /// The next chunk of the export, None once every row has been written
+    /// # Errors
+    /// Returns the database error if a page can't be read
+    pub async fn next_chunk(&mut self, db: &DatabaseConnection) -> Result<Option<String>, TransferError> {
+        match self.table {
+            HistoryTable::Weather => self.chunk::<weather_reading::Model>(db).await,
+            HistoryTable::Pollution => self.chunk::<pollution_reading::Model>(db).await,
+            HistoryTable::SensorReadings => self.chunk::<SensorReadingRecord>(db).await,
+            HistoryTable::ManualChanges => self.chunk::<ManualChangeRecord>(db).await,
+        }
+    }
+    async fn chunk<R: HistoryRecord>(&mut self, db: &DatabaseConnection) -> Result<Option<String>, TransferError> {
+        if self.finished {
+            return Ok(None)
+        }
+        let records: Vec<R> = R::page(db, &self.range, self.after, EXPORT_PAGE_SIZE).await?;
+        self.finished = (records.len() as u64) < EXPORT_PAGE_SIZE;
+        let Some(last) = records.last() else {
+            return Ok(None)
+        };
+        let last: Uuid = last.source_id();
+        self.after = Some(last);
+        let text: String = encode(self.format, &records, !self.started)?;
+        self.started = true;
+        trace!("Exported {} {} rows up to id {}", records.len(), self.table.name(), last);
+        Ok(Some(text))
+    }
+}
+
+fn encode<R: serde::Serialize>(format: Format, records: &[R], header: bool) -> Result<String, TransferError> {
+    let mut text: Vec<u8> = Vec::new();
+    match format {
+        Format::Csv => {
+            let mut writer: csv::Writer<&mut Vec<u8>> = csv::WriterBuilder::new().has_headers(header).from_writer(&mut text);
+            for record in records {
+                writer.serialize(record).map_err(|error| TransferError::Io(error.into()))?;
+            }
+            writer.flush()?;
+        },
+        Format::Ndjson => {
+            for record in records {
+                serde_json::to_writer(&mut text, record).map_err(|error| TransferError::Io(error.into()))?;
+                text.push(b'\n');
+            }
+        },
+    }
+    String::from_utf8(text).map_err(|error| TransferError::Io(std::io::Error::new(std::io::ErrorKind::InvalidData, error)))
+}
+
+/// Reads records from an export and stores them with their foreign keys looked up again<br>
+/// Every record is read before anything is stored, and they are stored in one transaction, so a bad file changes nothing
+/// # Errors
+/// A record that can't be read is a BadRecord, one that points at a row this database does not have is a
+/// MissingReference, and anything the database refuses is returned as is
+pub async fn import(db: &DatabaseConnection, table: HistoryTable, format: Format, input: &[u8]) -> Result<ImportReport, TransferError> {
+    let report: ImportReport = match table {
+        HistoryTable::Weather => import_records(db, decode::<weather_reading::Model>(format, input)?).await?,
+        HistoryTable::Pollution => import_records(db, decode::<pollution_reading::Model>(format, input)?).await?,
+        HistoryTable::SensorReadings => import_records(db, decode::<SensorReadingRecord>(format, input)?).await?,
+        HistoryTable::ManualChanges => import_records(db, decode::<ManualChangeRecord>(format, input)?).await?,
+    };
+    info!("Imported {} {} rows, skipped {} already stored", report.inserted, table.name(), report.skipped);
+    Ok(report)
+}
+
+fn decode<R: DeserializeOwned>(format: Format, input: &[u8]) -> Result<Vec<R>, TransferError> {
+    match format {
+        Format::Csv => csv::Reader::from_reader(input).deserialize().enumerate()
+            .map(|(index, record)| record.map_err(|error| TransferError::BadRecord { record: index + 1, reason: error.to_string() }))
+            .collect(),
+        Format::Ndjson => {
+            let text: &str = std::str::from_utf8(input)
+                .map_err(|error| TransferError::BadRecord { record: 1, reason: error.to_string() })?;
+            text.lines().filter(|line| !line.trim().is_empty()).enumerate()
+                .map(|(index, line)| serde_json::from_str(line)
+                    .map_err(|error| TransferError::BadRecord { record: index + 1, reason: error.to_string() }))
+                .collect()
+        },
+    }
+}
+
+async fn import_records<R: HistoryRecord>(db: &DatabaseConnection, records: Vec<R>) -> Result<ImportReport, TransferError> {
+    let txn: DatabaseTransaction = db.begin().await?;
+    let mut keys: KeyCache = KeyCache::default();
+    let mut report: ImportReport = ImportReport::default();
+    for (index, record) in records.into_iter().enumerate() {
+        if record.import(&txn, &mut keys, index + 1).await? {
+            report.inserted += 1;
+        } else {
+            report.skipped += 1;
+        }
+    }
+    txn.commit().await?;
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::audit::test_actor;
+    use crate::dbman;
+    use crate::units::Speed;
+    use crate::repo::sensors::{self as sensor_repo, NewSensor};
+
+    fn at(day: u32, hour: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 3, day).unwrap().and_hms_opt(hour, 0, 0).unwrap()
+    }
+
+    async fn weather(db: &DatabaseConnection, timestamp: NaiveDateTime, celsius: f64) -> Uuid {
+        weather_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(timestamp), condition: Set(Some("Rain".to_string())),
+            description: Set(Some("light rain, heavy".to_string())), icon: Set(Some("10d".to_string())), temp_real: Set(Temperature::from_celsius(celsius)),
+            temp_feel: Set(Temperature::from_celsius(celsius)), pressure_sea: Set(1009), humidity: Set(80), pressure_ground: Set(Some(1001)),
+            visibility: Set(Some(8000)), wind_speed: Set(Speed::from_meters_per_second(3.5)), wind_deg: Set(270),
+            wind_gust: Set(Some(Speed::from_meters_per_second(6.0))), rain1_h: Set(Some(0.4)), rain3_h: Set(None), snow1_h: Set(None),
+            snow3_h: Set(None), clouds: Set(90), dt: Set(0), sunrise: Set(0), sunset: Set(0), dew_point: Set(Some(Temperature::from_celsius(4.0))),
+            heat_index: Set(None), wind_chill: Set(None), humidex: Set(None), absolute_humidity: Set(None),
+            lat: Set(Some(51.5)), lon: Set(Some(-0.12)) }.insert(db).await.unwrap().id
+    }
+
+    async fn pollution(db: &DatabaseConnection, timestamp: NaiveDateTime) -> Uuid {
+        pollution_reading::ActiveModel { id: Set(Uuid::now_v7()), timestamp: Set(timestamp), aqi: Set(2), co: Set(Some(201.9)), no: Set(Some(0.0)),
+            no2: Set(Some(0.8)), o3: Set(Some(68.7)), so2: Set(Some(0.6)), pm2_5: Set(Some(0.5)), pm10: Set(Some(0.5)), nh3: Set(Some(0.1)),
+            lat: Set(Some(51.5)), lon: Set(Some(-0.12)) }.insert(db).await.unwrap().id
+    }
+
+    async fn sensor(db: &DatabaseConnection, name: &str) -> i32 {
+        sensor_repo::create(db, &test_actor(), NewSensor { name: name.to_string(), associated_zone: None, com_type: 1 })
+            .await.unwrap().sensor.id
+    }
+
+    async fn export_all(db: &DatabaseConnection, table: HistoryTable, format: Format, range: TimeRange) -> String {
+        let mut exporter: Exporter = Exporter::new(table, format, range);
+        let mut text: String = String::new();
+        while let Some(chunk) = exporter.next_chunk(db).await.unwrap() {
+            text.push_str(&chunk);
+        }
+        text
+    }
+
+    #[test]
+    fn names_and_times_parse() {
+        assert_eq!(HistoryTable::parse("Sensor-Readings").unwrap(), HistoryTable::SensorReadings);
+        assert!(matches!(HistoryTable::parse("zones"), Err(TransferError::UnknownTable(_))));
+        assert_eq!(Format::from_path("backup/weather.jsonl"), Some(Format::Ndjson));
+        assert_eq!(Format::from_path("weather"), None);
+
+        let range: TimeRange = TimeRange::parse(Some("2024-03-01"), Some("2024-03-02")).unwrap();
+        assert_eq!(range.from, Some(at(1, 0)));
+        assert_eq!(range.to, Some(at(3, 0)));
+        assert_eq!(TimeRange::parse(Some("2024-03-01T06:00:00"), None).unwrap().from, Some(at(1, 6)));
+        assert!(matches!(TimeRange::parse(None, Some("last week")), Err(TransferError::BadTime(_))));
+    }
+
+    #[tokio::test]
+    async fn weather_round_trips_through_csv_and_ndjson() {
+        let source: DatabaseConnection = dbman::test_connection().await;
+        weather(&source, at(1, 8), 6.5).await;
+        weather(&source, at(2, 8), 7.5).await;
+        weather(&source, at(5, 8), 9.0).await;
+        let range: TimeRange = TimeRange::parse(Some("2024-03-01"), Some("2024-03-02")).unwrap();
+
+        for format in [Format::Csv, Format::Ndjson] {
+            let exported: String = export_all(&source, HistoryTable::Weather, format, range).await;
+            let target: DatabaseConnection = dbman::test_connection().await;
+            let report: ImportReport = import(&target, HistoryTable::Weather, format, exported.as_bytes()).await.unwrap();
+            assert_eq!(report, ImportReport { inserted: 2, skipped: 0 });
+
+            let copied: Vec<weather_reading::Model> = weather_reading::Entity::find().order_by_asc(weather_reading::Column::Timestamp).all(&target).await.unwrap();
+            let originals: Vec<weather_reading::Model> = range.apply(weather_reading::Entity::find(), weather_reading::Column::Timestamp)
+                .order_by_asc(weather_reading::Column::Timestamp).all(&source).await.unwrap();
+            assert_eq!(copied, originals);
+
+            let again: ImportReport = import(&target, HistoryTable::Weather, format, exported.as_bytes()).await.unwrap();
+            assert_eq!(again, ImportReport { inserted: 0, skipped: 2 });
+        }
+    }
+
+    #[tokio::test]
+    async fn csv_header_is_only_written_once() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        for hour in 0..3 {
+            pollution(&db, at(1, hour)).await;
+        }
+        let mut exporter: Exporter = Exporter::new(HistoryTable::Pollution, Format::Csv, TimeRange::default());
+        let first: String = exporter.next_chunk(&db).await.unwrap().unwrap();
+        assert!(first.starts_with("id,timestamp,aqi,"));
+        assert_eq!(first.lines().count(), 4);
+        assert!(exporter.next_chunk(&db).await.unwrap().is_none());
+        assert!(export_all(&db, HistoryTable::Weather, Format::Csv, TimeRange::default()).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sensor_readings_are_remapped_by_sensor_name() {
+        let source: DatabaseConnection = dbman::test_connection().await;
+        sensor(&source, "Spare").await;
+        let hall: i32 = sensor(&source, "Hall").await;
+        sensor_reading_history::ActiveModel { id: Set(Uuid::now_v7()), sensor_id: Set(hall), timestamp: Set(at(1, 9)),
+            reading_temp: Set(Some(Temperature::from_celsius(20.5))), reading_humidity: Set(Some(41)), reading_presence: Set(None),
+            reading_threshold_open: Set(Some(false)) }.insert(&source).await.unwrap();
+        let exported: String = export_all(&source, HistoryTable::SensorReadings, Format::Csv, TimeRange::default()).await;
+
+        let target: DatabaseConnection = dbman::test_connection().await;
+        let missing: Result<ImportReport, TransferError> = import(&target, HistoryTable::SensorReadings, Format::Csv, exported.as_bytes()).await;
+        assert!(matches!(missing, Err(TransferError::MissingReference { record: 1, field: "sensor", .. })));
+
+        let target_hall: i32 = sensor(&target, "Hall").await;
+        import(&target, HistoryTable::SensorReadings, Format::Csv, exported.as_bytes()).await.unwrap();
+        let copied: sensor_reading_history::Model = sensor_reading_history::Entity::find().one(&target).await.unwrap().unwrap();
+        assert_ne!(target_hall, hall);
+        assert_eq!(copied.sensor_id, target_hall);
+        assert_eq!(copied.reading_humidity, Some(41));
+        assert_eq!(copied.reading_presence, None);
+
+        // With two sensors of that name there is no telling which one the readings belong to
+        sensor(&target, "Hall").await;
+        let ambiguous: Result<ImportReport, TransferError> = import(&target, HistoryTable::SensorReadings, Format::Csv, exported.as_bytes()).await;
+        assert!(matches!(ambiguous, Err(TransferError::AmbiguousReference { record: 1, field: "sensor", .. })));
+    }
+
+    #[tokio::test]
+    async fn manual_changes_follow_their_weather_and_pollution() {
+        let source: DatabaseConnection = dbman::test_connection().await;
+        weather(&source, at(1, 7), 3.0).await;
+        let weather_id: Uuid = weather(&source, at(1, 8), 4.0).await;
+        let pollution_id: Uuid = pollution(&source, at(1, 8)).await;
+        manual_change_history::ActiveModel { id: Set(Uuid::now_v7()), change_timing: Set(at(1, 8)), change_weather: Set(weather_id),
+            change_pollution: Set(pollution_id), change_source: Set(4), new_temp: Set(Some(Temperature::from_celsius(21.0))),
+            new_humidity: Set(None), change_schedule: Set(None), cancelled_timing: Set(None) }.insert(&source).await.unwrap();
+
+        let target: DatabaseConnection = dbman::test_connection().await;
+        let changes: String = export_all(&source, HistoryTable::ManualChanges, Format::Ndjson, TimeRange::default()).await;
+        // Without the weather it was made under the change can't be rebuilt, and nothing is stored
+        assert!(matches!(import(&target, HistoryTable::ManualChanges, Format::Ndjson, changes.as_bytes()).await,
+            Err(TransferError::MissingReference { field: "change_weather", .. })));
+        assert!(manual_change_history::Entity::find().all(&target).await.unwrap().is_empty());
+
+        for table in [HistoryTable::Weather, HistoryTable::Pollution] {
+            let rows: String = export_all(&source, table, Format::Ndjson, TimeRange::default()).await;
+            import(&target, table, Format::Ndjson, rows.as_bytes()).await.unwrap();
+        }
+        let report: ImportReport = import(&target, HistoryTable::ManualChanges, Format::Ndjson, changes.as_bytes()).await.unwrap();
+        assert_eq!(report.inserted, 1);
+
+        let copied: manual_change_history::Model = manual_change_history::Entity::find().one(&target).await.unwrap().unwrap();
+        assert_eq!((copied.change_weather, copied.change_pollution), (weather_id, pollution_id));
+        let copied_weather: weather_reading::Model = weather_reading::Entity::find_by_id(copied.change_weather).one(&target).await.unwrap().unwrap();
+        assert_eq!(copied_weather.timestamp, at(1, 8));
+        assert_eq!(copied.change_source, 4);
+    }
+
+    #[tokio::test]
+    async fn history_from_two_houses_merges_without_collisions() {
+        let target: DatabaseConnection = dbman::test_connection().await;
+        let mut ids: Vec<Uuid> = Vec::new();
+        for celsius in [4.0, 9.0] {
+            let house: DatabaseConnection = dbman::test_connection().await;
+            ids.push(weather(&house, at(1, 8), celsius).await);
+            let rows: String = export_all(&house, HistoryTable::Weather, Format::Csv, TimeRange::default()).await;
+            assert_eq!(import(&target, HistoryTable::Weather, Format::Csv, rows.as_bytes()).await.unwrap().inserted, 1);
+        }
+        let mut stored: Vec<Uuid> = weather_reading::Entity::find().all(&target).await.unwrap().into_iter().map(|row| row.id).collect();
+        stored.sort();
+        ids.sort();
+        assert_eq!(stored, ids);
+    }
+
+    #[tokio::test]
+    async fn unreadable_records_are_numbered() {
+        let db: DatabaseConnection = dbman::test_connection().await;
+        let input: &str = "{\"id\":\"0190f0a4-6c3e-7000-8000-000000000001\",\"sensor\":\"Hall\",\"timestamp\":\"2024-03-01T09:00:00\"}\n\nnot json\n";
+        assert!(matches!(import(&db, HistoryTable::SensorReadings, Format::Ndjson, input.as_bytes()).await,
+            Err(TransferError::BadRecord { record: 2, .. })));
+    }
+}
@@ -0,0 +1,288 @@
+//! # Rusty Thermostat Units
+//! Typed temperatures, speeds and pressures<br>
+//! Every value is held and stored in one canonical unit: Celsius, meters per second and hectopascals. Values are only
+//! turned into a unit system when they come in from a provider or go out through the API
+
+use std::fmt;
+use sea_orm::{DeriveValueType, Value, sea_query::Nullable};
+use serde_derive::{Serialize, Deserialize};
+
+const KELVIN_OFFSET: f64 = 273.15;
+const MPH_PER_MS: f64 = 2.236_936;
+const HPA_PER_INHG: f64 = 33.863_886;
+
+/// The unit systems accepted by the weather settings and the API
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    Standard,
+    Metric,
+    #[default]
+    Imperial,
+}
+
+impl UnitSystem {
+    /// Reads the unit system from a settings string. Anything unknown is imperial
+    pub fn parse(units: &str) -> UnitSystem {
+        match units.to_lowercase().as_str() {
+            "standard" => UnitSystem::Standard,
+            "metric" => UnitSystem::Metric,
+            _ => UnitSystem::Imperial,
+        }
+    }
+    // Name the weather APIs use for this unit system
+    pub fn api_name(self) -> &'static str {
+        match self {
+            UnitSystem::Standard => "standard",
+            UnitSystem::Metric => "metric",
+            UnitSystem::Imperial => "imperial",
+        }
+    }
+}
+
+impl fmt::Display for UnitSystem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.api_name())
+    }
+}
+
+/// A temperature, held in Celsius
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize, DeriveValueType)]
+#[serde(transparent)]
+pub struct Temperature(f64);
+
+impl Temperature {
+    pub fn from_celsius(celsius: f64) -> Temperature {
+        Temperature(celsius)
+    }
+    pub fn from_fahrenheit(fahrenheit: f64) -> Temperature {
+        Temperature((fahrenheit - 32.0) * 5.0 / 9.0)
+    }
+    pub fn from_kelvin(kelvin: f64) -> Temperature {
+        Temperature(kelvin - KELVIN_OFFSET)
+    }
+    /// Reads a temperature given in the unit system: Kelvin for standard, Celsius for metric and Fahrenheit for imperial
+    pub fn from_units(value: f64, units: UnitSystem) -> Temperature {
+        match units {
+            UnitSystem::Standard => Temperature::from_kelvin(value),
+            UnitSystem::Metric => Temperature::from_celsius(value),
+            UnitSystem::Imperial => Temperature::from_fahrenheit(value),
+        }
+    }
+    pub fn celsius(self) -> f64 {
+        self.0
+    }
+    pub fn fahrenheit(self) -> f64 {
+        self.0 * 9.0 / 5.0 + 32.0
+    }
+    pub fn kelvin(self) -> f64 {
+        self.0 + KELVIN_OFFSET
+    }
+    /// Gets the temperature in the unit system
+    pub fn in_units(self, units: UnitSystem) -> f64 {
+        match units {
+            UnitSystem::Standard => self.kelvin(),
+            UnitSystem::Metric => self.celsius(),
+            UnitSystem::Imperial => self.fahrenheit(),
+        }
+    }
+}
+
+impl Nullable for Temperature {
+    fn null() -> Value {
+        Value::Double(None)
+    }
+}
+
+impl fmt::Display for Temperature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}C", self.0)
+    }
+}
+
+/// Converts a temperature difference in Celsius degrees, like a degree day, into the unit system<br>
+/// Kelvin and Celsius degrees are the same size, Fahrenheit degrees are smaller
+pub fn degrees_in_units(celsius_degrees: f64, units: UnitSystem) -> f64 {
+    match units {
+        UnitSystem::Imperial => celsius_degrees * 9.0 / 5.0,
+        _ => celsius_degrees,
+    }
+}
+
+/// A speed, held in meters per second
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize, DeriveValueType)]
+#[serde(transparent)]
+pub struct Speed(f64);
+
+impl Speed {
+    pub fn from_meters_per_second(meters_per_second: f64) -> Speed {
+        Speed(meters_per_second)
+    }
+    pub fn from_mph(mph: f64) -> Speed {
+        Speed(mph / MPH_PER_MS)
+    }
+    /// Reads a speed given in the unit system: miles per hour for imperial, meters per second otherwise
+    pub fn from_units(value: f64, units: UnitSystem) -> Speed {
+        match units {
+            UnitSystem::Imperial => Speed::from_mph(value),
+            _ => Speed::from_meters_per_second(value),
+        }
+    }
+    pub fn meters_per_second(self) -> f64 {
+        self.0
+    }
+    pub fn mph(self) -> f64 {
+        self.0 * MPH_PER_MS
+    }
+    /// Gets the speed in the unit system
+    pub fn in_units(self, units: UnitSystem) -> f64 {
+        match units {
+            UnitSystem::Imperial => self.mph(),
+            _ => self.meters_per_second(),
+        }
+    }
+}
+
+impl Nullable for Speed {
+    fn null() -> Value {
+        Value::Double(None)
+    }
+}
+
+impl fmt::Display for Speed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.1}m/s", self.0)
+    }
+}
+
+/// An atmospheric pressure, held in hectopascals<br>
+/// The weather APIs report hectopascals in every unit system, so only the API edge ever sees inches of mercury
+#[derive(Debug, Clone, Copy, Default, PartialEq, PartialOrd, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Pressure(f64);
+
+impl Pressure {
+    pub fn from_hpa(hpa: f64) -> Pressure {
+        Pressure(hpa)
+    }
+    pub fn from_inhg(inhg: f64) -> Pressure {
+        Pressure(inhg * HPA_PER_INHG)
+    }
+    pub fn hpa(self) -> f64 {
+        self.0
+    }
+    pub fn inhg(self) -> f64 {
+        self.0 / HPA_PER_INHG
+    }
+    // Whole hectopascals, the way the pressure columns store them
+    pub fn whole_hpa(self) -> i32 {
+        self.0.round() as i32
+    }
+    /// Gets the pressure in the unit system: inches of mercury for imperial, hectopascals otherwise
+    pub fn in_units(self, units: UnitSystem) -> f64 {
+        match units {
+            UnitSystem::Imperial => self.inhg(),
+            _ => self.hpa(),
+        }
+    }
+}
+
+impl From<i32> for Pressure {
+    fn from(hpa: i32) -> Self {
+        Pressure::from_hpa(hpa.into())
+    }
+}
+
+impl fmt::Display for Pressure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.0}hPa", self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn unit_system() -> impl Strategy<Value = UnitSystem> {
+        prop_oneof![Just(UnitSystem::Standard), Just(UnitSystem::Metric), Just(UnitSystem::Imperial)]
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!((actual - expected).abs() <= 1e-9 * expected.abs().max(1.0), "expected {} to be {}", actual, expected);
+    }
+
+    #[test]
+    fn unit_system_parses_like_set_units() {
+        assert_eq!(UnitSystem::parse("Metric"), UnitSystem::Metric);
+        assert_eq!(UnitSystem::parse("standard"), UnitSystem::Standard);
+        assert_eq!(UnitSystem::parse("absolutely hot garbage"), UnitSystem::Imperial);
+    }
+
+    #[test]
+    fn temperature_reference_points() {
+        assert_close(Temperature::from_fahrenheit(212.0).celsius(), 100.0);
+        assert_close(Temperature::from_kelvin(273.15).celsius(), 0.0);
+        assert_close(Temperature::from_celsius(-40.0).fahrenheit(), -40.0);
+    }
+
+    #[test]
+    fn pressure_reference_point() {
+        assert!((Pressure::from_hpa(1013.25).inhg() - 29.92).abs() < 0.01);
+    }
+
+    #[test]
+    fn unit_system_serializes_lowercase() {
+        assert_eq!(serde_json::to_string(&UnitSystem::Metric).unwrap(), "\"metric\"");
+    }
+
+    proptest! {
+        #[test]
+        fn temperature_round_trips(value in -500.0f64..500.0, units in unit_system()) {
+            assert_close(Temperature::from_units(value, units).in_units(units), value);
+        }
+
+        #[test]
+        fn temperature_converts_between_systems(celsius in -200.0f64..200.0, from in unit_system(), to in unit_system()) {
+            let original: Temperature = Temperature::from_celsius(celsius);
+            let converted: Temperature = Temperature::from_units(original.in_units(from), from);
+            assert_close(converted.in_units(to), original.in_units(to));
+        }
+
+        #[test]
+        fn temperature_order_is_kept(first in -200.0f64..200.0, second in -200.0f64..200.0, units in unit_system()) {
+            let (low, high) = (first.min(second), first.max(second));
+            prop_assert!(Temperature::from_celsius(low).in_units(units) <= Temperature::from_celsius(high).in_units(units));
+        }
+
+        #[test]
+        fn temperature_differences_scale_like_degrees(first in -200.0f64..200.0, second in -200.0f64..200.0, units in unit_system()) {
+            let difference: f64 = Temperature::from_celsius(first).in_units(units) - Temperature::from_celsius(second).in_units(units);
+            assert_close(difference, degrees_in_units(first - second, units));
+        }
+
+        #[test]
+        fn speed_round_trips(value in 0.0f64..200.0, units in unit_system()) {
+            assert_close(Speed::from_units(value, units).in_units(units), value);
+        }
+
+        #[test]
+        fn speed_mph_is_faster_number(meters_per_second in 0.1f64..200.0) {
+            let speed: Speed = Speed::from_meters_per_second(meters_per_second);
+            prop_assert!(speed.mph() > speed.meters_per_second());
+            assert_close(Speed::from_mph(speed.mph()).meters_per_second(), meters_per_second);
+        }
+
+        #[test]
+        fn pressure_round_trips(hpa in 800.0f64..1100.0) {
+            assert_close(Pressure::from_inhg(Pressure::from_hpa(hpa).inhg()).hpa(), hpa);
+        }
+
+        #[test]
+        fn canonical_values_survive_json(celsius in -200.0f64..200.0) {
+            let json: String = serde_json::to_string(&Temperature::from_celsius(celsius)).unwrap();
+            let parsed: Temperature = serde_json::from_str(&json).unwrap();
+            assert_close(parsed.celsius(), celsius);
+        }
+    }
+}
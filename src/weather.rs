@@ -1,25 +1,48 @@
 //! # Rusty Thermostat OpenWeatherMaps API Library
 //! This library holds all structs and methods to collect data from OpenWeatherMaps API
 
-use std::{env, fmt};
+use std::{env, fmt, sync::Arc, time::Duration};
+use async_trait::async_trait;
 use reqwest::{self, RequestBuilder};
-use sea_orm::ActiveValue::{Set, NotSet};
 use serde_derive::{Serialize, Deserialize};
-use chrono::Utc;
 use serde_json;
-use crate::schema::{weather_reading, pollution_reading};
+use crate::schema::{weather_reading, pollution_reading, forecast_reading};
+use crate::psychrometrics::ComfortMetrics;
+use crate::units::{Temperature, Speed, Pressure, UnitSystem};
+
+pub mod provider;
+pub mod open_meteo;
+pub mod quota;
+pub mod retry;
+pub mod cache;
+
+pub use provider::{WeatherProvider, ProviderKind, CurrentConditions, ConditionsReport, ForecastPoint, AirQuality};
+pub use quota::{ApiQuota, QuotaSnapshot, QuotaWindow};
+pub use retry::RetryPolicy;
+pub use cache::{WeatherCache, Cached, CacheSource};
 
 // Responses from the GeoLocating API can be held here
+// Only the zip code lookup returns a zip and only city lookups return a state
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GeoLocation {
-    pub zip: i32,
+    pub zip: Option<String>,
     pub name: String,
     pub lat: f32,
     pub lon: f32,
+    #[serde(default)]
     pub country: String,
+    pub state: Option<String>,
 }
 
 impl GeoLocation {
+    /// The GeoLocation's spot on its own
+    pub fn coordinates(&self) -> Coordinates {
+        Coordinates::new(self.lat, self.lon)
+    }
+    /// Creates a GeoLocation for a spot given directly as latitude and longitude
+    pub fn from_coordinates(lat: f32, lon: f32) -> GeoLocation {
+        GeoLocation { zip: None, name: format!("{},{}", lat, lon), lat, lon, country: String::new(), state: None }
+    }
     pub fn create_uri(&self) -> String {
         format!("lat={}&lon={}", self.lat, self.lon)
     }
@@ -28,6 +51,8 @@ impl GeoLocation {
 // Responses from the Air Pollution API can be held here
 #[derive(Debug, Clone, Deserialize)]
 pub struct AirPollutionResponse {
+    // Coordinates the air pollution was found for
+    coord: Coordinates,
     list: Vec<PollList>,
 }
 impl fmt::Display for AirPollutionResponse {
@@ -37,18 +62,19 @@ impl fmt::Display for AirPollutionResponse {
 }
 
 impl AirPollutionResponse {
+    // Get the coordinates contained in an AirPollutionResponse
+    pub fn get_coordinates(&self) -> Coordinates {
+        self.coord
+    }
     /// Consumes a AirPollutionResponse to ready it for writing to a database<br>
-    /// Note: This function assumes a response with only 1 pollution check. If multiple locations were somehow returned in a single response, all but the first will be discarded
-    pub fn generate_db_model(self) -> pollution_reading::ActiveModel {
-        let current_aqi: MainAqi = self.list[0].main.clone();
-        let current_pollution: Components = self.list[0].components.clone();
-        pollution_reading::ActiveModel { timestamp: Set(Utc::now().naive_utc()),
-            id: NotSet, aqi: Set(current_aqi.aqi.into()), co: Set(current_pollution.co.into()),
-            no: Set(current_pollution.no.into()), no2: Set(current_pollution.no2.into()),
-            o3: Set(current_pollution.o3.into()), so2: Set(current_pollution.so2.into()),
-            pm2_5: Set(current_pollution.pm2_5.into()), pm10: Set(current_pollution.pm10.into()),
-            nh3: Set(current_pollution.nh3.into()) }
-
+    /// Every entry in the list becomes its own reading, timestamped with the time the API calculated it for
+    pub fn generate_db_models(self) -> Vec<pollution_reading::ActiveModel> {
+        let location: Coordinates = self.coord;
+        self.into_air_quality().into_iter().map(|reading| reading.generate_db_model(Some(location))).collect()
+    }
+    /// Consumes a AirPollutionResponse into the normalized readings shared by every WeatherProvider
+    pub fn into_air_quality(self) -> Vec<AirQuality> {
+        self.list.into_iter().map(AirQuality::from).collect()
     }
 }
 
@@ -58,7 +84,27 @@ impl AirPollutionResponse {
 struct PollList {
     components: Components,
     main: MainAqi,
+    // Time the pollution values were calculated for in unix UTC
+    dt: i64,
+}
+
+impl From<PollList> for AirQuality {
+    fn from(entry: PollList) -> Self {
+        AirQuality {
+            measured_at: entry.dt,
+            aqi: entry.main.aqi.into(),
+            co: Some(entry.components.co.into()),
+            no: Some(entry.components.no.into()),
+            no2: Some(entry.components.no2.into()),
+            o3: Some(entry.components.o3.into()),
+            so2: Some(entry.components.so2.into()),
+            pm2_5: Some(entry.components.pm2_5.into()),
+            pm10: Some(entry.components.pm10.into()),
+            nh3: Some(entry.components.nh3.into()),
+        }
+    }
 }
+
 impl fmt::Display for PollList {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "AQI: {}, Components: {}", self.main.aqi, self.components)
@@ -98,15 +144,17 @@ impl fmt::Display for MainAqi {
 // Response from the current weather API can be held here
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WeatherResponse {
-    // Weather conditions
-    weather: WeatherInfo,
+    // Coordinates the weather was found for
+    coord: Coordinates,
+    // Weather conditions, the first entry is the primary one
+    weather: Vec<WeatherInfo>,
     // Internally used string I'm keeping for now. Example has "stations"
-    base: String,
+    base: Option<String>,
     // Temperature information
     #[serde(rename = "main")]
     temperature: TemperatureInfo,
-    // Visibility based on conditions in selected units
-    visibility: i32,
+    // Visibility in meters, left out when it is not measured
+    visibility: Option<i32>,
     // Wind information
     wind: WindInfo,
     // Rain accumulation
@@ -118,21 +166,37 @@ pub struct WeatherResponse {
     // Time of calculation unix UTC
     dt: i32,
     // System information with sunrise and sunset
-    sys_info: SysInfo,
+    sys: SysInfo,
+    // Shift in seconds from UTC
+    timezone: i32,
+    // City ID
+    id: i64,
+    // City name
+    name: String,
 }
 
 impl WeatherResponse {
-    // Get a copy of the WeatherInfo contained in a WeatherResponse
-    pub fn get_conditions_info(&self) -> WeatherInfo {
+    // Get a copy of the primary WeatherInfo contained in a WeatherResponse
+    // possible that it will be None
+    pub fn get_conditions_info(&self) -> Option<WeatherInfo> {
+        self.weather.first().cloned()
+    }
+    // Get every WeatherInfo contained in a WeatherResponse
+    pub fn get_all_conditions(&self) -> Vec<WeatherInfo> {
         self.weather.clone()
     }
+    // Get the coordinates contained in a WeatherResponse
+    pub fn get_coordinates(&self) -> Coordinates {
+        self.coord
+    }
     // Get a copy of the TemperatureInfo contained in a WeatherResponse
     pub fn get_temp_info(&self) -> TemperatureInfo {
         self.temperature.clone()
     }
     // Get the current visibility in a WeatherResponse
-    pub fn get_visibility(&self) -> i32 {
-        self.visibility.clone()
+    // possible that it will be None
+    pub fn get_visibility(&self) -> Option<i32> {
+        self.visibility
     }
     // Get a copy of the WindInfo contained in a WeatherResponse
     pub fn get_wind_info(&self) -> WindInfo {
@@ -150,62 +214,100 @@ impl WeatherResponse {
     }
     // Get the current cloudiness percentage
     pub fn get_cloudiness(&self) -> i32 {
-        self.clouds.all.clone()
+        self.clouds.all
     }
     // Get the day's sunrise in unix UTC
     pub fn get_sunrise(&self) -> i32 {
-        self.sys_info.sunrise.clone()
+        self.sys.sunrise
     }
     // Get the day's sunset in unix UTC
     pub fn get_sunset(&self) -> i32 {
-        self.sys_info.sunset.clone()
-    }
-    // Consumes a WeatherResponse into an ActiveModel to be put into the DB
-    pub fn generate_db_model(self) -> weather_reading::ActiveModel {
-        let mut reading: weather_reading::ActiveModel = weather_reading::ActiveModel {
-            id: NotSet,
-            timestamp: Set(Utc::now().naive_utc()),
-            condition: Set(self.weather.main),
-            description: Set(self.weather.description),
-            icon: Set(self.weather.icon),
-            temp_real: Set(self.temperature.temp.into()),
-            temp_feel: Set(self.temperature.feels_like.into()),
-            pressure_sea: Set(self.temperature.pressure),
-            humidity: Set(self.temperature.humidity),
-            pressure_ground: Set(self.temperature.grnd_level),
-            visibility: Set(self.visibility),
-            wind_speed: Set(self.wind.speed.into()),
-            wind_deg: Set(self.wind.deg),
-            wind_gust: Set(self.wind.gust.into()),
-            rain1_h: NotSet,
-            rain3_h: NotSet,
-            snow1_h: NotSet,
-            snow3_h: NotSet,
-            clouds: Set(self.clouds.all),
-            dt: Set(self.dt),
-            sunrise: Set(self.sys_info.sunrise),
-            sunset: Set(self.sys_info.sunset),
+        self.sys.sunset
+    }
+    // Get the country code of the location
+    // possible that it will be None
+    pub fn get_country(&self) -> Option<String> {
+        self.sys.country.clone()
+    }
+    // Get the location's shift in seconds from UTC
+    pub fn get_timezone_offset(&self) -> i32 {
+        self.timezone
+    }
+    // Get the OpenWeather city ID of the location
+    pub fn get_city_id(&self) -> i64 {
+        self.id
+    }
+    // Get the city name of the location
+    pub fn get_city_name(&self) -> String {
+        self.name.clone()
+    }
+    // Consumes a WeatherResponse requested in the given units into an ActiveModel to be put into the DB
+    pub fn generate_db_model(self, units: UnitSystem) -> weather_reading::ActiveModel {
+        let location: Coordinates = self.coord;
+        self.into_conditions(units).generate_db_model(Some(location))
+    }
+    /// Consumes a WeatherResponse requested in the given units into the normalized CurrentConditions
+    pub fn into_conditions(self, units: UnitSystem) -> CurrentConditions {
+        let response: WeatherResponse = self;
+        let rain: Option<RainInfo> = response.rain;
+        let snow: Option<SnowInfo> = response.snow;
+        let conditions: Option<WeatherInfo> = response.weather.into_iter().next();
+        let (condition, description, icon) = match conditions {
+            Some(conditions) => (Some(conditions.main), Some(conditions.description), Some(conditions.icon)),
+            None => (None, None, None),
         };
-        let rain: Option<RainInfo> = self.rain;
-        let snow: Option<SnowInfo> = self.snow;
-
-        if rain.is_some() {
-            let unpacked: RainInfo = rain.unwrap();
-            reading.rain1_h = Set(Some(unpacked.onehour.into()));
-            if unpacked.threehour.is_some() {
-                let threehour: f64 = unpacked.threehour.unwrap().into();
-                reading.rain3_h = Set(Some(threehour));
-            }
+        CurrentConditions {
+            calculated_at: response.dt.into(),
+            condition,
+            description,
+            icon,
+            temperature: Temperature::from_units(response.temperature.temp.into(), units),
+            feels_like: Temperature::from_units(response.temperature.feels_like.into(), units),
+            humidity: response.temperature.humidity,
+            pressure_sea: response.temperature.sea_level.unwrap_or(response.temperature.pressure).into(),
+            pressure_ground: response.temperature.grnd_level.map(Pressure::from),
+            visibility: response.visibility,
+            wind_speed: Speed::from_units(response.wind.speed.into(), units),
+            wind_deg: response.wind.deg,
+            wind_gust: response.wind.gust.map(|gust| Speed::from_units(gust.into(), units)),
+            clouds: response.clouds.all,
+            rain_1h: rain.as_ref().and_then(|unpacked| unpacked.onehour).map(|amount| amount.into()),
+            rain_3h: rain.and_then(|unpacked| unpacked.threehour).map(|amount| amount.into()),
+            snow_1h: snow.as_ref().and_then(|unpacked| unpacked.onehour).map(|amount| amount.into()),
+            snow_3h: snow.and_then(|unpacked| unpacked.threehour).map(|amount| amount.into()),
+            sunrise: response.sys.sunrise.into(),
+            sunset: response.sys.sunset.into(),
+            comfort: None,
         }
-        if snow.is_some() {
-            let unpacked: SnowInfo = snow.unwrap();
-            reading.snow1_h = Set(Some(unpacked.onehour.into()));
-            if unpacked.threehour.is_some() {
-                let threehour: f64 = unpacked.threehour.unwrap().into();
-                reading.snow3_h = Set(Some(threehour));
-            }
-        }
-        reading
+    }
+}
+
+// Coordinates sent back with current weather and air pollution responses
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Coordinates {
+    lon: f32,
+    lat: f32,
+}
+
+impl Coordinates {
+    pub fn new(lat: f32, lon: f32) -> Coordinates {
+        Coordinates { lon, lat }
+    }
+    /// The latitude as it is stored with a reading
+    pub fn latitude(self) -> f64 {
+        f64::from(self.lat)
+    }
+    /// The longitude as it is stored with a reading
+    pub fn longitude(self) -> f64 {
+        f64::from(self.lon)
+    }
+    // Get the latitude
+    pub fn get_lat(&self) -> f32 {
+        self.lat
+    }
+    // Get the longitude
+    pub fn get_lon(&self) -> f32 {
+        self.lon
     }
 }
 
@@ -225,7 +327,11 @@ pub struct WeatherInfo {
 impl WeatherInfo {
     // Get the URL to the icon for the current condition
     pub fn get_icon(&self) -> String {
-        format!("https://openweathermap.org/img/wn/{}@2x.png", self.icon.clone())
+        format!("https://openweathermap.org/img/wn/{}@2x.png", self.icon)
+    }
+    // Get the weather condition id
+    pub fn get_condition_id(&self) -> i32 {
+        self.id
     }
     // Get the weather group
     pub fn get_weather_head(&self) -> String {
@@ -244,22 +350,23 @@ pub struct WindInfo {
     speed: f32,
     // Wind direction, degrees (meteorological)
     deg: i32,
-    // Wind gusts based on units selected
-    gust: f32,
+    // Wind gusts based on units selected, left out in calm weather
+    gust: Option<f32>,
 }
 
 impl WindInfo {
     // Get a copy of the wind speed in WindInfo
     pub fn get_wind_speed(&self) -> f32 {
-        self.speed.clone()
+        self.speed
     }
     // Get a copy of wind direction in WindInfo
     pub fn get_wind_direction(&self) -> i32 {
-        self.deg.clone()
+        self.deg
     }
     // Get the gust speed in WindInfo
-    pub fn get_wind_gust(&self) -> f32 {
-        self.gust.clone()
+    // possible that it will be None
+    pub fn get_wind_gust(&self) -> Option<f32> {
+        self.gust
     }
 }
 
@@ -270,43 +377,53 @@ pub struct TemperatureInfo {
     temp: f32,
     // Temperature "feels like" given conditions
     feels_like: f32,
-    // Atmospheric pressure at sea level
+    // Minimum temperature currently observed across the area
+    temp_min: Option<f32>,
+    // Maximum temperature currently observed across the area
+    temp_max: Option<f32>,
+    // Atmospheric pressure, at sea level when sea_level is left out
     pressure: i32,
     // Humidity in percentage
     humidity: i32,
+    // Atmospheric pressure at sea level
+    sea_level: Option<i32>,
     // Atmospheric pressure at ground level
-    grnd_level: i32,
+    grnd_level: Option<i32>,
 }
 
 impl TemperatureInfo {
     // Get a copy of the temperature contained in TemperatureInfo
     pub fn get_temp(&self) -> f32 {
-        self.temp.clone()
+        self.temp
     }
     // Get a copy of the human feel temperature contained in TemperatureInfo
     pub fn get_feels_like(&self) -> f32 {
-        self.feels_like.clone()
+        self.feels_like
     }
     // Get a copy of the sea level atomospheric pressure contained in TemperatureInfo
     pub fn get_sea_level_pressure(&self) -> i32 {
-        self.pressure.clone()
+        self.sea_level.unwrap_or(self.pressure)
     }
     // Get a copy of the humidity percentage contained in TemperatureInfo
     pub fn get_humidity(&self) -> i32 {
-        self.humidity.clone()
+        self.humidity
     }
     // Get a copy of the ground level atomospheric pressure contained in TemperatureInfo
-    pub fn get_ground_level_pressure(&self) -> i32 {
-        self.grnd_level.clone()
+    // possible that it will be None
+    pub fn get_ground_level_pressure(&self) -> Option<i32> {
+        self.grnd_level
+    }
+    // Work out the comfort metrics for these readings and wind, given the units they were requested in
+    pub fn comfort(&self, wind: &WindInfo, units: UnitSystem) -> ComfortMetrics {
+        ComfortMetrics::outdoor(Temperature::from_units(self.temp.into(), units), self.humidity.into(), Speed::from_units(wind.speed.into(), units))
     }
 }
 
 // System information: sunrise/sunset timing is stored here
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SysInfo {
-    #[serde(rename = "type")]
     // Country code from where the check came from
-    country: String,
+    country: Option<String>,
     // Timing of the sunrise in unix UTC
     sunrise: i32,
     // Timing of the sunset in unix UTC
@@ -318,7 +435,7 @@ pub struct SysInfo {
 pub struct RainInfo {
     // Rain accumulation in 1 hour
     #[serde(rename = "1h")]
-    onehour: f32,
+    onehour: Option<f32>,
     // Rain accumulation in 3 hours
     #[serde(rename = "3h")]
     threehour: Option<f32>,
@@ -329,7 +446,7 @@ pub struct RainInfo {
 pub struct SnowInfo {
     // Snow accumulation in 1 hour
     #[serde(rename = "1h")]
-    onehour: f32,
+    onehour: Option<f32>,
     // Snow accumulation in 3 hours
     #[serde(rename = "3h")]
     threehour: Option<f32>,
@@ -342,30 +459,257 @@ pub struct CloudInfo {
     all: i32,
 }
 
-/// APIError is for containing any errors passed by the OpenWeather API
+// Response from the 5 day / 3 hour forecast API can be held here
 #[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct APIError {
-    #[serde(rename = "code")]
-    status_code: String,
-    message: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    parameters: Option<Vec<String>>,
+pub struct ForecastResponse {
+    // Number of forecast entries returned
+    cnt: i32,
+    // Forecast entries, one for every 3 hours
+    list: Vec<ForecastEntry>,
+    // Information about the city the forecast is for
+    city: ForecastCity,
+}
+
+impl ForecastResponse {
+    // Get a copy of the forecast entries contained in a ForecastResponse
+    pub fn get_entries(&self) -> Vec<ForecastEntry> {
+        self.list.clone()
+    }
+    // Get the name of the city the forecast is for
+    pub fn get_city_name(&self) -> String {
+        self.city.name.clone()
+    }
+    /// Consumes a ForecastResponse requested in the given units into ActiveModels to be put into the DB<br>
+    /// Every entry shares the same fetched_at so a single forecast snapshot can be pulled back out together
+    pub fn generate_db_models(self, units: UnitSystem) -> Vec<forecast_reading::ActiveModel> {
+        provider::generate_forecast_models(self.into_points(units))
+    }
+    /// Consumes a ForecastResponse requested in the given units into the normalized forecast shared by every WeatherProvider<br>
+    /// Entries without any weather conditions are skipped
+    pub fn into_points(self, units: UnitSystem) -> Vec<ForecastPoint> {
+        self.list.into_iter().filter_map(|entry| entry.into_point(units)).collect()
+    }
+}
+
+// A single forecast entry in the ForecastResponse
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastEntry {
+    // Time of the forecasted data in unix UTC
+    dt: i64,
+    // Temperature information
+    #[serde(rename = "main")]
+    temperature: ForecastTemperatureInfo,
+    // Weather conditions
+    weather: Vec<WeatherInfo>,
+    // Cloudiness percentage
+    clouds: CloudInfo,
+    // Wind information
+    wind: WindInfo,
+    // Visibility based on conditions in selected units
+    visibility: Option<i32>,
+    // Probability of precipitation from 0 to 1
+    pop: f32,
+    // Rain accumulation
+    rain: Option<ForecastPrecipInfo>,
+    // Snow accumulation
+    snow: Option<ForecastPrecipInfo>,
 }
 
-impl fmt::Display for APIError {
+impl ForecastEntry {
+    // Get the time being forecasted in unix UTC
+    pub fn get_forecast_time(&self) -> i64 {
+        self.dt
+    }
+    // Get a copy of the ForecastTemperatureInfo contained in a ForecastEntry
+    pub fn get_temp_info(&self) -> ForecastTemperatureInfo {
+        self.temperature.clone()
+    }
+    // Get the probability of precipitation from 0 to 1
+    pub fn get_precipitation_chance(&self) -> f32 {
+        self.pop
+    }
+    // Consumes a ForecastEntry into the normalized ForecastPoint
+    fn into_point(self, units: UnitSystem) -> Option<ForecastPoint> {
+        let conditions: WeatherInfo = self.weather.into_iter().next()?;
+        Some(ForecastPoint {
+            forecast_time: self.dt,
+            condition: conditions.main,
+            description: conditions.description,
+            icon: conditions.icon,
+            temperature: Temperature::from_units(self.temperature.temp.into(), units),
+            feels_like: Temperature::from_units(self.temperature.feels_like.into(), units),
+            temp_min: Temperature::from_units(self.temperature.temp_min.into(), units),
+            temp_max: Temperature::from_units(self.temperature.temp_max.into(), units),
+            pressure_sea: self.temperature.pressure.into(),
+            humidity: self.temperature.humidity,
+            pressure_ground: self.temperature.grnd_level.map(Pressure::from),
+            visibility: self.visibility,
+            wind_speed: Speed::from_units(self.wind.speed.into(), units),
+            wind_deg: self.wind.deg,
+            wind_gust: self.wind.gust.map(|gust| Speed::from_units(gust.into(), units)),
+            clouds: self.clouds.all,
+            pop: self.pop.into(),
+            rain_3h: self.rain.and_then(|rain| rain.threehour).map(|amount| amount.into()),
+            snow_3h: self.snow.and_then(|snow| snow.threehour).map(|amount| amount.into()),
+        })
+    }
+}
+
+// Forecasted temperature information from a ForecastEntry is stored here
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastTemperatureInfo {
+    // Forecasted temperature
+    temp: f32,
+    // Temperature "feels like" given conditions
+    feels_like: f32,
+    // Minimum temperature expected across the area
+    temp_min: f32,
+    // Maximum temperature expected across the area
+    temp_max: f32,
+    // Atmospheric pressure at sea level
+    pressure: i32,
+    // Humidity in percentage
+    humidity: i32,
+    // Atmospheric pressure at ground level
+    grnd_level: Option<i32>,
+}
+
+impl ForecastTemperatureInfo {
+    // Get a copy of the forecasted temperature
+    pub fn get_temp(&self) -> f32 {
+        self.temp
+    }
+    // Get a copy of the minimum forecasted temperature
+    pub fn get_temp_min(&self) -> f32 {
+        self.temp_min
+    }
+    // Get a copy of the maximum forecasted temperature
+    pub fn get_temp_max(&self) -> f32 {
+        self.temp_max
+    }
+    // Get a copy of the forecasted humidity percentage
+    pub fn get_humidity(&self) -> i32 {
+        self.humidity
+    }
+}
+
+// Forecasted rain or snow. The forecast only reports 3 hour accumulation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastPrecipInfo {
+    // Accumulation in 3 hours
+    #[serde(rename = "3h")]
+    threehour: Option<f32>,
+}
+
+// City information returned with a forecast
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ForecastCity {
+    // City name
+    name: String,
+    // Country code
+    country: Option<String>,
+    // Shift in seconds from UTC
+    timezone: Option<i32>,
+}
+
+/// WeatherError covers everything that can go wrong while talking to a weather API
+#[derive(Debug)]
+pub enum WeatherError {
+    /// The request could not be built or sent, or the response could not be read. Timeouts and dropped connections end up here
+    Transport(reqwest::Error),
+    /// The API answered with an error status and a body that was not a recognized error message
+    Status { status: u16, body: String },
+    /// The API answered with an error status and its own error message
+    Api { status: u16, message: String, parameters: Option<Vec<String>> },
+    /// The API answered successfully but the body did not match what was expected
+    Deserialize(serde_json::Error),
+    /// A setting needed to make the request has not been configured
+    MissingConfig(String),
+    /// The API answered 429 Too Many Requests, optionally saying how long to wait
+    RateLimited { retry_after: Option<Duration>, message: String },
+    /// The configured call quota has run out so the request was never sent
+    QuotaExceeded(QuotaWindow),
+    /// The geocoding API had no match for the location given
+    LocationNotFound(String),
+}
+
+impl WeatherError {
+    /// Turns an error response from OpenWeather into a WeatherError<br>
+    /// OpenWeather usually sends a body with a message and sometimes the parameters that were wrong. If it doesn't, the raw body is kept
+    pub fn from_response(status: u16, body: String) -> WeatherError {
+        match serde_json::from_str::<OpenWeatherErrorBody>(&body) {
+            Ok(api_error) => WeatherError::Api { status, message: api_error.message, parameters: api_error.parameters },
+            Err(_) => WeatherError::Status { status, body },
+        }
+    }
+    /// Gets the HTTP status the API answered with, if it answered at all
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            WeatherError::Status { status, .. } => Some(*status),
+            WeatherError::Api { status, .. } => Some(*status),
+            WeatherError::RateLimited { .. } => Some(429),
+            WeatherError::Transport(error) => error.status().map(|status| status.as_u16()),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WeatherError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let mut display_message = self.message.clone();
-        if self.parameters.is_some() {
-            display_message = format!("{}, Related parameters: ", display_message);
-            let items = self.parameters.clone().unwrap();
-            for item in items.iter() {
-                display_message = format!("{}, {}", display_message, item);
-            }
+        match self {
+            WeatherError::Transport(error) => write!(f, "Unable to reach the weather API: {}", error),
+            WeatherError::Status { status, body } => write!(f, "Status: {}, Body: {}", status, body),
+            WeatherError::Api { status, message, parameters } => {
+                write!(f, "Status: {}, Message: {}", status, message)?;
+                if let Some(items) = parameters {
+                    write!(f, ", Related parameters: {}", items.join(", "))?;
+                }
+                Ok(())
+            },
+            WeatherError::Deserialize(error) => write!(f, "Unexpected response from the weather API: {}", error),
+            WeatherError::MissingConfig(setting) => write!(f, "Weather setting missing: {}", setting),
+            WeatherError::RateLimited { retry_after, message } => {
+                write!(f, "Rate limited by the weather API: {}", message)?;
+                if let Some(wait) = retry_after {
+                    write!(f, ", Retry after: {}s", wait.as_secs())?;
+                }
+                Ok(())
+            },
+            WeatherError::QuotaExceeded(window) => write!(f, "API quota for the {} has been used up", window),
+            WeatherError::LocationNotFound(location) => write!(f, "No location found for: {}", location),
         }
-        write!(f, "Status: {}, Message: {}", self.status_code, display_message)
     }
 }
 
+impl std::error::Error for WeatherError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WeatherError::Transport(error) => Some(error),
+            WeatherError::Deserialize(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for WeatherError {
+    fn from(error: reqwest::Error) -> Self {
+        WeatherError::Transport(error)
+    }
+}
+
+impl From<serde_json::Error> for WeatherError {
+    fn from(error: serde_json::Error) -> Self {
+        WeatherError::Deserialize(error)
+    }
+}
+
+// Error body OpenWeather sends back with a client or server error
+#[derive(Debug, Deserialize)]
+struct OpenWeatherErrorBody {
+    message: String,
+    parameters: Option<Vec<String>>,
+}
+
 // Relevant information for building the URL and containing the reqwest client are stored here
 #[derive(Debug, Clone, Deserialize)]
 pub struct Configuration {
@@ -375,7 +719,14 @@ pub struct Configuration {
     #[serde(skip_deserializing)]
     pub client: reqwest::Client,
     pub api_key: Option<String>,
-    pub units: String,
+    // Units asked for from the API. Responses are converted to the canonical units as they come in
+    #[serde(skip_deserializing)]
+    pub units: UnitSystem,
+    // Shared with the API so the counters can be shown
+    #[serde(skip_deserializing)]
+    pub quota: Arc<ApiQuota>,
+    #[serde(skip_deserializing)]
+    pub retry: RetryPolicy,
 }
 
 impl Configuration {
@@ -384,71 +735,101 @@ impl Configuration {
     }
     // Build a configuration file based on environmental variables present
     // # Errors
-    // When this parses a zip code into a GeoLocation, the API can return an error for a bad zipcode which will interrupt the process
+    // When this parses a zip code into a GeoLocation, the API can return an error for a bad zipcode which will leave the location unset
     pub async fn new_env() -> Configuration {
         let mut new_config = Configuration::default();
-        match env::var("RUSTY_WEATHER_BASE_PATH") {
-            Ok(bpath) => new_config.base_path = bpath,
-            _ => (), 
+        if let Ok(bpath) = env::var("RUSTY_WEATHER_BASE_PATH") {
+            new_config.base_path = bpath;
+        }
+        if let Ok(useragent) = env::var("RUSTY_WEATHER_USER_AGENT") {
+            new_config.user_agent = Some(useragent);
         }
-        match env::var("RUSTY_WEATHER_USER_AGENT") {
-            Ok(useragent) => new_config.user_agent = Some(useragent),
-            _ => (),
+        if let Ok(apikey) = env::var("RUSTY_WEATHER_API_KEY") {
+            new_config.api_key = Some(apikey);
         }
-        match env::var("RUSTY_WEATHER_API_KEY") {
-            Ok(apikey) => new_config.api_key = Some(apikey),
-            _ => (),
+        if let Ok(set_units) = env::var("RUSTY_WEATHER_UNITS") {
+            new_config.set_units(&set_units);
         }
-        match env::var("RUSTY_WEATHER_UNITS") {
-            Ok(set_units) => new_config.set_units(&set_units),
-            _ => (),
+        if let Ok(city) = env::var("RUSTY_WEATHER_CITY") {
+            new_config.location = match new_config.parse_city(&city).await {
+                Ok(location) => Some(location),
+                Err(error) => {
+                    warn!("Unable to look up city {}: {}", city, error);
+                    None
+                }
+            };
         }
-        match env::var("RUSTY_WEATHER_LOCATION") {
-            Ok(zip) => new_config.location = new_config.parse_zipcode(&zip).await.ok(),
-            _ => (),
+        if let Ok(zip) = env::var("RUSTY_WEATHER_LOCATION") {
+            new_config.location = match new_config.parse_zipcode(&zip).await {
+                Ok(location) => Some(location),
+                Err(error) => {
+                    warn!("Unable to look up location {}: {}", zip, error);
+                    None
+                }
+            };
         }
         new_config
     }
     pub fn api_set(&self) -> bool {
-        if self.api_key.is_some() {
-            true
-        } else {
-            false
-        }
+        self.api_key.is_some()
     }
-    pub fn set_units(&mut self, set_units: &str) -> () {
-        let valid_units = vec!["standard".to_string(), "imperial".to_string(), "metric".to_string()];
-        let lower_case_units = set_units.to_lowercase();
-        if valid_units.contains(&lower_case_units) {
-            self.units = lower_case_units;
-        } else {
-            self.units = "imperial".to_string();
-        }
+    pub fn set_units(&mut self, set_units: &str) {
+        self.units = UnitSystem::parse(set_units);
     }
-    pub async fn parse_zipcode(&self, zipcode: &str) -> Result<GeoLocation, APIError> {
+    pub async fn parse_zipcode(&self, zipcode: &str) -> Result<GeoLocation, WeatherError> {
         let mut zip = zipcode.to_string();
-        if !zip.contains(",") {
+        if !zip.contains(',') {
             //If there is no comma in the provided zipcode, we probably forgot the ISO country code and I'm defaulting to the US
             zip = format!("{},840", zipcode);
         }
 
-        let uri = format!("geo/1.0/zip?zip={}", zip);
+        let uri = format!("geo/1.0/zip?zip={}", urlencoding::encode(&zip));
         let req_builder = self.build_request(&uri, reqwest::Method::GET);
+        let return_contents = self.execute_request(req_builder).await?;
 
-        let built_req = match req_builder.build() {
-            Ok(request) => request,
-            Err(error) => return Err(APIError{status_code: "400".to_string(), message: error.to_string(), parameters: None})
-        };
-
-        let geo_response = match self.client.execute(built_req).await {
-            Ok(resp) => resp,
-            Err(error) => return Err(APIError {status_code: error.status().unwrap().to_string(), message: error.to_string(), parameters: None})
-        };
+        Ok(serde_json::from_str(&return_contents)?)
+    }
+    // Looks up a city name, optionally followed by a state code and country code such as "London,GB" or "Springfield,IL,US"
+    // The best match from the API is used
+    // # Errors
+    // Returns LocationNotFound if the API has no match for the city, or any error from making the request
+    pub async fn parse_city(&self, city: &str) -> Result<GeoLocation, WeatherError> {
+        let uri = format!("geo/1.0/direct?q={}&limit=1", urlencoding::encode(city.trim()));
+        let req_builder = self.build_request(&uri, reqwest::Method::GET);
+        let return_contents = self.execute_request(req_builder).await?;
 
-        let return_contents = geo_response.text().await.ok().unwrap();
+        let mut matches: Vec<GeoLocation> = serde_json::from_str(&return_contents)?;
+        if matches.is_empty() {
+            return Err(WeatherError::LocationNotFound(city.to_string()))
+        }
+        Ok(matches.swap_remove(0))
+    }
+    // Finds the name and country of the place nearest to the given coordinates
+    // The coordinates given are kept rather than the ones of the named place so readings stay where they were configured
+    // # Errors
+    // Returns LocationNotFound if there is no named place near the coordinates, or any error from making the request
+    pub async fn reverse_geocode(&self, lat: f32, lon: f32) -> Result<GeoLocation, WeatherError> {
+        let uri = format!("geo/1.0/reverse?lat={}&lon={}&limit=1", lat, lon);
+        let req_builder = self.build_request(&uri, reqwest::Method::GET);
+        let return_contents = self.execute_request(req_builder).await?;
 
-        //parsing the returned JSON will either get us the GeoLocation or an APIError
-        serde_json::from_str(&return_contents).ok().unwrap()
+        let mut matches: Vec<GeoLocation> = serde_json::from_str(&return_contents)?;
+        if matches.is_empty() {
+            return Err(WeatherError::LocationNotFound(format!("{},{}", lat, lon)))
+        }
+        let mut nearest: GeoLocation = matches.swap_remove(0);
+        nearest.lat = lat;
+        nearest.lon = lon;
+        Ok(nearest)
+    }
+    // Builds the URI for an endpoint that needs the configured location
+    // # Errors
+    // Returns MissingConfig if no location has been set
+    fn location_uri(&self, endpoint: &str) -> Result<String, WeatherError> {
+        match &self.location {
+            Some(local_location) => Ok(format!("{}{}", endpoint, local_location.create_uri())),
+            None => Err(WeatherError::MissingConfig("location".to_string())),
+        }
     }
     // Accepts a URI and a reqwest method to create the RequestBuilder using the object's already established client
     pub fn build_request(&self, uri: &str, method: reqwest::Method) -> RequestBuilder {
@@ -462,29 +843,54 @@ impl Configuration {
         };
         req_builder
     }
-    // Takes a complete RequestBuilder and submits it to the API
+    // Takes a complete RequestBuilder and submits it to the API, retrying as the retry policy allows
     // # Errors
-    // This will return errors from the API if something doesn't work. Library will hijack a 400 error to display an internal error to building the request
-    pub async fn execute_request(&self, final_request: RequestBuilder) -> Result<String, APIError> {
-        let built_req = match final_request.build() {
-            Ok(request) => request,
-            Err(error) => return Err(APIError{status_code: "400".to_string(), message: error.to_string(), parameters: None})
-        };
-        let web_response = match self.client.execute(built_req).await {
-            Ok(resp) => resp,
-            Err(error) => return Err(APIError {status_code: error.status().unwrap().to_string(), message: error.to_string(), parameters: None})
-        };
-    
+    // Returns MissingConfig without making the request if no API key is set and QuotaExceeded if the quota has run out
+    // Returns Transport if the API can't be reached, RateLimited on a 429 and Api or Status if the API answers with any other error
+    pub async fn execute_request(&self, final_request: RequestBuilder) -> Result<String, WeatherError> {
+        if !self.api_set() {
+            return Err(WeatherError::MissingConfig("openweather_apikey".to_string()))
+        }
+        let mut attempt: u32 = 1;
+        loop {
+            // A GET without a streamed body can always be cloned, if not there is only one shot at it
+            let Some(this_request) = final_request.try_clone() else {
+                return self.execute_once(final_request).await
+            };
+            match self.execute_once(this_request).await {
+                Ok(web_content) => return Ok(web_content),
+                Err(error) => match self.retry.delay_for(&error, attempt) {
+                    Some(wait) => {
+                        debug!("Weather request attempt {} failed, retrying in {}ms: {}", attempt, wait.as_millis(), error);
+                        tokio::time::sleep(wait).await;
+                        attempt += 1;
+                    },
+                    None => return Err(error),
+                },
+            }
+        }
+    }
+    // Sends a single request if the quota allows it
+    async fn execute_once(&self, request: RequestBuilder) -> Result<String, WeatherError> {
+        self.quota.try_acquire().map_err(WeatherError::QuotaExceeded)?;
+        let built_req = request.build()?;
+        let web_response = self.client.execute(built_req).await?;
+
         let web_status = web_response.status();
-        let web_content = match web_response.text().await {
-            Ok(resp) => resp,
-            Err(error) => return Err(APIError {status_code: web_status.to_string(), message: error.to_string(), parameters: None})
-        };
+        if web_status == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            self.quota.record_rate_limited();
+            let retry_after: Option<Duration> = web_response.headers().get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            let message = web_response.text().await?;
+            return Err(WeatherError::RateLimited { retry_after, message })
+        }
+        let web_content = web_response.text().await?;
         if !web_status.is_client_error() && !web_status.is_server_error() {
             Ok(web_content)
         } else {
-            let api_error: APIError = serde_json::from_str(&web_content).ok().unwrap();
-            Err(api_error)
+            Err(WeatherError::from_response(web_status.as_u16(), web_content))
         }
     }
 }
@@ -497,47 +903,88 @@ impl Default for Configuration {
             location: None,
             client: reqwest::Client::new(),
             api_key: None,
-            units: "imperial".to_string(),
+            units: UnitSystem::Imperial,
+            quota: Arc::new(ApiQuota::default()),
+            retry: RetryPolicy::default(),
         }
     }
 }
 
 // Using information contained in the provided configuration, a request for current weather conditions will be processed
 // # Errors
-// This will return errors from the API if something doesn't work. Library will hijack a 400 error to display an internal error to building the request
-pub async fn fetch_current_weather(local_config: &Configuration) -> Result<WeatherResponse, APIError> {
-    let mut request_uri: String = "data/2.5/weather?".to_string();
-    if let Some(local_location) = &local_config.location {
-        request_uri = format!("{}{}", request_uri, local_location.create_uri());
-    };
+// Returns a WeatherError if the location or API key are not set, the API can't be reached, the API returns an error or the response can't be read
+pub async fn fetch_current_weather(local_config: &Configuration) -> Result<WeatherResponse, WeatherError> {
+    let mut request_uri: String = local_config.location_uri("data/2.5/weather?")?;
     request_uri = format!("{}&units={}", request_uri, local_config.units);
     let weather_request = local_config.build_request(&request_uri, reqwest::Method::GET);
 
-    match local_config.execute_request(weather_request).await {
-        Ok(web_response) => return serde_json::from_str(&web_response).ok().unwrap(),
-        Err(error) => return Err(error),
-    }
+    let web_response = local_config.execute_request(weather_request).await?;
+    Ok(serde_json::from_str(&web_response)?)
 }
 
 // Using information contained in the provided configuration, a request for current air pollution stats will be processed
 // # Errors
-// This will return errors from the API if something doesn't work. Library will hijack a 400 error to display an internal error to building the request
-pub async fn fetch_current_air_poll(local_config: &Configuration) -> Result<AirPollutionResponse, APIError> {
-    let mut request_uri: String = "data/2.5/air_pollution?".to_string();
-    if let Some(local_location) = &local_config.location {
-        request_uri = format!("{}{}", request_uri, local_location.create_uri());
-    };
+// Returns a WeatherError if the location or API key are not set, the API can't be reached, the API returns an error or the response can't be read
+pub async fn fetch_current_air_poll(local_config: &Configuration) -> Result<AirPollutionResponse, WeatherError> {
+    let request_uri: String = local_config.location_uri("data/2.5/air_pollution?")?;
     let air_request = local_config.build_request(&request_uri, reqwest::Method::GET);
 
-    match local_config.execute_request(air_request).await {
-        Ok(web_response) => return serde_json::from_str(&web_response).ok().unwrap(),
-        Err(error) => return Err(error),
+    let web_response = local_config.execute_request(air_request).await?;
+    Ok(serde_json::from_str(&web_response)?)
+}
+
+// Using information contained in the provided configuration, a request for the 5 day / 3 hour forecast will be processed
+// # Errors
+// Returns a WeatherError if the location or API key are not set, the API can't be reached, the API returns an error or the response can't be read
+pub async fn fetch_forecast(local_config: &Configuration) -> Result<ForecastResponse, WeatherError> {
+    let mut request_uri: String = local_config.location_uri("data/2.5/forecast?")?;
+    request_uri = format!("{}&units={}", request_uri, local_config.units);
+    let forecast_request = local_config.build_request(&request_uri, reqwest::Method::GET);
+
+    let web_response = local_config.execute_request(forecast_request).await?;
+    Ok(serde_json::from_str(&web_response)?)
+}
+
+#[async_trait]
+impl WeatherProvider for Configuration {
+    fn name(&self) -> &'static str {
+        "OpenWeather"
+    }
+    fn location(&self) -> Option<Coordinates> {
+        self.location.as_ref().map(GeoLocation::coordinates)
+    }
+    async fn current(&self) -> Result<CurrentConditions, WeatherError> {
+        let conditions: CurrentConditions = fetch_current_weather(self).await?.into_conditions(self.units);
+        Ok(conditions.with_comfort())
+    }
+    async fn forecast(&self) -> Result<Vec<ForecastPoint>, WeatherError> {
+        Ok(fetch_forecast(self).await?.into_points(self.units))
+    }
+    async fn air_quality(&self) -> Result<Vec<AirQuality>, WeatherError> {
+        Ok(fetch_current_air_poll(self).await?.into_air_quality())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::DateTime;
+    use sea_orm::ActiveValue::Set;
+    use wiremock::{MockServer, Mock, ResponseTemplate};
+    use wiremock::matchers::{method, path, query_param};
+
+    async fn mock_configuration(server: &MockServer) -> Configuration {
+        let mut config = Configuration::new();
+        config.base_path = format!("{}/", server.uri());
+        config.api_key = Some("testkey".to_string());
+        config.location = Some(GeoLocation::from_coordinates(44.34, 10.99));
+        config.retry = RetryPolicy::none();
+        config
+    }
+
+    fn quick_retry(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts, base_delay: Duration::from_millis(1), max_delay: Duration::from_millis(50) }
+    }
 
     #[test]
     fn configuration_default_basepath_not_empty() {
@@ -580,7 +1027,7 @@ mod tests {
     fn configuration_default_units_is_imperial() {
         let new_config = Configuration::new();
 
-        assert_eq!(new_config.units, "imperial".to_string());
+        assert_eq!(new_config.units, UnitSystem::Imperial);
     }
 
     #[test]
@@ -605,7 +1052,7 @@ mod tests {
 
         new_config.set_units("metric");
 
-        assert_eq!(new_config.units, "metric".to_string());
+        assert_eq!(new_config.units, UnitSystem::Metric);
     }
 
     #[test]
@@ -614,7 +1061,7 @@ mod tests {
 
         new_config.set_units("standard");
 
-        assert_eq!(new_config.units, "standard".to_string());
+        assert_eq!(new_config.units, UnitSystem::Standard);
     }
 
     #[test]
@@ -623,7 +1070,7 @@ mod tests {
 
         new_config.set_units("imperial");
 
-        assert_eq!(new_config.units, "imperial".to_string());
+        assert_eq!(new_config.units, UnitSystem::Imperial);
     }
 
     #[test]
@@ -632,6 +1079,402 @@ mod tests {
 
         new_config.set_units("absolutely hot garbage");
 
-        assert_eq!(new_config.units, "imperial".to_string());
+        assert_eq!(new_config.units, UnitSystem::Imperial);
+    }
+
+    #[test]
+    fn air_pollution_generates_model_per_entry() {
+        let body = r#"{"coord":{"lon":-0.1278,"lat":51.5074},"list":[
+            {"main":{"aqi":2},"components":{"co":201.94,"no":0.02,"no2":0.77,"o3":68.66,"so2":0.64,"pm2_5":0.5,"pm10":0.54,"nh3":0.12},"dt":1605182400},
+            {"main":{"aqi":3},"components":{"co":211.94,"no":0.03,"no2":0.87,"o3":70.66,"so2":0.74,"pm2_5":0.6,"pm10":0.64,"nh3":0.22},"dt":1605186000}]}"#;
+        let response: AirPollutionResponse = serde_json::from_str(body).unwrap();
+
+        let models = response.generate_db_models();
+
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].aqi, Set(2));
+        assert_eq!(models[1].aqi, Set(3));
+        assert_eq!(models[1].timestamp, Set(DateTime::from_timestamp(1605186000, 0).unwrap().naive_utc()));
+    }
+
+    #[test]
+    fn forecast_generates_model_per_entry() {
+        let body = r#"{"cod":"200","message":0,"cnt":2,"list":[
+            {"dt":1661871600,"main":{"temp":296.76,"feels_like":296.98,"temp_min":296.76,"temp_max":297.87,"pressure":1015,"sea_level":1015,"grnd_level":933,"humidity":69,"temp_kf":-1.11},
+             "weather":[{"id":500,"main":"Rain","description":"light rain","icon":"10d"}],"clouds":{"all":100},"wind":{"speed":0.62,"deg":349,"gust":1.18},
+             "visibility":10000,"pop":0.32,"rain":{"3h":0.26},"sys":{"pod":"d"},"dt_txt":"2022-08-30 15:00:00"},
+            {"dt":1661882400,"main":{"temp":295.45,"feels_like":295.59,"temp_min":292.84,"temp_max":295.45,"pressure":1015,"sea_level":1015,"grnd_level":931,"humidity":71,"temp_kf":2.61},
+             "weather":[{"id":800,"main":"Clear","description":"clear sky","icon":"01n"}],"clouds":{"all":0},"wind":{"speed":1.97,"deg":157,"gust":3.39},
+             "visibility":10000,"pop":0,"sys":{"pod":"n"},"dt_txt":"2022-08-30 18:00:00"}],
+            "city":{"id":3163858,"name":"Zocca","coord":{"lat":44.34,"lon":10.99},"country":"IT","population":4593,"timezone":7200,"sunrise":1661834187,"sunset":1661882248}}"#;
+        let response: ForecastResponse = serde_json::from_str(body).unwrap();
+
+        assert_eq!(response.get_city_name(), "Zocca".to_string());
+        let models = response.generate_db_models(UnitSystem::Standard);
+
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].fetched_at, models[1].fetched_at);
+        assert_eq!(models[0].rain3_h, Set(Some(0.26f32.into())));
+        assert_eq!(models[0].temp_real, Set(Temperature::from_kelvin(296.76f32.into())));
+        assert_eq!(models[1].rain3_h, Set(None));
+        assert_eq!(models[1].condition, Set("Clear".to_string()));
+        assert_eq!(models[1].forecast_time, Set(DateTime::from_timestamp(1661882400, 0).unwrap().naive_utc()));
+    }
+
+    #[tokio::test]
+    async fn openweather_provider_forecast_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/forecast")).and(query_param("appid", "testkey"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../tests/fixtures/weather/owm_forecast.json")))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let forecast = config.forecast().await.unwrap();
+
+        assert_eq!(forecast.len(), 2);
+        assert_eq!(forecast[0].condition, "Rain".to_string());
+        assert_eq!(forecast[0].humidity, 69);
+        assert_eq!(forecast[1].forecast_time, 1661882400);
+        assert_eq!(forecast[1].rain_3h, None);
+    }
+
+    #[tokio::test]
+    async fn openweather_provider_air_quality_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../tests/fixtures/weather/owm_air_pollution.json")))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let readings = config.air_quality().await.unwrap();
+
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].aqi, 1);
+        assert_eq!(readings[0].measured_at, 1606147200);
+        assert_eq!(readings[0].pm2_5, Some(0.5));
+    }
+
+    #[tokio::test]
+    async fn fetch_without_location_is_missing_config() {
+        let mut config = Configuration::new();
+        config.api_key = Some("testkey".to_string());
+
+        let error = fetch_current_weather(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::MissingConfig(_)));
+    }
+
+    #[tokio::test]
+    async fn fetch_without_api_key_is_missing_config() {
+        let mut config = Configuration::new();
+        config.location = Some(GeoLocation::from_coordinates(44.34, 10.99));
+
+        let error = fetch_forecast(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::MissingConfig(_)));
+    }
+
+    #[tokio::test]
+    async fn fetch_api_error_body_is_api_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"cod":401, "message": "Invalid API key. Please see https://openweathermap.org/faq#error401 for more info."}"#))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let error = fetch_current_air_poll(&config).await.unwrap_err();
+
+        assert_eq!(error.status(), Some(401));
+        assert!(matches!(error, WeatherError::Api { .. }));
+    }
+
+    #[tokio::test]
+    async fn fetch_unrecognized_error_body_is_status() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/forecast"))
+            .respond_with(ResponseTemplate::new(502).set_body_string("<html>Bad Gateway</html>"))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let error = fetch_forecast(&config).await.unwrap_err();
+
+        assert_eq!(error.status(), Some(502));
+        assert!(matches!(error, WeatherError::Status { .. }));
+    }
+
+    #[tokio::test]
+    async fn fetch_unexpected_body_is_deserialize() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/forecast"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"{"surprise": true}"#))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let error = fetch_forecast(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::Deserialize(_)));
+    }
+
+    #[tokio::test]
+    async fn fetch_unreachable_api_is_transport() {
+        let mut config = Configuration::new();
+        config.base_path = "http://127.0.0.1:1/".to_string();
+        config.api_key = Some("testkey".to_string());
+        config.location = Some(GeoLocation::from_coordinates(44.34, 10.99));
+        config.retry = RetryPolicy::none();
+
+        let error = fetch_current_air_poll(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::Transport(_)));
+    }
+
+    #[test]
+    fn weather_error_display_lists_parameters() {
+        let error = WeatherError::Api { status: 400, message: "Nothing to geocode".to_string(), parameters: Some(vec!["zip".to_string(), "country".to_string()]) };
+
+        assert_eq!(error.to_string(), "Status: 400, Message: Nothing to geocode, Related parameters: zip, country".to_string());
+    }
+
+    #[tokio::test]
+    async fn fetch_retries_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(503).set_body_string("Service Unavailable"))
+            .up_to_n_times(2)
+            .mount(&server).await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../tests/fixtures/weather/owm_air_pollution.json")))
+            .mount(&server).await;
+        let mut config = mock_configuration(&server).await;
+        config.retry = quick_retry(3);
+
+        let readings = config.air_quality().await.unwrap();
+
+        assert_eq!(readings.len(), 1);
+        assert_eq!(config.quota.snapshot().total_calls, 3);
+    }
+
+    #[tokio::test]
+    async fn fetch_does_not_retry_client_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(401).set_body_string(r#"{"cod":401, "message": "Invalid API key."}"#))
+            .expect(1)
+            .mount(&server).await;
+        let mut config = mock_configuration(&server).await;
+        config.retry = quick_retry(3);
+
+        let error = fetch_current_air_poll(&config).await.unwrap_err();
+
+        assert_eq!(error.status(), Some(401));
+    }
+
+    #[tokio::test]
+    async fn fetch_too_many_requests_is_rate_limited() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/forecast"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0")
+                .set_body_string(r#"{"cod":429, "message": "Your account is temporary blocked due to exceeding of requests limitation of your subscription type."}"#))
+            .expect(2)
+            .mount(&server).await;
+        let mut config = mock_configuration(&server).await;
+        config.retry = quick_retry(2);
+
+        let error = fetch_forecast(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::RateLimited { retry_after: Some(_), .. }));
+        assert_eq!(config.quota.snapshot().rate_limited, 2);
+    }
+
+    #[tokio::test]
+    async fn fetch_skipped_when_quota_used_up() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/air_pollution"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../tests/fixtures/weather/owm_air_pollution.json")))
+            .expect(1)
+            .mount(&server).await;
+        let mut config = mock_configuration(&server).await;
+        config.quota = Arc::new(ApiQuota::new(Some(1), None));
+
+        assert!(fetch_current_air_poll(&config).await.is_ok());
+        let error = fetch_current_air_poll(&config).await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::QuotaExceeded(QuotaWindow::Minute)));
+        assert_eq!(config.quota.snapshot().skipped_calls, 1);
+    }
+
+    #[tokio::test]
+    async fn parse_city_returns_best_match() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/geo/1.0/direct")).and(query_param("q", "London,GB")).and(query_param("limit", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"[{"name":"London","local_names":{"en":"London","fr":"Londres"},
+                "lat":51.5073219,"lon":-0.1276474,"country":"GB","state":"England"}]"#))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let location = config.parse_city("London,GB").await.unwrap();
+
+        assert_eq!(location.name, "London".to_string());
+        assert_eq!(location.country, "GB".to_string());
+        assert_eq!(location.state, Some("England".to_string()));
+        assert!(location.zip.is_none());
+    }
+
+    #[tokio::test]
+    async fn parse_city_encodes_the_name() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/geo/1.0/direct")).and(query_param("q", "Mostar & Blagaj #2,BA")).and(query_param("limit", "1"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"[{"name":"Mostar","lat":43.3438,"lon":17.8078,"country":"BA"}]"#))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let location = config.parse_city(" Mostar & Blagaj #2,BA ").await.unwrap();
+
+        assert_eq!(location.name, "Mostar".to_string());
+    }
+
+    #[tokio::test]
+    async fn parse_city_without_match_is_not_found() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/geo/1.0/direct"))
+            .respond_with(ResponseTemplate::new(200).set_body_string("[]"))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let error = config.parse_city("Atlantis").await.unwrap_err();
+
+        assert!(matches!(error, WeatherError::LocationNotFound(_)));
+    }
+
+    #[tokio::test]
+    async fn reverse_geocode_keeps_given_coordinates() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/geo/1.0/reverse")).and(query_param("lat", "51.5098")).and(query_param("lon", "-0.118"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(r#"[{"name":"City of Westminster","local_names":{"en":"City of Westminster"},
+                "lat":51.5000,"lon":-0.1300,"country":"GB","state":"England"}]"#))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let location = config.reverse_geocode(51.5098, -0.1180).await.unwrap();
+
+        assert_eq!(location.name, "City of Westminster".to_string());
+        assert_eq!(location.lat, 51.5098);
+        assert_eq!(location.lon, -0.1180);
+    }
+
+    fn current_fixture(body: &str) -> WeatherResponse {
+        serde_json::from_str(body).unwrap()
+    }
+
+    #[test]
+    fn current_weather_clear_fixture() {
+        let response = current_fixture(include_str!("../tests/fixtures/weather/owm_current_clear.json"));
+
+        assert_eq!(response.get_coordinates().get_lat(), 37.7749);
+        assert_eq!(response.get_timezone_offset(), -25200);
+        assert_eq!(response.get_city_id(), 5391959);
+        assert_eq!(response.get_city_name(), "San Francisco".to_string());
+        assert_eq!(response.get_country(), Some("US".to_string()));
+        assert!(response.get_rain_info().is_none());
+
+        let conditions = response.into_conditions(UnitSystem::Imperial);
+        assert_eq!(conditions.condition, Some("Clear".to_string()));
+        assert!((conditions.temperature.fahrenheit() - 64.2).abs() < 0.001);
+        assert_eq!(conditions.pressure_ground.map(Pressure::whole_hpa), Some(1012));
+        assert!((conditions.wind_gust.unwrap().mph() - 14.97).abs() < 0.001);
+        assert_eq!(conditions.rain_1h, None);
+    }
+
+    #[test]
+    fn current_weather_rain_fixture() {
+        let response = current_fixture(include_str!("../tests/fixtures/weather/owm_current_rain.json"));
+
+        assert_eq!(response.get_all_conditions().len(), 2);
+        assert_eq!(response.get_conditions_info().unwrap().get_condition_id(), 501);
+
+        let conditions = response.into_conditions(UnitSystem::Imperial);
+        assert_eq!(conditions.condition, Some("Rain".to_string()));
+        assert_eq!(conditions.rain_1h, Some(3.16f32 as f64));
+        assert_eq!(conditions.rain_3h, None);
+        assert_eq!(conditions.sunset, 1661882248);
+    }
+
+    #[test]
+    fn current_weather_snow_fixture() {
+        let response = current_fixture(include_str!("../tests/fixtures/weather/owm_current_snow.json"));
+
+        assert!(response.get_wind_info().get_wind_gust().is_none());
+
+        let conditions = response.into_conditions(UnitSystem::Imperial);
+        assert_eq!(conditions.condition, Some("Snow".to_string()));
+        assert_eq!(conditions.snow_1h, Some(1.27f32 as f64));
+        assert_eq!(conditions.snow_3h, Some(3.05f32 as f64));
+        assert_eq!(conditions.visibility, Some(1609));
+        assert_eq!(conditions.wind_gust, None);
+    }
+
+    #[test]
+    fn current_weather_missing_fields_fixture() {
+        let response = current_fixture(include_str!("../tests/fixtures/weather/owm_current_missing_fields.json"));
+
+        assert!(response.get_visibility().is_none());
+        assert!(response.get_country().is_none());
+        assert!(response.get_temp_info().get_ground_level_pressure().is_none());
+
+        let conditions = response.into_conditions(UnitSystem::Imperial);
+        assert_eq!(conditions.pressure_sea.whole_hpa(), 1021);
+        assert_eq!(conditions.pressure_ground, None);
+        assert_eq!(conditions.visibility, None);
+    }
+
+    #[test]
+    fn current_weather_without_conditions_leaves_them_out() {
+        let mut body: serde_json::Value = serde_json::from_str(include_str!("../tests/fixtures/weather/owm_current_clear.json")).unwrap();
+        body["weather"] = serde_json::json!([]);
+        let response: WeatherResponse = serde_json::from_value(body).unwrap();
+
+        let conditions = response.into_conditions(UnitSystem::Imperial);
+
+        assert_eq!((conditions.condition, conditions.description, conditions.icon), (None, None, None));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn air_pollution_fixture_has_coordinates() {
+        let response: AirPollutionResponse = serde_json::from_str(include_str!("../tests/fixtures/weather/owm_air_pollution.json")).unwrap();
+
+        assert_eq!(response.get_coordinates().get_lat(), 50.0);
+        assert_eq!(response.into_air_quality().len(), 1);
+    }
+
+    #[test]
+    fn geo_direct_fixture() {
+        let locations: Vec<GeoLocation> = serde_json::from_str(include_str!("../tests/fixtures/weather/owm_geo_direct.json")).unwrap();
+
+        assert_eq!(locations.len(), 1);
+        assert_eq!(locations[0].state, Some("England".to_string()));
+        assert!(locations[0].zip.is_none());
+    }
+
+    #[test]
+    fn geo_zip_fixture() {
+        let location: GeoLocation = serde_json::from_str(include_str!("../tests/fixtures/weather/owm_geo_zip.json")).unwrap();
+
+        assert_eq!(location.zip, Some("90210".to_string()));
+        assert_eq!(location.country, "US".to_string());
+    }
+
+    #[tokio::test]
+    async fn openweather_provider_current_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/data/2.5/weather")).and(query_param("units", "imperial"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../tests/fixtures/weather/owm_current_clear.json")))
+            .mount(&server).await;
+        let config = mock_configuration(&server).await;
+
+        let conditions = config.current().await.unwrap();
+
+        assert_eq!(conditions.calculated_at, 1697572800);
+        assert_eq!(conditions.humidity, 61);
+        assert!(conditions.comfort.is_some());
+    }
+}
@@ -0,0 +1,476 @@
+//! # Rusty Thermostat Weather Cache
+//! Keeps the latest outdoor conditions in memory so everything that needs them shares one set of API calls
+
+use std::{future::Future, sync::Arc, time::Duration};
+use async_trait::async_trait;
+use chrono::Utc;
+use sea_orm::{ColumnTrait, DatabaseConnection, EntityTrait, QueryFilter, QueryOrder, Select};
+use serde_derive::Serialize;
+use tokio::sync::Mutex;
+use crate::schema::{weather_reading, pollution_reading};
+use super::{WeatherProvider, WeatherError, Coordinates, CurrentConditions, ForecastPoint, AirQuality};
+
+/// Seconds current conditions stay fresh when no max-age is configured
+pub const DEFAULT_CURRENT_MAX_AGE: u64 = 600;
+/// Seconds air quality stays fresh when no max-age is configured
+pub const DEFAULT_AIR_QUALITY_MAX_AGE: u64 = 3600;
+// Degrees a stored reading's location may be off from the provider's and still count as the same place, about 100 m
+const LOCATION_TOLERANCE: f64 = 0.001;
+
+/// Where a cached value originally came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum CacheSource {
+    Provider,
+    Database,
+}
+
+/// A value handed out by the cache along with how old it is<br>
+/// A stale value is older than its max-age and could not be refreshed, so it is the best there is right now
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Cached<T> {
+    pub value: T,
+    // Time the value was fetched or stored in unix UTC
+    pub fetched_at: i64,
+    // Seconds since fetched_at
+    pub age: u64,
+    // Seconds the value is considered fresh for
+    pub max_age: u64,
+    pub stale: bool,
+    pub source: CacheSource,
+}
+
+impl<T> Cached<T> {
+    /// Swaps the value for something made from it, keeping the age details
+    pub fn map<U>(self, convert: impl FnOnce(T) -> U) -> Cached<U> {
+        Cached {
+            value: convert(self.value),
+            fetched_at: self.fetched_at,
+            age: self.age,
+            max_age: self.max_age,
+            stale: self.stale,
+            source: self.source,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CacheEntry<T> {
+    value: T,
+    fetched_at: i64,
+    source: CacheSource,
+}
+
+impl<T> CacheEntry<T> {
+    fn fetched_now(value: T) -> CacheEntry<T> {
+        CacheEntry { value, fetched_at: Utc::now().timestamp(), source: CacheSource::Provider }
+    }
+    fn age(&self) -> u64 {
+        Utc::now().timestamp().saturating_sub(self.fetched_at).max(0) as u64
+    }
+}
+
+// One cached value and how long it stays fresh. The entry lock is only held to read or swap the entry, a refresh
+// holds its own lock so callers share one API call: those with a stale value to serve get it straight away, and only
+// callers with nothing to serve wait for the refresh
+#[derive(Debug)]
+struct CacheSlot<T> {
+    entry: std::sync::Mutex<Option<CacheEntry<T>>>,
+    refreshing: Mutex<()>,
+    max_age: Duration,
+}
+
+impl<T: Clone> CacheSlot<T> {
+    fn new(max_age: Duration) -> CacheSlot<T> {
+        CacheSlot { entry: std::sync::Mutex::new(None), refreshing: Mutex::new(()), max_age }
+    }
+    fn hand_out(&self, entry: &CacheEntry<T>) -> Cached<T> {
+        let age: u64 = entry.age();
+        Cached {
+            value: entry.value.clone(),
+            fetched_at: entry.fetched_at,
+            age,
+            max_age: self.max_age.as_secs(),
+            stale: age >= self.max_age.as_secs(),
+            source: entry.source,
+        }
+    }
+    // Whatever is held right now, fresh or not
+    fn held(&self) -> Option<Cached<T>> {
+        self.entry.lock().unwrap().as_ref().map(|held| self.hand_out(held))
+    }
+    // Serves the entry if it is fresh, otherwise tries the fetch and falls back to whatever is already held
+    // An empty slot is first filled from the database so a restart does not cost an API call
+    async fn get<Fetch, Load>(&self, fetch: Fetch, load: Load) -> Result<Cached<T>, WeatherError>
+    where
+        Fetch: Future<Output = Result<T, WeatherError>>,
+        Load: Future<Output = Option<CacheEntry<T>>>,
+    {
+        if let Some(cached) = self.held().filter(|cached| !cached.stale) {
+            return Ok(cached)
+        }
+        let _refreshing = match self.refreshing.try_lock() {
+            Ok(guard) => guard,
+            Err(_) => match self.held() {
+                Some(stale) => {
+                    debug!("Cached weather is being refreshed, serving a value {} seconds old", stale.age);
+                    return Ok(stale)
+                },
+                None => self.refreshing.lock().await,
+            },
+        };
+        if self.entry.lock().unwrap().is_none() {
+            if let Some(loaded) = load.await {
+                self.entry.lock().unwrap().get_or_insert(loaded);
+            }
+        }
+        // The refresh this caller waited on may have left a fresh value
+        if let Some(cached) = self.held().filter(|cached| !cached.stale) {
+            return Ok(cached)
+        }
+        match fetch.await {
+            Ok(value) => {
+                let fresh: CacheEntry<T> = CacheEntry::fetched_now(value);
+                let cached: Cached<T> = self.hand_out(&fresh);
+                *self.entry.lock().unwrap() = Some(fresh);
+                Ok(cached)
+            },
+            Err(error) => match self.held() {
+                Some(stale) => {
+                    warn!("Unable to refresh cached weather, serving a value {} seconds old: {}", stale.age, error);
+                    Ok(stale)
+                },
+                None => Err(error),
+            },
+        }
+    }
+    // Stores a value fetched elsewhere as the newest entry
+    fn store(&self, value: T) {
+        *self.entry.lock().unwrap() = Some(CacheEntry::fetched_now(value));
+    }
+}
+
+/// Shared cache in front of a WeatherProvider<br>
+/// Readers use latest_current and latest_air_quality. The cache also implements WeatherProvider itself so the collector can
+/// hand it fresh values as they are polled
+#[derive(Debug)]
+pub struct WeatherCache {
+    provider: Arc<dyn WeatherProvider>,
+    current: CacheSlot<CurrentConditions>,
+    air_quality: CacheSlot<Vec<AirQuality>>,
+}
+
+impl WeatherCache {
+    /// Creates a cache with the default max-ages
+    pub fn new(provider: Arc<dyn WeatherProvider>) -> WeatherCache {
+        WeatherCache {
+            provider,
+            current: CacheSlot::new(Duration::from_secs(DEFAULT_CURRENT_MAX_AGE)),
+            air_quality: CacheSlot::new(Duration::from_secs(DEFAULT_AIR_QUALITY_MAX_AGE)),
+        }
+    }
+    /// Sets the seconds current conditions stay fresh
+    pub fn set_current_max_age(&mut self, seconds: u64) {
+        self.current.max_age = Duration::from_secs(seconds);
+    }
+    /// Sets the seconds air quality stays fresh
+    pub fn set_air_quality_max_age(&mut self, seconds: u64) {
+        self.air_quality.max_age = Duration::from_secs(seconds);
+    }
+    /// Gets the latest current conditions, only calling the provider when the cached value has expired<br>
+    /// After a restart the newest WeatherReading row stored for the provider's location is used until it expires
+    /// # Errors
+    /// Returns the provider's error only when there is nothing cached or stored to fall back on
+    pub async fn latest_current(&self, db: &DatabaseConnection) -> Result<Cached<CurrentConditions>, WeatherError> {
+        self.current.get(self.provider.current(), newest_weather_reading(db, self.provider.location())).await
+    }
+    /// Gets the latest air quality, only calling the provider when the cached value has expired<br>
+    /// After a restart the newest PollutionReading row stored for the provider's location is used until it expires
+    /// # Errors
+    /// Returns the provider's error only when there is nothing cached or stored to fall back on
+    pub async fn latest_air_quality(&self, db: &DatabaseConnection) -> Result<Cached<Vec<AirQuality>>, WeatherError> {
+        self.air_quality.get(self.provider.air_quality(), newest_pollution_reading(db, self.provider.location())).await
+    }
+}
+
+// Every fetch through the trait goes to the provider and refreshes the cache on success
+#[async_trait]
+impl WeatherProvider for WeatherCache {
+    fn name(&self) -> &'static str {
+        self.provider.name()
+    }
+    fn location(&self) -> Option<Coordinates> {
+        self.provider.location()
+    }
+    async fn current(&self) -> Result<CurrentConditions, WeatherError> {
+        let conditions: CurrentConditions = self.provider.current().await?;
+        self.current.store(conditions.clone());
+        Ok(conditions)
+    }
+    async fn forecast(&self) -> Result<Vec<ForecastPoint>, WeatherError> {
+        self.provider.forecast().await
+    }
+    async fn air_quality(&self) -> Result<Vec<AirQuality>, WeatherError> {
+        let readings: Vec<AirQuality> = self.provider.air_quality().await?;
+        self.air_quality.store(readings.clone());
+        Ok(readings)
+    }
+}
+
+// Narrows a query to rows stored for the location. Without one there is nothing to tell readings apart by
+fn stored_at<E: EntityTrait>(query: Select<E>, location: Option<Coordinates>, lat: E::Column, lon: E::Column) -> Select<E> {
+    match location {
+        Some(location) => query
+            .filter(lat.between(location.latitude() - LOCATION_TOLERANCE, location.latitude() + LOCATION_TOLERANCE))
+            .filter(lon.between(location.longitude() - LOCATION_TOLERANCE, location.longitude() + LOCATION_TOLERANCE)),
+        None => query,
+    }
+}
+
+async fn newest_weather_reading(db: &DatabaseConnection, location: Option<Coordinates>) -> Option<CacheEntry<CurrentConditions>> {
+    let query = stored_at(weather_reading::Entity::find(), location, weather_reading::Column::Lat, weather_reading::Column::Lon);
+    match query.order_by_desc(weather_reading::Column::Timestamp).one(db).await {
+        Ok(Some(reading)) => Some(CacheEntry {
+            fetched_at: reading.timestamp.and_utc().timestamp(),
+            value: reading.into(),
+            source: CacheSource::Database,
+        }),
+        Ok(None) => None,
+        Err(error) => {
+            warn!("Unable to load the newest weather reading: {}", error);
+            None
+        }
+    }
+}
+
+async fn newest_pollution_reading(db: &DatabaseConnection, location: Option<Coordinates>) -> Option<CacheEntry<Vec<AirQuality>>> {
+    let query = stored_at(pollution_reading::Entity::find(), location, pollution_reading::Column::Lat, pollution_reading::Column::Lon);
+    match query.order_by_desc(pollution_reading::Column::Timestamp).one(db).await {
+        Ok(Some(reading)) => Some(CacheEntry {
+            fetched_at: reading.timestamp.and_utc().timestamp(),
+            value: vec![reading.into()],
+            source: CacheSource::Database,
+        }),
+        Ok(None) => None,
+        Err(error) => {
+            warn!("Unable to load the newest air pollution reading: {}", error);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use chrono::NaiveDateTime;
+    use crate::units::{Temperature, Speed, Pressure};
+    use crate::dbman;
+    use sea_orm::{ActiveModelTrait, IntoActiveModel};
+
+    // Provider that counts calls and can be told to fail
+    #[derive(Debug, Default)]
+    struct CountingProvider {
+        calls: AtomicUsize,
+        failing: bool,
+        // Holds every current() call until notified
+        gate: Option<tokio::sync::Notify>,
+        location: Option<Coordinates>,
+    }
+
+    fn conditions(temperature: f64) -> CurrentConditions {
+        CurrentConditions { calculated_at: 1700000000, condition: Some("Clear".to_string()), description: Some("clear sky".to_string()),
+            icon: Some("01d".to_string()), temperature: Temperature::from_celsius(temperature), feels_like: Temperature::from_celsius(temperature),
+            humidity: 50, pressure_sea: Pressure::from_hpa(1015.0), pressure_ground: Some(Pressure::from_hpa(1010.0)), visibility: Some(10000),
+            wind_speed: Speed::from_meters_per_second(3.0), wind_deg: 180, wind_gust: Some(Speed::from_meters_per_second(4.0)), clouds: 0, rain_1h: None, rain_3h: None,
+            snow_1h: None, snow_3h: None, sunrise: 1699990000, sunset: 1700030000, comfort: None }
+    }
+
+    #[async_trait]
+    impl WeatherProvider for CountingProvider {
+        fn name(&self) -> &'static str {
+            "Counting"
+        }
+        fn location(&self) -> Option<Coordinates> {
+            self.location
+        }
+        async fn current(&self) -> Result<CurrentConditions, WeatherError> {
+            let call: usize = self.calls.fetch_add(1, Ordering::SeqCst);
+            if let Some(gate) = &self.gate {
+                gate.notified().await;
+            }
+            if self.failing {
+                return Err(WeatherError::Status { status: 503, body: "down".to_string() })
+            }
+            Ok(conditions(20.0 + call as f64))
+        }
+        async fn forecast(&self) -> Result<Vec<ForecastPoint>, WeatherError> {
+            Ok(Vec::new())
+        }
+        async fn air_quality(&self) -> Result<Vec<AirQuality>, WeatherError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing {
+                return Err(WeatherError::Status { status: 503, body: "down".to_string() })
+            }
+            Ok(Vec::new())
+        }
+    }
+
+    // In-memory database with the full schema and nothing stored
+    async fn empty_db() -> DatabaseConnection {
+        dbman::test_connection().await
+    }
+
+    async fn db_with_reading(timestamp: NaiveDateTime) -> DatabaseConnection {
+        let db: DatabaseConnection = empty_db().await;
+        stored_reading(timestamp).into_active_model().reset_all().insert(&db).await.unwrap();
+        db
+    }
+
+    fn stored_reading(timestamp: NaiveDateTime) -> weather_reading::Model {
+        weather_reading::Model { id: uuid::Uuid::now_v7(), timestamp, condition: Some("Rain".to_string()), description: Some("light rain".to_string()),
+            icon: Some("10d".to_string()), temp_real: Temperature::from_celsius(12.5), temp_feel: Temperature::from_celsius(11.0),
+            pressure_sea: 1008, humidity: 90, pressure_ground: Some(1001), visibility: Some(8000), wind_speed: Speed::from_meters_per_second(5.5), wind_deg: 200,
+            wind_gust: Some(Speed::from_meters_per_second(8.0)), rain1_h: Some(0.4), rain3_h: None, snow1_h: None,
+            snow3_h: None, clouds: 100, dt: 1700000000, sunrise: 1699990000, sunset: 1700030000,
+            dew_point: None, heat_index: None, wind_chill: None, humidex: None, absolute_humidity: None, lat: None, lon: None }
+    }
+
+    fn stored_air_quality(timestamp: NaiveDateTime, aqi: i32, location: Coordinates) -> pollution_reading::ActiveModel {
+        let mut reading = AirQuality { measured_at: timestamp.and_utc().timestamp(), aqi, co: Some(201.9), no: None, no2: Some(0.8),
+            o3: Some(68.7), so2: Some(0.6), pm2_5: Some(0.5), pm10: Some(0.5), nh3: None }.generate_db_model(Some(location));
+        reading.timestamp = sea_orm::ActiveValue::Set(timestamp);
+        reading
+    }
+
+    #[tokio::test]
+    async fn cache_serves_fresh_value_from_memory() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider::default());
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+        let db: DatabaseConnection = empty_db().await;
+
+        let first = cache.latest_current(&db).await.unwrap();
+        let second = cache.latest_current(&db).await.unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(first.value, second.value);
+        assert!(!second.stale);
+        assert_eq!(second.source, CacheSource::Provider);
+        assert_eq!(second.max_age, DEFAULT_CURRENT_MAX_AGE);
+    }
+
+    #[tokio::test]
+    async fn cache_refetches_expired_value() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider::default());
+        let mut cache: WeatherCache = WeatherCache::new(provider.clone());
+        cache.set_current_max_age(0);
+        let db: DatabaseConnection = empty_db().await;
+
+        cache.latest_current(&db).await.unwrap();
+        let second = cache.latest_current(&db).await.unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 2);
+        assert_eq!(second.value.temperature, Temperature::from_celsius(21.0));
+    }
+
+    #[tokio::test]
+    async fn cache_serves_stale_value_while_another_caller_refreshes() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider { gate: Some(tokio::sync::Notify::new()), ..Default::default() });
+        let mut cache: WeatherCache = WeatherCache::new(provider.clone());
+        cache.set_current_max_age(0);
+        cache.current.store(conditions(20.0));
+        let db: DatabaseConnection = empty_db().await;
+
+        // The first caller is stuck in the provider, the second is answered without waiting for it
+        let (refreshed, served) = tokio::join!(cache.latest_current(&db), async {
+            let served = cache.latest_current(&db).await;
+            provider.gate.as_ref().unwrap().notify_one();
+            served
+        });
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        let served = served.unwrap();
+        assert!(served.stale);
+        assert_eq!(served.value.temperature, Temperature::from_celsius(20.0));
+        assert_eq!(refreshed.unwrap().source, CacheSource::Provider);
+    }
+
+    #[tokio::test]
+    async fn cache_collector_fetch_refreshes_memory() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider::default());
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+        let db: DatabaseConnection = empty_db().await;
+
+        let polled: CurrentConditions = cache.current().await.unwrap();
+        let cached = cache.latest_current(&db).await.unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cached.value, polled);
+    }
+
+    #[tokio::test]
+    async fn cache_falls_back_to_newest_stored_reading() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider { failing: true, ..Default::default() });
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+        let stored_at: NaiveDateTime = Utc::now().naive_utc() - chrono::Duration::hours(2);
+        let db: DatabaseConnection = db_with_reading(stored_at).await;
+
+        let cached = cache.latest_current(&db).await.unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 1);
+        assert_eq!(cached.source, CacheSource::Database);
+        assert!(cached.stale);
+        assert_eq!(cached.value.temperature, Temperature::from_celsius(12.5));
+        assert_eq!(cached.value.rain_1h, Some(0.4));
+    }
+
+    #[tokio::test]
+    async fn cache_falls_back_to_readings_stored_for_its_own_location() {
+        let (home, cabin) = (Coordinates::new(51.5074, -0.1278), Coordinates::new(57.1497, -2.0943));
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider { failing: true, location: Some(home), ..Default::default() });
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+        let db: DatabaseConnection = empty_db().await;
+        let earlier: NaiveDateTime = Utc::now().naive_utc() - chrono::Duration::hours(3);
+        let later: NaiveDateTime = Utc::now().naive_utc() - chrono::Duration::hours(2);
+        stored_air_quality(earlier, 2, home).insert(&db).await.unwrap();
+        stored_air_quality(later, 5, cabin).insert(&db).await.unwrap();
+        let mut weather = stored_reading(earlier);
+        (weather.lat, weather.lon) = (Some(home.latitude()), Some(home.longitude()));
+        weather.into_active_model().reset_all().insert(&db).await.unwrap();
+        let mut weather = stored_reading(later);
+        (weather.lat, weather.lon, weather.temp_real) = (Some(cabin.latitude()), Some(cabin.longitude()), Temperature::from_celsius(3.0));
+        weather.into_active_model().reset_all().insert(&db).await.unwrap();
+
+        let air = cache.latest_air_quality(&db).await.unwrap();
+        assert_eq!(air.source, CacheSource::Database);
+        assert_eq!(air.value[0].aqi, 2);
+        assert_eq!(cache.latest_current(&db).await.unwrap().value.temperature, Temperature::from_celsius(12.5));
+
+        // Somewhere nothing was stored for has nothing to fall back on
+        let elsewhere: WeatherCache = WeatherCache::new(Arc::new(CountingProvider { failing: true, location: Some(Coordinates::new(40.7, -74.0)),
+            ..Default::default() }));
+        assert_eq!(elsewhere.latest_air_quality(&db).await.unwrap_err().status(), Some(503));
+    }
+
+    #[tokio::test]
+    async fn cache_fresh_stored_reading_saves_api_call() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider::default());
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+        let db: DatabaseConnection = db_with_reading(Utc::now().naive_utc()).await;
+
+        let cached = cache.latest_current(&db).await.unwrap();
+
+        assert_eq!(provider.calls.load(Ordering::SeqCst), 0);
+        assert!(!cached.stale);
+        assert_eq!(cached.source, CacheSource::Database);
+    }
+
+    #[tokio::test]
+    async fn cache_with_nothing_to_serve_returns_error() {
+        let provider: Arc<CountingProvider> = Arc::new(CountingProvider { failing: true, ..Default::default() });
+        let cache: WeatherCache = WeatherCache::new(provider.clone());
+
+        let error = cache.latest_current(&empty_db().await).await.unwrap_err();
+
+        assert_eq!(error.status(), Some(503));
+    }
+}
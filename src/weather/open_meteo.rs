@@ -0,0 +1,510 @@
+//! # Rusty Thermostat Open-Meteo Library
+//! A keyless WeatherProvider built on the Open-Meteo forecast and air quality APIs
+
+use async_trait::async_trait;
+use reqwest::{self, RequestBuilder};
+use serde_derive::Deserialize;
+use serde_json;
+use crate::units::{Temperature, Speed, Pressure, UnitSystem};
+use super::{WeatherError, Coordinates, GeoLocation, WeatherProvider, CurrentConditions, ForecastPoint, AirQuality};
+
+/// Values asked for when fetching current conditions
+const CURRENT_FIELDS: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,is_day,rain,showers,snowfall,weather_code,cloud_cover,pressure_msl,surface_pressure,wind_speed_10m,wind_direction_10m,wind_gusts_10m,visibility";
+/// Values asked for when fetching the hourly forecast
+const FORECAST_FIELDS: &str = "temperature_2m,relative_humidity_2m,apparent_temperature,is_day,precipitation_probability,rain,showers,snowfall,weather_code,cloud_cover,pressure_msl,surface_pressure,wind_speed_10m,wind_direction_10m,wind_gusts_10m,visibility";
+/// Values asked for when fetching air quality. Open-Meteo does not measure nitrogen monoxide
+const AIR_FIELDS: &str = "european_aqi,carbon_monoxide,nitrogen_dioxide,sulphur_dioxide,ozone,pm2_5,pm10,ammonia";
+/// Days of forecast to ask for, matching the OpenWeather 5 day forecast
+const FORECAST_DAYS: i32 = 5;
+/// Hours rolled into each ForecastPoint, matching the OpenWeather 3 hour forecast
+const FORECAST_STEP: usize = 3;
+/// OpenWeather caps visibility at 10km, so Open-Meteo readings are capped the same way
+const MAX_VISIBILITY: f64 = 10000.0;
+
+// Relevant information for building Open-Meteo URLs and containing the reqwest client are stored here
+#[derive(Debug, Clone)]
+pub struct OpenMeteo {
+    pub base_path: String,
+    pub air_quality_path: String,
+    pub user_agent: Option<String>,
+    pub client: reqwest::Client,
+    pub location: GeoLocation,
+    // Units asked for from the API. Responses are converted to the canonical units as they come in
+    pub units: UnitSystem,
+}
+
+impl OpenMeteo {
+    /// Creates an Open-Meteo provider for the given location using imperial units
+    pub fn new(location: GeoLocation) -> OpenMeteo {
+        OpenMeteo {
+            base_path: "https://api.open-meteo.com/".to_owned(),
+            air_quality_path: "https://air-quality-api.open-meteo.com/".to_owned(),
+            user_agent: Some("rusty_thermostat/0.0.1".to_owned()),
+            client: reqwest::Client::new(),
+            location,
+            units: UnitSystem::Imperial,
+        }
+    }
+    /// Accepts the same units as the OpenWeather Configuration. Anything else falls back to imperial
+    pub fn set_units(&mut self, set_units: &str) {
+        self.units = UnitSystem::parse(set_units);
+    }
+    // Open-Meteo has no Kelvin option, so standard units ask for Celsius like metric does
+    fn response_units(&self) -> UnitSystem {
+        match self.units {
+            UnitSystem::Imperial => UnitSystem::Imperial,
+            _ => UnitSystem::Metric,
+        }
+    }
+    fn unit_params(&self) -> &'static str {
+        match self.response_units() {
+            UnitSystem::Imperial => "temperature_unit=fahrenheit&wind_speed_unit=mph",
+            _ => "temperature_unit=celsius&wind_speed_unit=ms",
+        }
+    }
+    // Reads a temperature from a response
+    fn temperature(&self, value: f64) -> Temperature {
+        Temperature::from_units(value, self.response_units())
+    }
+    // Reads a wind speed from a response
+    fn speed(&self, value: f64) -> Speed {
+        Speed::from_units(value, self.response_units())
+    }
+    // Accepts a base path and URI to create the RequestBuilder using the object's already established client
+    fn build_request(&self, base_path: &str, uri: &str) -> RequestBuilder {
+        let total_url: String = format!("{0}{1}&latitude={2}&longitude={3}&timeformat=unixtime",
+            base_path, uri, self.location.lat, self.location.lon);
+        let mut req_builder: RequestBuilder = self.client.get(total_url);
+        if let Some(local_user_agent) = &self.user_agent {
+            req_builder = req_builder.header(reqwest::header::USER_AGENT, local_user_agent);
+        };
+        req_builder
+    }
+    // Takes a complete RequestBuilder and submits it to the API
+    // Open-Meteo reports problems as {"error": true, "reason": "..."} which is turned into WeatherError::Api
+    async fn execute_request(&self, final_request: RequestBuilder) -> Result<String, WeatherError> {
+        let web_response = final_request.send().await?;
+        let web_status = web_response.status();
+        let web_content = web_response.text().await?;
+        if web_status.is_success() {
+            Ok(web_content)
+        } else {
+            let status: u16 = web_status.as_u16();
+            match serde_json::from_str::<OpenMeteoError>(&web_content) {
+                Ok(api_error) => Err(WeatherError::Api { status, message: api_error.reason, parameters: None }),
+                Err(_) => Err(WeatherError::Status { status, body: web_content }),
+            }
+        }
+    }
+}
+
+// Open-Meteo error body
+#[derive(Debug, Deserialize)]
+struct OpenMeteoError {
+    reason: String,
+}
+
+// Response from the forecast API when asking for current conditions
+#[derive(Debug, Deserialize)]
+struct CurrentResponse {
+    current: CurrentValues,
+    daily: DailyValues,
+}
+
+#[derive(Debug, Deserialize)]
+struct CurrentValues {
+    time: i64,
+    temperature_2m: f64,
+    relative_humidity_2m: f64,
+    apparent_temperature: f64,
+    is_day: i32,
+    rain: Option<f64>,
+    showers: Option<f64>,
+    snowfall: Option<f64>,
+    weather_code: i32,
+    cloud_cover: f64,
+    pressure_msl: f64,
+    surface_pressure: f64,
+    wind_speed_10m: f64,
+    wind_direction_10m: f64,
+    wind_gusts_10m: f64,
+    visibility: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DailyValues {
+    sunrise: Vec<i64>,
+    sunset: Vec<i64>,
+}
+
+// Response from the forecast API when asking for the hourly forecast. Every field is a column of the same length as time
+#[derive(Debug, Deserialize)]
+struct ForecastResponse {
+    hourly: HourlyValues,
+}
+
+#[derive(Debug, Deserialize)]
+struct HourlyValues {
+    time: Vec<i64>,
+    temperature_2m: Vec<Option<f64>>,
+    relative_humidity_2m: Vec<Option<f64>>,
+    apparent_temperature: Vec<Option<f64>>,
+    is_day: Vec<Option<i32>>,
+    precipitation_probability: Vec<Option<f64>>,
+    rain: Vec<Option<f64>>,
+    showers: Vec<Option<f64>>,
+    snowfall: Vec<Option<f64>>,
+    weather_code: Vec<Option<i32>>,
+    cloud_cover: Vec<Option<f64>>,
+    pressure_msl: Vec<Option<f64>>,
+    surface_pressure: Vec<Option<f64>>,
+    wind_speed_10m: Vec<Option<f64>>,
+    wind_direction_10m: Vec<Option<f64>>,
+    wind_gusts_10m: Vec<Option<f64>>,
+    visibility: Vec<Option<f64>>,
+}
+
+// Response from the air quality API when asking for current values
+#[derive(Debug, Deserialize)]
+struct AirResponse {
+    current: AirValues,
+}
+
+#[derive(Debug, Deserialize)]
+struct AirValues {
+    time: i64,
+    european_aqi: Option<f64>,
+    carbon_monoxide: Option<f64>,
+    nitrogen_dioxide: Option<f64>,
+    sulphur_dioxide: Option<f64>,
+    ozone: Option<f64>,
+    pm2_5: Option<f64>,
+    pm10: Option<f64>,
+    ammonia: Option<f64>,
+}
+
+/// Translates a WMO weather code into an OpenWeather style condition, description and icon
+pub fn describe_weather_code(code: i32, is_day: bool) -> (String, String, String) {
+    let (condition, description, icon): (&str, &str, &str) = match code {
+        0 => ("Clear", "clear sky", "01"),
+        1 => ("Clouds", "mainly clear", "02"),
+        2 => ("Clouds", "partly cloudy", "03"),
+        3 => ("Clouds", "overcast", "04"),
+        45 => ("Fog", "fog", "50"),
+        48 => ("Fog", "depositing rime fog", "50"),
+        51 => ("Drizzle", "light drizzle", "09"),
+        53 => ("Drizzle", "moderate drizzle", "09"),
+        55 => ("Drizzle", "dense drizzle", "09"),
+        56 | 57 => ("Drizzle", "freezing drizzle", "09"),
+        61 => ("Rain", "slight rain", "10"),
+        63 => ("Rain", "moderate rain", "10"),
+        65 => ("Rain", "heavy rain", "10"),
+        66 | 67 => ("Rain", "freezing rain", "13"),
+        71 => ("Snow", "slight snow fall", "13"),
+        73 => ("Snow", "moderate snow fall", "13"),
+        75 => ("Snow", "heavy snow fall", "13"),
+        77 => ("Snow", "snow grains", "13"),
+        80 => ("Rain", "slight rain showers", "09"),
+        81 => ("Rain", "moderate rain showers", "09"),
+        82 => ("Rain", "violent rain showers", "09"),
+        85 => ("Snow", "slight snow showers", "13"),
+        86 => ("Snow", "heavy snow showers", "13"),
+        95 => ("Thunderstorm", "thunderstorm", "11"),
+        96 | 99 => ("Thunderstorm", "thunderstorm with hail", "11"),
+        _ => ("Unknown", "unknown conditions", "01"),
+    };
+    let day_night: &str = if is_day { "d" } else { "n" };
+    (condition.to_string(), description.to_string(), format!("{}{}", icon, day_night))
+}
+
+/// Translates the European AQI (0 to 100+) into the OpenWeather 1 to 5 scale which uses the same bands
+pub fn european_aqi_to_index(european_aqi: f64) -> i32 {
+    match european_aqi {
+        value if value < 20.0 => 1,
+        value if value < 40.0 => 2,
+        value if value < 60.0 => 3,
+        value if value < 80.0 => 4,
+        _ => 5,
+    }
+}
+
+// Open-Meteo reports no precipitation as 0 while OpenWeather leaves it out
+fn precipitation(amount: f64) -> Option<f64> {
+    if amount > 0.0 {
+        Some(amount)
+    } else {
+        None
+    }
+}
+
+// Snowfall comes back in cm and is stored in mm like OpenWeather
+fn snowfall_mm(snowfall_cm: f64) -> f64 {
+    snowfall_cm * 10.0
+}
+
+// Gets a value out of an hourly column, treating anything missing as None
+fn hourly<T: Copy>(column: &[Option<T>], index: usize) -> Option<T> {
+    column.get(index).copied().flatten()
+}
+
+// Sums an hourly column over a range of hours
+fn hourly_sum(column: &[Option<f64>], hours: std::ops::Range<usize>) -> f64 {
+    hours.filter_map(|index| hourly(column, index)).sum()
+}
+
+#[async_trait]
+impl WeatherProvider for OpenMeteo {
+    fn name(&self) -> &'static str {
+        "Open-Meteo"
+    }
+    fn location(&self) -> Option<Coordinates> {
+        Some(self.location.coordinates())
+    }
+    async fn current(&self) -> Result<CurrentConditions, WeatherError> {
+        let request_uri: String = format!("v1/forecast?current={}&daily=sunrise,sunset&forecast_days=1&{}", CURRENT_FIELDS, self.unit_params());
+        let current_request = self.build_request(&self.base_path, &request_uri);
+        let web_response = self.execute_request(current_request).await?;
+        let parsed: CurrentResponse = serde_json::from_str(&web_response)?;
+
+        let values: CurrentValues = parsed.current;
+        let (condition, description, icon) = describe_weather_code(values.weather_code, values.is_day == 1);
+        let rain: f64 = values.rain.unwrap_or_default() + values.showers.unwrap_or_default();
+        Ok(CurrentConditions {
+            calculated_at: values.time,
+            condition: Some(condition),
+            description: Some(description),
+            icon: Some(icon),
+            temperature: self.temperature(values.temperature_2m),
+            feels_like: self.temperature(values.apparent_temperature),
+            humidity: values.relative_humidity_2m.round() as i32,
+            pressure_sea: Pressure::from_hpa(values.pressure_msl),
+            pressure_ground: Some(Pressure::from_hpa(values.surface_pressure)),
+            visibility: values.visibility.map(|visibility| visibility.min(MAX_VISIBILITY).round() as i32),
+            wind_speed: self.speed(values.wind_speed_10m),
+            wind_deg: values.wind_direction_10m.round() as i32,
+            wind_gust: Some(self.speed(values.wind_gusts_10m)),
+            clouds: values.cloud_cover.round() as i32,
+            rain_1h: precipitation(rain),
+            rain_3h: None,
+            snow_1h: precipitation(snowfall_mm(values.snowfall.unwrap_or_default())),
+            snow_3h: None,
+            sunrise: parsed.daily.sunrise.first().copied().unwrap_or_default(),
+            sunset: parsed.daily.sunset.first().copied().unwrap_or_default(),
+            comfort: None,
+        }.with_comfort())
+    }
+    async fn forecast(&self) -> Result<Vec<ForecastPoint>, WeatherError> {
+        let request_uri: String = format!("v1/forecast?hourly={}&forecast_days={}&{}", FORECAST_FIELDS, FORECAST_DAYS, self.unit_params());
+        let forecast_request = self.build_request(&self.base_path, &request_uri);
+        let web_response = self.execute_request(forecast_request).await?;
+        let parsed: ForecastResponse = serde_json::from_str(&web_response)?;
+
+        // Hours are rolled up into 3 hour blocks. The first hour describes the block, precipitation is summed
+        let columns: HourlyValues = parsed.hourly;
+        let mut points: Vec<ForecastPoint> = Vec::new();
+        for start in (0..columns.time.len()).step_by(FORECAST_STEP) {
+            let end: usize = (start + FORECAST_STEP).min(columns.time.len());
+            let temperatures: Vec<f64> = (start..end).filter_map(|index| hourly(&columns.temperature_2m, index)).collect();
+            let (temperature, feels_like, code) = match (hourly(&columns.temperature_2m, start), hourly(&columns.apparent_temperature, start), hourly(&columns.weather_code, start)) {
+                (Some(temperature), Some(feels_like), Some(code)) => (temperature, feels_like, code),
+                _ => continue,
+            };
+            let temp_min: f64 = temperatures.iter().copied().fold(temperature, f64::min);
+            let temp_max: f64 = temperatures.iter().copied().fold(temperature, f64::max);
+            let pop: f64 = (start..end).filter_map(|index| hourly(&columns.precipitation_probability, index)).fold(0.0, f64::max);
+            let (condition, description, icon) = describe_weather_code(code, hourly(&columns.is_day, start).unwrap_or(1) == 1);
+            points.push(ForecastPoint {
+                forecast_time: columns.time[start],
+                condition,
+                description,
+                icon,
+                temperature: self.temperature(temperature),
+                feels_like: self.temperature(feels_like),
+                temp_min: self.temperature(temp_min),
+                temp_max: self.temperature(temp_max),
+                pressure_sea: Pressure::from_hpa(hourly(&columns.pressure_msl, start).unwrap_or_default()),
+                humidity: hourly(&columns.relative_humidity_2m, start).unwrap_or_default().round() as i32,
+                pressure_ground: hourly(&columns.surface_pressure, start).map(Pressure::from_hpa),
+                visibility: hourly(&columns.visibility, start).map(|visibility| visibility.min(MAX_VISIBILITY).round() as i32),
+                wind_speed: self.speed(hourly(&columns.wind_speed_10m, start).unwrap_or_default()),
+                wind_deg: hourly(&columns.wind_direction_10m, start).unwrap_or_default().round() as i32,
+                wind_gust: hourly(&columns.wind_gusts_10m, start).map(|gust| self.speed(gust)),
+                clouds: hourly(&columns.cloud_cover, start).unwrap_or_default().round() as i32,
+                pop: pop / 100.0,
+                rain_3h: precipitation(hourly_sum(&columns.rain, start..end) + hourly_sum(&columns.showers, start..end)),
+                snow_3h: precipitation(snowfall_mm(hourly_sum(&columns.snowfall, start..end))),
+            });
+        }
+        Ok(points)
+    }
+    async fn air_quality(&self) -> Result<Vec<AirQuality>, WeatherError> {
+        let request_uri: String = format!("v1/air-quality?current={}", AIR_FIELDS);
+        let air_request = self.build_request(&self.air_quality_path, &request_uri);
+        let web_response = self.execute_request(air_request).await?;
+        let parsed: AirResponse = serde_json::from_str(&web_response)?;
+
+        let values: AirValues = parsed.current;
+        let european_aqi: f64 = match values.european_aqi {
+            Some(aqi) => aqi,
+            None => return Ok(Vec::new()),
+        };
+        Ok(vec![AirQuality {
+            measured_at: values.time,
+            aqi: european_aqi_to_index(european_aqi),
+            co: values.carbon_monoxide,
+            no: None,
+            no2: values.nitrogen_dioxide,
+            o3: values.ozone,
+            so2: values.sulphur_dioxide,
+            pm2_5: values.pm2_5,
+            pm10: values.pm10,
+            nh3: values.ammonia,
+        }])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{MockServer, Mock, ResponseTemplate};
+    use wiremock::matchers::{method, path, query_param};
+
+    fn test_location() -> GeoLocation {
+        GeoLocation { zip: None, name: "Berlin".to_string(), lat: 52.52, lon: 13.41, country: "DE".to_string(), state: None }
+    }
+
+    async fn mock_provider(server: &MockServer) -> OpenMeteo {
+        let mut provider = OpenMeteo::new(test_location());
+        provider.base_path = format!("{}/", server.uri());
+        provider.air_quality_path = format!("{}/", server.uri());
+        provider
+    }
+
+    #[test]
+    fn describe_weather_code_clear_day() {
+        let (condition, description, icon) = describe_weather_code(0, true);
+
+        assert_eq!(condition, "Clear".to_string());
+        assert_eq!(description, "clear sky".to_string());
+        assert_eq!(icon, "01d".to_string());
+    }
+
+    #[test]
+    fn describe_weather_code_snow_night() {
+        let (condition, _, icon) = describe_weather_code(73, false);
+
+        assert_eq!(condition, "Snow".to_string());
+        assert_eq!(icon, "13n".to_string());
+    }
+
+    #[test]
+    fn european_aqi_bands() {
+        assert_eq!(european_aqi_to_index(5.0), 1);
+        assert_eq!(european_aqi_to_index(32.0), 2);
+        assert_eq!(european_aqi_to_index(59.9), 3);
+        assert_eq!(european_aqi_to_index(60.0), 4);
+        assert_eq!(european_aqi_to_index(140.0), 5);
+    }
+
+    #[test]
+    fn open_meteo_set_units_nonsense_is_imperial() {
+        let mut provider = OpenMeteo::new(test_location());
+
+        provider.set_units("absolutely hot garbage");
+
+        assert_eq!(provider.units, UnitSystem::Imperial);
+    }
+
+    #[tokio::test]
+    async fn open_meteo_current_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/v1/forecast")).and(query_param("temperature_unit", "celsius"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../../tests/fixtures/weather/open_meteo_current.json")))
+            .mount(&server).await;
+        let mut provider = mock_provider(&server).await;
+        provider.set_units("metric");
+
+        let current = provider.current().await.unwrap();
+
+        assert_eq!(current.calculated_at, 1700056800);
+        assert_eq!(current.condition, Some("Rain".to_string()));
+        assert_eq!(current.icon, Some("10d".to_string()));
+        assert_eq!(current.temperature, Temperature::from_celsius(8.6));
+        assert_eq!(current.humidity, 87);
+        assert_eq!(current.pressure_sea.whole_hpa(), 1003);
+        assert_eq!(current.visibility, Some(10000));
+        assert_eq!(current.rain_1h, Some(0.4));
+        assert_eq!(current.snow_1h, None);
+        assert_eq!(current.sunrise, 1700029920);
+    }
+
+    #[tokio::test]
+    async fn open_meteo_current_standard_units_asks_for_celsius() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/v1/forecast")).and(query_param("temperature_unit", "celsius"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../../tests/fixtures/weather/open_meteo_current.json")))
+            .mount(&server).await;
+        let mut provider = mock_provider(&server).await;
+        provider.set_units("standard");
+
+        let current = provider.current().await.unwrap();
+
+        assert!((current.temperature.kelvin() - 281.75).abs() < 0.001);
+    }
+
+    #[tokio::test]
+    async fn open_meteo_forecast_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/v1/forecast")).and(query_param("forecast_days", "5"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../../tests/fixtures/weather/open_meteo_forecast.json")))
+            .mount(&server).await;
+        let provider = mock_provider(&server).await;
+
+        let forecast = provider.forecast().await.unwrap();
+
+        assert_eq!(forecast.len(), 2);
+        assert_eq!(forecast[0].forecast_time, 1700049600);
+        assert!((forecast[0].temp_min.fahrenheit() - 46.2).abs() < 0.001);
+        assert!((forecast[0].temp_max.fahrenheit() - 48.1).abs() < 0.001);
+        assert_eq!(forecast[0].pop, 0.8);
+        assert_eq!(forecast[0].rain_3h, Some(1.5));
+        assert_eq!(forecast[1].condition, "Snow".to_string());
+        assert_eq!(forecast[1].snow_3h, Some(2.5));
+    }
+
+    #[tokio::test]
+    async fn open_meteo_air_quality_from_fixture() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/v1/air-quality"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(include_str!("../../tests/fixtures/weather/open_meteo_air_quality.json")))
+            .mount(&server).await;
+        let provider = mock_provider(&server).await;
+
+        let readings = provider.air_quality().await.unwrap();
+
+        assert_eq!(readings.len(), 1);
+        assert_eq!(readings[0].aqi, 2);
+        assert_eq!(readings[0].no, None);
+        assert_eq!(readings[0].nh3, None);
+        assert_eq!(readings[0].pm2_5, Some(8.1));
+    }
+
+    #[tokio::test]
+    async fn open_meteo_error_body_becomes_weather_error() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET")).and(path("/v1/forecast"))
+            .respond_with(ResponseTemplate::new(400).set_body_string(r#"{"error":true,"reason":"Latitude must be in range of -90 to 90°."}"#))
+            .mount(&server).await;
+        let provider = mock_provider(&server).await;
+
+        let error = provider.current().await.unwrap_err();
+
+        match error {
+            WeatherError::Api { status, message, .. } => {
+                assert_eq!(status, 400);
+                assert!(message.contains("Latitude"));
+            },
+            other => panic!("Expected an API error, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,395 @@
+//! # Rusty Thermostat Weather Providers
+//! The WeatherProvider trait and the normalized data every provider hands back, no matter how its API is laid out
+
+use std::fmt;
+use async_trait::async_trait;
+use chrono::{DateTime, NaiveDateTime, Utc};
+use sea_orm::ActiveValue::{Set, NotSet};
+use serde_derive::{Serialize, Deserialize};
+use uuid::Uuid;
+use crate::schema::{weather_reading, pollution_reading, forecast_reading};
+use crate::psychrometrics::ComfortMetrics;
+use crate::units::{Temperature, Speed, Pressure, UnitSystem};
+use super::{Coordinates, WeatherError};
+
+/// Anything that can tell us the current weather, the forecast and the air quality for the configured location
+#[async_trait]
+pub trait WeatherProvider: fmt::Debug + Send + Sync {
+    /// Short name of the provider for logging
+    fn name(&self) -> &'static str;
+    /// Where readings are fetched for, None while no location is configured
+    fn location(&self) -> Option<Coordinates>;
+    /// Fetch the current weather conditions
+    async fn current(&self) -> Result<CurrentConditions, WeatherError>;
+    /// Fetch the upcoming forecast, earliest entry first
+    async fn forecast(&self) -> Result<Vec<ForecastPoint>, WeatherError>;
+    /// Fetch the current air quality. Some providers return more than one reading
+    async fn air_quality(&self) -> Result<Vec<AirQuality>, WeatherError>;
+}
+
+/// Which WeatherProvider to use, as set in the weather settings
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ProviderKind {
+    #[default]
+    OpenWeather,
+    OpenMeteo,
+}
+
+impl ProviderKind {
+    /// Reads the provider from a settings string. Anything unknown falls back to OpenWeather
+    pub fn parse(provider: &str) -> ProviderKind {
+        match provider.to_lowercase().replace(['-', '_', ' '], "").as_str() {
+            "openmeteo" => ProviderKind::OpenMeteo,
+            "openweather" | "openweathermap" | "owm" => ProviderKind::OpenWeather,
+            _ => {
+                warn!("Unknown weather provider {}, using OpenWeather", provider);
+                ProviderKind::OpenWeather
+            }
+        }
+    }
+}
+
+/// Current weather conditions. Providers convert into the canonical units when they build this
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CurrentConditions {
+    // Time the provider calculated the conditions in unix UTC
+    pub calculated_at: i64,
+    // Group of weather parameters (Rain, Snow, Clouds etc.), None along with the description and icon when the
+    // provider sent no condition
+    pub condition: Option<String>,
+    // Weather condition within the group
+    pub description: Option<String>,
+    // OpenWeather style icon id
+    pub icon: Option<String>,
+    pub temperature: Temperature,
+    pub feels_like: Temperature,
+    // Humidity in percentage
+    pub humidity: i32,
+    // Atmospheric pressure at sea level
+    pub pressure_sea: Pressure,
+    // Atmospheric pressure at ground level, None when the provider did not report it
+    pub pressure_ground: Option<Pressure>,
+    // Visibility in meters, None when the provider did not report it
+    pub visibility: Option<i32>,
+    pub wind_speed: Speed,
+    // Wind direction, degrees (meteorological)
+    pub wind_deg: i32,
+    // None when the provider did not report a gust
+    pub wind_gust: Option<Speed>,
+    // Cloudiness percentage
+    pub clouds: i32,
+    // Rain and snow accumulation in mm
+    pub rain_1h: Option<f64>,
+    pub rain_3h: Option<f64>,
+    pub snow_1h: Option<f64>,
+    pub snow_3h: Option<f64>,
+    // Timing of the sunrise and sunset in unix UTC
+    pub sunrise: i64,
+    pub sunset: i64,
+    // Dew point, heat index and the like worked out from the conditions above
+    pub comfort: Option<ComfortMetrics>,
+}
+
+impl CurrentConditions {
+    // Works out the comfort metrics for the conditions
+    pub fn with_comfort(mut self) -> CurrentConditions {
+        self.comfort = Some(ComfortMetrics::outdoor(self.temperature, self.humidity.into(), self.wind_speed));
+        self
+    }
+    /// Gets the conditions as plain numbers in the unit system, for handing out through the API
+    pub fn report(&self, units: UnitSystem) -> ConditionsReport {
+        ConditionsReport {
+            units,
+            calculated_at: self.calculated_at,
+            condition: self.condition.clone(),
+            description: self.description.clone(),
+            icon: self.icon.clone(),
+            temperature: self.temperature.in_units(units),
+            feels_like: self.feels_like.in_units(units),
+            humidity: self.humidity,
+            pressure_sea: self.pressure_sea.in_units(units),
+            pressure_ground: self.pressure_ground.map(|pressure| pressure.in_units(units)),
+            visibility: self.visibility,
+            wind_speed: self.wind_speed.in_units(units),
+            wind_deg: self.wind_deg,
+            wind_gust: self.wind_gust.map(|gust| gust.in_units(units)),
+            clouds: self.clouds,
+            rain_1h: self.rain_1h,
+            rain_3h: self.rain_3h,
+            snow_1h: self.snow_1h,
+            snow_3h: self.snow_3h,
+            sunrise: self.sunrise,
+            sunset: self.sunset,
+            dew_point: self.comfort.map(|metrics| metrics.dew_point.in_units(units)),
+            heat_index: self.comfort.map(|metrics| metrics.heat_index.in_units(units)),
+            wind_chill: self.comfort.map(|metrics| metrics.wind_chill.in_units(units)),
+            humidex: self.comfort.map(|metrics| metrics.humidex.in_units(units)),
+            absolute_humidity: self.comfort.map(|metrics| metrics.absolute_humidity),
+        }
+    }
+    // Consumes the CurrentConditions taken at the location into an ActiveModel to be put into the DB
+    pub fn generate_db_model(self, location: Option<Coordinates>) -> weather_reading::ActiveModel {
+        weather_reading::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            timestamp: Set(Utc::now().naive_utc()),
+            condition: Set(self.condition),
+            description: Set(self.description),
+            icon: Set(self.icon),
+            temp_real: Set(self.temperature),
+            temp_feel: Set(self.feels_like),
+            pressure_sea: Set(self.pressure_sea.whole_hpa()),
+            humidity: Set(self.humidity),
+            pressure_ground: Set(self.pressure_ground.map(Pressure::whole_hpa)),
+            visibility: Set(self.visibility),
+            wind_speed: Set(self.wind_speed),
+            wind_deg: Set(self.wind_deg),
+            wind_gust: Set(self.wind_gust),
+            rain1_h: Set(self.rain_1h),
+            rain3_h: Set(self.rain_3h),
+            snow1_h: Set(self.snow_1h),
+            snow3_h: Set(self.snow_3h),
+            clouds: Set(self.clouds),
+            dt: Set(self.calculated_at as i32),
+            sunrise: Set(self.sunrise as i32),
+            sunset: Set(self.sunset as i32),
+            dew_point: Set(self.comfort.map(|metrics| metrics.dew_point)),
+            heat_index: Set(self.comfort.map(|metrics| metrics.heat_index)),
+            wind_chill: Set(self.comfort.map(|metrics| metrics.wind_chill)),
+            humidex: Set(self.comfort.map(|metrics| metrics.humidex)),
+            absolute_humidity: Set(self.comfort.map(|metrics| metrics.absolute_humidity)),
+            lat: Set(location.map(Coordinates::latitude)),
+            lon: Set(location.map(Coordinates::longitude)),
+        }
+    }
+}
+
+// Rebuilds the conditions from a stored WeatherReading, used when nothing newer is in memory
+impl From<weather_reading::Model> for CurrentConditions {
+    fn from(reading: weather_reading::Model) -> Self {
+        let comfort: Option<ComfortMetrics> = stored_comfort(&reading);
+        CurrentConditions {
+            calculated_at: reading.dt as i64,
+            condition: reading.condition,
+            description: reading.description,
+            icon: reading.icon,
+            temperature: reading.temp_real,
+            feels_like: reading.temp_feel,
+            humidity: reading.humidity,
+            pressure_sea: reading.pressure_sea.into(),
+            pressure_ground: reading.pressure_ground.map(Pressure::from),
+            visibility: reading.visibility,
+            wind_speed: reading.wind_speed,
+            wind_deg: reading.wind_deg,
+            wind_gust: reading.wind_gust,
+            clouds: reading.clouds,
+            rain_1h: reading.rain1_h,
+            rain_3h: reading.rain3_h,
+            snow_1h: reading.snow1_h,
+            snow_3h: reading.snow3_h,
+            sunrise: reading.sunrise as i64,
+            sunset: reading.sunset as i64,
+            comfort,
+        }
+    }
+}
+
+/// Current weather conditions in one unit system, the way the API hands them out<br>
+/// Pressures are in inches of mercury for imperial and hectopascals otherwise
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ConditionsReport {
+    pub units: UnitSystem,
+    pub calculated_at: i64,
+    pub condition: Option<String>,
+    pub description: Option<String>,
+    pub icon: Option<String>,
+    pub temperature: f64,
+    pub feels_like: f64,
+    pub humidity: i32,
+    pub pressure_sea: f64,
+    pub pressure_ground: Option<f64>,
+    pub visibility: Option<i32>,
+    pub wind_speed: f64,
+    pub wind_deg: i32,
+    pub wind_gust: Option<f64>,
+    pub clouds: i32,
+    pub rain_1h: Option<f64>,
+    pub rain_3h: Option<f64>,
+    pub snow_1h: Option<f64>,
+    pub snow_3h: Option<f64>,
+    pub sunrise: i64,
+    pub sunset: i64,
+    pub dew_point: Option<f64>,
+    pub heat_index: Option<f64>,
+    pub wind_chill: Option<f64>,
+    pub humidex: Option<f64>,
+    pub absolute_humidity: Option<f64>,
+}
+
+/// A single forecast entry. Providers convert into the canonical units when they build this
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ForecastPoint {
+    // Time being forecasted in unix UTC
+    pub forecast_time: i64,
+    pub condition: String,
+    pub description: String,
+    pub icon: String,
+    pub temperature: Temperature,
+    pub feels_like: Temperature,
+    pub temp_min: Temperature,
+    pub temp_max: Temperature,
+    pub pressure_sea: Pressure,
+    pub humidity: i32,
+    pub pressure_ground: Option<Pressure>,
+    pub visibility: Option<i32>,
+    pub wind_speed: Speed,
+    pub wind_deg: i32,
+    pub wind_gust: Option<Speed>,
+    pub clouds: i32,
+    // Probability of precipitation from 0 to 1
+    pub pop: f64,
+    // Rain and snow accumulation over 3 hours in mm
+    pub rain_3h: Option<f64>,
+    pub snow_3h: Option<f64>,
+}
+
+impl ForecastPoint {
+    // Consumes the ForecastPoint into an ActiveModel to be put into the DB
+    // Points without a usable time are skipped
+    pub fn generate_db_model(self, fetched_at: NaiveDateTime) -> Option<forecast_reading::ActiveModel> {
+        let forecast_time = DateTime::from_timestamp(self.forecast_time, 0)?.naive_utc();
+        Some(forecast_reading::ActiveModel {
+            id: NotSet,
+            fetched_at: Set(fetched_at),
+            forecast_time: Set(forecast_time),
+            condition: Set(self.condition),
+            description: Set(self.description),
+            icon: Set(self.icon),
+            temp_real: Set(self.temperature),
+            temp_feel: Set(self.feels_like),
+            temp_min: Set(self.temp_min),
+            temp_max: Set(self.temp_max),
+            pressure_sea: Set(self.pressure_sea.whole_hpa()),
+            humidity: Set(self.humidity),
+            pressure_ground: Set(self.pressure_ground.map(Pressure::whole_hpa)),
+            visibility: Set(self.visibility),
+            wind_speed: Set(self.wind_speed),
+            wind_deg: Set(self.wind_deg),
+            wind_gust: Set(self.wind_gust),
+            clouds: Set(self.clouds),
+            pop: Set(self.pop),
+            rain3_h: Set(self.rain_3h),
+            snow3_h: Set(self.snow_3h),
+        })
+    }
+}
+
+/// Consumes a whole forecast into ActiveModels that share one fetched_at so the snapshot can be pulled back out together
+pub fn generate_forecast_models(points: Vec<ForecastPoint>) -> Vec<forecast_reading::ActiveModel> {
+    let fetched_at = Utc::now().naive_utc();
+    points.into_iter().filter_map(|point| point.generate_db_model(fetched_at)).collect()
+}
+
+/// Air quality reading. Pollutant amounts are in μg/m3 and are None when the provider does not measure them
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AirQuality {
+    // Time the reading was calculated for in unix UTC
+    pub measured_at: i64,
+    // Air Quality Index on the OpenWeather 1 (good) to 5 (very poor) scale
+    pub aqi: i32,
+    pub co: Option<f64>,
+    pub no: Option<f64>,
+    pub no2: Option<f64>,
+    pub o3: Option<f64>,
+    pub so2: Option<f64>,
+    pub pm2_5: Option<f64>,
+    pub pm10: Option<f64>,
+    pub nh3: Option<f64>,
+}
+
+impl AirQuality {
+    // Consumes the AirQuality taken at the location into an ActiveModel to be put into the DB
+    pub fn generate_db_model(self, location: Option<Coordinates>) -> pollution_reading::ActiveModel {
+        let timestamp = match DateTime::from_timestamp(self.measured_at, 0) {
+            Some(calculated) => calculated.naive_utc(),
+            None => Utc::now().naive_utc(),
+        };
+        pollution_reading::ActiveModel {
+            id: Set(Uuid::now_v7()),
+            timestamp: Set(timestamp),
+            aqi: Set(self.aqi),
+            co: Set(self.co),
+            no: Set(self.no),
+            no2: Set(self.no2),
+            o3: Set(self.o3),
+            so2: Set(self.so2),
+            pm2_5: Set(self.pm2_5),
+            pm10: Set(self.pm10),
+            nh3: Set(self.nh3),
+            lat: Set(location.map(Coordinates::latitude)),
+            lon: Set(location.map(Coordinates::longitude)),
+        }
+    }
+}
+
+// Rows stored before the comfort metrics existed have none of them
+fn stored_comfort(reading: &weather_reading::Model) -> Option<ComfortMetrics> {
+    Some(ComfortMetrics {
+        dew_point: reading.dew_point?,
+        heat_index: reading.heat_index?,
+        wind_chill: reading.wind_chill?,
+        humidex: reading.humidex?,
+        absolute_humidity: reading.absolute_humidity?,
+    })
+}
+
+// Rebuilds the air quality from a stored PollutionReading
+impl From<pollution_reading::Model> for AirQuality {
+    fn from(reading: pollution_reading::Model) -> Self {
+        AirQuality {
+            measured_at: reading.timestamp.and_utc().timestamp(),
+            aqi: reading.aqi,
+            co: reading.co,
+            no: reading.no,
+            no2: reading.no2,
+            o3: reading.o3,
+            so2: reading.so2,
+            pm2_5: reading.pm2_5,
+            pm10: reading.pm10,
+            nh3: reading.nh3,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn provider_kind_parses_open_meteo() {
+        assert_eq!(ProviderKind::parse("open-meteo"), ProviderKind::OpenMeteo);
+        assert_eq!(ProviderKind::parse("OpenMeteo"), ProviderKind::OpenMeteo);
+    }
+
+    #[test]
+    fn provider_kind_parses_openweather() {
+        assert_eq!(ProviderKind::parse("openweather"), ProviderKind::OpenWeather);
+        assert_eq!(ProviderKind::parse("OWM"), ProviderKind::OpenWeather);
+    }
+
+    #[test]
+    fn provider_kind_nonsense_is_openweather() {
+        assert_eq!(ProviderKind::parse("absolutely hot garbage"), ProviderKind::OpenWeather);
+    }
+
+    #[test]
+    fn air_quality_missing_pollutants_stored_as_none() {
+        let reading = AirQuality { measured_at: 1700000000, aqi: 2, co: Some(210.0), no: None, no2: Some(12.3),
+            o3: Some(40.0), so2: Some(1.2), pm2_5: Some(8.1), pm10: Some(11.0), nh3: None };
+
+        let model = reading.generate_db_model(Some(Coordinates::new(51.5, -0.12)));
+
+        assert_eq!(model.no, Set(None));
+        assert_eq!(model.lat, Set(Some(51.5)));
+        assert_eq!(model.co, Set(Some(210.0)));
+    }
+}
@@ -0,0 +1,222 @@
+//! # Rusty Thermostat API Quota
+//! Counts calls made to a rate limited weather API so a poll can be skipped instead of burning the key
+
+use std::{collections::VecDeque, fmt, sync::Mutex, time::{Duration, Instant}};
+use chrono::{NaiveDate, Utc};
+use serde_derive::Serialize;
+
+/// Calls per minute allowed on a free OpenWeather key
+pub const DEFAULT_PER_MINUTE: u32 = 60;
+/// Calls per day allowed on a free OpenWeather key
+pub const DEFAULT_PER_DAY: u32 = 1000;
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+/// Which quota window ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum QuotaWindow {
+    Minute,
+    Day,
+}
+
+impl fmt::Display for QuotaWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            QuotaWindow::Minute => write!(f, "minute"),
+            QuotaWindow::Day => write!(f, "day"),
+        }
+    }
+}
+
+/// Tracks calls per minute and per day against configured limits<br>
+/// The minute is a sliding window. The day follows the UTC calendar the same way OpenWeather does
+#[derive(Debug)]
+pub struct ApiQuota {
+    per_minute: Option<u32>,
+    per_day: Option<u32>,
+    usage: Mutex<QuotaUsage>,
+}
+
+#[derive(Debug)]
+struct QuotaUsage {
+    recent_calls: VecDeque<Instant>,
+    day: NaiveDate,
+    day_calls: u32,
+    total_calls: u64,
+    skipped_calls: u64,
+    rate_limited: u64,
+}
+
+/// Point in time copy of the quota counters, ready to be shown through the API
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct QuotaSnapshot {
+    pub per_minute_limit: Option<u32>,
+    pub per_day_limit: Option<u32>,
+    pub calls_last_minute: u32,
+    pub calls_today: u32,
+    pub total_calls: u64,
+    pub skipped_calls: u64,
+    pub rate_limited: u64,
+}
+
+impl ApiQuota {
+    /// Creates a quota with the given limits. None means that window is not limited
+    pub fn new(per_minute: Option<u32>, per_day: Option<u32>) -> ApiQuota {
+        ApiQuota {
+            per_minute,
+            per_day,
+            usage: Mutex::new(QuotaUsage {
+                recent_calls: VecDeque::new(),
+                day: Utc::now().date_naive(),
+                day_calls: 0,
+                total_calls: 0,
+                skipped_calls: 0,
+                rate_limited: 0,
+            }),
+        }
+    }
+    /// Creates a quota that never runs out but still counts calls
+    pub fn unlimited() -> ApiQuota {
+        ApiQuota::new(None, None)
+    }
+    /// Records a call if there is room in both windows<br>
+    /// # Errors
+    /// Returns the window that ran out. The call is counted as skipped, not made
+    pub fn try_acquire(&self) -> Result<(), QuotaWindow> {
+        self.try_acquire_at(Instant::now(), Utc::now().date_naive())
+    }
+    fn try_acquire_at(&self, now: Instant, today: NaiveDate) -> Result<(), QuotaWindow> {
+        let mut usage = self.usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        usage.roll(now, today);
+        if let Some(limit) = self.per_minute {
+            if usage.recent_calls.len() as u32 >= limit {
+                usage.skipped_calls += 1;
+                return Err(QuotaWindow::Minute)
+            }
+        }
+        if let Some(limit) = self.per_day {
+            if usage.day_calls >= limit {
+                usage.skipped_calls += 1;
+                return Err(QuotaWindow::Day)
+            }
+        }
+        usage.recent_calls.push_back(now);
+        usage.day_calls += 1;
+        usage.total_calls += 1;
+        Ok(())
+    }
+    /// Records that the API answered with 429 Too Many Requests
+    pub fn record_rate_limited(&self) {
+        let mut usage = self.usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        usage.rate_limited += 1;
+    }
+    /// Gets a copy of the current counters
+    pub fn snapshot(&self) -> QuotaSnapshot {
+        self.snapshot_at(Instant::now(), Utc::now().date_naive())
+    }
+    fn snapshot_at(&self, now: Instant, today: NaiveDate) -> QuotaSnapshot {
+        let mut usage = self.usage.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        usage.roll(now, today);
+        QuotaSnapshot {
+            per_minute_limit: self.per_minute,
+            per_day_limit: self.per_day,
+            calls_last_minute: usage.recent_calls.len() as u32,
+            calls_today: usage.day_calls,
+            total_calls: usage.total_calls,
+            skipped_calls: usage.skipped_calls,
+            rate_limited: usage.rate_limited,
+        }
+    }
+}
+
+impl QuotaUsage {
+    // Drops calls that have left the minute window and resets the day count when the date changes
+    fn roll(&mut self, now: Instant, today: NaiveDate) {
+        while let Some(oldest) = self.recent_calls.front() {
+            if now.duration_since(*oldest) >= MINUTE {
+                self.recent_calls.pop_front();
+            } else {
+                break
+            }
+        }
+        if today != self.day {
+            self.day = today;
+            self.day_calls = 0;
+        }
+    }
+}
+
+impl Default for ApiQuota {
+    fn default() -> Self {
+        ApiQuota::new(Some(DEFAULT_PER_MINUTE), Some(DEFAULT_PER_DAY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn day(offset: u64) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, 1, 1).unwrap() + chrono::Days::new(offset)
+    }
+
+    #[test]
+    fn quota_default_is_free_tier() {
+        let snapshot = ApiQuota::default().snapshot();
+
+        assert_eq!(snapshot.per_minute_limit, Some(DEFAULT_PER_MINUTE));
+        assert_eq!(snapshot.per_day_limit, Some(DEFAULT_PER_DAY));
+    }
+
+    #[test]
+    fn quota_minute_limit_skips_call() {
+        let quota = ApiQuota::new(Some(2), None);
+        let start = Instant::now();
+
+        assert!(quota.try_acquire_at(start, day(0)).is_ok());
+        assert!(quota.try_acquire_at(start, day(0)).is_ok());
+        assert_eq!(quota.try_acquire_at(start, day(0)), Err(QuotaWindow::Minute));
+
+        let snapshot = quota.snapshot_at(start, day(0));
+        assert_eq!(snapshot.calls_last_minute, 2);
+        assert_eq!(snapshot.skipped_calls, 1);
+    }
+
+    #[test]
+    fn quota_minute_window_slides() {
+        let quota = ApiQuota::new(Some(1), None);
+        let start = Instant::now();
+
+        assert!(quota.try_acquire_at(start, day(0)).is_ok());
+        assert!(quota.try_acquire_at(start + Duration::from_secs(30), day(0)).is_err());
+        assert!(quota.try_acquire_at(start + Duration::from_secs(60), day(0)).is_ok());
+    }
+
+    #[test]
+    fn quota_day_limit_resets_next_day() {
+        let quota = ApiQuota::new(None, Some(1));
+        let start = Instant::now();
+
+        assert!(quota.try_acquire_at(start, day(0)).is_ok());
+        assert_eq!(quota.try_acquire_at(start, day(0)), Err(QuotaWindow::Day));
+        assert!(quota.try_acquire_at(start, day(1)).is_ok());
+
+        let snapshot = quota.snapshot_at(start, day(1));
+        assert_eq!(snapshot.calls_today, 1);
+        assert_eq!(snapshot.total_calls, 2);
+    }
+
+    #[test]
+    fn quota_unlimited_counts_calls() {
+        let quota = ApiQuota::unlimited();
+
+        for _ in 0..100 {
+            assert!(quota.try_acquire().is_ok());
+        }
+        quota.record_rate_limited();
+
+        let snapshot = quota.snapshot();
+        assert_eq!(snapshot.total_calls, 100);
+        assert_eq!(snapshot.rate_limited, 1);
+    }
+}
@@ -0,0 +1,130 @@
+//! # Rusty Thermostat Retry Policy
+//! Decides if and when a failed weather API call should be tried again
+
+use std::time::Duration;
+use rand::Rng;
+use super::WeatherError;
+
+/// Attempts made for a single request when no policy is configured, including the first
+pub const DEFAULT_ATTEMPTS: u32 = 3;
+
+/// Retries failed requests with jittered exponential backoff<br>
+/// Dropped connections, timeouts, server errors and 429 responses are retried. Anything else is returned right away
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// Creates a policy that tries each request up to max_attempts times
+    pub fn new(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy { max_attempts: max_attempts.max(1), ..RetryPolicy::default() }
+    }
+    /// Creates a policy that never retries
+    pub fn none() -> RetryPolicy {
+        RetryPolicy::new(1)
+    }
+    /// Gets how long to wait before trying again after the given attempt failed, starting at 1<br>
+    /// None means the request should not be tried again
+    pub fn delay_for(&self, error: &WeatherError, attempt: u32) -> Option<Duration> {
+        if attempt >= self.max_attempts {
+            return None
+        }
+        match error {
+            WeatherError::RateLimited { retry_after: Some(wait), .. } => {
+                // A server asking for a longer wait than we would ever back off is better skipped until the next poll
+                if *wait <= self.max_delay {
+                    Some(*wait)
+                } else {
+                    None
+                }
+            },
+            WeatherError::RateLimited { retry_after: None, .. } => Some(self.backoff(attempt)),
+            WeatherError::Transport(_) => Some(self.backoff(attempt)),
+            WeatherError::Status { status, .. } | WeatherError::Api { status, .. } if *status >= 500 => Some(self.backoff(attempt)),
+            _ => None,
+        }
+    }
+    // Doubles the base delay for every attempt, caps it at max_delay, then picks a random point in the top half
+    fn backoff(&self, attempt: u32) -> Duration {
+        let exponent: u32 = attempt.saturating_sub(1).min(16);
+        let ceiling: Duration = self.base_delay.saturating_mul(1 << exponent).min(self.max_delay);
+        let half: Duration = ceiling / 2;
+        let jitter_millis: u64 = rand::thread_rng().gen_range(0..=half.as_millis() as u64);
+        half + Duration::from_millis(jitter_millis)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: DEFAULT_ATTEMPTS,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn server_error() -> WeatherError {
+        WeatherError::Status { status: 503, body: "Service Unavailable".to_string() }
+    }
+
+    #[test]
+    fn retry_policy_gives_up_after_max_attempts() {
+        let policy = RetryPolicy::new(3);
+
+        assert!(policy.delay_for(&server_error(), 1).is_some());
+        assert!(policy.delay_for(&server_error(), 2).is_some());
+        assert!(policy.delay_for(&server_error(), 3).is_none());
+    }
+
+    #[test]
+    fn retry_policy_none_never_retries() {
+        assert!(RetryPolicy::none().delay_for(&server_error(), 1).is_none());
+    }
+
+    #[test]
+    fn retry_policy_does_not_retry_client_errors() {
+        let policy = RetryPolicy::default();
+        let error = WeatherError::Api { status: 401, message: "Invalid API key".to_string(), parameters: None };
+
+        assert!(policy.delay_for(&error, 1).is_none());
+        assert!(policy.delay_for(&WeatherError::MissingConfig("location".to_string()), 1).is_none());
+    }
+
+    #[test]
+    fn retry_policy_honors_retry_after() {
+        let policy = RetryPolicy::default();
+        let error = WeatherError::RateLimited { retry_after: Some(Duration::from_secs(7)), message: String::new() };
+
+        assert_eq!(policy.delay_for(&error, 1), Some(Duration::from_secs(7)));
+    }
+
+    #[test]
+    fn retry_policy_skips_long_retry_after() {
+        let policy = RetryPolicy::default();
+        let error = WeatherError::RateLimited { retry_after: Some(Duration::from_secs(3600)), message: String::new() };
+
+        assert_eq!(policy.delay_for(&error, 1), None);
+    }
+
+    #[test]
+    fn retry_policy_backoff_grows_and_is_capped() {
+        let policy = RetryPolicy { max_attempts: 20, base_delay: Duration::from_secs(1), max_delay: Duration::from_secs(30) };
+
+        for _ in 0..50 {
+            let first = policy.delay_for(&server_error(), 1).unwrap();
+            assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+            let third = policy.delay_for(&server_error(), 3).unwrap();
+            assert!(third >= Duration::from_secs(2) && third <= Duration::from_secs(4));
+            let late = policy.delay_for(&server_error(), 15).unwrap();
+            assert!(late >= Duration::from_secs(15) && late <= Duration::from_secs(30));
+        }
+    }
+}